@@ -25,6 +25,7 @@ use async_trait::async_trait;
 use axum_mcp::{
     extract_integer_opt, extract_string, extract_string_opt, McpServer, ServerConfig, Tool,
 };
+use plc::autofix::apply_mechanical_fixes_for_first_error;
 use proofpatch_core as plc;
 use serde_json::{json, Value};
 use smtkit;
@@ -278,6 +279,7 @@ impl Tool for ProofpatchTool {
             ("patch_region", ProofpatchPatchRegionTool.schema()),
             ("smt_probe", ProofpatchSmtProbeTool.schema()),
             ("smt_repro", ProofpatchSmtReproTool.schema()),
+            ("lint_style", ProofpatchLintStyleTool.schema()),
         ];
 
         let mut one_of: Vec<Value> = Vec::new();
@@ -326,6 +328,7 @@ impl Tool for ProofpatchTool {
                     "patch_region": ProofpatchPatchRegionTool.schema(),
                     "smt_probe": ProofpatchSmtProbeTool.schema(),
                     "smt_repro": ProofpatchSmtReproTool.schema(),
+                    "lint_style": ProofpatchLintStyleTool.schema(),
                 });
                 Ok(json!({
                     "ok": true,
@@ -340,7 +343,8 @@ impl Tool for ProofpatchTool {
                         "patch_nearest",
                         "patch_region",
                         "smt_probe",
-                        "smt_repro"
+                        "smt_repro",
+                        "lint_style"
                     ],
                     "schemas": schemas
                 }))
@@ -359,6 +363,7 @@ impl Tool for ProofpatchTool {
                     "patch_region" => ProofpatchPatchRegionTool.call(&sub).await,
                     "smt_probe" => ProofpatchSmtProbeTool.call(&sub).await,
                     "smt_repro" => ProofpatchSmtReproTool.call(&sub).await,
+                    "lint_style" => ProofpatchLintStyleTool.call(&sub).await,
                     _ => Err(format!("unknown action: {other}")),
                 }
             }
@@ -1341,37 +1346,32 @@ impl Tool for ProofpatchTreeSearchNearestTool {
             let locs0 = plc::locate_sorries_in_text(&original, 50, 1).unwrap_or_default();
             let primary0 = plc::select_primary_sorry(None, &locs0)
                 .ok_or_else(|| "No `sorry`/`admit` tokens found in file.".to_string())?;
+            let goal_snapshot = goal_dump_v
+                .as_ref()
+                .and_then(|gd| gd.get("pp_dump"))
+                .and_then(|pp| plc::hyp_rank::goal_snapshot(pp, plc::hyp_rank::DEFAULT_MAX_HYPS));
+            let research_notes = research_notes.as_ref().map(|notes| {
+                let max_chars = 12_000usize;
+                let mut kept: String = notes.chars().take(max_chars).collect();
+                if notes.chars().count() > max_chars {
+                    kept.push_str("\n\n[proofpatch: research_notes truncated]\n");
+                }
+                kept
+            });
             let payload = plc::build_region_patch_prompt(
                 &repo_root,
                 &file,
                 primary0.region_start,
                 primary0.region_end,
                 None,
+                &plc::prompts::PromptExtras {
+                    goal: goal_snapshot,
+                    research_notes,
+                    ..plc::prompts::PromptExtras::default()
+                },
             )?;
             let mut system = payload.system.clone();
             system.push_str("\n\nReturn a JSON array of 6 distinct candidate Lean replacements (strings). Each element must be a proof term only (no markdown fences).");
-            if let Some(gd) = goal_dump_v.as_ref() {
-                if let Some(pretty) = gd
-                    .get("pp_dump")
-                    .and_then(|v| v.get("goals"))
-                    .and_then(|v| v.as_array())
-                    .and_then(|xs| xs.first())
-                    .and_then(|v| v.get("pretty"))
-                    .and_then(|v| v.as_str())
-                {
-                    system.push_str("\n\nGoal snapshot (pretty):\n");
-                    system.push_str(pretty);
-                }
-            }
-            if let Some(notes) = research_notes.as_ref() {
-                let max_chars = 12_000usize;
-                let kept: String = notes.chars().take(max_chars).collect();
-                system.push_str("\n\nResearch notes (external; may be incomplete):\n");
-                system.push_str(&kept);
-                if notes.chars().count() > max_chars {
-                    system.push_str("\n\n[proofpatch: research_notes truncated]\n");
-                }
-            }
             let res = plc::llm::chat_completion(
                 &system,
                 &payload.user,
@@ -1904,10 +1904,8 @@ impl Tool for ProofpatchTriageFileTool {
         let rubberduck_prompt_first_error = if include_prompts {
             context_pack_first_error
                 .as_ref()
-                .and_then(|v| v.get("focus"))
-                .and_then(|f| f.get("excerpt"))
-                .and_then(|e| e.as_str())
-                .and_then(|excerpt| {
+                .and_then(|v| serde_json::from_value::<plc::ContextPack>(v.clone()).ok())
+                .and_then(|pack| {
                     let label = summary
                         .get("first_error_loc")
                         .and_then(|v| v.get("line"))
@@ -1918,8 +1916,12 @@ impl Tool for ProofpatchTriageFileTool {
                         &repo_root,
                         &file,
                         &label,
-                        excerpt,
+                        &pack.focus.excerpt,
                         first_error_text.as_deref(),
+                        &plc::prompts::PromptExtras {
+                            context_pack: Some(plc::prompts::context_pack_text(&pack)),
+                            ..plc::prompts::PromptExtras::default()
+                        },
                     )
                     .ok()
                 })
@@ -1938,6 +1940,7 @@ impl Tool for ProofpatchTriageFileTool {
                         s.region_start,
                         s.region_end,
                         first_error_text.as_deref(),
+                        &plc::prompts::PromptExtras::default(),
                     )
                     .ok()
                 })
@@ -2018,93 +2021,6 @@ impl Tool for ProofpatchTriageFileTool {
     }
 }
 
-fn apply_mechanical_fixes_for_first_error(
-    text: &str,
-    first_error_line_1: Option<usize>,
-    first_error_text: Option<&str>,
-) -> (String, Vec<Value>) {
-    let Some(line1) = first_error_line_1 else {
-        return (text.to_string(), vec![]);
-    };
-    let Some(msg) = first_error_text else {
-        return (text.to_string(), vec![]);
-    };
-
-    let mut lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
-    if lines.is_empty() {
-        return (text.to_string(), vec![]);
-    }
-
-    let mut edits = Vec::new();
-    let start0 = line1.saturating_sub(1).saturating_sub(40);
-    let end0 = usize::min(lines.len().saturating_sub(1), (line1 - 1) + 60);
-
-    // Heuristic 1: if Lean suggests `ring_nf`, replace a nearby `ring` with `ring_nf`.
-    if msg.contains("ring_nf") {
-        for i0 in start0..=end0 {
-            let ln = &lines[i0];
-            if ln.trim() == "ring" {
-                let before = ln.clone();
-                let indent: String = ln.chars().take_while(|c| c.is_whitespace()).collect();
-                lines[i0] = format!("{indent}ring_nf");
-                edits.push(json!({
-                    "kind": "replace_tactic",
-                    "line": i0 + 1,
-                    "before": before,
-                    "after": lines[i0],
-                    "note": "Lean suggested ring_nf; replaced nearby `ring` with `ring_nf`.",
-                }));
-                break;
-            }
-        }
-    }
-
-    // Heuristic 2: if the first error is a missing `Decidable` and we have a nearby `decide`,
-    // insert `classical` before `decide`.
-    //
-    // This is common in mathlib code where a `decide` proof works under classical but Lean
-    // cannot synthesize an instance automatically.
-    if msg.contains("failed to synthesize") && msg.contains("Decidable") {
-        for i0 in start0..=end0 {
-            let ln = &lines[i0];
-            if ln.trim() != "decide" {
-                continue;
-            }
-
-            // Avoid duplicating `classical` if it's already in the local tactic block.
-            let scan0 = i0.saturating_sub(4);
-            let already_classical = (scan0..=i0).any(|j0| {
-                lines
-                    .get(j0)
-                    .map(|s| s.trim() == "classical")
-                    .unwrap_or(false)
-            });
-            if already_classical {
-                continue;
-            }
-
-            let before = ln.clone();
-            let indent: String = ln.chars().take_while(|c| c.is_whitespace()).collect();
-            let replacement_lines = vec![format!("{indent}classical"), format!("{indent}decide")];
-            lines.splice(i0..=i0, replacement_lines.clone());
-            edits.push(json!({
-                "kind": "insert_classical_before_decide",
-                "line": i0 + 1,
-                "before": before,
-                "after": replacement_lines.join("\n"),
-                "note": "First error looks like missing Decidable; inserted `classical` before a nearby `decide`.",
-            }));
-            break;
-        }
-    }
-
-    let mut out = lines.join("\n");
-    if text.ends_with('\n') {
-        out.push('\n');
-    }
-    (out, edits)
-}
-
 fn first_error_snippet(stdout: &str, stderr: &str, max_lines: usize) -> Option<String> {
     fn from_text(txt: &str, max_lines: usize) -> Option<String> {
         let lines: Vec<&str> = txt.lines().collect();
@@ -2241,6 +2157,83 @@ fn escape_html(s: &str) -> String {
     out
 }
 
+struct ProofpatchLintStyleTool;
+
+#[async_trait]
+impl Tool for ProofpatchLintStyleTool {
+    fn description(&self) -> &str {
+        "Style-lint files (trailing whitespace and long lines; `fun x =>`, `$` and copyright when listed in `[lint] rules`) and optionally fix them."
+    }
+
+    fn schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "repo_root": { "type": "string" },
+                "files": { "type": "array", "items": { "type": "string" } },
+                "fix": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "If true, write mechanical fixes back to the files."
+                },
+                "dry_run": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "If true, compute fixes and diffs without writing (overrides `fix`)."
+                }
+            },
+            "required": ["repo_root", "files"]
+        })
+    }
+
+    async fn call(&self, args: &Value) -> Result<Value, String> {
+        let repo_root = repo_root_from_args(args)?;
+        let fix = args.get("fix").and_then(|v| v.as_bool()).unwrap_or(false);
+        let dry_run = args
+            .get("dry_run")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let files_v = args
+            .get("files")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| "missing/invalid `files` (expected array of strings)".to_string())?;
+        let mut files: Vec<String> = Vec::with_capacity(files_v.len());
+        for v in files_v {
+            let s = v
+                .as_str()
+                .ok_or_else(|| "invalid `files` entry (expected string)".to_string())?;
+            files.push(s.to_string());
+        }
+
+        let repo_root = resolve_lean_repo_root(repo_root, files.first().map(|s| s.as_str()))?;
+        let cfg = plc::config::load_from_repo_root(&repo_root)?
+            .map(|c| c.lint)
+            .unwrap_or_default();
+        let mode = if dry_run {
+            plc::autofix::FixMode::DryRun
+        } else if fix {
+            plc::autofix::FixMode::Write
+        } else {
+            plc::autofix::FixMode::Check
+        };
+
+        let mut reports: Vec<plc::autofix::LintFileReport> = Vec::with_capacity(files.len());
+        for f in &files {
+            reports.push(plc::autofix::lint_file(&repo_root, f, &cfg, &[], mode)?);
+        }
+        let findings: usize = reports.iter().map(|r| r.findings.len()).sum();
+        let fixes: usize = reports.iter().map(|r| r.fixes.len()).sum();
+        let remaining: usize = reports.iter().map(|r| r.remaining.len()).sum();
+        Ok(json!({
+            "ok": findings == 0 || (mode == plc::autofix::FixMode::Write && remaining == 0),
+            "kind": "lint_style",
+            "repo_root": repo_root.display().to_string(),
+            "counts": { "files": reports.len(), "findings": findings, "fixes": fixes, "remaining": remaining },
+            "files": reports,
+        }))
+    }
+}
+
 struct ProofpatchReportHtmlTool;
 
 #[async_trait]
@@ -2439,6 +2432,8 @@ impl Tool for ProofpatchRubberduckPromptTool {
                 "file": { "type": "string" },
                 "lemma": { "type": "string" },
                 "diagnostics": { "type": "string", "description": "Optional Lean output/error context to include (raw stdout/stderr excerpt or JSON)" },
+                "goal": { "type": "object", "description": "Optional goal dump (`goal-dump-nearest` output or a bare `pp_dump`) for the prompt's goal state" },
+                "max_hyps": { "type": "integer", "description": "Hypotheses kept in the goal state, best-ranked first (default 12; 0 keeps all)" },
                 "proofpatch_root": { "type": "string" }
             },
             "required": ["repo_root", "file", "lemma"]
@@ -2450,8 +2445,20 @@ impl Tool for ProofpatchRubberduckPromptTool {
         let file = extract_string(args, "file")?;
         let lemma = extract_string(args, "lemma")?;
         let diagnostics = extract_string_opt(args, "diagnostics");
-        let payload =
-            plc::build_rubberduck_prompt(&repo_root, &file, &lemma, diagnostics.as_deref())?;
+        let max_hyps = extract_u64_opt(args, "max_hyps")?
+            .map(|v| v as usize)
+            .unwrap_or(plc::hyp_rank::DEFAULT_MAX_HYPS);
+        let goal = args
+            .get("goal")
+            .filter(|v| !v.is_null())
+            .and_then(|v| plc::hyp_rank::goal_snapshot(v.get("pp_dump").unwrap_or(v), max_hyps));
+        let payload = plc::build_rubberduck_prompt(
+            &repo_root,
+            &file,
+            &lemma,
+            diagnostics.as_deref(),
+            &plc::prompts::PromptExtras::with_goal(goal.as_deref()),
+        )?;
         serde_json::to_value(payload).map_err(|e| format!("failed to serialize payload: {}", e))
     }
 }
@@ -2709,6 +2716,12 @@ struct RubberduckArgs {
     lemma: String,
     #[serde(default)]
     diagnostics: Option<String>,
+    /// Goal dump (`goal-dump-nearest` output or a bare `pp_dump`) for the prompt's goal state.
+    #[serde(default)]
+    goal: Option<serde_json::Value>,
+    /// Hypotheses kept in the goal state (default 12; 0 keeps all).
+    #[serde(default)]
+    max_hyps: Option<u64>,
     // Schema compatibility only (unused).
     #[serde(default)]
     proofpatch_root: Option<String>,
@@ -2746,6 +2759,17 @@ struct ReportHtmlArgs {
     output_path: Option<String>,
 }
 
+#[cfg(feature = "stdio")]
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+struct LintStyleArgs {
+    repo_root: String,
+    files: Vec<String>,
+    #[serde(default)]
+    fix: Option<bool>,
+    #[serde(default)]
+    dry_run: Option<bool>,
+}
+
 #[cfg(feature = "stdio")]
 #[derive(Clone)]
 struct ProofpatchStdioMcp {
@@ -2899,10 +2923,8 @@ impl ProofpatchStdioMcp {
         let rubberduck_prompt_first_error = if include_prompts {
             context_pack_first_error
                 .as_ref()
-                .and_then(|v| v.get("focus"))
-                .and_then(|f| f.get("excerpt"))
-                .and_then(|e| e.as_str())
-                .and_then(|excerpt| {
+                .and_then(|v| serde_json::from_value::<plc::ContextPack>(v.clone()).ok())
+                .and_then(|pack| {
                     let label = summary
                         .get("first_error_loc")
                         .and_then(|v| v.get("line"))
@@ -2913,8 +2935,12 @@ impl ProofpatchStdioMcp {
                         &repo_root,
                         &file,
                         &label,
-                        excerpt,
+                        &pack.focus.excerpt,
                         first_error_text.as_deref(),
+                        &plc::prompts::PromptExtras {
+                            context_pack: Some(plc::prompts::context_pack_text(&pack)),
+                            ..plc::prompts::PromptExtras::default()
+                        },
                     )
                     .ok()
                 })
@@ -2933,6 +2959,7 @@ impl ProofpatchStdioMcp {
                         s.region_start,
                         s.region_end,
                         first_error_text.as_deref(),
+                        &plc::prompts::PromptExtras::default(),
                     )
                     .ok()
                 })
@@ -3367,6 +3394,23 @@ impl ProofpatchStdioMcp {
             out.to_string(),
         )]))
     }
+
+    #[tool(description = "Style-lint files and optionally apply mechanical fixes")]
+    async fn proofpatch_lint_style(
+        &self,
+        params: Parameters<LintStyleArgs>,
+    ) -> Result<CallToolResult, McpError> {
+        let tool = ProofpatchLintStyleTool;
+        let v = serde_json::to_value(&params.0)
+            .map_err(|e| McpError::invalid_params(e.to_string(), None))?;
+        let out = tool
+            .call(&v)
+            .await
+            .map_err(|e| McpError::internal_error(e, None))?;
+        Ok(CallToolResult::success(vec![Content::text(
+            out.to_string(),
+        )]))
+    }
 }
 
 #[cfg(feature = "stdio")]
@@ -3530,6 +3574,7 @@ pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
             .tool("proofpatch_triage_file", ProofpatchTriageFileTool)?
             .tool("proofpatch_agent_step", ProofpatchAgentStepTool)?
             .tool("proofpatch_report_html", ProofpatchReportHtmlTool)?
+            .tool("proofpatch_lint_style", ProofpatchLintStyleTool)?
             .tool(
                 "proofpatch_rubberduck_prompt",
                 ProofpatchRubberduckPromptTool,
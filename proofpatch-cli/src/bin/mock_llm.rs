@@ -0,0 +1,52 @@
+//! `proofpatch-mock-llm`: serve a scripted OpenAI-compatible endpoint for tests.
+//!
+//! Usage:
+//!   proofpatch-mock-llm --fixture <path.json> [--addr 127.0.0.1:0] [--log <requests.jsonl>]
+//!
+//! Prints `{"ok":true,"base_url":...}` on the first stdout line, then serves until killed.
+//! See `proofpatch_core::mock_llm` for the fixture format.
+
+use proofpatch_core::mock_llm::{load_script, MockLlmServer};
+use std::io::Write;
+use std::path::PathBuf;
+
+fn arg_value(args: &[String], key: &str) -> Option<String> {
+    args.iter()
+        .position(|a| a == key)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+fn main() -> Result<(), String> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help" || a == "-h") {
+        println!(
+            "proofpatch-mock-llm --fixture <path.json> [--addr 127.0.0.1:0] [--log <requests.jsonl>]"
+        );
+        return Ok(());
+    }
+    let fixture = arg_value(&args, "--fixture")
+        .map(PathBuf::from)
+        .ok_or_else(|| "missing --fixture".to_string())?;
+    let addr = arg_value(&args, "--addr").unwrap_or_else(|| "127.0.0.1:0".to_string());
+    let log = arg_value(&args, "--log").map(PathBuf::from);
+    let script = load_script(&fixture)?;
+
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| format!("failed to build tokio runtime: {e}"))?;
+    rt.block_on(async move {
+        let server = MockLlmServer::bind(&addr, script, log).await?;
+        println!(
+            "{}",
+            serde_json::json!({
+                "ok": true,
+                "addr": server.addr.to_string(),
+                "base_url": server.base_url,
+                "fixture": fixture.display().to_string(),
+            })
+        );
+        let _ = std::io::stdout().flush();
+        server.wait().await;
+        Ok(())
+    })
+}
@@ -14,23 +14,6 @@ fn extract_json_from_text(s: &str) -> Option<serde_json::Value> {
     plc::json_extract::extract_first_json_value(s)
 }
 
-#[cfg(feature = "axi-agent")]
-fn default_context_lines() -> u64 {
-    8
-}
-#[cfg(feature = "axi-agent")]
-fn default_nearby_lines() -> u64 {
-    120
-}
-#[cfg(feature = "axi-agent")]
-fn default_max_nearby() -> u64 {
-    30
-}
-#[cfg(feature = "axi-agent")]
-fn default_max_imports() -> u64 {
-    30
-}
-
 fn truncate_str(s: &str, max_chars: usize) -> String {
     if s.chars().count() <= max_chars {
         return s.to_string();
@@ -239,114 +222,6 @@ fn research_summary_system_prompt(kind: &str) -> String {
     }
 }
 
-// NOTE: Advanced “agent tool-calling” mode (axi-based) is not part of the public, standalone
-// build of proofpatch-core. Keep `proofpatch` usable without any extra workspace crates.
-
-#[cfg(feature = "axi-agent")]
-#[derive(Debug, Clone, serde::Deserialize, schemars::JsonSchema)]
-#[serde(deny_unknown_fields)]
-#[schemars(deny_unknown_fields)]
-struct ContextPackArgs {
-    file: String,
-    // OpenAI-strict schemas want every property listed in `required`. We keep these as Option
-    // but mark them `required` so the schema includes them, and allow `null` when unset.
-    #[schemars(required)]
-    #[serde(default)]
-    decl: Option<String>,
-    #[schemars(required)]
-    #[serde(default)]
-    line: Option<u64>,
-    #[schemars(required)]
-    #[serde(default = "default_context_lines")]
-    context_lines: u64,
-    #[schemars(required)]
-    #[serde(default = "default_nearby_lines")]
-    nearby_lines: u64,
-    #[schemars(required)]
-    #[serde(default = "default_max_nearby")]
-    max_nearby: u64,
-    #[schemars(required)]
-    #[serde(default = "default_max_imports")]
-    max_imports: u64,
-}
-
-#[cfg(feature = "axi-agent")]
-fn default_timeout_s() -> u64 {
-    90
-}
-
-#[cfg(feature = "axi-agent")]
-#[derive(Debug, Clone, serde::Deserialize, schemars::JsonSchema)]
-#[serde(deny_unknown_fields)]
-#[schemars(deny_unknown_fields)]
-struct VerifySummaryArgs {
-    file: String,
-    #[schemars(required)]
-    #[serde(default = "default_timeout_s")]
-    timeout_s: u64,
-}
-
-#[cfg(feature = "axi-agent")]
-fn default_sorry_context_lines() -> u64 {
-    1
-}
-
-#[cfg(feature = "axi-agent")]
-fn default_max_sorries() -> u64 {
-    30
-}
-
-#[cfg(feature = "axi-agent")]
-#[derive(Debug, Clone, serde::Deserialize, schemars::JsonSchema)]
-#[serde(deny_unknown_fields)]
-#[schemars(deny_unknown_fields)]
-struct LocateSorriesArgs {
-    file: String,
-    #[schemars(required)]
-    #[serde(default = "default_max_sorries")]
-    max_sorries: u64,
-    #[schemars(required)]
-    #[serde(default = "default_sorry_context_lines")]
-    context_lines: u64,
-}
-
-#[cfg(feature = "axi-agent")]
-fn default_patch_timeout_s() -> u64 {
-    120
-}
-
-#[cfg(feature = "axi-agent")]
-fn default_patch_verify() -> bool {
-    true
-}
-
-#[cfg(feature = "axi-agent")]
-fn default_patch_write() -> bool {
-    false
-}
-
-#[cfg(feature = "axi-agent")]
-#[derive(Debug, Clone, serde::Deserialize, schemars::JsonSchema)]
-#[serde(deny_unknown_fields)]
-#[schemars(deny_unknown_fields)]
-struct PatchDeclArgs {
-    file: String,
-    decl: String,
-    replacement: String,
-    /// If true, write the patched text back to the file on disk.
-    /// If false, patch and verify in-memory only.
-    #[schemars(required)]
-    #[serde(default = "default_patch_write")]
-    write: bool,
-    /// If true, run Lean verification after patching.
-    #[schemars(required)]
-    #[serde(default = "default_patch_verify")]
-    verify: bool,
-    #[schemars(required)]
-    #[serde(default = "default_patch_timeout_s")]
-    timeout_s: u64,
-}
-
 fn arg_value(args: &[String], key: &str) -> Option<String> {
     args.iter()
         .position(|a| a == key)
@@ -374,6 +249,38 @@ fn arg_flag(args: &[String], key: &str) -> bool {
     args.iter().any(|a| a == key)
 }
 
+/// `context_pack` prompt text (imports, nearby declaration headers) around `line_1` of `text`.
+fn region_context_pack(text: &str, line_1: usize) -> Option<String> {
+    plc::build_context_pack_in_text(text, None, Some(line_1), 0, 80, 12, 30)
+        .ok()
+        .map(|pack| plc::prompts::context_pack_text(&pack))
+        .filter(|t| !t.is_empty())
+}
+
+/// Repo-relative `.lean` paths, each counted once however it was spelled (`A.lean`, `./A.lean`, an
+/// absolute path), in sorted order. Paths that do not resolve are kept as given.
+fn unique_repo_files(repo_root: &std::path::Path, files: Vec<String>) -> Vec<String> {
+    let root = repo_root
+        .canonicalize()
+        .unwrap_or_else(|_| repo_root.to_path_buf());
+    let set: std::collections::BTreeSet<String> = files
+        .into_iter()
+        .map(|f| {
+            repo_root
+                .join(&f)
+                .canonicalize()
+                .ok()
+                .and_then(|p| {
+                    p.strip_prefix(&root)
+                        .ok()
+                        .map(|r| r.to_string_lossy().replace('\\', "/"))
+                })
+                .unwrap_or(f)
+        })
+        .collect();
+    set.into_iter().collect()
+}
+
 fn load_hint_rules(
     repo_root: &std::path::Path,
     research_preset: Option<&str>,
@@ -418,6 +325,258 @@ fn arg_u64(args: &[String], key: &str) -> Option<u64> {
     arg_value(args, key).and_then(|s| s.trim().parse::<u64>().ok())
 }
 
+/// `--llm-max-tokens` / `--llm-max-cost`: run-level LLM budget (each overrides `[llm.budget]`).
+fn llm_budget_from_args(args: &[String]) -> Result<plc::config::LlmBudget, String> {
+    let max_tokens = match arg_value(args, "--llm-max-tokens") {
+        Some(s) => Some(
+            s.trim()
+                .parse::<u64>()
+                .map_err(|_| format!("invalid --llm-max-tokens: {s}"))?,
+        ),
+        None => None,
+    };
+    let max_cost_usd = match arg_value(args, "--llm-max-cost") {
+        Some(s) => {
+            let v = s
+                .trim()
+                .trim_start_matches('$')
+                .parse::<f64>()
+                .map_err(|_| format!("invalid --llm-max-cost: {s}"))?;
+            if !v.is_finite() || v < 0.0 {
+                return Err(format!("invalid --llm-max-cost: {s}"));
+            }
+            Some(v)
+        }
+        None => None,
+    };
+    Ok(plc::config::LlmBudget {
+        max_tokens,
+        max_cost_usd,
+    })
+}
+
+/// Add the run's LLM token/cost totals to a report (`llm_usage`), plus the response cache
+/// settings when the cache is on (`llm_cache`).
+fn attach_llm_usage(out: &mut serde_json::Value) {
+    if let Some(obj) = out.as_object_mut() {
+        obj.insert(
+            "llm_usage".to_string(),
+            serde_json::to_value(plc::llm_usage::summary()).unwrap_or(serde_json::Value::Null),
+        );
+        let mode = plc::llm_cache::mode();
+        if mode != plc::llm_cache::LlmCacheMode::Off {
+            obj.insert(
+                "llm_cache".to_string(),
+                json!({
+                    "mode": mode.as_str(),
+                    "dir": plc::llm_cache::cache_dir().map(|d| d.display().to_string()),
+                }),
+            );
+        }
+    }
+}
+
+fn llm_stream_progress(
+    root: &std::path::Path,
+    event: &str,
+    message: String,
+    data: serde_json::Value,
+) {
+    plc::review::emit_progress(
+        root,
+        plc::review::ProgressEvent {
+            event: event.to_string(),
+            ts: String::new(),
+            phase: "llm_stream".to_string(),
+            message,
+            data,
+        },
+    );
+}
+
+/// `chat_completion` over a streamed response (`--stream`), with live progress on stderr
+/// (`PROOFPATCH_REVIEW_PROGRESS`).
+///
+/// With `stop_on_code_block`, the stream is cancelled once a complete Lean code block has
+/// arrived and `content` is that block. A timeout mid-stream salvages the text received so far.
+/// Also returns the stream report for the command's JSON output (`stream`).
+fn chat_completion_streamed(
+    rt: &tokio::runtime::Runtime,
+    root: &std::path::Path,
+    system: &str,
+    user: &str,
+    timeout: StdDuration,
+    stop_on_code_block: bool,
+) -> Result<(plc::llm::ChatCompletionResult, serde_json::Value), String> {
+    let mut last = std::time::Instant::now();
+    llm_stream_progress(root, "start", "streaming".to_string(), json!({}));
+    let res = rt.block_on(plc::llm::chat_completion_stream(
+        system,
+        user,
+        timeout,
+        |_delta, text| {
+            if stop_on_code_block && plc::llm::complete_lean_code_block(text).is_some() {
+                return plc::llm::StreamControl::Stop;
+            }
+            if last.elapsed() >= StdDuration::from_millis(500) {
+                last = std::time::Instant::now();
+                llm_stream_progress(
+                    root,
+                    "delta",
+                    format!("{} chars", text.len()),
+                    json!({ "chars": text.len() }),
+                );
+            }
+            plc::llm::StreamControl::Continue
+        },
+    ))?;
+    llm_stream_progress(
+        root,
+        "end",
+        format!(
+            "{} ({} chunks, {} chars, {} ms)",
+            res.finish,
+            res.chunks,
+            res.content.len(),
+            res.elapsed_ms
+        ),
+        json!({ "finish": res.finish, "chunks": res.chunks }),
+    );
+    let report = json!({
+        "finish": res.finish,
+        "partial": res.partial,
+        "finish_reason": res.finish_reason,
+        "chunks": res.chunks,
+        "elapsed_ms": res.elapsed_ms,
+        "error": res.error,
+        "code_block": res.code_block.is_some(),
+    });
+    let content = match res.code_block {
+        Some(block) if stop_on_code_block => block,
+        _ => res.content,
+    };
+    Ok((
+        plc::llm::ChatCompletionResult {
+            provider: res.provider,
+            model: res.model,
+            model_source: res.model_source,
+            model_env: res.model_env,
+            content,
+            usage: res.usage,
+            raw: serde_json::Value::Null,
+        },
+        report,
+    ))
+}
+
+/// `--samples` knobs shared by `suggest` and `loop`.
+struct SampleArgs {
+    n: usize,
+    temperature: Option<f64>,
+    max_verify: usize,
+    jobs: usize,
+    /// `--timeout-s`, for each LLM request and each verification.
+    timeout: StdDuration,
+}
+
+fn sample_args_from(rest: &[String]) -> Result<SampleArgs, String> {
+    let temperature = match arg_value(rest, "--sample-temperature") {
+        Some(s) => Some(
+            s.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("invalid --sample-temperature: {s}"))?,
+        ),
+        None => None,
+    };
+    Ok(SampleArgs {
+        n: arg_u64(rest, "--samples").unwrap_or(1) as usize,
+        temperature,
+        max_verify: arg_u64(rest, "--max-verify").unwrap_or(8) as usize,
+        jobs: arg_u64(rest, "--verify-jobs").unwrap_or(4) as usize,
+        timeout: StdDuration::from_secs(arg_u64(rest, "--timeout-s").unwrap_or(120)),
+    })
+}
+
+/// Sample `args.n` proofs, rank them by agreement, and verify the top candidates against `text`.
+///
+/// Returns the proof to use (best verified, else the most agreed-upon candidate), its check when it
+/// was verified, and the `samples` report.
+fn sample_and_verify(
+    rt: &tokio::runtime::Runtime,
+    repo_root: &std::path::Path,
+    text: &str,
+    lemma: &str,
+    system: &str,
+    user: &str,
+    args: &SampleArgs,
+) -> Result<
+    (
+        plc::llm::SampledCompletions,
+        Option<plc::sampling::CandidateCheck>,
+        String,
+        serde_json::Value,
+    ),
+    String,
+> {
+    let sampled = rt.block_on(plc::llm::chat_completion_samples(
+        system,
+        user,
+        args.n,
+        args.temperature,
+        args.timeout,
+    ))?;
+    let candidates = plc::sampling::rank_candidates(&sampled.samples);
+    let checks = rt.block_on(plc::sampling::verify_candidates(
+        repo_root,
+        text,
+        lemma,
+        &candidates,
+        args.max_verify,
+        args.jobs,
+        args.timeout,
+    ));
+    let stats = plc::sampling::sample_stats(sampled.samples.len(), &candidates, &checks);
+    let best = plc::sampling::best_check(&checks);
+    let best_rank = best.map(|c| c.rank);
+    let chosen = best.or_else(|| checks.first()).cloned();
+    let proof = chosen
+        .as_ref()
+        .map(|c| c.proof.clone())
+        .or_else(|| candidates.first().map(|c| c.proof.clone()))
+        .ok_or_else(|| "all samples were empty".to_string())?;
+    let report = json!({
+        "mode": sampled.mode,
+        "requests": sampled.requests,
+        "errors": sampled.errors,
+        "stats": stats,
+        "best_rank": best_rank,
+        "candidates": checks
+            .iter()
+            .map(|c| {
+                json!({
+                    "rank": c.rank,
+                    "proof": c.proof,
+                    "votes": c.votes,
+                    "ok": c.ok,
+                    "solved": c.solved,
+                    "error": c.error,
+                    "verify": c.verify.as_ref().map(|v| {
+                        verify_summary_from_raw_value(
+                            &serde_json::to_value(v).unwrap_or(serde_json::Value::Null),
+                        )
+                    }),
+                })
+            })
+            .collect::<Vec<_>>(),
+        "unverified": candidates
+            .iter()
+            .skip(checks.len())
+            .map(|c| json!({ "proof": c.proof, "votes": c.votes }))
+            .collect::<Vec<_>>(),
+    });
+    Ok((sampled, chosen, proof, report))
+}
+
 fn write_json(path: &std::path::Path, value: &serde_json::Value) -> Result<(), String> {
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)
@@ -908,6 +1067,26 @@ fn smt_entails_from_pp_dump_escalating(
     Ok((None, attempts))
 }
 
+/// A one-goal `pp_dump`-shaped object from cached hypothesis texts and a target.
+fn pp_dump_from_hyps_target(hyps_texts: &[String], target: &str) -> serde_json::Value {
+    let mut pretty = String::new();
+    for h in hyps_texts {
+        pretty.push_str(h);
+        pretty.push('\n');
+    }
+    pretty.push_str("⊢ ");
+    pretty.push_str(target);
+
+    let hyps_json: Vec<serde_json::Value> =
+        hyps_texts.iter().map(|s| json!({ "text": s })).collect();
+    json!({
+        "goals": [{
+            "pretty": pretty,
+            "hyps": hyps_json
+        }]
+    })
+}
+
 fn smt_entails_from_hyps_target_escalating(
     hyps_texts: &[String],
     target: &str,
@@ -920,25 +1099,7 @@ fn smt_entails_from_hyps_target_escalating(
     trace: &mut Vec<serde_json::Value>,
 ) -> Result<(Option<bool>, u64), String> {
     // Build a minimal pp_dump-shaped object so we can reuse the same logic.
-    let mut pretty = String::new();
-    for h in hyps_texts.iter().take(48) {
-        pretty.push_str(h);
-        pretty.push('\n');
-    }
-    pretty.push_str("⊢ ");
-    pretty.push_str(target);
-
-    let hyps_json: Vec<serde_json::Value> = hyps_texts
-        .iter()
-        .take(48)
-        .map(|s| json!({ "text": s }))
-        .collect();
-    let pp_dump = json!({
-        "goals": [{
-            "pretty": pretty,
-            "hyps": hyps_json
-        }]
-    });
+    let pp_dump = pp_dump_from_hyps_target(&hyps_texts[..hyps_texts.len().min(48)], target);
     smt_entails_from_pp_dump_escalating(
         &pp_dump,
         timeout_ms,
@@ -1041,6 +1202,103 @@ fn verify_summary_from_raw_value(raw_v: &serde_json::Value) -> serde_json::Value
     })
 }
 
+/// `prompt render`: preview prompt templates with the repo's overrides and the given variables.
+fn prompt_render(rest: &[String]) -> Result<(), String> {
+    let repo_root = arg_value(rest, "--repo")
+        .ok_or_else(|| "missing --repo".to_string())
+        .map(PathBuf::from)?;
+    let file = arg_value(rest, "--file");
+    let lemma = arg_value(rest, "--lemma");
+    let with_context_pack = arg_flag(rest, "--context-pack");
+    let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+
+    let repo_root = plc::find_lean_repo_root(&repo_root).map_err(|e| format!("repo_root: {e}"))?;
+    // Unlike the prompt builders, a broken template set is an error here.
+    let templates = plc::prompts::PromptTemplates::load(&repo_root)?;
+
+    let mut vars = plc::prompts::PromptVars::new();
+    if let (Some(file), Some(lemma)) = (file.as_deref(), lemma.as_deref()) {
+        let p = repo_root.join(file);
+        let txt = fs::read_to_string(&p).map_err(|e| format!("read {}: {e}", p.display()))?;
+        vars.insert("excerpt".into(), plc::extract_decl_block(&txt, lemma)?);
+        vars.insert("excerpt_label".into(), "Declaration excerpt".into());
+        vars.insert("focus_label".into(), lemma.to_string());
+        if with_context_pack {
+            let pack =
+                plc::build_context_pack(&repo_root, file, Some(lemma), None, 8, 120, 30, 30)?;
+            vars.insert(
+                "context_pack".into(),
+                plc::prompts::context_pack_text(&pack),
+            );
+        }
+    } else if with_context_pack {
+        return Err("--context-pack requires --file and --lemma".to_string());
+    }
+    for kv in arg_values(rest, "--var") {
+        let (k, v) = kv
+            .split_once('=')
+            .ok_or_else(|| format!("--var expects name=value, got `{kv}`"))?;
+        vars.insert(k.trim().to_string(), v.to_string());
+    }
+    for kv in arg_values(rest, "--var-file") {
+        let (k, p) = kv
+            .split_once('=')
+            .ok_or_else(|| format!("--var-file expects name=path, got `{kv}`"))?;
+        let v = fs::read_to_string(p).map_err(|e| format!("read {p}: {e}"))?;
+        vars.insert(k.trim().to_string(), v.trim_end().to_string());
+    }
+
+    let mut names = arg_values(rest, "--template");
+    if names.is_empty() {
+        names = plc::prompts::TEMPLATES
+            .iter()
+            .map(|t| t.name.to_string())
+            .collect();
+    }
+    let mut rendered = Vec::new();
+    for name in &names {
+        let spec = plc::prompts::spec(name).ok_or_else(|| {
+            format!("unknown prompt template `{name}` (see `prompt render` without --template)")
+        })?;
+        let (src, source) = templates.get(name).expect("known template");
+        let used = plc::prompts::template_vars(src)?;
+        let unset: Vec<&String> = used
+            .iter()
+            .filter(|v| vars.get(*v).is_none_or(|x| x.trim().is_empty()))
+            .collect();
+        let text = templates.render(name, &vars)?;
+        rendered.push(json!({
+            "name": name,
+            "description": spec.description,
+            "source": source,
+            "variables": used,
+            "unset": unset,
+            "chars": text.chars().count(),
+            "text": text,
+        }));
+    }
+    let out = json!({
+        "kind": "prompt_render",
+        "repo_root": repo_root.display().to_string(),
+        "templates": rendered,
+    });
+    if let Some(p) = output_json {
+        write_json(&p, &out)?;
+        println!(
+            "{}",
+            json!({
+                "ok": true,
+                "written": p.display().to_string(),
+                "kind": "prompt_render",
+                "result_kind": serde_json::Value::Null,
+            })
+        );
+    } else {
+        println!("{}", out);
+    }
+    Ok(())
+}
+
 fn usage() -> String {
     [
         "proofpatch — Lean proof debugging loop + SMT oracle.",
@@ -1048,6 +1306,11 @@ fn usage() -> String {
         "Core (Lean loop):",
         "  triage-file          --repo <path> --file <relpath> ...",
         "  verify-summary       --repo <path> --file <relpath> ...",
+        "  audit-axioms         --repo <path> --file <relpath>|--package <dir> ...",
+        "  profile-file         --repo <path> --file <relpath> [--threshold-ms <n>] [--heartbeats] ...",
+        "  heartbeats-triage    --repo <path> --file <relpath> [--decl <name>|--line <n>] ...",
+        "  migrate-deprecations --repo <path> --file <relpath> [--write] [--output-diff <path>] ...",
+        "  lint-style           --repo <path> --module <Root>|--file <relpath> [--fix|--dry-run] ...",
         "  locate-sorries       --repo <path> --file <relpath> ...",
         "  context-pack         --repo <path> --file <relpath> ...",
         "  patch|patch-region|patch-nearest   --repo <path> --file <relpath> ...",
@@ -1056,16 +1319,28 @@ fn usage() -> String {
         "SMT oracle (via smtkit):",
         "  smt-probe            [--output-json <path>]",
         "  smt-repro            --input-json <path|-> ...",
+        "  smt-minimize         --bundle-dir <dir> | --smt2 <path> [--solver <z3|cvc5|yices|cmd>] ...",
         "  tree-search-nearest  --repo <path> --file <relpath> ... (includes SMT knobs)",
         "",
         "Optional (LLM/research/review):",
         "  suggest | loop",
+        "  agent-prove          --repo <path> --file <relpath> --lemma <name> [--max-steps <n>] [--max-turns <n>]",
+        "                       [--timeout-s <n>] [--llm-timeout-s <n>] [--write] [--transcript <path>]",
+        "                       [--output-json <path>] (requires cargo feature `axi-agent`)",
         "  arxiv-search | research-auto | research-ingest | research-attach",
-        "  review-prompt | review-diff | llm-chat",
+        "  review-prompt | review-diff | llm-chat | llm-providers",
+        "  (any command: --llm-max-tokens <n> --llm-max-cost <usd> caps LLM spend for the run)",
+        "  (any command: --llm-cache read-write|read-only|off; read-only replays without network)",
+        "  (suggest | loop | llm-chat: --stream streams the reply with live progress on stderr)",
+        "  (suggest | loop: --samples <n> [--sample-temperature <t>] [--max-verify <k>] [--verify-jobs <j>]",
+        "   asks for n proofs, ranks them by agreement, and verifies the top k in parallel)",
+        "  (loop: --conversation [--history-bytes <n>] keeps one chat with prior attempts, errors, goals, diffs)",
         "",
         "Other:",
         "  goal-dump-nearest | goal-analyze | goal-try",
-        "  report | lint-style | agent-step | prompt | rubberduck-prompt",
+        "  report | agent-step | prompt | rubberduck-prompt",
+        "  prompt render        --repo <path> [--template <name>]... [--file <relpath> --lemma <name> [--context-pack]]",
+        "                       [--var name=value]... [--var-file name=path]...  (preview prompt templates)",
         "  lean-embed-smoke (requires cargo feature `lean-embed`)",
         "",
         "Notes:",
@@ -1092,54 +1367,6 @@ fn escape_html(s: &str) -> String {
     out
 }
 
-fn apply_mechanical_fixes_for_first_error(
-    text: &str,
-    first_error_line_1: Option<usize>,
-    first_error_text: Option<&str>,
-) -> (String, Vec<serde_json::Value>) {
-    let Some(line1) = first_error_line_1 else {
-        return (text.to_string(), vec![]);
-    };
-    let Some(msg) = first_error_text else {
-        return (text.to_string(), vec![]);
-    };
-    // Heuristic 1: if Lean suggests `ring_nf`, replace a nearby `ring` with `ring_nf`.
-    if !msg.contains("ring_nf") {
-        return (text.to_string(), vec![]);
-    }
-
-    let mut lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
-    if lines.is_empty() {
-        return (text.to_string(), vec![]);
-    }
-
-    let mut edits = Vec::new();
-    let start0 = line1.saturating_sub(1).saturating_sub(40);
-    let end0 = usize::min(lines.len().saturating_sub(1), (line1 - 1) + 60);
-    for i0 in start0..=end0 {
-        let ln = &lines[i0];
-        let trimmed = ln.trim();
-        if trimmed == "ring" {
-            let before = ln.clone();
-            let indent: String = ln.chars().take_while(|c| c.is_whitespace()).collect();
-            lines[i0] = format!("{indent}ring_nf");
-            edits.push(json!({
-                "kind": "replace_tactic",
-                "line": i0 + 1,
-                "before": before,
-                "after": lines[i0],
-                "note": "Lean suggested ring_nf; replaced nearby `ring` with `ring_nf`.",
-            }));
-            break;
-        }
-    }
-
-    (
-        lines.join("\n") + if text.ends_with('\n') { "\n" } else { "" },
-        edits,
-    )
-}
-
 fn main() -> Result<(), String> {
     let args: Vec<String> = std::env::args().collect();
     let cmd = args.get(1).map(|s| s.as_str()).unwrap_or("");
@@ -1163,15 +1390,17 @@ fn main() -> Result<(), String> {
     // Aliases / grouping:
     // - `proofpatch smt probe` == `proofpatch smt-probe`
     // - `proofpatch smt repro` == `proofpatch smt-repro`
+    // - `proofpatch smt minimize` == `proofpatch smt-minimize`
     let (cmd, rest): (&str, &[String]) = if cmd == "smt" {
         let sub = rest.get(0).map(|s| s.as_str()).unwrap_or("");
         let tail: &[String] = if rest.len() > 1 { &rest[1..] } else { &[] };
         match sub {
             "probe" => ("smt-probe", tail),
             "repro" => ("smt-repro", tail),
+            "minimize" => ("smt-minimize", tail),
             _ => {
                 return Err(format!(
-                    "{}\n\nsmt subcommands:\n  smt probe\n  smt repro\n",
+                    "{}\n\nsmt subcommands:\n  smt probe\n  smt repro\n  smt minimize\n",
                     usage()
                 ));
             }
@@ -1180,6 +1409,11 @@ fn main() -> Result<(), String> {
         (cmd, rest)
     };
 
+    plc::llm_usage::set_budget_override(llm_budget_from_args(rest)?);
+    if let Some(m) = arg_value(rest, "--llm-cache") {
+        plc::llm_cache::set_mode_override(Some(plc::llm_cache::LlmCacheMode::parse(&m)?));
+    }
+
     match cmd {
         "smt-probe" => {
             let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
@@ -1351,6 +1585,10 @@ fn main() -> Result<(), String> {
                             &label,
                             &pack.focus.excerpt,
                             first_error_text.as_deref(),
+                            &plc::prompts::PromptExtras {
+                                context_pack: Some(plc::prompts::context_pack_text(&pack)),
+                                ..plc::prompts::PromptExtras::default()
+                            },
                         )
                         .ok()
                     })
@@ -1369,6 +1607,7 @@ fn main() -> Result<(), String> {
                             s.region_start,
                             s.region_end,
                             first_error_text.as_deref(),
+                            &plc::prompts::PromptExtras::default(),
                         )
                         .ok()
                     })
@@ -2486,7 +2725,7 @@ fn main() -> Result<(), String> {
             }
 
             // Execute `next_action` heuristically: fix-first-error with safe mechanical edits.
-            let (patched_text, edits) = apply_mechanical_fixes_for_first_error(
+            let (patched_text, edits) = plc::autofix::apply_mechanical_fixes_for_first_error(
                 &original_text,
                 first_error_line,
                 first_error_text.as_deref(),
@@ -2561,6 +2800,9 @@ fn main() -> Result<(), String> {
         }
 
         "prompt" => {
+            if rest.first().map(String::as_str) == Some("render") {
+                return prompt_render(&rest[1..]);
+            }
             let repo_root = arg_value(rest, "--repo")
                 .ok_or_else(|| "missing --repo".to_string())
                 .map(PathBuf::from)?;
@@ -2600,6 +2842,16 @@ fn main() -> Result<(), String> {
             let file = arg_value(rest, "--file").ok_or_else(|| "missing --file".to_string())?;
             let lemma = arg_value(rest, "--lemma").ok_or_else(|| "missing --lemma".to_string())?;
             let diagnostics_file = arg_value(rest, "--diagnostics-file").map(PathBuf::from);
+            // A goal dump (`goal-dump-nearest` output or a bare `pp_dump`) for the prompt's
+            // "Goal state" section, trimmed to the best-ranked hypotheses.
+            let goal_json = arg_value(rest, "--goal-json").map(PathBuf::from);
+            let max_hyps = arg_u64(rest, "--max-hyps")
+                .map(|v| v as usize)
+                .unwrap_or(plc::hyp_rank::DEFAULT_MAX_HYPS);
+            // The other template extras: imports/nearby headers, a notes file, free-form hints.
+            let with_context_pack = arg_flag(rest, "--context-pack");
+            let research_notes_file = arg_value(rest, "--research-notes-file").map(PathBuf::from);
+            let hints = arg_value(rest, "--hints");
             let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
 
             let repo_root =
@@ -2615,8 +2867,44 @@ fn main() -> Result<(), String> {
                 None
             };
 
-            let payload =
-                plc::build_rubberduck_prompt(&repo_root, &file, &lemma, diagnostics.as_deref())?;
+            let goal = match goal_json {
+                Some(p) => {
+                    let txt = std::fs::read_to_string(&p)
+                        .map_err(|e| format!("read {}: {e}", p.display()))?;
+                    let v: serde_json::Value = serde_json::from_str(&txt)
+                        .map_err(|e| format!("parse {}: {e}", p.display()))?;
+                    let pp = v.get("pp_dump").unwrap_or(&v);
+                    plc::hyp_rank::goal_snapshot(pp, max_hyps)
+                }
+                None => None,
+            };
+            let context_pack = if with_context_pack {
+                let pack =
+                    plc::build_context_pack(&repo_root, &file, Some(&lemma), None, 8, 120, 30, 30)?;
+                Some(plc::prompts::context_pack_text(&pack))
+            } else {
+                None
+            };
+            let research_notes = match research_notes_file {
+                Some(p) => Some(
+                    std::fs::read_to_string(&p)
+                        .map_err(|e| format!("read {}: {e}", p.display()))?,
+                ),
+                None => None,
+            };
+
+            let payload = plc::build_rubberduck_prompt(
+                &repo_root,
+                &file,
+                &lemma,
+                diagnostics.as_deref(),
+                &plc::prompts::PromptExtras {
+                    goal,
+                    context_pack,
+                    research_notes,
+                    hints,
+                },
+            )?;
             let out = serde_json::to_value(payload).map_err(|e| format!("json encode: {e}"))?;
 
             if let Some(p) = output_json {
@@ -2729,78 +3017,499 @@ fn main() -> Result<(), String> {
             Ok(())
         }
 
-        "patch" => {
+        "audit-axioms" => {
             let repo_root = arg_value(rest, "--repo")
                 .ok_or_else(|| "missing --repo".to_string())
                 .map(PathBuf::from)?;
-            let file = arg_value(rest, "--file").ok_or_else(|| "missing --file".to_string())?;
-            let lemma = arg_value(rest, "--lemma").ok_or_else(|| "missing --lemma".to_string())?;
-            let replacement_file = arg_value(rest, "--replacement-file")
-                .ok_or_else(|| "missing --replacement-file".to_string())
-                .map(PathBuf::from)?;
-            let timeout_s = arg_u64(rest, "--timeout-s").unwrap_or(120);
-            let write = arg_flag(rest, "--write");
-            let include_raw_verify = arg_flag(rest, "--include-raw-verify");
+            let files_arg = arg_values(rest, "--file");
+            let package = arg_value(rest, "--package");
+            let include_defs = arg_flag(rest, "--include-defs");
+            let allow_unresolved = arg_flag(rest, "--allow-unresolved");
+            let extra_allowed = arg_values(rest, "--allow");
+            let timeout_s = arg_u64(rest, "--timeout-s").unwrap_or(300);
             let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+            if files_arg.is_empty() && package.is_none() {
+                return Err("audit-axioms requires --file <relpath> or --package <dir>".to_string());
+            }
 
             let repo_root =
                 plc::find_lean_repo_root(&repo_root).map_err(|e| format!("repo_root: {e}"))?;
             plc::load_dotenv_smart(&repo_root);
 
-            let abs = repo_root.join(&file);
-            if !abs.exists() {
-                return Err(format!("File not found: {}", abs.display()));
+            let mut cfg = plc::config::load_from_repo_root(&repo_root)?
+                .map(|c| c.axioms)
+                .unwrap_or_default();
+            for a in extra_allowed {
+                if !cfg.allowed.contains(&a) {
+                    cfg.allowed.push(a);
+                }
             }
-            let original_text = std::fs::read_to_string(&abs)
-                .map_err(|e| format!("read {}: {e}", abs.display()))?;
-            let replacement = std::fs::read_to_string(&replacement_file)
-                .map_err(|e| format!("read {}: {e}", replacement_file.display()))?;
 
-            let patched = plc::patch_first_sorry_in_decl(&original_text, &lemma, &replacement)?;
-            let still_has_sorry = plc::decl_block_contains_sorry(&patched.text, &lemma)?;
+            let mut files: Vec<String> = files_arg;
+            if let Some(dir) = package.as_deref() {
+                files.extend(plc::axiom_audit::list_lean_files(&repo_root, dir)?);
+            }
+            let files = unique_repo_files(&repo_root, files);
 
             let rt = tokio::runtime::Runtime::new()
                 .map_err(|e| format!("failed to build tokio runtime: {e}"))?;
-            let mut written_file: Option<String> = None;
-            if write {
-                std::fs::write(&abs, patched.text.as_bytes())
-                    .map_err(|e| format!("write {}: {e}", abs.display()))?;
-                written_file = Some(abs.display().to_string());
+            let mut audits: Vec<plc::axiom_audit::FileAxiomAudit> = Vec::new();
+            for f in &files {
+                let a = rt
+                    .block_on(plc::axiom_audit::audit_axioms_in_file(
+                        &repo_root,
+                        f,
+                        &cfg,
+                        include_defs,
+                        StdDuration::from_secs(timeout_s),
+                    ))
+                    .map_err(|e| format!("audit {f}: {e}"))?;
+                audits.push(a);
             }
-            let verify = if write {
-                rt.block_on(plc::verify_lean_file(
-                    &repo_root,
-                    &file,
-                    StdDuration::from_secs(timeout_s),
-                ))
-                .map_err(|e| format!("verify failed: {e}"))?
-            } else {
-                rt.block_on(plc::verify_lean_text(
-                    &repo_root,
-                    &patched.text,
-                    StdDuration::from_secs(timeout_s),
-                ))
-                .map_err(|e| format!("verify failed: {e}"))?
-            };
-            let verify_raw_v =
-                serde_json::to_value(verify).map_err(|e| format!("serialize verify: {e}"))?;
-            let verify_summary = verify_summary_from_raw_value(&verify_raw_v);
 
+            let decls_total: usize = audits.iter().map(|a| a.decls.len()).sum();
+            let violations: usize = audits.iter().map(|a| a.violations).sum();
+            let unresolved: usize = audits.iter().map(|a| a.unresolved).sum();
+            let violating: Vec<serde_json::Value> = audits
+                .iter()
+                .flat_map(|a| {
+                    a.decls
+                        .iter()
+                        .filter(|d| d.status == "violation")
+                        .map(move |d| {
+                            json!({
+                                "file": a.file,
+                                "line": d.line,
+                                "name": d.name,
+                                "violations": d.violations,
+                            })
+                        })
+                })
+                .collect();
+            let failed = violations > 0 || (unresolved > 0 && !allow_unresolved);
             let out = json!({
-                "repo_root": repo_root.display().to_string(),
-                "file": file,
-                "lemma": lemma,
-                "written_file": written_file,
-                "patch": {
-                    "line": patched.line,
-                    "before": patched.before,
-                    "after": patched.after,
-                    "indent": patched.indent,
+                "ok": !failed,
+                "kind": "audit_axioms",
+                "result_kind": if violations > 0 {
+                    "violations"
+                } else if unresolved > 0 {
+                    "unresolved"
+                } else {
+                    "clean"
                 },
-                "lemma_still_contains_sorry": still_has_sorry,
-                "verify": {
-                    "summary": verify_summary,
-                    "raw": if include_raw_verify { verify_raw_v } else { serde_json::Value::Null }
+                "repo_root": repo_root.display().to_string(),
+                "allowed": cfg.allowed,
+                "counts": {
+                    "files": audits.len(),
+                    "decls": decls_total,
+                    "violations": violations,
+                    "unresolved": unresolved,
+                },
+                "violating": violating,
+                "files": audits,
+            });
+
+            if let Some(p) = output_json {
+                write_json(&p, &out)?;
+                println!(
+                    "{}",
+                    json!({
+                        "ok": !failed,
+                        "written": p.display().to_string(),
+                        "kind": "audit_axioms",
+                        "result_kind": out.get("result_kind").cloned().unwrap_or(serde_json::Value::Null),
+                        "counts": out.get("counts").cloned().unwrap_or(serde_json::Value::Null),
+                    })
+                );
+            } else {
+                println!("{}", out);
+            }
+            if failed {
+                return Err(format!(
+                    "audit-axioms: {violations} violation(s), {unresolved} unresolved decl(s)"
+                ));
+            }
+            Ok(())
+        }
+
+        "profile-file" => {
+            let repo_root = arg_value(rest, "--repo")
+                .ok_or_else(|| "missing --repo".to_string())
+                .map(PathBuf::from)?;
+            let file = arg_value(rest, "--file").ok_or_else(|| "missing --file".to_string())?;
+            let threshold_ms = arg_u64(rest, "--threshold-ms").unwrap_or(10);
+            let heartbeats = arg_flag(rest, "--heartbeats");
+            let top = arg_u64(rest, "--top").unwrap_or(20) as usize;
+            let timeout_s = arg_u64(rest, "--timeout-s").unwrap_or(300);
+            let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+            let output_html = arg_value(rest, "--output-html").map(PathBuf::from);
+
+            let repo_root =
+                plc::find_lean_repo_root(&repo_root).map_err(|e| format!("repo_root: {e}"))?;
+            plc::load_dotenv_smart(&repo_root);
+
+            let rt = tokio::runtime::Runtime::new()
+                .map_err(|e| format!("failed to build tokio runtime: {e}"))?;
+            let prof = rt
+                .block_on(plc::profile::profile_lean_file(
+                    &repo_root,
+                    &file,
+                    threshold_ms,
+                    heartbeats,
+                    StdDuration::from_secs(timeout_s),
+                ))
+                .map_err(|e| format!("profile failed: {e}"))?;
+
+            // Compact ranking (the full per-decl breakdown is under `profile.decls`).
+            let ranked: Vec<serde_json::Value> = prof
+                .decls
+                .iter()
+                .filter(|d| d.total_ms > 0.0 || d.heartbeats.is_some())
+                .take(top)
+                .map(|d| {
+                    json!({
+                        "name": d.name,
+                        "kind": d.kind,
+                        "line": d.line,
+                        "total_ms": d.total_ms,
+                        "heartbeats": d.heartbeats,
+                        "top_tactics": d.tactics.iter().take(3).collect::<Vec<_>>(),
+                    })
+                })
+                .collect();
+
+            let mut html_path_out: Option<String> = None;
+            if let Some(out_path) = output_html {
+                if let Some(parent) = out_path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| format!("failed to create dir {}: {}", parent.display(), e))?;
+                }
+                let mut html = String::new();
+                html.push_str("<!doctype html>\n<html><head><meta charset=\"utf-8\"/>\n");
+                html.push_str("<title>proofpatch profile</title>\n");
+                html.push_str("<style>body{font-family:ui-sans-serif,system-ui,Segoe UI,Roboto,Helvetica,Arial;max-width:1200px;margin:24px auto;padding:0 16px}table{border-collapse:collapse;width:100%}th,td{border:1px solid #ddd;padding:8px;vertical-align:top}th{background:#f6f6f6;text-align:left}td.num{text-align:right}code{font-family:ui-monospace,SFMono-Regular,Menlo,Monaco,Consolas,\"Liberation Mono\",monospace}</style>\n");
+                html.push_str("</head><body>\n");
+                html.push_str(&format!(
+                    "<h2>proofpatch profile: <code>{}</code></h2>\n",
+                    escape_html(&file)
+                ));
+                html.push_str(&format!(
+                    "<p><b>verify ok</b>: {} &middot; <b>wall</b>: {} ms &middot; <b>threshold</b>: {} ms</p>\n",
+                    prof.verify_ok, prof.wall_ms, prof.threshold_ms
+                ));
+                html.push_str("<table>\n<thead><tr><th>#</th><th>decl</th><th>line</th><th>total ms</th><th>heartbeats</th><th>categories</th><th>tactics</th></tr></thead>\n<tbody>\n");
+                for (i, d) in prof.decls.iter().take(top).enumerate() {
+                    let cats = d
+                        .categories
+                        .iter()
+                        .map(|(k, v)| format!("{}: {:.1}", escape_html(k), v))
+                        .collect::<Vec<_>>()
+                        .join("<br/>");
+                    let tacs = d
+                        .tactics
+                        .iter()
+                        .take(8)
+                        .map(|t| {
+                            format!(
+                                "<code>{}</code> {:.1} (x{})",
+                                escape_html(&t.name),
+                                t.ms,
+                                t.count
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("<br/>");
+                    html.push_str(&format!(
+                        "<tr><td>{}</td><td><code>{}</code> <small>{}</small></td><td class=\"num\">{}</td><td class=\"num\">{:.1}</td><td class=\"num\">{}</td><td>{}</td><td>{}</td></tr>\n",
+                        i + 1,
+                        escape_html(&d.name),
+                        escape_html(&d.kind),
+                        d.line,
+                        d.total_ms,
+                        d.heartbeats.map(|h| h.to_string()).unwrap_or_default(),
+                        cats,
+                        tacs
+                    ));
+                }
+                html.push_str("</tbody></table></body></html>\n");
+                std::fs::write(&out_path, html.as_bytes())
+                    .map_err(|e| format!("failed to write html {}: {e}", out_path.display()))?;
+                html_path_out = Some(out_path.display().to_string());
+            }
+
+            let result_kind = if prof.timeout {
+                "timeout"
+            } else if !prof.verify_ok {
+                "verify_failed"
+            } else {
+                "ok"
+            };
+            let out = json!({
+                "ok": prof.verify_ok,
+                "kind": "profile_file",
+                "result_kind": result_kind,
+                "repo_root": repo_root.display().to_string(),
+                "file": file,
+                "ranked": ranked,
+                "html_path": html_path_out,
+                "profile": prof,
+            });
+            if let Some(p) = output_json {
+                write_json(&p, &out)?;
+                println!(
+                    "{}",
+                    json!({
+                        "ok": true,
+                        "written": p.display().to_string(),
+                        "kind": "profile_file",
+                        "result_kind": result_kind,
+                    })
+                );
+            } else {
+                println!("{}", out);
+            }
+            Ok(())
+        }
+
+        "heartbeats-triage" => {
+            let repo_root = arg_value(rest, "--repo")
+                .ok_or_else(|| "missing --repo".to_string())
+                .map(PathBuf::from)?;
+            let file = arg_value(rest, "--file").ok_or_else(|| "missing --file".to_string())?;
+            let decl = arg_value(rest, "--decl");
+            let line = arg_u64(rest, "--line").map(|x| x as usize);
+            let defaults = plc::heartbeats::TriageOptions::default();
+            let opts = plc::heartbeats::TriageOptions {
+                max_heartbeats: arg_u64(rest, "--max-heartbeats")
+                    .unwrap_or(defaults.max_heartbeats),
+                max_rec_depth: arg_u64(rest, "--max-rec-depth").unwrap_or(defaults.max_rec_depth),
+                bisect: !arg_flag(rest, "--no-bisect"),
+            };
+            let timeout_s = arg_u64(rest, "--timeout-s").unwrap_or(300);
+            let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+
+            let repo_root =
+                plc::find_lean_repo_root(&repo_root).map_err(|e| format!("repo_root: {e}"))?;
+            plc::load_dotenv_smart(&repo_root);
+
+            let rt = tokio::runtime::Runtime::new()
+                .map_err(|e| format!("failed to build tokio runtime: {e}"))?;
+            let triage = rt
+                .block_on(plc::heartbeats::triage_resource_limits(
+                    &repo_root,
+                    &file,
+                    decl.as_deref(),
+                    line,
+                    &opts,
+                    StdDuration::from_secs(timeout_s),
+                ))
+                .map_err(|e| format!("heartbeats-triage failed: {e}"))?;
+
+            let result_kind = if triage.kind == "none" {
+                "no_resource_failure"
+            } else if triage.needed.is_some() || triage.culprit.is_some() {
+                "triaged"
+            } else {
+                "unresolved"
+            };
+            let out = json!({
+                "ok": true,
+                "kind": "heartbeats_triage",
+                "result_kind": result_kind,
+                "repo_root": repo_root.display().to_string(),
+                "file": file,
+                "options": {
+                    "max_heartbeats": opts.max_heartbeats,
+                    "max_rec_depth": opts.max_rec_depth,
+                    "bisect": opts.bisect,
+                    "timeout_s": timeout_s,
+                },
+                "triage": triage,
+            });
+            if let Some(p) = output_json {
+                write_json(&p, &out)?;
+                println!(
+                    "{}",
+                    json!({
+                        "ok": true,
+                        "written": p.display().to_string(),
+                        "kind": "heartbeats_triage",
+                        "result_kind": result_kind,
+                    })
+                );
+            } else {
+                println!("{}", out);
+            }
+            Ok(())
+        }
+
+        "migrate-deprecations" => {
+            let repo_root = arg_value(rest, "--repo")
+                .ok_or_else(|| "missing --repo".to_string())
+                .map(PathBuf::from)?;
+            let file = arg_value(rest, "--file").ok_or_else(|| "missing --file".to_string())?;
+            let write = arg_flag(rest, "--write");
+            let no_verify = arg_flag(rest, "--no-verify");
+            let timeout_s = arg_u64(rest, "--timeout-s").unwrap_or(300);
+            let diff_context = arg_u64(rest, "--diff-context").unwrap_or(3) as usize;
+            let output_diff = arg_value(rest, "--output-diff").map(PathBuf::from);
+            let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+
+            let repo_root =
+                plc::find_lean_repo_root(&repo_root).map_err(|e| format!("repo_root: {e}"))?;
+            plc::load_dotenv_smart(&repo_root);
+
+            let rt = tokio::runtime::Runtime::new()
+                .map_err(|e| format!("failed to build tokio runtime: {e}"))?;
+            let mig = rt
+                .block_on(plc::deprecations::migrate_deprecations_in_file(
+                    &repo_root,
+                    &file,
+                    !no_verify,
+                    StdDuration::from_secs(timeout_s),
+                ))
+                .map_err(|e| format!("migrate-deprecations failed: {e}"))?;
+
+            let (diff, diff_truncated) = unified_diff_bounded(
+                &mig.original_text,
+                &mig.migrated_text,
+                diff_context,
+                200_000,
+            );
+            if let Some(p) = output_diff.as_ref() {
+                if let Some(parent) = p.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| format!("failed to create dir {}: {}", parent.display(), e))?;
+                }
+                std::fs::write(p, diff.as_bytes())
+                    .map_err(|e| format!("write {}: {e}", p.display()))?;
+            }
+            let mut written_file: Option<String> = None;
+            if write && !mig.applied.is_empty() {
+                let abs = repo_root.join(&file);
+                std::fs::write(&abs, mig.migrated_text.as_bytes())
+                    .map_err(|e| format!("write {}: {e}", abs.display()))?;
+                written_file = Some(abs.display().to_string());
+            }
+
+            let result_kind = if mig.warnings == 0 {
+                "no_deprecations"
+            } else if mig.manual.is_empty() {
+                "migrated"
+            } else if mig.applied.is_empty() {
+                "manual_only"
+            } else {
+                "partial"
+            };
+            let out = json!({
+                "ok": true,
+                "kind": "migrate_deprecations",
+                "result_kind": result_kind,
+                "repo_root": repo_root.display().to_string(),
+                "file": file,
+                "written_file": written_file,
+                "counts": {
+                    "warnings": mig.warnings,
+                    "applied": mig.applied.len(),
+                    "manual": mig.manual.len(),
+                },
+                "migration": mig,
+                "diff": {
+                    "unified": diff,
+                    "truncated": diff_truncated,
+                    "written": output_diff.map(|p| p.display().to_string()),
+                },
+            });
+            if let Some(p) = output_json {
+                write_json(&p, &out)?;
+                println!(
+                    "{}",
+                    json!({
+                        "ok": true,
+                        "written": p.display().to_string(),
+                        "kind": "migrate_deprecations",
+                        "result_kind": result_kind,
+                        "counts": out.get("counts").cloned().unwrap_or(serde_json::Value::Null),
+                    })
+                );
+            } else {
+                println!("{}", out);
+            }
+            Ok(())
+        }
+
+        "patch" => {
+            let repo_root = arg_value(rest, "--repo")
+                .ok_or_else(|| "missing --repo".to_string())
+                .map(PathBuf::from)?;
+            let file = arg_value(rest, "--file").ok_or_else(|| "missing --file".to_string())?;
+            let lemma = arg_value(rest, "--lemma").ok_or_else(|| "missing --lemma".to_string())?;
+            let replacement_file = arg_value(rest, "--replacement-file")
+                .ok_or_else(|| "missing --replacement-file".to_string())
+                .map(PathBuf::from)?;
+            let timeout_s = arg_u64(rest, "--timeout-s").unwrap_or(120);
+            let write = arg_flag(rest, "--write");
+            let include_raw_verify = arg_flag(rest, "--include-raw-verify");
+            let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+
+            let repo_root =
+                plc::find_lean_repo_root(&repo_root).map_err(|e| format!("repo_root: {e}"))?;
+            plc::load_dotenv_smart(&repo_root);
+
+            let abs = repo_root.join(&file);
+            if !abs.exists() {
+                return Err(format!("File not found: {}", abs.display()));
+            }
+            let original_text = std::fs::read_to_string(&abs)
+                .map_err(|e| format!("read {}: {e}", abs.display()))?;
+            let replacement = std::fs::read_to_string(&replacement_file)
+                .map_err(|e| format!("read {}: {e}", replacement_file.display()))?;
+
+            let patched = plc::patch_first_sorry_in_decl(&original_text, &lemma, &replacement)?;
+            let still_has_sorry = plc::decl_block_contains_sorry(&patched.text, &lemma)?;
+
+            let rt = tokio::runtime::Runtime::new()
+                .map_err(|e| format!("failed to build tokio runtime: {e}"))?;
+            let mut written_file: Option<String> = None;
+            if write {
+                std::fs::write(&abs, patched.text.as_bytes())
+                    .map_err(|e| format!("write {}: {e}", abs.display()))?;
+                written_file = Some(abs.display().to_string());
+            }
+            let verify = if write {
+                rt.block_on(plc::verify_lean_file(
+                    &repo_root,
+                    &file,
+                    StdDuration::from_secs(timeout_s),
+                ))
+                .map_err(|e| format!("verify failed: {e}"))?
+            } else {
+                rt.block_on(plc::verify_lean_text(
+                    &repo_root,
+                    &patched.text,
+                    StdDuration::from_secs(timeout_s),
+                ))
+                .map_err(|e| format!("verify failed: {e}"))?
+            };
+            let verify_raw_v =
+                serde_json::to_value(verify).map_err(|e| format!("serialize verify: {e}"))?;
+            let verify_summary = verify_summary_from_raw_value(&verify_raw_v);
+
+            let out = json!({
+                "repo_root": repo_root.display().to_string(),
+                "file": file,
+                "lemma": lemma,
+                "written_file": written_file,
+                "patch": {
+                    "line": patched.line,
+                    "before": patched.before,
+                    "after": patched.after,
+                    "indent": patched.indent,
+                },
+                "lemma_still_contains_sorry": still_has_sorry,
+                "verify": {
+                    "summary": verify_summary,
+                    "raw": if include_raw_verify { verify_raw_v } else { serde_json::Value::Null }
                 },
             });
 
@@ -3129,6 +3838,9 @@ fn main() -> Result<(), String> {
                 smt_hint: Option<serde_json::Value>,
                 // Compact explanation of candidate ranking at the expanded hole (top-k).
                 rank_hint: Option<serde_json::Value>,
+                // Profiled time of the target declaration in this node's text (None for the root,
+                // cache hits, or without `--slow-verify-ms`).
+                decl_profile_ms: Option<u64>,
             }
 
             #[derive(Clone)]
@@ -3144,7 +3856,7 @@ fn main() -> Result<(), String> {
                 adapt_candidates_for_error, adapt_candidates_for_sorry_context,
                 default_det_candidates, extract_initial_goal_block, hash_state_key, hash_text,
                 is_made_no_progress, parse_json_string_array, progress_score_key,
-                sanitize_candidates, verify_score_key,
+                sanitize_candidates, slow_verify_penalty, verify_score_key,
             };
 
             let repo_root = arg_value(rest, "--repo")
@@ -3261,6 +3973,10 @@ fn main() -> Result<(), String> {
             // - depth_bonus: small nudge to prefer deeper nodes when sorting frontier (best-first tie-break)
             let goal_meta_penalty = arg_u64(rest, "--goal-meta-penalty").unwrap_or(0) as i64;
             let depth_bonus = arg_u64(rest, "--depth-bonus").unwrap_or(0) as i64;
+            // - slow_verify_ms: penalize candidates whose target declaration takes at least this
+            //   long (profiled with `set_option profiler true in` on that declaration only)
+            let slow_verify_ms = arg_u64(rest, "--slow-verify-ms").unwrap_or(0);
+            let slow_verify_weight = arg_u64(rest, "--slow-verify-weight").unwrap_or(1) as i64;
             let no_cache = arg_flag(rest, "--no-cache");
             let cache_dir_opt = arg_value(rest, "--cache-dir").map(PathBuf::from);
             let profile = arg_flag(rest, "--profile");
@@ -3297,6 +4013,17 @@ fn main() -> Result<(), String> {
             let mut smt_support_source = if smt_support { "explicit" } else { "off" };
             let smt_support_max =
                 arg_u64(rest, "--smt-support-max").unwrap_or(8).clamp(0, 64) as usize;
+            let mut smt_tactics = arg_flag(rest, "--smt-tactics");
+            let mut smt_tactics_source = if smt_tactics { "explicit" } else { "off" };
+            let mut smt_counterexample = arg_flag(rest, "--smt-counterexample");
+            let mut smt_counterexample_source = if smt_counterexample {
+                "explicit"
+            } else {
+                "off"
+            };
+            // Race every detected solver (z3/cvc5/yices) per query; opt-in since it multiplies
+            // solver processes.
+            let smt_portfolio = arg_flag(rest, "--smt-portfolio");
             let smt_proof_explicit = arg_flag(rest, "--smt-proof");
             let mut smt_proof = smt_proof_explicit;
             let mut smt_proof_source = if smt_proof { "explicit" } else { "off" };
@@ -3346,6 +4073,14 @@ fn main() -> Result<(), String> {
                     smt_proof = true;
                     smt_proof_source = "aggressive_default";
                 }
+                if !smt_tactics {
+                    smt_tactics = true;
+                    smt_tactics_source = "aggressive_default";
+                }
+                if !smt_counterexample {
+                    smt_counterexample = true;
+                    smt_counterexample_source = "aggressive_default";
+                }
                 if !arg_value(rest, "--smt-unsat-core-max").is_some() {
                     smt_unsat_core_max = smt_unsat_core_max.max(16);
                     smt_unsat_core_max_source = "aggressive_default";
@@ -3385,6 +4120,11 @@ fn main() -> Result<(), String> {
             let smt_depth_raw = arg_u64(rest, "--smt-depth").unwrap_or(0) as usize;
             let llm_timeout_s = arg_u64(rest, "--llm-timeout-s").unwrap_or(60);
             let goal_dump_raw = arg_flag(rest, "--goal-dump");
+            // LLM prompt goal snapshots keep the best-ranked hypotheses (`hyp_rank`); 0 keeps all.
+            let prompt_max_hyps_explicit = arg_value(rest, "--prompt-max-hyps").is_some();
+            let prompt_max_hyps = arg_u64(rest, "--prompt-max-hyps")
+                .map(|v| v as usize)
+                .unwrap_or(plc::hyp_rank::DEFAULT_MAX_HYPS);
             let escalate_llm_flag = arg_flag(rest, "--escalate-llm");
             let escalate_llm_source = if escalate_llm_flag {
                 "explicit"
@@ -4160,6 +4900,9 @@ fn main() -> Result<(), String> {
                 "smt_unsat_core_max": { "value": smt_unsat_core_max, "source": smt_unsat_core_max_source },
                 "smt_support": { "value": smt_support, "source": smt_support_source },
                 "smt_support_max": { "value": smt_support_max, "source": if arg_value(rest, "--smt-support-max").is_some() { "explicit" } else { "default" } },
+                "smt_tactics": { "value": smt_tactics, "source": smt_tactics_source },
+                "smt_counterexample": { "value": smt_counterexample, "source": smt_counterexample_source },
+                "smt_portfolio": { "value": smt_portfolio, "source": if smt_portfolio { "explicit" } else { "off" } },
                 "smt_dump": { "value": smt_dump, "source": smt_dump_source },
                 "smt_dump_max": { "value": smt_dump_max, "source": smt_dump_max_source },
                 "smt_dump_dir": { "value": smt_dump_dir_opt.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "".to_string()), "source": if arg_value(rest, "--smt-dump-dir").is_some() { "explicit" } else { "default" } },
@@ -4174,6 +4917,10 @@ fn main() -> Result<(), String> {
                 },
                 "smt_explain_max_hyps": { "value": smt_explain_max_hyps, "source": smt_explain_max_hyps_source },
                 "goal_dump": { "value": goal_dump, "source": goal_dump_source },
+                "prompt_max_hyps": {
+                    "value": prompt_max_hyps,
+                    "source": if prompt_max_hyps_explicit { "explicit" } else { "default" }
+                },
                 "smt_repro_dir": {
                     "value": smt_repro_dir_opt
                         .as_ref()
@@ -4523,12 +5270,22 @@ fn main() -> Result<(), String> {
                 let locs0 = plc::locate_sorries_in_text(&original_text, 50, 1)?;
                 let primary0 = plc::select_primary_sorry(None, &locs0)
                     .ok_or_else(|| "No `sorry`/`admit` tokens found in file.".to_string())?;
+                let goal_snapshot = goal_dump_v
+                    .as_ref()
+                    .and_then(|gd| gd.get("pp_dump"))
+                    .and_then(|pp| plc::hyp_rank::goal_snapshot(pp, prompt_max_hyps));
                 let payload = plc::build_region_patch_prompt(
                     &repo_root,
                     &file,
                     primary0.region_start,
                     primary0.region_end,
                     None,
+                    &plc::prompts::PromptExtras {
+                        goal: goal_snapshot,
+                        context_pack: region_context_pack(&original_text, primary0.region_start),
+                        research_notes: research_notes_text.clone(),
+                        hints: None,
+                    },
                 )?;
 
                 let mut system = payload.system.clone();
@@ -4536,23 +5293,6 @@ fn main() -> Result<(), String> {
                 if !allow_sorry_candidates {
                     system.push_str("\n\nConstraints:\n- Do not use `sorry` or `admit` anywhere.\n- Return complete proof terms only (no placeholders).");
                 }
-                if let Some(gd) = goal_dump_v.as_ref() {
-                    if let Some(pretty) = gd
-                        .get("pp_dump")
-                        .and_then(|v| v.get("goals"))
-                        .and_then(|v| v.as_array())
-                        .and_then(|xs| xs.first())
-                        .and_then(|v| v.get("pretty"))
-                        .and_then(|v| v.as_str())
-                    {
-                        system.push_str("\n\nGoal snapshot (pretty):\n");
-                        system.push_str(pretty);
-                    }
-                }
-                if let Some(notes) = research_notes_text.as_ref() {
-                    system.push_str("\n\nResearch context (may be incomplete):\n");
-                    system.push_str(notes);
-                }
 
                 let res = rt.block_on(plc::llm::chat_completion(
                     &system,
@@ -4799,6 +5539,14 @@ fn main() -> Result<(), String> {
             let mut planner_cache_misses: u64 = 0;
             #[cfg(feature = "planner")]
             let mut prof_planner_ms: u64 = 0;
+            // Set once the LLM budget is exhausted; no further planner calls are made this run.
+            #[cfg(feature = "planner")]
+            let mut planner_stopped: Option<String> = None;
+            // The last confident planner decision, as `hints` for LLM candidate prompts.
+            #[cfg(feature = "planner")]
+            let mut planner_hints: Option<String> = None;
+            #[cfg(not(feature = "planner"))]
+            let planner_hints: Option<String> = None;
 
             let mut smt_entails_cache: std::collections::HashMap<(u64, u64, usize), bool> =
                 std::collections::HashMap::new(); // ((state_key, goal_sig, depth) -> entails)
@@ -4831,8 +5579,18 @@ fn main() -> Result<(), String> {
             let mut smt_entails_attempts: u64 = 0;
             let mut smt_entails_escalations: u64 = 0;
             let mut smt_entails_trace: Vec<serde_json::Value> = Vec::new();
+            // (state_key, goal_sig) -> counterexample (with its Lean check), once per goal.
+            let mut smt_counterexample_cache: std::collections::HashMap<
+                (u64, u64),
+                Option<serde_json::Value>,
+            > = std::collections::HashMap::new();
+            let mut smt_refuted: Vec<serde_json::Value> = Vec::new();
             let mut prof_smt_ms: u64 = 0;
             let mut smt_reuse: Option<plc::smt_lia::ReusableSmtSession> = None;
+            if smt_portfolio && smt_precheck {
+                // Best-effort: without solvers this stays `None` and the usual lazy init applies.
+                smt_reuse = plc::smt_lia::ReusableSmtSession::new_portfolio().unwrap_or(None);
+            }
 
             // Best-effort: if the user asked for SMT proofs/dumps, sometimes we want to attempt
             // once directly from the initial goal dump (if present).
@@ -5133,7 +5891,14 @@ fn main() -> Result<(), String> {
             // Small helper for grokkable reporting.
             let classify_failure_mode = |first_error: Option<&str>| -> &'static str {
                 let s = first_error.unwrap_or("").to_lowercase();
-                if s.contains("unknown tactic") {
+                if let Some(kind) = plc::tree_search::resource_limit_kind(first_error) {
+                    // Resource limits are a distinct class: see `heartbeats-triage`.
+                    if kind == "recursion" {
+                        "recursion_depth_exceeded"
+                    } else {
+                        "heartbeats_exceeded"
+                    }
+                } else if s.contains("unknown tactic") {
                     "unknown_tactic"
                 } else if s.contains("synthinstancefailed")
                     || s.contains("failed to synthesize instance")
@@ -5162,6 +5927,8 @@ fn main() -> Result<(), String> {
 
             let mut next_id = 1usize;
             let mut all: Vec<Node> = Vec::new();
+            let mut decl_profile_ms_by_hash: std::collections::HashMap<u64, u64> =
+                std::collections::HashMap::new();
             let mut eval_cache: std::collections::HashMap<u64, CachedEval> =
                 std::collections::HashMap::new();
             let mut disk_cache_eval_hits: u64 = 0;
@@ -5188,6 +5955,7 @@ fn main() -> Result<(), String> {
                 conservative_sorries: Some(root_conservative_sorries),
                 smt_hint: None,
                 rank_hint: None,
+                decl_profile_ms: None,
             }];
 
             let mut best_done: Option<Node> = None;
@@ -5381,6 +6149,20 @@ fn main() -> Result<(), String> {
                         kb.1 =
                             kb.1.saturating_sub(depth_bonus.saturating_mul(b.depth as i64));
                     }
+                    if slow_verify_ms > 0 {
+                        ka.1 = ka.1.saturating_add(slow_verify_penalty(
+                            ka.0 == 0,
+                            a.decl_profile_ms,
+                            slow_verify_ms,
+                            slow_verify_weight,
+                        ));
+                        kb.1 = kb.1.saturating_add(slow_verify_penalty(
+                            kb.0 == 0,
+                            b.decl_profile_ms,
+                            slow_verify_ms,
+                            slow_verify_weight,
+                        ));
+                    }
                     ka.cmp(&kb).then_with(|| a.id.cmp(&b.id))
                 });
 
@@ -5540,7 +6322,11 @@ fn main() -> Result<(), String> {
 
                         // Optional LLM planner: can override which hole to focus and how much oracle budget to spend.
                         // This is feature-gated and defaults off.
-                        let planner_selected: Option<plc::SorryLocation> = if llm_planner {
+                        #[cfg(feature = "planner")]
+                        let planner_live = llm_planner && planner_stopped.is_none();
+                        #[cfg(not(feature = "planner"))]
+                        let planner_live = llm_planner;
+                        let planner_selected: Option<plc::SorryLocation> = if planner_live {
                             #[cfg(not(feature = "planner"))]
                             {
                                 return Err(
@@ -5710,6 +6496,20 @@ fn main() -> Result<(), String> {
                                             target: truncate_str(&target, 400),
                                             n_goals: n_goals as u64,
                                             hyps_total: hyps_total as u64,
+                                            relevant_hyps: plc::hyp_rank::rank_hypotheses(
+                                                &pp_dump_from_hyps_target(
+                                                    goal_dump_hyps_cache
+                                                        .get(&key)
+                                                        .map(|v| v.as_slice())
+                                                        .unwrap_or(&[]),
+                                                    &target,
+                                                ),
+                                            )
+                                            .into_iter()
+                                            .filter(|h| h.score > 0.0)
+                                            .take(plc::hyp_rank::DEFAULT_MAX_HYPS)
+                                            .map(|h| truncate_str(&h.text, 200))
+                                            .collect(),
                                         },
                                         candidate_holes: cands
                                             .iter()
@@ -5754,27 +6554,49 @@ Constraints:
                                     ));
                                     prof_planner_ms = prof_planner_ms
                                         .saturating_add(t0.elapsed().as_millis() as u64);
-                                    let d = match res {
-                                        Ok((d, _raw)) => d,
-                                        Err(_) => plc::planner::PlannerDecision {
-                                            confidence: 0.0,
-                                            focus_line_1: None,
-                                            oracle_passes: None,
-                                            oracle_tactics: vec![],
-                                            ban_oracle_tactics: vec![],
-                                            rationale: "planner_failed".to_string(),
-                                        },
-                                    };
-                                    planner_cache.insert(cache_key, d.clone());
-                                    if let Some(cd) = cache_dir.as_ref() {
-                                        if let Ok(v) = serde_json::to_value(&d) {
-                                            cache_write_planner(cd, state_key, goal_sig, &v);
+                                    match res {
+                                        Ok((d, _raw)) => {
+                                            // Only successful decisions are cached; a failure
+                                            // (timeout, budget, replay miss) must not be replayed.
+                                            planner_cache.insert(cache_key, d.clone());
+                                            // A replay run leaves every cache as it found it.
+                                            let replay = plc::llm_cache::mode()
+                                                == plc::llm_cache::LlmCacheMode::ReadOnly;
+                                            if let Some(cd) = cache_dir.as_ref().filter(|_| !replay)
+                                            {
+                                                if let Ok(v) = serde_json::to_value(&d) {
+                                                    cache_write_planner(
+                                                        cd, state_key, goal_sig, &v,
+                                                    );
+                                                }
+                                            }
+                                            d
+                                        }
+                                        Err(e) => {
+                                            if e.contains(plc::llm_usage::BUDGET_EXCEEDED) {
+                                                planner_stopped = Some(truncate_str(&e, 200));
+                                            }
+                                            plc::planner::PlannerDecision {
+                                                confidence: 0.0,
+                                                focus_line_1: None,
+                                                oracle_passes: None,
+                                                oracle_tactics: vec![],
+                                                ban_oracle_tactics: vec![],
+                                                rationale: "planner_failed".to_string(),
+                                            }
                                         }
                                     }
-                                    d
                                 };
 
                                 if decision.confidence >= 0.4 {
+                                    let mut h = decision.rationale.trim().to_string();
+                                    if !decision.oracle_tactics.is_empty() {
+                                        h.push_str(&format!(
+                                            "\nSuggested tactics: {}",
+                                            decision.oracle_tactics.join(", ")
+                                        ));
+                                    }
+                                    planner_hints = Some(h);
                                     if let Some(passes) = decision.oracle_passes {
                                         std::env::set_var(
                                             "PROOFPATCH_ORACLE_PASSES",
@@ -6876,31 +7698,28 @@ Constraints:
                         && candidates_mode != "llm"
                         && is_made_no_progress(parent_first_error)
                     {
+                        let goal_snapshot = goal_dump_v
+                            .as_ref()
+                            .and_then(|gd| gd.get("pp_dump"))
+                            .and_then(|pp| plc::hyp_rank::goal_snapshot(pp, prompt_max_hyps));
                         let payload = plc::build_region_patch_prompt(
                             &repo_root,
                             &file,
                             region.0,
                             region.1,
                             parent_first_error,
+                            &plc::prompts::PromptExtras {
+                                goal: goal_snapshot,
+                                context_pack: region_context_pack(&parent.text, region.0),
+                                research_notes: research_notes_text.clone(),
+                                hints: planner_hints.clone(),
+                            },
                         )?;
                         let mut system = payload.system.clone();
                         system.push_str("\n\nReturn a JSON array of 6 distinct candidate Lean replacements (strings). Each element must be a proof term only (no markdown fences).");
                         if !allow_sorry_candidates {
                             system.push_str("\n\nConstraints:\n- Do not use `sorry` or `admit` anywhere.\n- Return complete proof terms only (no placeholders).");
                         }
-                        if let Some(gd) = goal_dump_v.as_ref() {
-                            if let Some(pretty) = gd
-                                .get("pp_dump")
-                                .and_then(|v| v.get("goals"))
-                                .and_then(|v| v.as_array())
-                                .and_then(|xs| xs.first())
-                                .and_then(|v| v.get("pretty"))
-                                .and_then(|v| v.as_str())
-                            {
-                                system.push_str("\n\nGoal snapshot (pretty):\n");
-                                system.push_str(pretty);
-                            }
-                        }
                         // Also try to harvest a goal snapshot from `aesop`-style failures even when goal_dump is off.
                         if let Some(raw) = parent.verify_raw.as_ref() {
                             if let Some(stdout) = raw.get("stdout").and_then(|v| v.as_str()) {
@@ -6910,10 +7729,6 @@ Constraints:
                                 }
                             }
                         }
-                        if let Some(notes) = research_notes_text.as_ref() {
-                            system.push_str("\n\nResearch context (may be incomplete):\n");
-                            system.push_str(notes);
-                        }
                         let extra = rt.block_on(plc::llm::chat_completion(
                             &system,
                             &payload.user,
@@ -7933,6 +8748,84 @@ Constraints:
                             smt_entails_opt
                         };
 
+                    // With `--smt-counterexample`, a SAT answer comes with a model. If it refutes the
+                    // goal itself (every hypothesis is encoded) and Lean accepts the instantiated
+                    // `example`, no candidate can close this hole: flag the node and do not expand it.
+                    if smt_counterexample && smt_entails_effective == Some(false) {
+                        let th = hash_text(&parent.text);
+                        let k = (th, parent.text.len(), sel.line);
+                        let target = goal_dump_cache
+                            .get(&k)
+                            .map(|(_, _, _, t)| t.clone())
+                            .unwrap_or_default();
+                        let hyps_texts = goal_dump_hyps_cache.get(&k).cloned().unwrap_or_default();
+                        let pretty = format!("{}\n⊢ {}", hyps_texts.join("\n"), target);
+                        let ck = (
+                            state_key_opt.unwrap_or(UNKNOWN_STATE_KEY),
+                            hash_text(&pretty),
+                        );
+                        if let std::collections::hash_map::Entry::Vacant(slot) =
+                            smt_counterexample_cache.entry(ck)
+                        {
+                            let pp_dump = json!({
+                                "goals": [{
+                                    "pretty": pretty,
+                                    "hyps": hyps_texts.iter().map(|s| json!({"text": s})).collect::<Vec<_>>()
+                                }]
+                            });
+                            let mut cx = plc::smt_lia::counterexample_from_pp_dump(
+                                &pp_dump,
+                                smt_timeout_ms,
+                                smt_seed,
+                                smt_depth,
+                            )
+                            .unwrap_or(None);
+                            if let Some(obj) = cx.as_mut().and_then(|v| v.as_object_mut()) {
+                                let complete = obj.get("complete") == Some(&json!(true));
+                                let example = obj
+                                    .get("lean_example")
+                                    .and_then(|v| v.as_str())
+                                    .unwrap_or("")
+                                    .to_string();
+                                let verified = match budget_dur(timeout_s) {
+                                    Some(dur) if complete && !example.is_empty() => {
+                                        let imports: String = parent
+                                            .text
+                                            .lines()
+                                            .filter(|l| l.starts_with("import "))
+                                            .map(|l| format!("{l}\n"))
+                                            .collect();
+                                        let text = format!("{imports}\n{example}");
+                                        rt.block_on(plc::verify_lean_text(&repo_root, &text, dur))
+                                            .map(|r| r.ok)
+                                            .unwrap_or(false)
+                                    }
+                                    _ => false,
+                                };
+                                obj.insert("verified".to_string(), json!(verified));
+                            }
+                            slot.insert(cx);
+                        }
+                        if let Some(cx) = smt_counterexample_cache.get(&ck).cloned().flatten() {
+                            if let Some(v) = smt_hint_json.as_mut().and_then(|v| v.as_object_mut())
+                            {
+                                v.insert("counterexample".to_string(), cx.clone());
+                            }
+                            if cx.get("verified") == Some(&json!(true)) {
+                                let row = json!({
+                                    "node_id": parent.id,
+                                    "depth": parent.depth,
+                                    "line": sel.line,
+                                    "target": target,
+                                    "counterexample": cx,
+                                });
+                                record_event("smt_refuted", row.clone());
+                                smt_refuted.push(row);
+                                continue;
+                            }
+                        }
+                    }
+
                     // If requested, attach a small, bounded “fragment explanation” (what SMT actually
                     // saw after depth filtering). This is designed to be cheap (no extra Lean calls).
                     if smt_explain {
@@ -8031,9 +8924,54 @@ Constraints:
                                 is_tactic_context,
                             );
                         }
+                        // With `--smt-tactics`, turn the certificate itself (an IDL path or an
+                        // unsat core) into explicit candidates; these go before everything else.
+                        let mut certificate_candidates: Vec<String> = Vec::new();
+                        if smt_tactics {
+                            let th = hash_text(&parent.text);
+                            let k = (th, parent.text.len(), sel.line);
+                            let target = goal_dump_cache
+                                .get(&k)
+                                .map(|(_, _, _, t)| t.clone())
+                                .unwrap_or_default();
+                            let hyps_texts =
+                                goal_dump_hyps_cache.get(&k).cloned().unwrap_or_default();
+                            let pp_dump = json!({
+                                "goals": [{
+                                    "pretty": format!("{}\n⊢ {}", hyps_texts.join("\n"), target),
+                                    "hyps": hyps_texts.iter().take(48).map(|s| json!({"text": s})).collect::<Vec<_>>()
+                                }]
+                            });
+                            if let Ok(Some(v)) = plc::smt_lia::tactics_from_pp_dump(
+                                &pp_dump,
+                                smt_timeout_ms,
+                                smt_seed,
+                                smt_depth,
+                            ) {
+                                let xs: Vec<String> = v
+                                    .get("tactics")
+                                    .and_then(|v| v.as_array())
+                                    .map(|xs| {
+                                        xs.iter()
+                                            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                                            .collect()
+                                    })
+                                    .unwrap_or_default();
+                                certificate_candidates = adapt_candidates_for_sorry_context(
+                                    &xs,
+                                    &sel.line_text,
+                                    is_tactic_context,
+                                );
+                                if let Some(obj) =
+                                    smt_hint_json.as_mut().and_then(|v| v.as_object_mut())
+                                {
+                                    obj.insert("tactics".to_string(), v);
+                                }
+                            }
+                        }
                         // Candidates are proof-term replacements inside an existing `by` block,
                         // so these are tactics, not `by ...`.
-                        let mut inject: Vec<String> = Vec::new();
+                        let mut inject: Vec<String> = certificate_candidates;
                         inject.extend(support_candidates.into_iter().take(smt_support_max));
                         inject.extend(vec![
                             "omega".to_string(),
@@ -8505,11 +9443,20 @@ Constraints:
                                         );
                                         break;
                                     };
+                                    let profiled = (slow_verify_ms > 0)
+                                        .then(|| {
+                                            plc::profile::profiled_decl_source(
+                                                &rolled_text,
+                                                rolled_line,
+                                                slow_verify_ms / 10,
+                                            )
+                                        })
+                                        .flatten();
                                     let t0 = std::time::Instant::now();
                                     let raw = rt
                                         .block_on(plc::verify_lean_text(
                                             &repo_root,
-                                            &rolled_text,
+                                            profiled.as_deref().unwrap_or(&rolled_text),
                                             dur,
                                         ))
                                         .unwrap_or(plc::VerifyResult {
@@ -8523,6 +9470,16 @@ Constraints:
                                             tmp_file: None,
                                         });
                                     verify_ms = t0.elapsed().as_millis() as u64;
+                                    if profiled.is_some() {
+                                        let out = format!("{}\n{}", raw.stdout, raw.stderr);
+                                        if let Some(ms) = plc::profile::decl_profile_ms(
+                                            &rolled_text,
+                                            rolled_line,
+                                            &out,
+                                        ) {
+                                            decl_profile_ms_by_hash.insert(h, ms as u64);
+                                        }
+                                    }
                                     prof_verify_nodes_ms =
                                         prof_verify_nodes_ms.saturating_add(verify_ms);
                                     prof_verify_nodes_calls += 1;
@@ -8571,9 +9528,22 @@ Constraints:
                                     );
                                     break;
                                 };
+                                let profiled = (slow_verify_ms > 0)
+                                    .then(|| {
+                                        plc::profile::profiled_decl_source(
+                                            &rolled_text,
+                                            rolled_line,
+                                            slow_verify_ms / 10,
+                                        )
+                                    })
+                                    .flatten();
                                 let t0 = std::time::Instant::now();
                                 let raw = rt
-                                    .block_on(plc::verify_lean_text(&repo_root, &rolled_text, dur))
+                                    .block_on(plc::verify_lean_text(
+                                        &repo_root,
+                                        profiled.as_deref().unwrap_or(&rolled_text),
+                                        dur,
+                                    ))
                                     .unwrap_or(plc::VerifyResult {
                                         ok: false,
                                         timeout: false,
@@ -8585,6 +9555,16 @@ Constraints:
                                         tmp_file: None,
                                     });
                                 verify_ms = t0.elapsed().as_millis() as u64;
+                                if profiled.is_some() {
+                                    let out = format!("{}\n{}", raw.stdout, raw.stderr);
+                                    if let Some(ms) = plc::profile::decl_profile_ms(
+                                        &rolled_text,
+                                        rolled_line,
+                                        &out,
+                                    ) {
+                                        decl_profile_ms_by_hash.insert(h, ms as u64);
+                                    }
+                                }
                                 prof_verify_nodes_ms =
                                     prof_verify_nodes_ms.saturating_add(verify_ms);
                                 prof_verify_nodes_calls += 1;
@@ -8698,6 +9678,7 @@ Constraints:
                             conservative_sorries: Some(conservative2),
                             smt_hint: smt_hint_json.clone(),
                             rank_hint: rank_hint_json.clone(),
+                            decl_profile_ms: decl_profile_ms_by_hash.get(&h).copied(),
                         });
                         next_id += 1;
                     }
@@ -8736,22 +9717,35 @@ Constraints:
                     ),
                     smt_hint: None,
                     rank_hint: None,
+                    decl_profile_ms: None,
                 })
             } else {
                 let mut xs = all.clone();
                 xs.sort_by(|a, b| {
                     let sa = a.verify_summary.as_ref().unwrap();
                     let sb = b.verify_summary.as_ref().unwrap();
-                    let ka = verify_score_key(
+                    let mut ka = verify_score_key(
                         sa,
                         a.sorries.unwrap_or(999),
                         a.conservative_sorries.unwrap_or(999),
                     );
-                    let kb = verify_score_key(
+                    let mut kb = verify_score_key(
                         sb,
                         b.sorries.unwrap_or(999),
                         b.conservative_sorries.unwrap_or(999),
                     );
+                    ka.1 = ka.1.saturating_add(slow_verify_penalty(
+                        ka.0 == 0,
+                        a.decl_profile_ms,
+                        slow_verify_ms,
+                        slow_verify_weight,
+                    ));
+                    kb.1 = kb.1.saturating_add(slow_verify_penalty(
+                        kb.0 == 0,
+                        b.decl_profile_ms,
+                        slow_verify_ms,
+                        slow_verify_weight,
+                    ));
                     ka.cmp(&kb).then_with(|| a.id.cmp(&b.id))
                 });
                 xs[0].clone()
@@ -8800,7 +9794,22 @@ Constraints:
             best_ok_pool.retain(|n| seen.insert(n.id));
             let best_ok = best_ok_pool
                 .into_iter()
-                .min_by_key(|n| (n.sorries.unwrap_or(999), n.id))
+                .min_by_key(|n| {
+                    (
+                        n.sorries.unwrap_or(999),
+                        slow_verify_penalty(
+                            n.verify_summary
+                                .as_ref()
+                                .and_then(|s| s.get("ok"))
+                                .and_then(|v| v.as_bool())
+                                == Some(true),
+                            n.decl_profile_ms,
+                            slow_verify_ms,
+                            slow_verify_weight,
+                        ),
+                        n.id,
+                    )
+                })
                 .unwrap_or_else(|| best.clone());
 
             let pick = pick.trim().to_lowercase();
@@ -9525,6 +10534,10 @@ Constraints:
             let verify_node_total = events_by_kind.get("verify_node").copied().unwrap_or(0);
             let oracle_call_total = events_by_kind.get("oracle_call").copied().unwrap_or(0);
             let goal_dump_total = events_by_kind.get("goal_dump").copied().unwrap_or(0);
+            // Late portfolio answers can still reveal a solver disagreement.
+            if let Some(s) = smt_reuse.as_mut() {
+                s.settle();
+            }
 
             let mut out = json!({
                 "repo_root": repo_root.display().to_string(),
@@ -9628,6 +10641,8 @@ Constraints:
                     "goal_first_k": goal_first_k,
                     "goal_meta_penalty": goal_meta_penalty,
                     "depth_bonus": depth_bonus,
+                    "slow_verify_ms": slow_verify_ms,
+                    "slow_verify_weight": slow_verify_weight,
                     "fill_mode": fill_mode,
                     "profile": profile,
                     "llm_summary": llm_summary,
@@ -9682,6 +10697,7 @@ Constraints:
                         "entails_attempts": smt_entails_attempts,
                         "entails_escalations": smt_entails_escalations,
                         "entails_trace": smt_entails_trace,
+                        "refuted": smt_refuted,
                         "errors": smt_errors,
                         "last_error": smt_last_error,
                         "note": if smt_dump && smt_dumps_written == 0 && !goal_dump {
@@ -9735,6 +10751,11 @@ Constraints:
                             .as_ref()
                             .map(|s| s.stats())
                             .unwrap_or(serde_json::Value::Null),
+                        // `sat` vs `unsat` on the same query: at least one solver is wrong.
+                        "solver_disagreements": smt_reuse
+                            .as_ref()
+                            .map(|s| s.disagreements().to_vec())
+                            .unwrap_or_default(),
                     }
                 },
                 "baseline_verify": { "summary": baseline_summary },
@@ -9769,6 +10790,7 @@ Constraints:
                     // Back-compat alias.
                     "smt_hint": best.smt_hint,
                     "rank_hint": best.rank_hint,
+                    "decl_profile_ms": best.decl_profile_ms,
                     "verify": {
                         "summary": best.verify_summary,
                         "raw": if include_raw_verify { best.verify_raw.clone().unwrap_or(serde_json::Value::Null) } else { serde_json::Value::Null }
@@ -9791,6 +10813,7 @@ Constraints:
                     "smt_evidence": best_progress.smt_hint.clone(),
                     "smt_hint": best_progress.smt_hint,
                     "rank_hint": best_progress.rank_hint,
+                    "decl_profile_ms": best_progress.decl_profile_ms,
                     "verify": {
                         "summary": best_progress.verify_summary,
                         "raw": if include_raw_verify { best_progress.verify_raw.clone().unwrap_or(serde_json::Value::Null) } else { serde_json::Value::Null }
@@ -9813,6 +10836,7 @@ Constraints:
                     "smt_evidence": best_ok.smt_hint.clone(),
                     "smt_hint": best_ok.smt_hint,
                     "rank_hint": best_ok.rank_hint,
+                    "decl_profile_ms": best_ok.decl_profile_ms,
                     "verify": {
                         "summary": best_ok.verify_summary,
                         "raw": if include_raw_verify { best_ok.verify_raw.clone().unwrap_or(serde_json::Value::Null) } else { serde_json::Value::Null }
@@ -9835,6 +10859,7 @@ Constraints:
                     "smt_evidence": picked.smt_hint.clone(),
                     "smt_hint": picked.smt_hint,
                     "rank_hint": picked.rank_hint,
+                    "decl_profile_ms": picked.decl_profile_ms,
                     "verify": {
                         "summary": picked.verify_summary,
                         "raw": if include_raw_verify { picked.verify_raw.clone().unwrap_or(serde_json::Value::Null) } else { serde_json::Value::Null }
@@ -9916,6 +10941,13 @@ Constraints:
                 "trace": trace
             });
 
+            #[cfg(feature = "planner")]
+            if let Some(reason) = planner_stopped.as_ref() {
+                if let Some(obj) = out.as_object_mut() {
+                    obj.insert("planner_stopped".to_string(), json!(reason));
+                }
+            }
+
             if profile {
                 let total_ms = prof_t0.elapsed().as_millis() as u64;
                 #[cfg(feature = "planner")]
@@ -10275,6 +11307,8 @@ Constraints:
                 }
             }
 
+            attach_llm_usage(&mut out);
+
             if let Some(p) = output_json {
                 write_json(&p, &out)?;
                 println!(
@@ -10522,6 +11556,145 @@ Constraints:
             Ok(())
         }
 
+        "smt-minimize" => {
+            let bundle_dir = arg_value(rest, "--bundle-dir").map(PathBuf::from);
+            let smt2_path = arg_value(rest, "--smt2")
+                .map(PathBuf::from)
+                .or_else(|| bundle_dir.as_ref().map(|d| d.join("repro.smt2")))
+                .ok_or_else(|| "missing --bundle-dir or --smt2".to_string())?;
+            let timeout_ms = arg_u64(rest, "--timeout-ms")
+                .unwrap_or(10_000)
+                .clamp(1, 600_000);
+            let max_checks = arg_u64(rest, "--max-checks")
+                .unwrap_or(400)
+                .clamp(1, 100_000);
+            let solver = match arg_value(rest, "--solver").as_deref().map(str::trim) {
+                Some("z3") => "z3 -in -smt2".to_string(),
+                Some("cvc5") => "cvc5 --lang smt2 --incremental".to_string(),
+                Some("yices") => "yices-smt2 --incremental".to_string(),
+                Some(cmdline) if !cmdline.is_empty() => cmdline.to_string(),
+                _ => plc::smt_minimize::default_solver_cmdline()
+                    .ok_or_else(|| "no SMT solver found on PATH (pass --solver)".to_string())?,
+            };
+            let mut emit_smt2 = arg_value(rest, "--emit-smt2").map(PathBuf::from);
+            let mut output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+            if let Some(dir) = bundle_dir.as_ref() {
+                if emit_smt2.is_none() {
+                    emit_smt2 = Some(dir.join("minimized.smt2"));
+                }
+                if output_json.is_none() {
+                    output_json = Some(dir.join("smt_minimize.json"));
+                }
+            }
+
+            let script = std::fs::read_to_string(&smt2_path)
+                .map_err(|e| format!("read {}: {e}", smt2_path.display()))?;
+
+            // The goal the script came from, for the Lean-side hypothesis list: `--input-json`
+            // (same shapes as `smt-repro`), else the bundle's `pp_dump.json` / `tree_search.json`.
+            let pp_source = arg_value(rest, "--input-json")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    let dir = bundle_dir.as_ref()?;
+                    ["pp_dump.json", "tree_search.json"]
+                        .iter()
+                        .map(|f| dir.join(f))
+                        .find(|p| p.exists())
+                });
+            let pp_dump = match pp_source.as_ref() {
+                Some(p) => {
+                    let txt = std::fs::read_to_string(p)
+                        .map_err(|e| format!("read {}: {e}", p.display()))?;
+                    let v = serde_json::from_str::<serde_json::Value>(&txt)
+                        .map_err(|e| format!("json parse {}: {e}", p.display()))?;
+                    if v.get("goals").and_then(|x| x.as_array()).is_some() {
+                        Some(v)
+                    } else {
+                        v.get("pp_dump")
+                            .or_else(|| v.get("goal_dump").and_then(|gd| gd.get("pp_dump")))
+                            .cloned()
+                    }
+                }
+                None => None,
+            };
+
+            let mut spawn_error: Option<String> = None;
+            let min = plc::smt_minimize::minimize_script(&script, max_checks, |text| {
+                match plc::smt_minimize::run_solver(&solver, text, timeout_ms) {
+                    Ok(o) => o,
+                    Err(e) => {
+                        spawn_error.get_or_insert(e);
+                        plc::smt_minimize::Outcome::Error
+                    }
+                }
+            });
+            if let Some(e) = spawn_error {
+                return Err(e);
+            }
+            let min = min?;
+
+            let mut smt2_written: Option<String> = None;
+            if let Some(path) = emit_smt2.as_ref() {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .map_err(|e| format!("failed to create dir {}: {e}", parent.display()))?;
+                }
+                std::fs::write(path, min.script.as_bytes())
+                    .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+                smt2_written = Some(path.display().to_string());
+            }
+
+            let out = json!({
+                "ok": true,
+                "kind": "smt_minimize",
+                "input_smt2": smt2_path.display().to_string(),
+                "solver": solver,
+                "outcome": min.outcome.as_str(),
+                "exit_code": match min.outcome {
+                    plc::smt_minimize::Outcome::Crash(code) => json!(code),
+                    _ => serde_json::Value::Null,
+                },
+                "params": {
+                    "timeout_ms": timeout_ms,
+                    "max_checks": max_checks,
+                },
+                "checks": min.checks,
+                "exhausted": min.exhausted,
+                "assertions": {
+                    "before": min.assertions_before,
+                    "after": min.assertions_after,
+                },
+                "chars": {
+                    "before": script.chars().count(),
+                    "after": min.script.chars().count(),
+                },
+                "artifacts": {
+                    "smt2_written": smt2_written,
+                },
+                "smt2": min.script,
+                "lean": pp_dump
+                    .as_ref()
+                    .map(|pp| plc::smt_minimize::lean_hyps_for(pp, &min))
+                    .unwrap_or(serde_json::Value::Null),
+            });
+
+            if let Some(p) = output_json {
+                write_json(&p, &out)?;
+                println!(
+                    "{}",
+                    json!({
+                        "ok": true,
+                        "written": p.display().to_string(),
+                        "kind": "smt_minimize",
+                        "outcome": out["outcome"],
+                    })
+                );
+            } else {
+                println!("{}", out);
+            }
+            Ok(())
+        }
+
         "suggest" => {
             let repo_root = arg_value(rest, "--repo")
                 .ok_or_else(|| "missing --repo".to_string())
@@ -10530,6 +11703,11 @@ Constraints:
             let lemma = arg_value(rest, "--lemma").ok_or_else(|| "missing --lemma".to_string())?;
             let timeout_s = arg_u64(rest, "--timeout-s").unwrap_or(120);
             let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+            let stream = arg_flag(rest, "--stream");
+            let sampling = sample_args_from(rest)?;
+            if stream && sampling.n > 1 {
+                return Err("--stream and --samples cannot be combined".to_string());
+            }
 
             let repo_root =
                 plc::find_lean_repo_root(&repo_root).map_err(|e| format!("repo_root: {e}"))?;
@@ -10539,15 +11717,53 @@ Constraints:
                 .map_err(|e| format!("failed to build tokio runtime: {e}"))?;
 
             let payload = plc::build_proof_prompt(&repo_root, &file, &lemma)?;
-            let res = rt
-                .block_on(plc::llm::chat_completion(
+            let (res, extra) = if stream {
+                let (res, report) = chat_completion_streamed(
+                    &rt,
+                    &repo_root,
                     &payload.system,
                     &payload.user,
                     StdDuration::from_secs(timeout_s),
-                ))
-                .map_err(|e| format!("llm chat_completion failed: {e}"))?;
+                    true,
+                )
+                .map_err(|e| format!("llm chat_completion_stream failed: {e}"))?;
+                (res, Some(("stream", report)))
+            } else if sampling.n > 1 {
+                let p = repo_root.join(&file);
+                let text = std::fs::read_to_string(&p)
+                    .map_err(|e| format!("read {}: {e}", p.display()))?;
+                let (sampled, _best, proof, report) = sample_and_verify(
+                    &rt,
+                    &repo_root,
+                    &text,
+                    &lemma,
+                    &payload.system,
+                    &payload.user,
+                    &sampling,
+                )
+                .map_err(|e| format!("llm chat_completion_samples failed: {e}"))?;
+                let res = plc::llm::ChatCompletionResult {
+                    provider: sampled.provider,
+                    model: sampled.model,
+                    model_source: sampled.model_source,
+                    model_env: sampled.model_env,
+                    content: proof,
+                    usage: sampled.usage,
+                    raw: serde_json::Value::Null,
+                };
+                (res, Some(("samples", report)))
+            } else {
+                let res = rt
+                    .block_on(plc::llm::chat_completion(
+                        &payload.system,
+                        &payload.user,
+                        StdDuration::from_secs(timeout_s),
+                    ))
+                    .map_err(|e| format!("llm chat_completion failed: {e}"))?;
+                (res, None)
+            };
 
-            let out = json!({
+            let mut out = json!({
                 "provider": res.provider,
                 "model": res.model,
                 "lemma": lemma,
@@ -10557,8 +11773,13 @@ Constraints:
                     "combined_sha256": payload.prompt_combined_sha256,
                 },
                 "suggestion": res.content,
+                "usage": res.usage,
                 "raw": res.raw
             });
+            if let Some((key, report)) = extra {
+                out[key] = report;
+            }
+            attach_llm_usage(&mut out);
 
             if let Some(p) = output_json {
                 write_json(&p, &out)?;
@@ -10587,6 +11808,20 @@ Constraints:
             let max_iters = arg_u64(rest, "--max-iters").unwrap_or(3);
             let timeout_s = arg_u64(rest, "--timeout-s").unwrap_or(120);
             let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+            let stream = arg_flag(rest, "--stream");
+            let sampling = sample_args_from(rest)?;
+            let conversation = arg_flag(rest, "--conversation");
+            let history_bytes = arg_u64(rest, "--history-bytes")
+                .map(|n| n as usize)
+                .unwrap_or(plc::repair::DEFAULT_HISTORY_BYTES);
+            if stream && sampling.n > 1 {
+                return Err("--stream and --samples cannot be combined".to_string());
+            }
+            if conversation && (stream || sampling.n > 1) {
+                return Err(
+                    "--conversation cannot be combined with --stream or --samples".to_string(),
+                );
+            }
 
             if max_iters == 0 {
                 return Err("max-iters must be >= 1".to_string());
@@ -10607,27 +11842,85 @@ Constraints:
                 .map_err(|e| format!("failed to build tokio runtime: {e}"))?;
 
             let mut attempts: Vec<serde_json::Value> = Vec::new();
+            let mut budget_stop: Option<String> = None;
+            // Conversation mode: one growing chat, seeded with the original excerpt.
+            let mut convo = if conversation {
+                let excerpt = plc::extract_decl_block(&cur_text, &lemma)?;
+                Some(plc::repair::RepairConversation::new(
+                    plc::proof_system_prompt(),
+                    plc::proof_user_prompt(&excerpt),
+                    history_bytes,
+                ))
+            } else {
+                None
+            };
             for iter_idx in 0..max_iters {
                 let excerpt = plc::extract_decl_block(&cur_text, &lemma)?;
                 let system = plc::proof_system_prompt();
                 let user = plc::proof_user_prompt(&excerpt);
 
-                let res = rt
-                    .block_on(plc::llm::chat_completion(
+                let res = if let Some(c) = convo.as_ref() {
+                    rt.block_on(plc::llm::chat_completion_messages(
+                        &c.messages(),
+                        StdDuration::from_secs(timeout_s),
+                    ))
+                    .map(|r| (r, None, None))
+                } else if stream {
+                    chat_completion_streamed(
+                        &rt,
+                        &repo_root,
+                        &system,
+                        &user,
+                        StdDuration::from_secs(timeout_s),
+                        true,
+                    )
+                    .map(|(r, report)| (r, Some(("stream", report)), None))
+                } else if sampling.n > 1 {
+                    sample_and_verify(
+                        &rt, &repo_root, &cur_text, &lemma, &system, &user, &sampling,
+                    )
+                    .map(|(sampled, checked, proof, report)| {
+                        let r = plc::llm::ChatCompletionResult {
+                            provider: sampled.provider,
+                            model: sampled.model,
+                            model_source: sampled.model_source,
+                            model_env: sampled.model_env,
+                            content: proof,
+                            usage: sampled.usage,
+                            raw: serde_json::Value::Null,
+                        };
+                        (r, Some(("samples", report)), checked)
+                    })
+                } else {
+                    rt.block_on(plc::llm::chat_completion(
                         &system,
                         &user,
                         StdDuration::from_secs(timeout_s),
                     ))
-                    .map_err(|e| format!("llm chat_completion failed: {e}"))?;
+                    .map(|r| (r, None, None))
+                };
+                let (res, extra, checked) = match res {
+                    Ok(r) => r,
+                    // Out of budget: report what we have instead of failing the run.
+                    Err(e) if e.starts_with(plc::llm_usage::BUDGET_EXCEEDED) => {
+                        budget_stop = Some(e);
+                        break;
+                    }
+                    Err(e) => return Err(format!("llm chat_completion failed: {e}")),
+                };
 
-                let suggestion = json!({
+                let mut suggestion = json!({
                     "provider": res.provider,
                     "model": res.model,
                     "lemma": lemma,
                     "file": p.display().to_string(),
                     "suggestion": res.content,
+                    "usage": res.usage,
                     "raw": res.raw
                 });
+                if let Some((key, report)) = extra {
+                    suggestion[key] = report;
+                }
 
                 let replacement = suggestion
                     .get("suggestion")
@@ -10636,19 +11929,52 @@ Constraints:
                         "LLM suggestion did not contain `suggestion` field".to_string()
                     })?;
 
-                let patched = plc::patch_first_sorry_in_decl(&cur_text, &lemma, replacement)?;
-                cur_text = patched.text.clone();
-
-                let still_has_sorry = plc::decl_block_contains_sorry(&cur_text, &lemma)?;
-                let verify = rt
-                    .block_on(plc::verify_lean_text(
-                        &repo_root,
+                // Sampling already patched and verified the chosen proof.
+                let (patched, verify) = match checked.and_then(|c| Some((c.patch?, c.verify?))) {
+                    Some(done) => done,
+                    None => {
+                        let patched =
+                            plc::patch_first_sorry_in_decl(&cur_text, &lemma, replacement)?;
+                        let verify = rt
+                            .block_on(plc::verify_lean_text(
+                                &repo_root,
+                                &patched.text,
+                                StdDuration::from_secs(timeout_s),
+                            ))
+                            .map_err(|e| format!("verify failed: {e}"))?;
+                        (patched, verify)
+                    }
+                };
+                let still_has_sorry = plc::decl_block_contains_sorry(&patched.text, &lemma)?;
+                let feedback = convo.as_mut().map(|c| {
+                    let mut f = plc::repair::attempt_feedback(
+                        iter_idx as usize + 1,
+                        replacement,
                         &cur_text,
-                        StdDuration::from_secs(timeout_s),
-                    ))
-                    .map_err(|e| format!("verify failed: {e}"))?;
+                        &patched.text,
+                        &lemma,
+                        &verify,
+                    );
+                    // No goal in the error message: dump the goal at the error instead.
+                    if let (None, Some(loc)) = (&f.goal, &f.first_error_loc) {
+                        f.goal = rt.block_on(plc::repair::goal_at_error(
+                            &repo_root,
+                            &file,
+                            &patched.text,
+                            loc.line,
+                            StdDuration::from_secs(timeout_s),
+                        ));
+                    }
+                    c.push(f.clone());
+                    f
+                });
+                // In a conversation, a patch that does not compile is discarded so the next reply
+                // replaces the same `sorry`.
+                if feedback.is_none() || verify.ok {
+                    cur_text = patched.text.clone();
+                }
 
-                attempts.push(json!({
+                let mut attempt = json!({
                     "iter": iter_idx + 1,
                     "suggestion": suggestion,
                     "patch": {
@@ -10659,7 +11985,11 @@ Constraints:
                     },
                     "lemma_still_contains_sorry": still_has_sorry,
                     "verify": verify,
-                }));
+                });
+                if let Some(f) = feedback {
+                    attempt["feedback"] = json!(f);
+                }
+                attempts.push(attempt);
 
                 if verify.ok && !still_has_sorry {
                     break;
@@ -10668,13 +11998,18 @@ Constraints:
 
             let final_still_has_sorry =
                 plc::decl_block_contains_sorry(&cur_text, &lemma).unwrap_or(true);
-            let out = json!({
+            let mut out = json!({
                 "file": p.display().to_string(),
                 "lemma": lemma,
                 "max_iters": max_iters,
                 "attempts": attempts,
                 "final_lemma_contains_sorry": final_still_has_sorry,
+                "budget_stop": budget_stop,
             });
+            if let Some(c) = convo.as_ref() {
+                out["conversation"] = json!(c.stats());
+            }
+            attach_llm_usage(&mut out);
 
             if let Some(p) = output_json {
                 write_json(&p, &out)?;
@@ -10694,6 +12029,118 @@ Constraints:
             Ok(())
         }
 
+        "agent-prove" => {
+            #[cfg(feature = "axi-agent")]
+            {
+                let repo_root = arg_value(rest, "--repo")
+                    .ok_or_else(|| "missing --repo".to_string())
+                    .map(PathBuf::from)?;
+                let file = arg_value(rest, "--file").ok_or_else(|| "missing --file".to_string())?;
+                let lemma =
+                    arg_value(rest, "--lemma").ok_or_else(|| "missing --lemma".to_string())?;
+                let max_steps = arg_u64(rest, "--max-steps").unwrap_or(20) as usize;
+                let max_turns = arg_u64(rest, "--max-turns").unwrap_or(12) as usize;
+                // `--timeout-s` bounds each Lean run; `--llm-timeout-s` each LLM request.
+                let timeout_s = arg_u64(rest, "--timeout-s").unwrap_or(120);
+                let llm_timeout_s = arg_u64(rest, "--llm-timeout-s").unwrap_or(timeout_s);
+                let write = arg_flag(rest, "--write");
+                let transcript = arg_value(rest, "--transcript").map(PathBuf::from);
+                let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+                if max_steps == 0 || max_turns == 0 {
+                    return Err("max-steps and max-turns must be >= 1".to_string());
+                }
+
+                let repo_root =
+                    plc::find_lean_repo_root(&repo_root).map_err(|e| format!("repo_root: {e}"))?;
+                plc::load_dotenv_smart(&repo_root);
+                let p = repo_root.join(&file);
+                if !p.exists() {
+                    return Err(format!("File not found: {}", p.display()));
+                }
+
+                let rt = tokio::runtime::Runtime::new()
+                    .map_err(|e| format!("failed to build tokio runtime: {e}"))?;
+                let cfg = plc::agent::AgentConfig {
+                    repo_root: repo_root.clone(),
+                    file: file.clone(),
+                    lemma: lemma.clone(),
+                    max_steps,
+                    max_turns,
+                    llm_timeout: StdDuration::from_secs(llm_timeout_s),
+                    tool_timeout: StdDuration::from_secs(timeout_s),
+                };
+                let run = rt.block_on(plc::agent::run_agent(&cfg))?;
+
+                let written = if write && run.solved && run.changed {
+                    std::fs::write(&p, &run.text)
+                        .map_err(|e| format!("write {}: {e}", p.display()))?;
+                    true
+                } else {
+                    false
+                };
+
+                let transcript_path = match transcript {
+                    Some(t) if t.is_absolute() => t,
+                    Some(t) => repo_root.join(t),
+                    None => {
+                        let ms = std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map(|d| d.as_millis())
+                            .unwrap_or(0);
+                        repo_root
+                            .join(".generated")
+                            .join("proofpatch-agent")
+                            .join(format!("{}-{ms}.json", lemma.replace('.', "_")))
+                    }
+                };
+                let run_json = serde_json::to_value(&run).map_err(|e| e.to_string())?;
+                write_json(&transcript_path, &run_json)?;
+
+                let mut out = json!({
+                    "file": p.display().to_string(),
+                    "lemma": lemma,
+                    "solved": run.solved,
+                    "stop_reason": run.stop_reason,
+                    "turns": run.turns,
+                    "max_steps": max_steps,
+                    "max_turns": max_turns,
+                    "steps": run.steps.iter().map(|s| json!({
+                        "step": s.step,
+                        "turn": s.turn,
+                        "tool": s.tool,
+                        "ok": s.ok,
+                        "elapsed_ms": s.elapsed_ms,
+                    })).collect::<Vec<_>>(),
+                    "applied": run.applied,
+                    "final_message": run.final_message,
+                    "error": run.error,
+                    "written": written,
+                    "transcript": transcript_path.display().to_string(),
+                });
+                attach_llm_usage(&mut out);
+
+                if let Some(p) = output_json {
+                    write_json(&p, &out)?;
+                    println!(
+                        "{}",
+                        json!({
+                            "ok": true,
+                            "written": p.display().to_string(),
+                            "kind": "agent_prove",
+                            "result_kind": serde_json::Value::Null,
+                        })
+                    );
+                } else {
+                    println!("{}", out);
+                }
+                Ok(())
+            }
+            #[cfg(not(feature = "axi-agent"))]
+            {
+                Err("agent-prove requires building with: cargo build -p proofpatch-cli --features axi-agent".to_string())
+            }
+        }
+
         "review-prompt" => {
             let repo_root = arg_value(rest, "--repo")
                 .ok_or_else(|| "missing --repo".to_string())
@@ -11050,7 +12497,7 @@ Constraints:
                 StdDuration::from_secs(timeout_s),
             ));
 
-            let out = match res {
+            let mut out = match res {
                 Ok(r) => {
                     // Best-effort: extract the first JSON value from the model output.
                     let review_struct = extract_json_from_text(&r.content);
@@ -11161,6 +12608,8 @@ Constraints:
                 }
             };
 
+            attach_llm_usage(&mut out);
+
             if let Some(p) = output_json {
                 write_json(&p, &out)?;
                 println!(
@@ -11179,6 +12628,44 @@ Constraints:
             Ok(())
         }
 
+        "llm-providers" => {
+            let repo_root = arg_value(rest, "--repo").map(PathBuf::from);
+            let probe_timeout_ms = arg_u64(rest, "--probe-timeout-ms").unwrap_or(1500);
+
+            if let Some(rr) = repo_root.as_ref() {
+                let rr = plc::find_lean_repo_root(rr).unwrap_or_else(|_| rr.clone());
+                plc::load_dotenv_smart(&rr);
+            } else if let Ok(cwd) = std::env::current_dir() {
+                plc::load_dotenv_smart(&cwd);
+            }
+
+            let rt = tokio::runtime::Runtime::new()
+                .map_err(|e| format!("failed to build tokio runtime: {e}"))?;
+            let res = rt.block_on(plc::llm::select_provider_info(StdDuration::from_millis(
+                probe_timeout_ms,
+            )));
+            let out = match res {
+                Ok(mut info) => {
+                    // Never print keys; report presence only.
+                    let api_key_set = info.api_key.take().is_some();
+                    json!({
+                        "ok": true,
+                        "kind": "llm_providers",
+                        "selected": info,
+                        "api_key_set": api_key_set,
+                    })
+                }
+                Err(e) => json!({
+                    "ok": false,
+                    "kind": "llm_providers",
+                    "selected": serde_json::Value::Null,
+                    "error": e,
+                }),
+            };
+            println!("{}", out);
+            Ok(())
+        }
+
         "llm-chat" => {
             let repo_root = arg_value(rest, "--repo").map(PathBuf::from);
             let system = arg_value(rest, "--system");
@@ -11190,6 +12677,7 @@ Constraints:
             let _max_tool_iters = arg_u64(rest, "--max-tool-iters").unwrap_or(4) as usize;
             let tools = arg_value(rest, "--tools").unwrap_or_default();
             let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+            let stream = arg_flag(rest, "--stream");
 
             let system_txt = if let Some(p) = system_file {
                 fs::read_to_string(&p).map_err(|e| format!("read {}: {}", p.display(), e))?
@@ -11246,24 +12734,48 @@ Constraints:
                         .to_string(),
                 );
             }
-            let out = {
+            let mut out = {
                 // Simple one-shot chat completion (no tools).
-                let res0 = rt.block_on(plc::llm::chat_completion(
-                    &system_txt,
-                    &user_txt,
-                    StdDuration::from_secs(timeout_s),
-                ));
+                let res0 = if stream {
+                    let progress_root = repo_root
+                        .clone()
+                        .or_else(|| std::env::current_dir().ok())
+                        .unwrap_or_else(|| PathBuf::from("."));
+                    chat_completion_streamed(
+                        &rt,
+                        &progress_root,
+                        &system_txt,
+                        &user_txt,
+                        StdDuration::from_secs(timeout_s),
+                        false,
+                    )
+                    .map(|(r, report)| (r, Some(report)))
+                } else {
+                    rt.block_on(plc::llm::chat_completion(
+                        &system_txt,
+                        &user_txt,
+                        StdDuration::from_secs(timeout_s),
+                    ))
+                    .map(|r| (r, None))
+                };
                 match res0 {
-                    Ok(r) => json!({
-                        "ok": true,
-                        "provider": r.provider,
-                        "model": r.model,
-                        "model_source": r.model_source,
-                        "model_env": r.model_env,
-                        "content": r.content,
-                        "content_struct": extract_json_from_text(&r.content),
-                        "raw": r.raw,
-                    }),
+                    Ok((r, report)) => {
+                        let mut o = json!({
+                            "ok": true,
+                            "provider": r.provider,
+                            "model": r.model,
+                            "model_source": r.model_source,
+                            "model_env": r.model_env,
+                            "content": r.content,
+                            "content_struct": extract_json_from_text(&r.content),
+                            "usage": r.usage,
+                            "raw": r.raw,
+                        });
+                        if let Some(report) = report {
+                            o["stream"] = report;
+                        }
+                        o
+                    }
                     Err(e) => {
                         if require_key {
                             return Err(format!("llm-chat failed: {e}"));
@@ -11273,6 +12785,8 @@ Constraints:
                 }
             };
 
+            attach_llm_usage(&mut out);
+
             if let Some(p) = output_json {
                 write_json(&p, &out)?;
                 println!(
@@ -11297,30 +12811,147 @@ Constraints:
                 .map(PathBuf::from)?;
             let github = arg_flag(rest, "--github");
             let modules = arg_values(rest, "--module");
-            if modules.is_empty() {
-                return Err("lint-style requires at least one --module <Root>".to_string());
+            let files_arg = arg_values(rest, "--file");
+            let no_lake = arg_flag(rest, "--no-lake");
+            let fix = arg_flag(rest, "--fix");
+            let dry_run = arg_flag(rest, "--dry-run");
+            let output_json = arg_value(rest, "--output-json").map(PathBuf::from);
+            if modules.is_empty() && files_arg.is_empty() {
+                return Err(
+                    "lint-style requires at least one --module <Root> or --file <relpath>"
+                        .to_string(),
+                );
             }
 
             let repo_root =
                 plc::find_lean_repo_root(&repo_root).map_err(|e| format!("repo_root: {e}"))?;
-            let lake = plc::resolve_lake();
+            let cfg = plc::config::load_from_repo_root(&repo_root)?
+                .map(|c| c.lint)
+                .unwrap_or_default();
+
+            // Mathlib's linter (when the repo provides it), captured and parsed.
+            let mut lake_json = serde_json::Value::Null;
+            let mut external: Vec<plc::lint::LintFinding> = Vec::new();
+            if !modules.is_empty() && !no_lake {
+                let lake = plc::resolve_lake();
+                let mut cmd = std::process::Command::new(lake);
+                cmd.arg("exe").arg("lint-style");
+                if github {
+                    cmd.arg("--github");
+                }
+                for m in &modules {
+                    cmd.arg(m);
+                }
+                let out = cmd
+                    .current_dir(&repo_root)
+                    .output()
+                    .map_err(|e| format!("failed to run lake lint-style: {e}"))?;
+                let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+                let stderr = String::from_utf8_lossy(&out.stderr).to_string();
+                external = plc::lint::parse_lint_style_output(&format!("{stdout}\n{stderr}"));
+                let tail: Vec<&str> = stderr.lines().rev().take(20).collect();
+                lake_json = json!({
+                    "ok": out.status.success(),
+                    "returncode": out.status.code(),
+                    "parsed": external.len(),
+                    "stderr_tail": tail.into_iter().rev().collect::<Vec<_>>().join("\n"),
+                });
+            }
+
+            let mut files: Vec<String> = files_arg;
+            for m in &modules {
+                let dir = m.replace('.', "/");
+                if repo_root.join(&dir).is_dir() {
+                    files.extend(plc::axiom_audit::list_lean_files(&repo_root, &dir)?);
+                }
+                if repo_root.join(format!("{dir}.lean")).is_file() {
+                    files.push(format!("{dir}.lean"));
+                }
+            }
+            for f in &external {
+                if repo_root.join(&f.file).is_file() {
+                    files.push(f.file.clone());
+                }
+            }
+            let files = unique_repo_files(&repo_root, files);
 
-            let mut cmd = std::process::Command::new(lake);
-            cmd.arg("exe").arg("lint-style");
-            if github {
-                cmd.arg("--github");
+            let mode = if dry_run {
+                plc::autofix::FixMode::DryRun
+            } else if fix {
+                plc::autofix::FixMode::Write
+            } else {
+                plc::autofix::FixMode::Check
+            };
+            let mut reports: Vec<plc::autofix::LintFileReport> = Vec::new();
+            for f in &files {
+                let ext: Vec<plc::lint::LintFinding> =
+                    external.iter().filter(|x| &x.file == f).cloned().collect();
+                reports.push(plc::autofix::lint_file(&repo_root, f, &cfg, &ext, mode)?);
             }
-            for m in modules {
-                cmd.arg(m);
+
+            let findings: usize = reports.iter().map(|r| r.findings.len()).sum();
+            let fixable: usize = reports
+                .iter()
+                .map(|r| r.findings.iter().filter(|f| f.fixable).count())
+                .sum();
+            let fixes: usize = reports.iter().map(|r| r.fixes.len()).sum();
+            let remaining: usize = reports.iter().map(|r| r.remaining.len()).sum();
+            let lake_failed_silently =
+                lake_json.get("ok").and_then(|v| v.as_bool()) == Some(false) && external.is_empty();
+            let result_kind = if lake_failed_silently {
+                "lake_failed"
+            } else if findings == 0 {
+                "clean"
+            } else if mode == plc::autofix::FixMode::Check {
+                "findings"
+            } else if remaining == 0 {
+                "fixed"
+            } else {
+                "partial"
+            };
+            let out = json!({
+                "ok": result_kind == "clean" || (mode == plc::autofix::FixMode::Write && remaining == 0),
+                "kind": "lint_style",
+                "result_kind": result_kind,
+                "repo_root": repo_root.display().to_string(),
+                "mode": match mode {
+                    plc::autofix::FixMode::Check => "check",
+                    plc::autofix::FixMode::DryRun => "dry_run",
+                    plc::autofix::FixMode::Write => "fix",
+                },
+                "lake": lake_json,
+                "counts": {
+                    "files": reports.len(),
+                    "findings": findings,
+                    "fixable": fixable,
+                    "fixes": fixes,
+                    "remaining": remaining,
+                },
+                "files": reports,
+            });
+            let ok = out.get("ok").and_then(|v| v.as_bool()).unwrap_or(false);
+            if let Some(p) = output_json {
+                write_json(&p, &out)?;
+                println!(
+                    "{}",
+                    json!({
+                        "ok": ok,
+                        "written": p.display().to_string(),
+                        "kind": "lint_style",
+                        "result_kind": result_kind,
+                        "counts": out.get("counts").cloned().unwrap_or(serde_json::Value::Null),
+                    })
+                );
+            } else {
+                println!("{}", out);
             }
-            let status = cmd
-                .current_dir(&repo_root)
-                .status()
-                .map_err(|e| format!("failed to run lake lint-style: {e}"))?;
-            if status.success() {
+            // Dry runs only preview; they never fail the command.
+            if ok || mode == plc::autofix::FixMode::DryRun {
                 Ok(())
             } else {
-                Err(format!("lint-style failed with status: {status}"))
+                Err(format!(
+                    "lint-style: {remaining} finding(s) remaining ({result_kind})"
+                ))
             }
         }
 
@@ -11962,6 +13593,8 @@ Constraints:
                 );
             }
 
+            attach_llm_usage(&mut out);
+
             if let Some(p) = output_json {
                 write_json(&p, &out)?;
                 println!(
@@ -12174,6 +13807,8 @@ Constraints:
                 );
             }
 
+            attach_llm_usage(&mut out);
+
             if let Some(p) = output_json {
                 write_json(&p, &out)?;
                 println!(
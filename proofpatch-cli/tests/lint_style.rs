@@ -0,0 +1,33 @@
+//! `lint-style` file selection.
+
+use serde_json::Value;
+use std::process::Command;
+
+#[test]
+fn the_same_file_spelled_three_ways_is_linted_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let d = dir.path();
+    std::fs::write(d.join("lean-toolchain"), "leanprover/lean4:v4.20.0\n").unwrap();
+    std::fs::write(d.join("lakefile.lean"), "import Lake\nopen Lake DSL\n").unwrap();
+    std::fs::write(d.join("B.lean"), "theorem bar : True := trivial\n").unwrap();
+    std::fs::write(d.join("A.lean"), "theorem foo : True := trivial\n").unwrap();
+    let abs = d.join("A.lean");
+
+    let out = Command::new(env!("CARGO_BIN_EXE_proofpatch"))
+        .args(["lint-style", "--repo", d.to_str().unwrap(), "--no-lake"])
+        .args(["--file", "B.lean", "--file", "A.lean", "--file", "./A.lean"])
+        .args(["--file", abs.to_str().unwrap()])
+        .env("HOME", d)
+        .env("PROOFPATCH_DOTENV_SEARCH", "0")
+        .output()
+        .unwrap();
+    let v: Value = serde_json::from_slice(&out.stdout).unwrap();
+    let files: Vec<&str> = v["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|f| f["file"].as_str().unwrap())
+        .collect();
+    assert_eq!(files, vec!["A.lean", "B.lean"]);
+    assert_eq!(v["counts"]["files"], 2);
+}
@@ -0,0 +1,526 @@
+//! End-to-end runs of LLM-backed commands against `proofpatch-mock-llm` on localhost.
+
+use proofpatch_core::mock_llm::{MockLlmServer, MockScript};
+use serde_json::{json, Value};
+use std::io::BufRead;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+const FOO: &str = "theorem foo : True := by\n  sorry\n";
+
+/// A minimal Lean repo whose `[llm]` section points at `base_url`.
+fn lean_repo(dir: &Path, base_url: &str) {
+    std::fs::write(dir.join("lean-toolchain"), "leanprover/lean4:v4.20.0\n").unwrap();
+    std::fs::write(dir.join("lakefile.lean"), "import Lake\nopen Lake DSL\n").unwrap();
+    std::fs::write(dir.join("Foo.lean"), FOO).unwrap();
+    std::fs::write(
+        dir.join("proofpatch.toml"),
+        format!(
+            "[llm]\nprovider_order = [\"mock\"]\n\n[llm.providers.mock]\nbase_url = \"{base_url}\"\nmodel = \"mock-model\"\nprobe = false\n"
+        ),
+    )
+    .unwrap();
+}
+
+/// `lake env lean <file>` stand-in: rejects `bad`, warns about `sorry`, otherwise succeeds silently.
+fn fake_lake(dir: &Path) -> std::path::PathBuf {
+    let p = dir.join("fake-lake.sh");
+    std::fs::write(
+        &p,
+        "#!/bin/sh\nf=\"$3\"\nif grep -q bad \"$f\"; then echo \"$f:2:2: error: unknown identifier 'bad'\"; exit 1; fi\nif grep -q sorry \"$f\"; then echo \"$f:2:2: warning: declaration uses 'sorry'\"; fi\nexit 0\n",
+    )
+    .unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&p, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    p
+}
+
+fn proofpatch(home: &Path, args: &[&str]) -> Value {
+    let out = Command::new(env!("CARGO_BIN_EXE_proofpatch"))
+        .args(args)
+        .env("HOME", home)
+        .env("PROOFPATCH_DOTENV_SEARCH", "0")
+        .env("PROOFPATCH_PROVIDER_ORDER", "mock")
+        .env_remove("MOCK_MODEL")
+        .env_remove("PROOFPATCH_LLM_CACHE")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "proofpatch {args:?} failed:\n{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    serde_json::from_slice(&out.stdout).unwrap()
+}
+
+fn script(v: Value) -> MockScript {
+    serde_json::from_value(v).unwrap()
+}
+
+#[test]
+fn suggest_against_the_mock_binary() {
+    let dir = tempfile::tempdir().unwrap();
+    let fixture = dir.path().join("fixture.json");
+    std::fs::write(
+        &fixture,
+        json!({ "rules": [{ "when": { "contains": "theorem foo" }, "reply": { "content": "trivial" } }] })
+            .to_string(),
+    )
+    .unwrap();
+    let log = dir.path().join("requests.jsonl");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_proofpatch-mock-llm"))
+        .args(["--fixture", fixture.to_str().unwrap()])
+        .args(["--log", log.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut first = String::new();
+    std::io::BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut first)
+        .unwrap();
+    let started: Value = serde_json::from_str(&first).unwrap();
+    let base_url = started["base_url"].as_str().unwrap().to_string();
+
+    lean_repo(dir.path(), &base_url);
+    let repo = dir.path().to_str().unwrap();
+    let out = proofpatch(
+        dir.path(),
+        &[
+            "suggest", "--repo", repo, "--file", "Foo.lean", "--lemma", "foo",
+        ],
+    );
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert_eq!(out["provider"], "mock");
+    assert_eq!(out["suggestion"], "trivial");
+    assert_eq!(out["llm_usage"]["calls"], 1);
+    let logged = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(logged.lines().count(), 1);
+    assert!(logged.contains("/v1/chat/completions"));
+}
+
+#[test]
+fn suggest_streams_and_stops_at_the_first_lean_block() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let reply = format!(
+        "```lean\ntrivial\n```\n{}",
+        "Because True holds. ".repeat(30)
+    );
+    let server = rt
+        .block_on(MockLlmServer::start(script(json!({
+            "rules": [{ "reply": { "content": reply, "chunk_chars": 5, "chunk_delay_ms": 2 } }]
+        }))))
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path(), &server.base_url);
+    let repo = dir.path().to_str().unwrap();
+
+    let out = proofpatch(
+        dir.path(),
+        &[
+            "suggest", "--repo", repo, "--file", "Foo.lean", "--lemma", "foo", "--stream",
+        ],
+    );
+    assert_eq!(out["suggestion"], "trivial");
+    assert_eq!(out["stream"]["finish"], "cancelled");
+    assert_eq!(out["stream"]["code_block"], true);
+    assert_eq!(out["llm_usage"]["calls"], 1);
+    assert_eq!(server.requests()[0].body["stream"], true);
+}
+
+#[test]
+fn loop_patches_verifies_and_stops() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let server = rt
+        .block_on(MockLlmServer::start(script(json!({
+            "rules": [{ "reply": { "content": "trivial", "usage": { "prompt_tokens": 40, "completion_tokens": 2 } } }]
+        }))))
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path(), &server.base_url);
+    let lake = fake_lake(dir.path());
+    let repo = dir.path().to_str().unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_proofpatch"))
+        .args([
+            "loop", "--repo", repo, "--file", "Foo.lean", "--lemma", "foo",
+        ])
+        .args(["--max-iters", "3"])
+        .env("HOME", dir.path())
+        .env("LAKE", &lake)
+        .env("PROOFPATCH_VERIFY_BACKEND", "lake")
+        .env("PROOFPATCH_AUTO_BUILD", "0")
+        .env("PROOFPATCH_DOTENV_SEARCH", "0")
+        .env("PROOFPATCH_PROVIDER_ORDER", "mock")
+        .env_remove("PROOFPATCH_LLM_CACHE")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let out: Value = serde_json::from_slice(&out.stdout).unwrap();
+
+    assert_eq!(out["attempts"].as_array().unwrap().len(), 1);
+    assert_eq!(out["final_lemma_contains_sorry"], false);
+    assert_eq!(out["attempts"][0]["verify"]["ok"], true);
+    assert_eq!(out["llm_usage"]["total_tokens"], 42);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn loop_samples_rank_and_verify_in_parallel() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    // The server ignores `n`, so the loop tops up with parallel requests.
+    let server = rt
+        .block_on(MockLlmServer::start(script(json!({
+            "rules": [
+                { "times": 2, "reply": { "content": "exact bad" } },
+                { "reply": { "content": "```lean\ntrivial\n```" } }
+            ]
+        }))))
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path(), &server.base_url);
+    let lake = fake_lake(dir.path());
+    let repo = dir.path().to_str().unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_proofpatch"))
+        .args([
+            "loop", "--repo", repo, "--file", "Foo.lean", "--lemma", "foo",
+        ])
+        .args(["--samples", "5", "--verify-jobs", "2"])
+        .env("HOME", dir.path())
+        .env("LAKE", &lake)
+        .env("PROOFPATCH_VERIFY_BACKEND", "lake")
+        .env("PROOFPATCH_AUTO_BUILD", "0")
+        .env("PROOFPATCH_DOTENV_SEARCH", "0")
+        .env("PROOFPATCH_PROVIDER_ORDER", "mock")
+        .env_remove("PROOFPATCH_LLM_CACHE")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let out: Value = serde_json::from_slice(&out.stdout).unwrap();
+
+    let attempts = out["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 1);
+    let samples = &attempts[0]["suggestion"]["samples"];
+    assert_eq!(samples["mode"], "native+parallel");
+    assert_eq!(samples["stats"]["samples"], 5);
+    assert_eq!(samples["stats"]["distinct"], 2);
+    assert_eq!(samples["stats"]["compiled_samples"], 3);
+    assert_eq!(samples["candidates"][0]["proof"], "trivial");
+    assert_eq!(samples["candidates"][0]["votes"], 3);
+    assert_eq!(samples["candidates"][1]["ok"], false);
+    assert_eq!(attempts[0]["suggestion"]["suggestion"], "trivial");
+    assert_eq!(attempts[0]["verify"]["ok"], true);
+    assert_eq!(out["final_lemma_contains_sorry"], false);
+    assert_eq!(out["llm_usage"]["calls"], 5);
+    assert_eq!(server.requests()[0].body["n"], 5);
+}
+
+#[test]
+fn loop_conversation_feeds_back_the_previous_error() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let server = rt
+        .block_on(MockLlmServer::start(script(json!({
+            "rules": [
+                { "times": 1, "reply": { "content": "exact bad" } },
+                { "reply": { "content": "trivial" } }
+            ]
+        }))))
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path(), &server.base_url);
+    let lake = fake_lake(dir.path());
+    let repo = dir.path().to_str().unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_proofpatch"))
+        .args([
+            "loop", "--repo", repo, "--file", "Foo.lean", "--lemma", "foo",
+        ])
+        .args(["--max-iters", "3", "--conversation"])
+        .env("HOME", dir.path())
+        .env("LAKE", &lake)
+        .env("PROOFPATCH_VERIFY_BACKEND", "lake")
+        .env("PROOFPATCH_AUTO_BUILD", "0")
+        .env("PROOFPATCH_DOTENV_SEARCH", "0")
+        .env("PROOFPATCH_PROVIDER_ORDER", "mock")
+        .env_remove("PROOFPATCH_LLM_CACHE")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let out: Value = serde_json::from_slice(&out.stdout).unwrap();
+
+    // The failed patch was discarded, so the second reply replaced the same `sorry`.
+    let attempts = out["attempts"].as_array().unwrap();
+    assert_eq!(attempts.len(), 2);
+    assert_eq!(attempts[0]["feedback"]["first_error_loc"]["line"], 2);
+    assert!(attempts[0]["feedback"]["diff"]
+        .as_str()
+        .unwrap()
+        .contains("+  exact bad"));
+    assert_eq!(attempts[1]["verify"]["ok"], true);
+    assert_eq!(out["final_lemma_contains_sorry"], false);
+    assert_eq!(out["conversation"]["attempts"], 2);
+
+    let reqs = server.requests();
+    let msgs = reqs[1].body["messages"].as_array().unwrap();
+    let roles: Vec<&str> = msgs.iter().map(|m| m["role"].as_str().unwrap()).collect();
+    assert_eq!(roles, vec!["system", "user", "assistant", "user"]);
+    assert_eq!(msgs[2]["content"], "exact bad");
+    let feedback = msgs[3]["content"].as_str().unwrap();
+    assert!(feedback.contains("unknown identifier 'bad'"), "{feedback}");
+    assert!(feedback.contains("```diff"), "{feedback}");
+}
+
+#[test]
+fn loop_stops_when_the_token_budget_is_spent() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let server = rt
+        .block_on(MockLlmServer::start(script(json!({
+            "rules": [{ "reply": { "content": "sorry", "usage": { "prompt_tokens": 40, "completion_tokens": 2 } } }]
+        }))))
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path(), &server.base_url);
+    let lake = fake_lake(dir.path());
+    let repo = dir.path().to_str().unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_proofpatch"))
+        .args([
+            "loop", "--repo", repo, "--file", "Foo.lean", "--lemma", "foo",
+        ])
+        .args(["--max-iters", "3", "--llm-max-tokens", "10"])
+        .env("HOME", dir.path())
+        .env("LAKE", &lake)
+        .env("PROOFPATCH_VERIFY_BACKEND", "lake")
+        .env("PROOFPATCH_AUTO_BUILD", "0")
+        .env("PROOFPATCH_DOTENV_SEARCH", "0")
+        .env("PROOFPATCH_PROVIDER_ORDER", "mock")
+        .env_remove("PROOFPATCH_LLM_CACHE")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let out: Value = serde_json::from_slice(&out.stdout).unwrap();
+
+    assert_eq!(out["attempts"].as_array().unwrap().len(), 1);
+    assert!(out["budget_stop"]
+        .as_str()
+        .unwrap()
+        .starts_with("llm budget exceeded"));
+    assert_eq!(out["llm_usage"]["refused_calls"], 1);
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn review_diff_sends_the_diff_and_parses_the_review() {
+    let review = json!({
+        "overall": { "score": 90, "verdict": "approve", "summary": "fine" },
+        "axes": [],
+        "top_issues": [],
+        "quick_wins": [],
+        "questions": [],
+        "confidence": 0.9
+    });
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let server = rt
+        .block_on(MockLlmServer::start(script(json!({
+            "rules": [{ "when": { "contains": "exact trivial" }, "reply": { "content": review.to_string() } }]
+        }))))
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path(), &server.base_url);
+    let git = |args: &[&str]| {
+        let ok = Command::new("git")
+            .args(args)
+            .current_dir(dir.path())
+            .env("HOME", dir.path())
+            .env("GIT_AUTHOR_NAME", "t")
+            .env("GIT_AUTHOR_EMAIL", "t@example.com")
+            .env("GIT_COMMITTER_NAME", "t")
+            .env("GIT_COMMITTER_EMAIL", "t@example.com")
+            .output()
+            .unwrap()
+            .status
+            .success();
+        assert!(ok, "git {args:?}");
+    };
+    git(&["init", "-q"]);
+    git(&["add", "-A"]);
+    git(&["commit", "-qm", "init"]);
+    std::fs::write(
+        dir.path().join("Foo.lean"),
+        "theorem foo : True := by\n  exact trivial\n",
+    )
+    .unwrap();
+
+    let repo = dir.path().to_str().unwrap();
+    let out = proofpatch(
+        dir.path(),
+        &[
+            "review-diff",
+            "--repo",
+            repo,
+            "--scope",
+            "worktree",
+            "--no-verify",
+        ],
+    );
+    assert_eq!(out["provider"], "mock");
+    assert_eq!(out["review_struct"]["overall"]["verdict"], "approve");
+    assert_eq!(out["llm_usage"]["calls"], 1);
+    let reqs = server.requests();
+    assert_eq!(reqs.len(), 1);
+    assert_eq!(reqs[0].rule, Some(0));
+}
+
+#[cfg(feature = "axi-agent")]
+#[test]
+fn agent_prove_patches_writes_and_saves_the_transcript() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let server = rt
+        .block_on(MockLlmServer::start(script(json!({
+            "rules": [
+                { "times": 1, "reply": { "tool_calls": [
+                    { "name": "try_tactics", "arguments": { "tactics": ["exact bad", "trivial"], "line": null } }
+                ] } },
+                { "reply": { "tool_calls": [
+                    { "name": "patch_decl", "arguments": { "replacement": "trivial", "apply": true } }
+                ] } }
+            ]
+        }))))
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path(), &server.base_url);
+    let lake = fake_lake(dir.path());
+    let repo = dir.path().to_str().unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_proofpatch"))
+        .args([
+            "agent-prove",
+            "--repo",
+            repo,
+            "--file",
+            "Foo.lean",
+            "--lemma",
+            "foo",
+        ])
+        .args(["--max-steps", "5", "--write", "--transcript", "agent.json"])
+        .env("HOME", dir.path())
+        .env("LAKE", &lake)
+        .env("PROOFPATCH_VERIFY_BACKEND", "lake")
+        .env("PROOFPATCH_AUTO_BUILD", "0")
+        .env("PROOFPATCH_DOTENV_SEARCH", "0")
+        .env("PROOFPATCH_PROVIDER_ORDER", "mock")
+        .env_remove("PROOFPATCH_LLM_CACHE")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let out: Value = serde_json::from_slice(&out.stdout).unwrap();
+
+    assert_eq!(out["solved"], true);
+    assert_eq!(out["stop_reason"], "solved");
+    assert_eq!(out["written"], true);
+    assert_eq!(out["steps"].as_array().unwrap().len(), 2);
+    let foo = std::fs::read_to_string(dir.path().join("Foo.lean")).unwrap();
+    assert!(foo.contains("trivial") && !foo.contains("sorry"), "{foo}");
+
+    let transcript: Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.path().join("agent.json")).unwrap())
+            .unwrap();
+    let roles: Vec<&str> = transcript["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["role"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        roles,
+        vec!["system", "user", "assistant", "tool", "assistant", "tool"]
+    );
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[cfg(feature = "planner")]
+#[test]
+fn planner_replay_miss_leaves_the_caches_unchanged() {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let server = rt
+        .block_on(MockLlmServer::start(script(json!({
+            "rules": [{ "reply": { "content": "{\"confidence\": 0.9, \"rationale\": \"live\"}" } }]
+        }))))
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path(), &server.base_url);
+    let lake = fake_lake(dir.path());
+    let repo = dir.path().to_str().unwrap();
+    let llm_cache = dir.path().join(".generated").join("proofpatch-llm-cache");
+    std::fs::create_dir_all(&llm_cache).unwrap();
+    std::fs::write(llm_cache.join("unrelated.json"), "{}").unwrap();
+    let listing = |p: &Path| -> Vec<String> {
+        let mut xs: Vec<String> = std::fs::read_dir(p)
+            .map(|rd| {
+                rd.flatten()
+                    .map(|e| e.file_name().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        xs.sort();
+        xs
+    };
+    let before = listing(&llm_cache);
+
+    let out = Command::new(env!("CARGO_BIN_EXE_proofpatch"))
+        .args(["tree-search-nearest", "--repo", repo, "--file", "Foo.lean"])
+        .args(["--llm-planner", "--llm-cache", "read-only", "--profile"])
+        .args(["--candidates", "lean-try", "--goal-first-k", "1"])
+        .args(["--max-nodes", "3"])
+        .env("HOME", dir.path())
+        .env("LAKE", &lake)
+        .env("PROOFPATCH_VERIFY_BACKEND", "lake")
+        .env("PROOFPATCH_AUTO_BUILD", "0")
+        .env("PROOFPATCH_DOTENV_SEARCH", "0")
+        .env("PROOFPATCH_PROVIDER_ORDER", "mock")
+        .env_remove("PROOFPATCH_LLM_CACHE")
+        .env_remove("PROOFPATCH_LLM_CACHE_DIR")
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let out: Value = serde_json::from_slice(&out.stdout).unwrap();
+
+    assert_eq!(out["profile"]["planner_cache_misses"], 1);
+    assert_eq!(listing(&llm_cache), before);
+    let planner_dir = dir
+        .path()
+        .join(".generated")
+        .join("proofpatch-cache")
+        .join("planner");
+    assert!(listing(&planner_dir).is_empty(), "{planner_dir:?}");
+    assert!(server.requests().is_empty());
+}
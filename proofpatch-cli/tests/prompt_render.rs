@@ -0,0 +1,74 @@
+//! `prompt render` against a repo with template overrides.
+
+use serde_json::Value;
+use std::process::Command;
+
+#[test]
+fn prompt_render_previews_overrides_and_variables() {
+    let dir = tempfile::tempdir().unwrap();
+    let d = dir.path();
+    std::fs::write(d.join("lean-toolchain"), "leanprover/lean4:v4.20.0\n").unwrap();
+    std::fs::write(d.join("lakefile.lean"), "import Lake\nopen Lake DSL\n").unwrap();
+    std::fs::write(
+        d.join("Foo.lean"),
+        "import Mathlib.Tactic\n\ntheorem bar : 1 = 1 := rfl\n\ntheorem foo : True := by\n  sorry\n",
+    )
+    .unwrap();
+    std::fs::write(
+        d.join("proofpatch.toml"),
+        "[prompts.templates]\nproof_user = \"{{excerpt}}{{#context_pack}}\\n---\\n{{context_pack}}{{/context_pack}}{{#hints}}\\nHints: {{hints}}{{/hints}}\"\n",
+    )
+    .unwrap();
+    let repo = d.to_str().unwrap();
+
+    let run = |extra: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_proofpatch"))
+            .args(["prompt", "render", "--repo", repo])
+            .args(extra)
+            .env("HOME", d)
+            .env("PROOFPATCH_DOTENV_SEARCH", "0")
+            .output()
+            .unwrap()
+    };
+    let out = run(&[
+        "--template",
+        "proof_user",
+        "--template",
+        "proof_system",
+        "--file",
+        "Foo.lean",
+        "--lemma",
+        "foo",
+        "--context-pack",
+        "--var",
+        "hints=try omega",
+    ]);
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let v: Value = serde_json::from_slice(&out.stdout).unwrap();
+    let user = &v["templates"][0];
+    assert_eq!(user["source"], "proofpatch.toml");
+    let text = user["text"].as_str().unwrap();
+    assert!(
+        text.starts_with("theorem foo : True := by\n  sorry\n---\n"),
+        "{text}"
+    );
+    assert!(text.contains("Mathlib.Tactic"), "{text}");
+    assert!(text.contains("theorem bar"), "{text}");
+    assert!(text.ends_with("\nHints: try omega"), "{text}");
+    assert_eq!(user["unset"].as_array().unwrap().len(), 0);
+    assert_eq!(v["templates"][1]["source"], "builtin");
+
+    // Broken overrides fail loudly here.
+    std::fs::write(
+        d.join("proofpatch.toml"),
+        "[prompts.templates]\nproof_user = \"{{#excerpt}}\"\n",
+    )
+    .unwrap();
+    let out = run(&[]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("never closed"));
+}
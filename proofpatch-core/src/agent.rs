@@ -0,0 +1,581 @@
+//! Headless tool-calling proof agent (`axi-agent` feature).
+//!
+//! The LLM works on one lemma in one file and drives proofpatch primitives through typed tools:
+//! - `locate_sorries`: holes in the working copy
+//! - `context_pack`: imports, a focused excerpt, and nearby declaration headers
+//! - `goal_dump`: goals and hypotheses at a `sorry` (via `pp_dump`)
+//! - `try_tactics`: check candidate tactics at a `sorry`, without keeping them
+//! - `patch_decl`: replace the lemma's first `sorry`, verify, and optionally keep the result
+//! - `verify`: verify the working copy
+//! - `search_decls`: search declaration headers across the repo's `.lean` files
+//!
+//! The file is patched in memory; it is written back only when the run solves the lemma and the
+//! caller asks for it. Every tool call counts against `max_steps`, and the run stops when the lemma
+//! is solved, the model answers without calling a tool, or a budget (steps, turns, LLM spend) is
+//! spent. The full transcript (messages and tool calls) is returned for saving as an artifact.
+
+use crate::{llm, llm_usage, VerifyResult};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Tool results are cut to this many chars before going back to the model.
+const MAX_TOOL_RESULT_CHARS: usize = 12_000;
+/// At most this many tactics per `try_tactics` call.
+const MAX_TACTICS_PER_CALL: usize = 8;
+
+fn default_context_lines() -> u64 {
+    8
+}
+fn default_nearby_lines() -> u64 {
+    120
+}
+fn default_max_nearby() -> u64 {
+    30
+}
+fn default_max_imports() -> u64 {
+    30
+}
+fn default_max_sorries() -> u64 {
+    30
+}
+fn default_sorry_context_lines() -> u64 {
+    1
+}
+fn default_patch_apply() -> bool {
+    true
+}
+fn default_max_results() -> u64 {
+    20
+}
+
+// OpenAI-strict schemas want every property listed in `required`. Optional fields are kept as
+// `Option` but marked `required` so the schema includes them, and `null` means unset.
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(deny_unknown_fields)]
+pub struct LocateSorriesArgs {
+    #[schemars(required)]
+    #[serde(default = "default_max_sorries")]
+    pub max_sorries: u64,
+    #[schemars(required)]
+    #[serde(default = "default_sorry_context_lines")]
+    pub context_lines: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(deny_unknown_fields)]
+pub struct ContextPackArgs {
+    #[schemars(required)]
+    #[serde(default)]
+    pub decl: Option<String>,
+    #[schemars(required)]
+    #[serde(default)]
+    pub line: Option<u64>,
+    #[schemars(required)]
+    #[serde(default = "default_context_lines")]
+    pub context_lines: u64,
+    #[schemars(required)]
+    #[serde(default = "default_nearby_lines")]
+    pub nearby_lines: u64,
+    #[schemars(required)]
+    #[serde(default = "default_max_nearby")]
+    pub max_nearby: u64,
+    #[schemars(required)]
+    #[serde(default = "default_max_imports")]
+    pub max_imports: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(deny_unknown_fields)]
+pub struct GoalDumpArgs {
+    /// 1-based line of (or near) one of the lemma's `sorry`s; `null` picks its first one.
+    #[schemars(required)]
+    #[serde(default)]
+    pub line: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(deny_unknown_fields)]
+pub struct TryTacticsArgs {
+    /// Candidates, each replacing the `sorry` on its own (at most 8 are tried).
+    pub tactics: Vec<String>,
+    /// 1-based line of (or near) one of the lemma's `sorry`s; `null` picks its first one.
+    #[schemars(required)]
+    #[serde(default)]
+    pub line: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(deny_unknown_fields)]
+pub struct PatchDeclArgs {
+    /// Replaces the first `sorry`/`admit` in the target lemma.
+    pub replacement: String,
+    /// Keep the patched text as the working copy when it verifies.
+    #[schemars(required)]
+    #[serde(default = "default_patch_apply")]
+    pub apply: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(deny_unknown_fields)]
+pub struct VerifyArgs {}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(deny_unknown_fields)]
+pub struct SearchDeclsArgs {
+    /// Case-insensitive substring of the declaration name or header.
+    pub query: String,
+    #[schemars(required)]
+    #[serde(default = "default_max_results")]
+    pub max_results: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AgentConfig {
+    pub repo_root: PathBuf,
+    /// Repo-relative `.lean` file.
+    pub file: String,
+    pub lemma: String,
+    /// Tool calls allowed over the whole run.
+    pub max_steps: usize,
+    /// LLM requests allowed over the whole run.
+    pub max_turns: usize,
+    pub llm_timeout: Duration,
+    /// Per Lean run (verify, goal dump, each tactic try).
+    pub tool_timeout: Duration,
+}
+
+/// One tool call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentStep {
+    pub step: usize,
+    pub turn: usize,
+    pub tool: String,
+    pub arguments: Value,
+    pub ok: bool,
+    pub result: Value,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentRun {
+    pub file: String,
+    pub lemma: String,
+    pub solved: bool,
+    /// `solved` | `final_answer` (the model stopped calling tools) | `max_steps` | `max_turns` |
+    /// `llm_budget` | `llm_error`
+    pub stop_reason: String,
+    pub turns: usize,
+    pub steps: Vec<AgentStep>,
+    /// Replacements kept by `patch_decl`, in order.
+    pub applied: Vec<String>,
+    /// The model's last text reply.
+    pub final_message: Option<String>,
+    pub error: Option<String>,
+    /// Working copy at the end of the run.
+    pub text: String,
+    pub changed: bool,
+    /// Full conversation (OpenAI-shaped messages).
+    pub messages: Vec<Value>,
+}
+
+fn schema_for<T: JsonSchema>() -> Value {
+    serde_json::to_value(schemars::schema_for!(T)).unwrap_or_else(|_| json!({"type": "object"}))
+}
+
+fn tool(name: &str, description: &str, parameters: Value) -> Value {
+    json!({
+        "type": "function",
+        "function": { "name": name, "description": description, "parameters": parameters }
+    })
+}
+
+/// OpenAI-shaped tool definitions offered to the model.
+pub fn tool_specs() -> Value {
+    json!([
+        tool(
+            "locate_sorries",
+            "List `sorry`/`admit` holes in the working copy of the file.",
+            schema_for::<LocateSorriesArgs>(),
+        ),
+        tool(
+            "context_pack",
+            "Imports, a focused excerpt (decl or line window), and nearby declaration headers.",
+            schema_for::<ContextPackArgs>(),
+        ),
+        tool(
+            "goal_dump",
+            "Goals and hypotheses at a `sorry` in the working copy.",
+            schema_for::<GoalDumpArgs>(),
+        ),
+        tool(
+            "try_tactics",
+            "Check each candidate tactic in place of a `sorry`; nothing is kept.",
+            schema_for::<TryTacticsArgs>(),
+        ),
+        tool(
+            "patch_decl",
+            "Replace the target lemma's first `sorry` and verify; with `apply`, keep it if it compiles.",
+            schema_for::<PatchDeclArgs>(),
+        ),
+        tool(
+            "verify",
+            "Compile the working copy and report errors.",
+            schema_for::<VerifyArgs>(),
+        ),
+        tool(
+            "search_decls",
+            "Search declaration headers (theorem/lemma/def/...) across the repo's .lean files.",
+            schema_for::<SearchDeclsArgs>(),
+        ),
+    ])
+}
+
+pub fn agent_system_prompt() -> String {
+    [
+        "You are a Lean 4/mathlib proof agent working on one lemma.",
+        "Use the tools to inspect goals, search for lemmas, and test tactics before committing.",
+        "Replace the lemma's `sorry` with `patch_decl` (apply = true) once a proof compiles.",
+        "Prefer small, checkable steps. Stop calling tools when the lemma is solved or you are stuck,",
+        "and reply with a short summary.",
+    ]
+    .join("\n")
+}
+
+fn agent_user_prompt(file: &str, lemma: &str, excerpt: &str) -> String {
+    format!("File: {file}\nLemma: {lemma}\n\nCurrent declaration:\n{excerpt}\n")
+}
+
+/// Compact verify report for tool results.
+fn verify_brief(v: &VerifyResult) -> Value {
+    let merged = format!("{}\n{}", v.stdout, v.stderr);
+    let errors: Vec<&str> = merged
+        .lines()
+        .filter(|l| l.contains(": error"))
+        .take(5)
+        .collect();
+    json!({
+        "ok": v.ok,
+        "timeout": v.timeout,
+        "errors": errors,
+        "sorry_warnings": merged.matches("declaration uses 'sorry'").count(),
+    })
+}
+
+fn truncate(v: Value) -> Value {
+    let s = v.to_string();
+    if s.chars().count() <= MAX_TOOL_RESULT_CHARS {
+        return v;
+    }
+    let cut: String = s.chars().take(MAX_TOOL_RESULT_CHARS).collect();
+    json!({ "truncated": true, "prefix": cut })
+}
+
+struct AgentState<'a> {
+    cfg: &'a AgentConfig,
+    text: String,
+    applied: Vec<String>,
+    solved: bool,
+}
+
+impl AgentState<'_> {
+    /// The target lemma's `sorry` region nearest `line` (default: its first one).
+    ///
+    /// Holes in other declarations are never picked, so `goal_dump`/`try_tactics` stay on the lemma.
+    fn hole(&self, line: Option<u64>) -> Result<crate::SorryLocation, String> {
+        let locs: Vec<crate::SorryLocation> = crate::locate_sorries_in_text(&self.text, 200, 1)?
+            .into_iter()
+            .filter(|s| s.decl_name.as_deref() == Some(self.cfg.lemma.as_str()))
+            .collect();
+        let pick = match line {
+            Some(l) => locs
+                .iter()
+                .min_by_key(|s| (s.line as i64 - l as i64).abs())
+                .cloned(),
+            None => locs.first().cloned(),
+        };
+        pick.ok_or_else(|| format!("no `sorry`/`admit` left in `{}`", self.cfg.lemma))
+    }
+
+    async fn call(&mut self, name: &str, args: &Value) -> Result<Value, String> {
+        fn parse<T: serde::de::DeserializeOwned>(args: &Value) -> Result<T, String> {
+            serde_json::from_value(args.clone()).map_err(|e| format!("invalid arguments: {e}"))
+        }
+        let cfg = self.cfg;
+        match name {
+            "locate_sorries" => {
+                let a: LocateSorriesArgs = parse(args)?;
+                let locs = crate::locate_sorries_in_text(
+                    &self.text,
+                    a.max_sorries as usize,
+                    a.context_lines as usize,
+                )?;
+                Ok(json!({ "sorries": locs }))
+            }
+            "context_pack" => {
+                let a: ContextPackArgs = parse(args)?;
+                // From the working copy, so kept patches show up.
+                let mut pack = crate::build_context_pack_in_text(
+                    &self.text,
+                    a.decl.as_deref(),
+                    a.line.map(|l| l as usize),
+                    a.context_lines as usize,
+                    a.nearby_lines as usize,
+                    a.max_nearby as usize,
+                    a.max_imports as usize,
+                )?;
+                pack.repo_root = cfg.repo_root.display().to_string();
+                pack.file_rel = cfg.file.clone();
+                pack.file_abs = cfg.repo_root.join(&cfg.file).display().to_string();
+                serde_json::to_value(pack).map_err(|e| e.to_string())
+            }
+            "goal_dump" => {
+                let a: GoalDumpArgs = parse(args)?;
+                let hole = self.hole(a.line)?;
+                let dump = crate::goal_dump_in_text_at(
+                    &cfg.repo_root,
+                    &cfg.file,
+                    &self.text,
+                    cfg.tool_timeout,
+                    Some(hole.line),
+                    None,
+                )
+                .await?;
+                Ok(json!({
+                    "sorry_line": hole.line,
+                    "pp_dump": dump.get("pp_dump").cloned().unwrap_or(Value::Null),
+                }))
+            }
+            "try_tactics" => {
+                let a: TryTacticsArgs = parse(args)?;
+                let hole = self.hole(a.line)?;
+                let mut results = Vec::new();
+                for tactic in a.tactics.iter().take(MAX_TACTICS_PER_CALL) {
+                    let patched = crate::patch_first_sorry_in_region(
+                        &self.text,
+                        hole.region_start,
+                        hole.region_end,
+                        tactic,
+                    );
+                    let r = match patched {
+                        Ok(p) => {
+                            match crate::verify_lean_text(&cfg.repo_root, &p.text, cfg.tool_timeout)
+                                .await
+                            {
+                                Ok(v) => json!({ "tactic": tactic, "verify": verify_brief(&v) }),
+                                Err(e) => json!({ "tactic": tactic, "error": e }),
+                            }
+                        }
+                        Err(e) => json!({ "tactic": tactic, "error": e }),
+                    };
+                    results.push(r);
+                }
+                Ok(json!({ "sorry_line": hole.line, "results": results }))
+            }
+            "patch_decl" => {
+                let a: PatchDeclArgs = parse(args)?;
+                let p = crate::patch_first_sorry_in_decl(&self.text, &cfg.lemma, &a.replacement)?;
+                let v = crate::verify_lean_text(&cfg.repo_root, &p.text, cfg.tool_timeout).await?;
+                let still_has_sorry =
+                    crate::decl_block_contains_sorry(&p.text, &cfg.lemma).unwrap_or(true);
+                let kept = a.apply && v.ok;
+                if kept {
+                    self.text = p.text.clone();
+                    self.applied.push(a.replacement.clone());
+                    self.solved = !still_has_sorry;
+                }
+                Ok(json!({
+                    "line": p.line,
+                    "after": p.after,
+                    "verify": verify_brief(&v),
+                    "lemma_still_contains_sorry": still_has_sorry,
+                    "kept": kept,
+                    "solved": kept && !still_has_sorry,
+                }))
+            }
+            "verify" => {
+                let _: VerifyArgs = parse(args)?;
+                let v =
+                    crate::verify_lean_text(&cfg.repo_root, &self.text, cfg.tool_timeout).await?;
+                Ok(verify_brief(&v))
+            }
+            "search_decls" => {
+                let a: SearchDeclsArgs = parse(args)?;
+                let q = a.query.trim().to_lowercase();
+                if q.is_empty() {
+                    return Err("empty query".to_string());
+                }
+                let mut hits = Vec::new();
+                'files: for rel in crate::axiom_audit::list_lean_files(&cfg.repo_root, ".")? {
+                    let text = if rel == cfg.file {
+                        self.text.clone()
+                    } else {
+                        std::fs::read_to_string(cfg.repo_root.join(&rel)).unwrap_or_default()
+                    };
+                    for d in crate::index_decls_in_text(&text) {
+                        if d.full_name.to_lowercase().contains(&q)
+                            || d.header.to_lowercase().contains(&q)
+                        {
+                            hits.push(json!({
+                                "file": rel,
+                                "line": d.line,
+                                "kind": d.kind,
+                                "name": d.full_name,
+                                "header": d.header.trim(),
+                            }));
+                            if hits.len() >= a.max_results as usize {
+                                break 'files;
+                            }
+                        }
+                    }
+                }
+                Ok(json!({ "matches": hits }))
+            }
+            other => Err(format!("unknown tool: {other}")),
+        }
+    }
+}
+
+/// Run the agent loop on `cfg.lemma`.
+pub async fn run_agent(cfg: &AgentConfig) -> Result<AgentRun, String> {
+    let p = cfg.repo_root.join(&cfg.file);
+    let original = std::fs::read_to_string(&p).map_err(|e| format!("read {}: {e}", p.display()))?;
+    let excerpt = crate::extract_decl_block(&original, &cfg.lemma)?;
+    let mut state = AgentState {
+        cfg,
+        text: original.clone(),
+        applied: Vec::new(),
+        solved: false,
+    };
+    let tools = tool_specs();
+    let mut messages = vec![
+        json!({ "role": "system", "content": agent_system_prompt() }),
+        json!({ "role": "user", "content": agent_user_prompt(&cfg.file, &cfg.lemma, &excerpt) }),
+    ];
+    let mut steps: Vec<AgentStep> = Vec::new();
+    let mut final_message = None;
+    let mut error = None;
+    let mut turns = 0usize;
+
+    let stop_reason = 'run: loop {
+        if state.solved {
+            break 'run "solved";
+        }
+        if steps.len() >= cfg.max_steps {
+            break 'run "max_steps";
+        }
+        if turns >= cfg.max_turns {
+            break 'run "max_turns";
+        }
+        turns += 1;
+        let raw =
+            match llm::chat_completion_raw(&messages, Some(&tools), None, cfg.llm_timeout).await {
+                Ok(r) => r,
+                Err(e) => {
+                    let reason = if e.starts_with(llm_usage::BUDGET_EXCEEDED) {
+                        "llm_budget"
+                    } else {
+                        "llm_error"
+                    };
+                    error = Some(e);
+                    break 'run reason;
+                }
+            };
+        let msg = raw
+            .get("choices")
+            .and_then(|c| c.get(0))
+            .and_then(|c| c.get("message"))
+            .cloned()
+            .unwrap_or_else(|| json!({}));
+        let content = msg.get("content").and_then(|c| c.as_str()).unwrap_or("");
+        if !content.trim().is_empty() {
+            final_message = Some(content.to_string());
+        }
+        let calls: Vec<Value> = msg
+            .get("tool_calls")
+            .and_then(|t| t.as_array())
+            .cloned()
+            .unwrap_or_default();
+        let mut assistant = json!({ "role": "assistant", "content": content });
+        if !calls.is_empty() {
+            assistant["tool_calls"] = Value::Array(calls.clone());
+        }
+        messages.push(assistant);
+        if calls.is_empty() {
+            break 'run "final_answer";
+        }
+
+        for call in &calls {
+            let id = call
+                .get("id")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            let f = call.get("function").cloned().unwrap_or_else(|| json!({}));
+            let name = f
+                .get("name")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string();
+            let arguments: Value = match f.get("arguments") {
+                Some(Value::String(s)) if s.trim().is_empty() => json!({}),
+                Some(Value::String(s)) => serde_json::from_str(s).unwrap_or(json!({ "_raw": s })),
+                Some(v) => v.clone(),
+                None => json!({}),
+            };
+            // Every call gets an answer (providers reject dangling tool calls), even past the budget.
+            let result = if state.solved {
+                json!({ "error": "skipped: the lemma is already solved" })
+            } else if steps.len() >= cfg.max_steps {
+                json!({ "error": "skipped: step budget exhausted" })
+            } else {
+                let started = Instant::now();
+                let (ok, result) = match state.call(&name, &arguments).await {
+                    Ok(v) => (true, truncate(v)),
+                    Err(e) => (false, json!({ "error": e })),
+                };
+                steps.push(AgentStep {
+                    step: steps.len() + 1,
+                    turn: turns,
+                    tool: name.clone(),
+                    arguments,
+                    ok,
+                    result: result.clone(),
+                    elapsed_ms: started.elapsed().as_millis() as u64,
+                });
+                result
+            };
+            messages.push(json!({
+                "role": "tool",
+                "tool_call_id": id,
+                "name": name,
+                "content": result.to_string(),
+            }));
+        }
+    };
+
+    Ok(AgentRun {
+        file: cfg.file.clone(),
+        lemma: cfg.lemma.clone(),
+        solved: state.solved,
+        stop_reason: stop_reason.to_string(),
+        turns,
+        steps,
+        applied: state.applied,
+        final_message,
+        error,
+        changed: state.text != original,
+        text: state.text,
+        messages,
+    })
+}
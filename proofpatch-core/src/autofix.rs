@@ -0,0 +1,416 @@
+//! Mechanical fixes shared by the CLI and the MCP server.
+//!
+//! Two kinds of fixes live here:
+//!
+//! - lint fixes for the native `lint::RULES` (trailing whitespace, `fun x =>`, `$`, long lines,
+//!   copyright headers), driven by `lint::lint_text`
+//! - first-error fixes (`apply_mechanical_fixes_for_first_error`), driven by a Lean error message
+//!
+//! Everything here is text-only: nothing is verified. Callers that care should re-run
+//! `verify_lean_text` on the result.
+
+use crate::config::LintConfig;
+use crate::lint::{self, LintFinding};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use similar::TextDiff;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedFix {
+    /// 1-based line in the original text.
+    pub line: usize,
+    pub rule: String,
+    pub before: String,
+    pub after: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FixMode {
+    /// Report findings only.
+    Check,
+    /// Compute fixes and a diff, but leave the file alone.
+    DryRun,
+    /// Compute fixes and write the file.
+    Write,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LintFileReport {
+    pub file: String,
+    pub findings: Vec<LintFinding>,
+    pub fixes: Vec<AppliedFix>,
+    /// Findings left after fixing (equal to `findings` in check mode).
+    pub remaining: Vec<LintFinding>,
+    pub written: bool,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub diff: String,
+    #[serde(skip)]
+    pub original_text: String,
+    #[serde(skip)]
+    pub fixed_text: String,
+}
+
+/// Operators (and `,`) a code line may be broken after.
+const BREAK_AFTER: &[&str] = &[
+    ":=", "=", "≠", "→", "↔", "∧", "∨", "<|", "|>", "+", "-", "*", "•", "∘", "≤", "<", "≥", ">",
+    "∣", "∈", "∉", "⊆", "×", "^",
+];
+
+fn wrap_once(chars: &[char], mask: &[bool], max: usize) -> Option<(String, Vec<char>, Vec<bool>)> {
+    let indent = chars.iter().take_while(|c| c.is_whitespace()).count();
+    let head: String = chars.iter().collect();
+    if head.trim_start().starts_with("import ") {
+        return None;
+    }
+    let is_code = |i: usize| mask.get(i).copied().unwrap_or(false);
+    let comment_only = (0..chars.len()).all(|i| !is_code(i) || chars[i].is_whitespace());
+    let line_comment = comment_only && head.trim_start().starts_with("--");
+    let min = if line_comment { indent + 3 } else { indent + 1 };
+
+    let mut best: Option<usize> = None;
+    for p in (min..chars.len().min(max + 1)).rev() {
+        if chars[p] != ' ' {
+            continue;
+        }
+        let rest_empty = chars[p..].iter().all(|c| c.is_whitespace());
+        if rest_empty || chars[p - 1] == ' ' {
+            continue;
+        }
+        if comment_only {
+            best = Some(p);
+            break;
+        }
+        if !is_code(p) {
+            continue;
+        }
+        let tok_start = chars[..p]
+            .iter()
+            .rposition(|c| c.is_whitespace())
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let tok: String = chars[tok_start..p].iter().collect();
+        if tok.ends_with(',') || BREAK_AFTER.contains(&tok.as_str()) {
+            best = Some(p);
+            break;
+        }
+    }
+    let p = best?;
+    let first: String = chars[..p].iter().collect::<String>().trim_end().to_string();
+    let rest_start = p + chars[p..].iter().take_while(|c| c.is_whitespace()).count();
+    let (prefix, prefix_is_code): (String, bool) = if line_comment {
+        (format!("{}-- ", " ".repeat(indent)), false)
+    } else if comment_only {
+        (" ".repeat(indent), false)
+    } else {
+        (" ".repeat(indent + 4), true)
+    };
+    let mut rest: Vec<char> = prefix.chars().collect();
+    let mut rest_mask: Vec<bool> = vec![prefix_is_code; rest.len()];
+    rest.extend_from_slice(&chars[rest_start..]);
+    rest_mask.extend((rest_start..chars.len()).map(is_code));
+    Some((first, rest, rest_mask))
+}
+
+/// Break an over-long line into lines of at most `max` chars, or `None` if there is no safe
+/// break point.
+///
+/// Code lines are only broken after a `,` or a binary operator, with the continuation indented
+/// four columns deeper. Comment lines are re-wrapped at any space. Imports are left alone.
+pub fn wrap_long_line(line: &str, mask: &[bool], max: usize) -> Option<Vec<String>> {
+    let mut chars: Vec<char> = line.trim_end().chars().collect();
+    let mut mask: Vec<bool> = mask.iter().copied().take(chars.len()).collect();
+    let mut out: Vec<String> = Vec::new();
+    for _ in 0..16 {
+        if chars.len() <= max {
+            out.push(chars.iter().collect());
+            return Some(out);
+        }
+        let (first, rest, rest_mask) = wrap_once(&chars, &mask, max)?;
+        out.push(first);
+        chars = rest;
+        mask = rest_mask;
+    }
+    None
+}
+
+fn replace_cols(line: &str, cols: &[usize], width: usize, with: &str) -> String {
+    let mut chars: Vec<char> = line.chars().collect();
+    for &c in cols.iter().rev() {
+        if c + width <= chars.len() {
+            chars.splice(c..c + width, with.chars());
+        }
+    }
+    chars.into_iter().collect()
+}
+
+/// Apply fixes for `rules` (a subset of `lint::RULES`) to `text`.
+pub fn apply_lint_fixes(text: &str, rules: &[&str], cfg: &LintConfig) -> (String, Vec<AppliedFix>) {
+    let on = |r: &str| rules.contains(&r);
+    let mut lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
+    let mut fixes: Vec<AppliedFix> = Vec::new();
+    let mut record = |line0: usize, rule: &str, before: &str, after: &str| {
+        if before != after {
+            fixes.push(AppliedFix {
+                line: line0 + 1,
+                rule: rule.to_string(),
+                before: before.to_string(),
+                after: after.to_string(),
+            });
+        }
+    };
+
+    // Same-line rewrites (line count unchanged).
+    if on("trailing_whitespace") {
+        for (i0, ln) in lines.iter_mut().enumerate() {
+            let after = ln.trim_end().to_string();
+            record(i0, "trailing_whitespace", ln, &after);
+            *ln = after;
+        }
+    }
+    for (rule, width, with) in [("fun_arrow", 2, "↦"), ("dollar", 1, "<|")] {
+        if !on(rule) {
+            continue;
+        }
+        let refs: Vec<&str> = lines.iter().map(|s| s.as_str()).collect();
+        let masks = lint::code_masks(&refs);
+        let mut next: Vec<String> = Vec::with_capacity(lines.len());
+        for (i0, ln) in refs.iter().enumerate() {
+            let chars: Vec<char> = ln.chars().collect();
+            let cols = if rule == "fun_arrow" {
+                lint::fun_arrow_cols(&chars, &masks[i0])
+            } else {
+                lint::dollar_cols(&chars, &masks[i0])
+            };
+            let after = replace_cols(ln, &cols, width, with);
+            record(i0, rule, ln, &after);
+            next.push(after);
+        }
+        lines = next;
+    }
+
+    // Wrapping inserts lines; go bottom-up so earlier line numbers stay put.
+    if on("long_line") {
+        let refs: Vec<&str> = lines.iter().map(|s| s.as_str()).collect();
+        let masks = lint::code_masks(&refs);
+        let mut wraps: Vec<(usize, Vec<String>)> = Vec::new();
+        for (i0, ln) in refs.iter().enumerate() {
+            if ln.trim_end().chars().count() <= cfg.max_line_length
+                || ln.contains("http://")
+                || ln.contains("https://")
+            {
+                continue;
+            }
+            if let Some(parts) = wrap_long_line(ln, &masks[i0], cfg.max_line_length) {
+                wraps.push((i0, parts));
+            }
+        }
+        for (i0, parts) in wraps.into_iter().rev() {
+            record(i0, "long_line", &lines[i0], &parts.join("\n"));
+            lines.splice(i0..=i0, parts);
+        }
+    }
+
+    let mut out = lines.join("\n");
+    if text.ends_with('\n') {
+        out.push('\n');
+    }
+    if on("copyright") && !lint::has_copyright_header(&out) {
+        if let Some(h) = cfg.copyright_header.as_deref() {
+            let h = h.trim_end();
+            record(0, "copyright", "", h);
+            out = format!("{h}\n{out}");
+        }
+    }
+    fixes.sort_by_key(|f| f.line);
+    (out, fixes)
+}
+
+/// Lint one file (native checks plus any `external` findings, e.g. parsed `lint-style` output)
+/// and optionally fix what is mechanically fixable.
+///
+/// `file_rel` must resolve (after following `..` and symlinks) to a file under `repo_root`;
+/// anything else is refused before it is read or written.
+pub fn lint_file(
+    repo_root: &Path,
+    file_rel: &str,
+    cfg: &LintConfig,
+    external: &[LintFinding],
+    mode: FixMode,
+) -> Result<LintFileReport, String> {
+    let p = repo_root.join(file_rel);
+    if !p.exists() {
+        return Err(format!("File not found: {}", p.display()));
+    }
+    let root = repo_root
+        .canonicalize()
+        .map_err(|e| format!("failed to resolve repo root {}: {e}", repo_root.display()))?;
+    let p = p
+        .canonicalize()
+        .map_err(|e| format!("failed to resolve {}: {e}", p.display()))?;
+    if !p.starts_with(&root) {
+        return Err(format!(
+            "Refusing to lint {}: not under repo root {}",
+            p.display(),
+            root.display()
+        ));
+    }
+    let text = std::fs::read_to_string(&p).map_err(|e| format!("read {}: {e}", p.display()))?;
+    let native = lint::lint_text(file_rel, &text, cfg);
+
+    // External findings are fixable when the native checker flags the same thing as fixable;
+    // duplicates of native findings are dropped.
+    let mut findings = native.clone();
+    let mut external_left: Vec<LintFinding> = Vec::new();
+    for f in external {
+        if native
+            .iter()
+            .any(|n| n.rule == f.rule && (n.line == f.line || f.rule == "copyright"))
+        {
+            continue;
+        }
+        let mut f = f.clone();
+        f.fixable = false;
+        external_left.push(f.clone());
+        findings.push(f);
+    }
+    findings.sort_by(|a, b| a.line.cmp(&b.line).then(a.col.cmp(&b.col)));
+
+    if mode == FixMode::Check {
+        return Ok(LintFileReport {
+            file: file_rel.to_string(),
+            remaining: findings.clone(),
+            findings,
+            fixes: Vec::new(),
+            written: false,
+            diff: String::new(),
+            fixed_text: text.clone(),
+            original_text: text,
+        });
+    }
+
+    let rules: BTreeSet<&str> = findings
+        .iter()
+        .filter(|f| f.fixable)
+        .map(|f| f.rule.as_str())
+        .collect();
+    let rules: Vec<&str> = rules.into_iter().collect();
+    let (fixed, fixes) = apply_lint_fixes(&text, &rules, cfg);
+    let mut remaining = lint::lint_text(file_rel, &fixed, cfg);
+    remaining.extend(external_left);
+    remaining.sort_by(|a, b| a.line.cmp(&b.line).then(a.col.cmp(&b.col)));
+
+    let diff = if fixed == text {
+        String::new()
+    } else {
+        TextDiff::from_lines(&text, &fixed)
+            .unified_diff()
+            .context_radius(3)
+            .header(file_rel, file_rel)
+            .to_string()
+    };
+    let written = mode == FixMode::Write && fixed != text;
+    if written {
+        std::fs::write(&p, &fixed).map_err(|e| format!("write {}: {e}", p.display()))?;
+    }
+    Ok(LintFileReport {
+        file: file_rel.to_string(),
+        findings,
+        fixes,
+        remaining,
+        written,
+        diff,
+        original_text: text,
+        fixed_text: fixed,
+    })
+}
+
+/// Cheap, local fixes suggested by the first Lean error (used by `agent-step` loops).
+///
+/// Returns the patched text and a list of edit records (`kind`, `line`, `before`, `after`, `note`).
+pub fn apply_mechanical_fixes_for_first_error(
+    text: &str,
+    first_error_line_1: Option<usize>,
+    first_error_text: Option<&str>,
+) -> (String, Vec<Value>) {
+    let Some(line1) = first_error_line_1 else {
+        return (text.to_string(), vec![]);
+    };
+    let Some(msg) = first_error_text else {
+        return (text.to_string(), vec![]);
+    };
+
+    let mut lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
+    if lines.is_empty() || line1 == 0 {
+        return (text.to_string(), vec![]);
+    }
+
+    let mut edits = Vec::new();
+    let start0 = line1.saturating_sub(1).saturating_sub(40);
+    let end0 = usize::min(lines.len().saturating_sub(1), (line1 - 1) + 60);
+
+    // Heuristic 1: if Lean suggests `ring_nf`, replace a nearby `ring` with `ring_nf`.
+    if msg.contains("ring_nf") {
+        for (i0, ln) in lines.iter_mut().enumerate().take(end0 + 1).skip(start0) {
+            if ln.trim() == "ring" {
+                let before = ln.clone();
+                let indent: String = ln.chars().take_while(|c| c.is_whitespace()).collect();
+                *ln = format!("{indent}ring_nf");
+                edits.push(json!({
+                    "kind": "replace_tactic",
+                    "line": i0 + 1,
+                    "before": before,
+                    "after": ln.as_str(),
+                    "note": "Lean suggested ring_nf; replaced nearby `ring` with `ring_nf`.",
+                }));
+                break;
+            }
+        }
+    }
+
+    // Heuristic 2: if the first error is a missing `Decidable` and we have a nearby `decide`,
+    // insert `classical` before `decide`.
+    //
+    // This is common in mathlib code where a `decide` proof works under classical but Lean
+    // cannot synthesize an instance automatically.
+    if msg.contains("failed to synthesize") && msg.contains("Decidable") {
+        for i0 in start0..=end0.min(lines.len().saturating_sub(1)) {
+            let ln = &lines[i0];
+            if ln.trim() != "decide" {
+                continue;
+            }
+
+            // Avoid duplicating `classical` if it's already in the local tactic block.
+            let scan0 = i0.saturating_sub(4);
+            let already_classical = (scan0..=i0).any(|j0| {
+                lines
+                    .get(j0)
+                    .map(|s| s.trim() == "classical")
+                    .unwrap_or(false)
+            });
+            if already_classical {
+                continue;
+            }
+
+            let before = ln.clone();
+            let indent: String = ln.chars().take_while(|c| c.is_whitespace()).collect();
+            let replacement_lines = vec![format!("{indent}classical"), format!("{indent}decide")];
+            lines.splice(i0..=i0, replacement_lines.clone());
+            edits.push(json!({
+                "kind": "insert_classical_before_decide",
+                "line": i0 + 1,
+                "before": before,
+                "after": replacement_lines.join("\n"),
+                "note": "First error looks like missing Decidable; inserted `classical` before a nearby `decide`.",
+            }));
+            break;
+        }
+    }
+
+    let mut out = lines.join("\n");
+    if text.ends_with('\n') {
+        out.push('\n');
+    }
+    (out, edits)
+}
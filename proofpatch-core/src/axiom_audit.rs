@@ -0,0 +1,205 @@
+//! Axiom audits via `#print axioms`.
+//!
+//! A file can verify cleanly while a theorem still depends on `sorryAx` (e.g. through an
+//! admitted upstream lemma), on `Classical.choice` in a constructive development, or on a
+//! custom `axiom`. This module:
+//! - builds `#print axioms` queries for the declarations in a file (via `index_decls_in_text`)
+//! - runs them through the verifier (appended to the real file text)
+//! - parses the reported axiom sets and checks them against an allowlist
+
+use crate::config::AxiomsConfig;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeclAxioms {
+    pub name: String,
+    pub kind: String,
+    pub line: usize,
+    /// `ok` | `violation` | `unresolved` (no `#print axioms` output was found for the decl).
+    pub status: String,
+    pub axioms: Vec<String>,
+    /// Axioms not covered by the allowlist.
+    pub violations: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileAxiomAudit {
+    pub file: String,
+    pub verify_ok: bool,
+    pub timeout: bool,
+    pub decls: Vec<DeclAxioms>,
+    pub violations: usize,
+    pub unresolved: usize,
+}
+
+/// Whether a declaration of this kind should be audited.
+///
+/// Theorems/lemmas are always audited; `def`/`abbrev`/`instance` only when requested
+/// (data-carrying decls routinely use `Classical.choice` through `noncomputable`).
+fn audited_kind(kind: &str, include_defs: bool) -> bool {
+    matches!(kind, "theorem" | "lemma") || include_defs
+}
+
+/// Build the `#print axioms` block appended to a file for the given declaration names.
+pub fn print_axioms_queries(full_names: &[String]) -> String {
+    let mut out = String::new();
+    out.push_str("\n\n-- proofpatch: axiom audit\n");
+    for n in full_names {
+        out.push_str(&format!("#print axioms {n}\n"));
+    }
+    out
+}
+
+/// Parse `#print axioms` output (best-effort).
+///
+/// Recognized shapes (Lean 4):
+/// - `'Foo.bar' depends on axioms: [propext, Classical.choice, Quot.sound]`
+/// - `'Foo.bar' does not depend on any axioms`
+///
+/// Long axiom lists may be wrapped across lines by Lean's formatter; we accept that.
+pub fn parse_print_axioms_output(text: &str) -> BTreeMap<String, Vec<String>> {
+    let mut out: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    if let Ok(re) = Regex::new(r"'([^\n]+?)' depends on axioms: \[([^\]]*)\]") {
+        for cap in re.captures_iter(text) {
+            let name = normalize_printed_name(cap.get(1).map(|m| m.as_str()).unwrap_or(""));
+            let axioms: Vec<String> = cap
+                .get(2)
+                .map(|m| m.as_str())
+                .unwrap_or("")
+                .split(',')
+                .map(|s| normalize_printed_name(s.trim()))
+                .filter(|s| !s.is_empty())
+                .collect();
+            out.insert(name, axioms);
+        }
+    }
+    if let Ok(re) = Regex::new(r"'([^\n]+?)' does not depend on any axioms") {
+        for cap in re.captures_iter(text) {
+            let name = normalize_printed_name(cap.get(1).map(|m| m.as_str()).unwrap_or(""));
+            out.entry(name).or_default();
+        }
+    }
+    out
+}
+
+fn normalize_printed_name(s: &str) -> String {
+    // Lean escapes non-standard identifiers with «»; we compare on the plain spelling.
+    s.trim().replace(['«', '»'], "")
+}
+
+/// Audit the declarations of `file_rel` against `cfg.allowed`.
+///
+/// This verifies the *real* file text plus an appended `#print axioms` block, so a single Lean
+/// run covers every declaration in the file.
+pub async fn audit_axioms_in_file(
+    repo_root: &Path,
+    file_rel: &str,
+    cfg: &AxiomsConfig,
+    include_defs: bool,
+    timeout_s: Duration,
+) -> Result<FileAxiomAudit, String> {
+    let p = repo_root.join(file_rel);
+    if !p.exists() {
+        return Err(format!("File not found: {}", p.display()));
+    }
+    let text = std::fs::read_to_string(&p).map_err(|e| format!("read {}: {e}", p.display()))?;
+    let decls: Vec<crate::IndexedDecl> = crate::index_decls_in_text(&text)
+        .into_iter()
+        .filter(|d| audited_kind(&d.kind, include_defs))
+        .filter(|d| !cfg.ignore_decls.iter().any(|x| x == &d.full_name))
+        .collect();
+    if decls.is_empty() {
+        return Ok(FileAxiomAudit {
+            file: file_rel.to_string(),
+            verify_ok: true,
+            timeout: false,
+            decls: Vec::new(),
+            violations: 0,
+            unresolved: 0,
+        });
+    }
+
+    let names: Vec<String> = decls.iter().map(|d| d.full_name.clone()).collect();
+    let mut lean_text = text.clone();
+    lean_text.push_str(&print_axioms_queries(&names));
+    let raw = crate::verify_lean_text(repo_root, &lean_text, timeout_s).await?;
+    let printed = parse_print_axioms_output(&format!("{}\n{}", raw.stdout, raw.stderr));
+
+    let mut out_decls: Vec<DeclAxioms> = Vec::new();
+    let mut violations = 0usize;
+    let mut unresolved = 0usize;
+    for d in decls {
+        let (status, axioms, bad) = match printed.get(&normalize_printed_name(&d.full_name)) {
+            Some(axs) => {
+                let bad: Vec<String> = axs
+                    .iter()
+                    .filter(|a| !cfg.allowed.iter().any(|ok| ok == *a))
+                    .cloned()
+                    .collect();
+                let status = if bad.is_empty() { "ok" } else { "violation" };
+                (status, axs.clone(), bad)
+            }
+            None => ("unresolved", Vec::new(), Vec::new()),
+        };
+        match status {
+            "violation" => violations += 1,
+            "unresolved" => unresolved += 1,
+            _ => {}
+        }
+        out_decls.push(DeclAxioms {
+            name: d.full_name,
+            kind: d.kind,
+            line: d.line,
+            status: status.to_string(),
+            axioms,
+            violations: bad,
+        });
+    }
+
+    Ok(FileAxiomAudit {
+        file: file_rel.to_string(),
+        verify_ok: raw.ok,
+        timeout: raw.timeout,
+        decls: out_decls,
+        violations,
+        unresolved,
+    })
+}
+
+/// List `.lean` files under `dir_rel` (relative to `repo_root`), sorted, skipping `.lake`.
+pub fn list_lean_files(repo_root: &Path, dir_rel: &str) -> Result<Vec<String>, String> {
+    fn walk(root: &Path, dir: &Path, out: &mut Vec<String>) -> Result<(), String> {
+        let entries =
+            std::fs::read_dir(dir).map_err(|e| format!("read_dir {}: {e}", dir.display()))?;
+        for ent in entries.flatten() {
+            let p = ent.path();
+            let fname = ent.file_name().to_string_lossy().to_string();
+            if p.is_dir() {
+                if fname.starts_with('.') {
+                    continue;
+                }
+                walk(root, &p, out)?;
+            } else if fname.ends_with(".lean") {
+                if let Ok(rel) = p.strip_prefix(root) {
+                    out.push(rel.display().to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+    let dir = repo_root.join(dir_rel);
+    if dir.is_file() {
+        return Ok(vec![dir_rel.to_string()]);
+    }
+    if !dir.is_dir() {
+        return Err(format!("Directory not found: {}", dir.display()));
+    }
+    let mut out = Vec::new();
+    walk(repo_root, &dir, &mut out)?;
+    out.sort();
+    Ok(out)
+}
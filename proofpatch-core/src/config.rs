@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -9,6 +9,187 @@ pub struct ProofpatchConfig {
     pub research: ResearchConfig,
     #[serde(default)]
     pub hints: HintsConfig,
+    #[serde(default)]
+    pub axioms: AxiomsConfig,
+    #[serde(default)]
+    pub lint: LintConfig,
+    #[serde(default)]
+    pub llm: LlmConfig,
+    #[serde(default)]
+    pub prompts: PromptsConfig,
+}
+
+/// `[prompts]`: per-project prompt templates (see `prompts` for names and variables).
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PromptsConfig {
+    /// Directory of `<name>.txt` templates, relative to the repo root
+    /// (default: `.proofpatch/prompts`).
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// `[prompts.templates]`: inline templates by name; these win over files in `dir`.
+    #[serde(default)]
+    pub templates: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LlmConfig {
+    /// Provider names to try, in order (`PROOFPATCH_PROVIDER_ORDER` still wins).
+    ///
+    /// Default: providers defined only here (alphabetically), then the built-ins
+    /// `ollama, groq, openai, openrouter`.
+    #[serde(default)]
+    pub provider_order: Option<Vec<String>>,
+    /// `[llm.providers.<name>]`: new OpenAI-compatible endpoints (vLLM, llama.cpp, ...), or
+    /// overrides for a built-in provider of the same name.
+    #[serde(default)]
+    pub providers: BTreeMap<String, LlmProviderConfig>,
+    /// `[llm.prices."<model>"]` (or `"<provider>/<model>"`): used to turn token counts into cost.
+    #[serde(default)]
+    pub prices: BTreeMap<String, ModelPrice>,
+    /// `[llm.budget]`: run-level limits (`--llm-max-tokens` / `--llm-max-cost` win).
+    #[serde(default)]
+    pub budget: LlmBudget,
+}
+
+/// USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPrice {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LlmBudget {
+    /// Total (prompt + completion) tokens across all LLM calls in one run.
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    /// Total cost in USD. While it is set, calls to models without a `[llm.prices]` entry are
+    /// refused, since their cost is unknown.
+    #[serde(default)]
+    pub max_cost_usd: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WireFormat {
+    /// OpenAI-compatible `POST <base_url>/chat/completions`.
+    #[default]
+    #[serde(rename = "openai")]
+    OpenAi,
+    /// Anthropic Messages API: `POST <base_url>/messages`.
+    Anthropic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct LlmProviderConfig {
+    /// Set to `false` to remove a provider (including a built-in) from selection.
+    #[serde(default)]
+    pub enabled: Option<bool>,
+    /// Request/response shape (default: `openai`; the built-in `anthropic` provider uses
+    /// `anthropic`).
+    #[serde(default)]
+    pub wire_format: Option<WireFormat>,
+    /// Base URL up to (not including) `/chat/completions` (or `/messages`),
+    /// e.g. `http://localhost:8000/v1`.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Env var that overrides `base_url` when set.
+    #[serde(default)]
+    pub base_url_env: Option<String>,
+    /// Env var holding the API key. Unset means the endpoint needs no key.
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// Model to use when `model_env` is unset.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Env var that overrides `model` (default: `<NAME>_MODEL`).
+    #[serde(default)]
+    pub model_env: Option<String>,
+    /// Extra request headers (literal values).
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Extra request headers whose values come from env vars (`header = "ENV_VAR"`).
+    #[serde(default)]
+    pub headers_env: BTreeMap<String, String>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    /// Request timeout; overrides the caller's timeout (useful for slow local models).
+    #[serde(default)]
+    pub timeout_s: Option<u64>,
+    #[serde(default)]
+    pub connect_timeout_s: Option<u64>,
+    /// Check that the endpoint answers before selecting it (default: true for providers without
+    /// an API key, i.e. local servers).
+    #[serde(default)]
+    pub probe: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LintConfig {
+    /// Native rules checked by `lint-style`, from `lint::RULES` (default: `lint::DEFAULT_RULES`).
+    #[serde(default)]
+    pub rules: Option<Vec<String>>,
+    /// Maximum line length in Unicode scalar values (Mathlib uses 100).
+    #[serde(default = "default_max_line_length")]
+    pub max_line_length: usize,
+    /// Header inserted by the `copyright` fix, verbatim.
+    ///
+    /// Without it, missing headers are still reported but are not fixable.
+    #[serde(default)]
+    pub copyright_header: Option<String>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            rules: None,
+            max_line_length: default_max_line_length(),
+            copyright_header: None,
+        }
+    }
+}
+
+fn default_max_line_length() -> usize {
+    100
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AxiomsConfig {
+    /// Axioms a declaration may depend on without being reported (consumed by `audit-axioms`).
+    ///
+    /// Defaults to Lean's standard classical trio. Constructive developments can drop
+    /// `Classical.choice` here. `sorryAx` is only allowed if listed explicitly.
+    #[serde(default = "default_allowed_axioms")]
+    pub allowed: Vec<String>,
+    /// Fully qualified declaration names to skip entirely.
+    #[serde(default)]
+    pub ignore_decls: Vec<String>,
+}
+
+impl Default for AxiomsConfig {
+    fn default() -> Self {
+        Self {
+            allowed: default_allowed_axioms(),
+            ignore_decls: Vec::new(),
+        }
+    }
+}
+
+fn default_allowed_axioms() -> Vec<String> {
+    vec![
+        "propext".to_string(),
+        "Classical.choice".to_string(),
+        "Quot.sound".to_string(),
+    ]
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
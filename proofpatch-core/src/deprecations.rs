@@ -0,0 +1,340 @@
+//! Deprecation migration (`migrate-deprecations`).
+//!
+//! After a Mathlib bump, Lean reports uses of deprecated lemmas as positioned warnings that usually
+//! name the replacement:
+//!
+//! - ``path:12:8: warning: `Nat.pos_of_ne_zero` has been deprecated: use `Nat.pos_iff_ne_zero` instead``
+//! - ``path:12:8: warning: `Foo.bar` has been deprecated, use `Foo.baz` instead`` (older spelling)
+//!
+//! This module collects those warnings, rewrites the identifier at each reported position (keeping
+//! the spelling relative to opened namespaces / dot notation), and re-verifies per declaration so
+//! only edits that still check are kept. Everything else is reported for manual work.
+
+use crate::IndexedDecl;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeprecationWarning {
+    pub line: usize,
+    /// 0-based column (Unicode scalar values), as reported by Lean.
+    pub col: usize,
+    pub old: String,
+    pub replacement: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenameEdit {
+    pub line: usize,
+    pub col: usize,
+    /// The identifier as written in the source.
+    pub before: String,
+    pub after: String,
+    pub old: String,
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManualItem {
+    pub line: usize,
+    pub col: usize,
+    pub old: String,
+    pub replacement: Option<String>,
+    pub decl: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeprecationMigration {
+    pub file: String,
+    pub warnings: usize,
+    pub applied: Vec<RenameEdit>,
+    pub manual: Vec<ManualItem>,
+    /// Decls whose edits were verified (or all decls with edits when verification was skipped).
+    pub verified_decls: Vec<String>,
+    pub verified: bool,
+    #[serde(skip)]
+    pub original_text: String,
+    #[serde(skip)]
+    pub migrated_text: String,
+}
+
+/// Parse deprecation warnings out of verifier output.
+pub fn parse_deprecation_warnings(stdout: &str, stderr: &str) -> Vec<DeprecationWarning> {
+    let Ok(re) = Regex::new(
+        r"`([^`]+)` has been deprecated(?:[:,]?\s*(?:[Uu]se|[Uu]sing)\s+`([^`]+)`\s+instead)?",
+    ) else {
+        return Vec::new();
+    };
+    let mut out: Vec<DeprecationWarning> = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for ln in stdout.lines().chain(stderr.lines()) {
+        let Some(idx) = ln.find(": warning:") else {
+            continue;
+        };
+        let prefix = ln[..idx].trim_end();
+        let mut it = prefix.rsplitn(3, ':');
+        let (Some(col_s), Some(line_s), Some(_)) = (it.next(), it.next(), it.next()) else {
+            continue;
+        };
+        let (Ok(col), Ok(line)) = (
+            col_s.trim().parse::<usize>(),
+            line_s.trim().parse::<usize>(),
+        ) else {
+            continue;
+        };
+        let Some(c) = re.captures(&ln[idx..]) else {
+            continue;
+        };
+        if !seen.insert((line, col)) {
+            continue;
+        }
+        out.push(DeprecationWarning {
+            line,
+            col,
+            old: c[1].to_string(),
+            replacement: c.get(2).map(|m| m.as_str().to_string()),
+        });
+    }
+    out
+}
+
+fn is_ident_char(ch: char) -> bool {
+    crate::is_lean_ident_char(ch) || ch == '.' || ch == '!' || ch == '?' || ch == '«' || ch == '»'
+}
+
+/// Work out the rewrite for the identifier starting at `col` in `line_text`.
+///
+/// The source may spell `old` relative to an opened namespace (`bar` for `Foo.bar`) or via dot
+/// notation (`h.bar`). We keep the same relative spelling when the replacement lives in the same
+/// namespace, and fall back to the fully qualified replacement otherwise (except for dot notation,
+/// which cannot be requalified mechanically).
+///
+/// Returns `(before, after)` on success, or a reason for manual work.
+pub fn plan_rename(
+    line_text: &str,
+    col: usize,
+    old: &str,
+    new: &str,
+) -> Result<(String, String), String> {
+    let chars: Vec<char> = line_text.chars().collect();
+    if col >= chars.len() {
+        return Err("position past end of line".to_string());
+    }
+    let token: String = chars[col..]
+        .iter()
+        .take_while(|c| is_ident_char(**c))
+        .collect();
+    let token = token.trim_end_matches('.');
+    if token.is_empty() {
+        return Err("no identifier at reported position".to_string());
+    }
+    let after_dot = col > 0 && chars[col - 1] == '.';
+
+    let old_parts: Vec<&str> = old.split('.').collect();
+    let new_parts: Vec<&str> = new.split('.').collect();
+    for k in 0..old_parts.len() {
+        let suffix = old_parts[k..].join(".");
+        let rest = if token == suffix {
+            ""
+        } else if let Some(r) = token.strip_prefix(&format!("{suffix}.")) {
+            r
+        } else {
+            continue;
+        };
+        let dropped = &old_parts[..k];
+        let spelled = if new_parts.len() > k && &new_parts[..k] == dropped {
+            new_parts[k..].join(".")
+        } else if after_dot {
+            return Err(format!(
+                "dot notation `.{suffix}`: replacement `{new}` is in a different namespace"
+            ));
+        } else {
+            new.to_string()
+        };
+        let after = if rest.is_empty() {
+            spelled
+        } else {
+            format!("{spelled}.{rest}")
+        };
+        return Ok((token.to_string(), after));
+    }
+    Err(format!("identifier `{token}` does not match `{old}`"))
+}
+
+/// Apply edits to `text` (char-based columns; edits on the same line are applied right-to-left).
+pub fn apply_rename_edits(text: &str, edits: &[RenameEdit]) -> String {
+    let mut lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
+    let mut by_line: BTreeMap<usize, Vec<&RenameEdit>> = BTreeMap::new();
+    for e in edits {
+        by_line.entry(e.line).or_default().push(e);
+    }
+    for (line, mut es) in by_line {
+        if line == 0 || line > lines.len() {
+            continue;
+        }
+        es.sort_by_key(|e| std::cmp::Reverse(e.col));
+        let mut chars: Vec<char> = lines[line - 1].chars().collect();
+        for e in es {
+            let n = e.before.chars().count();
+            if e.col + n > chars.len() {
+                continue;
+            }
+            let cur: String = chars[e.col..e.col + n].iter().collect();
+            if cur != e.before {
+                continue;
+            }
+            chars.splice(e.col..e.col + n, e.after.chars());
+        }
+        lines[line - 1] = chars.into_iter().collect();
+    }
+    let mut out = lines.join("\n");
+    if text.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// `(start_line, end_line)` of each decl's command (attributes and docstring included).
+fn decl_spans(text: &str, decls: &[IndexedDecl]) -> Vec<(usize, usize)> {
+    let lines: Vec<&str> = text.lines().collect();
+    decls
+        .iter()
+        .map(|d| {
+            let start = if d.line >= 1 && d.line <= lines.len() {
+                crate::profile::command_start_line0(&lines, d.line - 1) + 1
+            } else {
+                d.line
+            };
+            (start, d.end_line.max(d.line))
+        })
+        .collect()
+}
+
+fn error_lines(stdout: &str, stderr: &str) -> Vec<usize> {
+    stdout
+        .lines()
+        .chain(stderr.lines())
+        .filter_map(|l| crate::heartbeats::parse_error_head(l).map(|(line, _, _)| line))
+        .collect()
+}
+
+/// Migrate deprecated identifiers in `file_rel`.
+///
+/// With `verify`, edits are grouped by declaration and each group is kept only if the file
+/// still has no more errors inside that declaration than before.
+pub async fn migrate_deprecations_in_file(
+    repo_root: &Path,
+    file_rel: &str,
+    verify: bool,
+    timeout_s: Duration,
+) -> Result<DeprecationMigration, String> {
+    let p = repo_root.join(file_rel);
+    if !p.exists() {
+        return Err(format!("File not found: {}", p.display()));
+    }
+    let text = std::fs::read_to_string(&p).map_err(|e| format!("read {}: {e}", p.display()))?;
+    let base = crate::verify_lean_text(repo_root, &text, timeout_s).await?;
+    let warnings = parse_deprecation_warnings(&base.stdout, &base.stderr);
+    let base_errors = error_lines(&base.stdout, &base.stderr);
+
+    let decls = crate::index_decls_in_text(&text);
+    let spans = decl_spans(&text, &decls);
+    let owner = |line: usize| spans.iter().position(|(s, e)| *s <= line && line <= *e);
+    let lines: Vec<&str> = text.lines().collect();
+
+    let mut manual: Vec<ManualItem> = Vec::new();
+    // Grouped by owning decl index (`None`: outside any decl).
+    let mut groups: BTreeMap<Option<usize>, Vec<RenameEdit>> = BTreeMap::new();
+    for w in &warnings {
+        let k = owner(w.line);
+        let decl = k.map(|k| decls[k].full_name.clone());
+        let Some(new) = w.replacement.as_deref() else {
+            manual.push(ManualItem {
+                line: w.line,
+                col: w.col,
+                old: w.old.clone(),
+                replacement: None,
+                decl,
+                reason: "no replacement suggested".to_string(),
+            });
+            continue;
+        };
+        let line_text = lines.get(w.line.wrapping_sub(1)).copied().unwrap_or("");
+        match plan_rename(line_text, w.col, &w.old, new) {
+            Ok((before, after)) => groups.entry(k).or_default().push(RenameEdit {
+                line: w.line,
+                col: w.col,
+                before,
+                after,
+                old: w.old.clone(),
+                replacement: new.to_string(),
+            }),
+            Err(reason) => manual.push(ManualItem {
+                line: w.line,
+                col: w.col,
+                old: w.old.clone(),
+                replacement: Some(new.to_string()),
+                decl,
+                reason,
+            }),
+        }
+    }
+
+    let mut current = text.clone();
+    let mut applied: Vec<RenameEdit> = Vec::new();
+    let mut verified_decls: Vec<String> = Vec::new();
+    for (k, edits) in groups {
+        let label = k
+            .map(|k| decls[k].full_name.clone())
+            .unwrap_or_else(|| "<top-level>".to_string());
+        let candidate = apply_rename_edits(&current, &edits);
+        if verify {
+            let in_group = |l: &usize| match k {
+                Some(k) => spans[k].0 <= *l && *l <= spans[k].1,
+                None => owner(*l).is_none(),
+            };
+            let r = crate::verify_lean_text(repo_root, &candidate, timeout_s).await?;
+            let before = base_errors.iter().filter(|l| in_group(l)).count();
+            let after = error_lines(&r.stdout, &r.stderr)
+                .iter()
+                .filter(|l| in_group(l))
+                .count();
+            if r.timeout || after > before {
+                for e in edits {
+                    manual.push(ManualItem {
+                        line: e.line,
+                        col: e.col,
+                        old: e.old,
+                        replacement: Some(e.replacement),
+                        decl: k.map(|k| decls[k].full_name.clone()),
+                        reason: if r.timeout {
+                            "verify timed out after rename".to_string()
+                        } else {
+                            "rename does not check (new errors in decl)".to_string()
+                        },
+                    });
+                }
+                continue;
+            }
+        }
+        current = candidate;
+        applied.extend(edits);
+        verified_decls.push(label);
+    }
+    manual.sort_by(|a, b| a.line.cmp(&b.line).then(a.col.cmp(&b.col)));
+
+    Ok(DeprecationMigration {
+        file: file_rel.to_string(),
+        warnings: warnings.len(),
+        applied,
+        manual,
+        verified_decls,
+        verified: verify,
+        original_text: text,
+        migrated_text: current,
+    })
+}
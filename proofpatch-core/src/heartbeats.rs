@@ -0,0 +1,483 @@
+//! Heartbeat / recursion-limit failure triage.
+//!
+//! `maximum number of heartbeats` and `maximum recursion depth` errors are resource failures, not
+//! "wrong proof" failures: the same script may succeed with a larger budget, or with a cheaper
+//! tactic in the one expensive step. This module:
+//! - recognizes those errors (and where they are reported)
+//! - re-runs the file with `set_option maxHeartbeats N in` / `set_option maxRecDepth N in` scoped to
+//!   the failing declaration, doubling N up to a cap, to find the budget it actually needs
+//! - bisects the declaration's top-level tactic block (truncating with `sorry`) to localize the
+//!   expensive tactic
+//! - proposes either the scoped `set_option` or cheaper variants of that tactic
+//!
+//! All edits keep line numbers stable (prefixes on the command's first line, truncated tactic lines
+//! blanked), so Lean's positions can be compared against the original file.
+
+use crate::IndexedDecl;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// Lean's default `maxHeartbeats` (in thousands of heartbeats, same unit as the option).
+pub const DEFAULT_MAX_HEARTBEATS: u64 = 200_000;
+/// Lean's default `maxRecDepth`.
+pub const DEFAULT_MAX_REC_DEPTH: u64 = 512;
+
+/// Classify a Lean error message as a resource-limit failure.
+///
+/// Returns `heartbeats` or `recursion`, or `None` for ordinary errors.
+pub fn resource_limit_kind(message: &str) -> Option<&'static str> {
+    let s = message.to_lowercase();
+    if s.contains("maximum number of heartbeats") || s.contains("(deterministic) timeout") {
+        Some("heartbeats")
+    } else if s.contains("maximum recursion depth") {
+        Some("recursion")
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceLimitError {
+    /// `heartbeats` | `recursion`
+    pub kind: String,
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+/// Split `path:line:col: error: msg` into `(line, col, msg)` (right-to-left, Windows-safe).
+pub(crate) fn parse_error_head(line: &str) -> Option<(usize, usize, &str)> {
+    let idx = line.find(": error:")?;
+    let prefix = line[..idx].trim_end();
+    let mut it = prefix.rsplitn(3, ':');
+    let col = it.next()?.trim().parse::<usize>().ok()?;
+    let line_no = it.next()?.trim().parse::<usize>().ok()?;
+    it.next()?;
+    Some((line_no, col, line[idx + ": error:".len()..].trim()))
+}
+
+/// Parse every positioned error block and keep the resource-limit ones.
+///
+/// A block is an `path:line:col: error: ...` line plus the unpositioned lines that follow it.
+pub fn parse_resource_limit_errors(stdout: &str, stderr: &str) -> Vec<ResourceLimitError> {
+    fn is_positioned(line: &str) -> bool {
+        [": error:", ": warning:", ": info:", ": information:"]
+            .iter()
+            .any(|t| line.contains(t))
+    }
+
+    let mut out = Vec::new();
+    let mut cur: Option<(usize, usize, String)> = None;
+    let flush = |cur: &mut Option<(usize, usize, String)>, out: &mut Vec<ResourceLimitError>| {
+        if let Some((line, col, msg)) = cur.take() {
+            if let Some(kind) = resource_limit_kind(&msg) {
+                out.push(ResourceLimitError {
+                    kind: kind.to_string(),
+                    line,
+                    col,
+                    message: msg.trim().to_string(),
+                });
+            }
+        }
+    };
+    for ln in stdout.lines().chain(stderr.lines()) {
+        if let Some((line, col, msg)) = parse_error_head(ln) {
+            flush(&mut cur, &mut out);
+            cur = Some((line, col, msg.to_string()));
+        } else if is_positioned(ln) {
+            flush(&mut cur, &mut out);
+        } else if let Some((_, _, msg)) = cur.as_mut() {
+            msg.push('\n');
+            msg.push_str(ln);
+        }
+    }
+    flush(&mut cur, &mut out);
+    out
+}
+
+/// Current limit reported in a heartbeat error (`... heartbeats (200000) has been reached`).
+pub fn reported_heartbeat_limit(message: &str) -> Option<u64> {
+    let re = regex::Regex::new(r"heartbeats \((\d+)\)").ok()?;
+    re.captures(message)?.get(1)?.as_str().parse().ok()
+}
+
+/// The option that controls a given resource-limit kind.
+pub fn limit_option_name(kind: &str) -> &'static str {
+    if kind == "recursion" {
+        "maxRecDepth"
+    } else {
+        "maxHeartbeats"
+    }
+}
+
+/// Scoped option prefix, e.g. `set_option maxHeartbeats 400000 in`.
+pub fn scoped_set_option(kind: &str, value: u64) -> String {
+    format!("set_option {} {value} in", limit_option_name(kind))
+}
+
+/// Budgets to try: doubling from `current` up to (and including) `cap`.
+pub fn budget_ladder(current: u64, cap: u64) -> Vec<u64> {
+    let mut out = Vec::new();
+    let mut v = current.max(1).saturating_mul(2);
+    while v < cap {
+        out.push(v);
+        v = v.saturating_mul(2);
+    }
+    if out.last() != Some(&cap) && cap > current {
+        out.push(cap);
+    }
+    out
+}
+
+/// One top-level tactic of a declaration's `by` block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopLevelTactic {
+    /// 1-based first line of the tactic.
+    pub line: usize,
+    /// 1-based last line (continuation lines are indented deeper).
+    pub end_line: usize,
+    pub text: String,
+}
+
+/// Top-level tactics of the decl's `by` block (best-effort; line based).
+///
+/// Only multi-line blocks (`:= by` at the end of a line) are split; a one-line `:= by tac` proof
+/// has a single step and nothing to bisect.
+pub fn top_level_tactics(text: &str, decl: &IndexedDecl) -> Vec<TopLevelTactic> {
+    let lines: Vec<&str> = text.lines().collect();
+    let start0 = decl.line.saturating_sub(1);
+    let end0 = decl
+        .end_line
+        .saturating_sub(1)
+        .min(lines.len().saturating_sub(1));
+    if start0 > end0 || lines.is_empty() {
+        return Vec::new();
+    }
+    let Some(by0) = (start0..=end0).find(|&i| {
+        let t = lines[i].trim_end();
+        t.ends_with(" by") || t == "by"
+    }) else {
+        return Vec::new();
+    };
+    let indent = |s: &str| s.len() - s.trim_start().len();
+    let body: Vec<usize> = (by0 + 1..=end0)
+        .filter(|&i| {
+            let t = lines[i].trim();
+            !t.is_empty() && !t.starts_with("--")
+        })
+        .collect();
+    let Some(base) = body.iter().map(|&i| indent(lines[i])).min() else {
+        return Vec::new();
+    };
+    let mut out: Vec<TopLevelTactic> = Vec::new();
+    for i in body {
+        // `| alt => ...` arms and `<;> ...` lines continue the previous tactic at the same indent.
+        let t = lines[i].trim_start();
+        let continues = t.starts_with('|') || t.starts_with("<;>");
+        if indent(lines[i]) == base && (!continues || out.is_empty()) {
+            out.push(TopLevelTactic {
+                line: i + 1,
+                end_line: i + 1,
+                text: lines[i].trim().to_string(),
+            });
+        } else if let Some(last) = out.last_mut() {
+            last.end_line = i + 1;
+            last.text.push('\n');
+            last.text.push_str(lines[i]);
+        }
+    }
+    out
+}
+
+/// Keep the first `k` top-level tactics and close the rest of the block with `sorry`.
+///
+/// Dropped lines are blanked rather than removed so positions stay comparable.
+pub fn truncate_tactic_block(text: &str, tactics: &[TopLevelTactic], k: usize) -> String {
+    let mut lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
+    let Some(first_dropped) = tactics.get(k) else {
+        return text.to_string();
+    };
+    let last_line = tactics
+        .last()
+        .map(|t| t.end_line)
+        .unwrap_or(first_dropped.line);
+    let indent: String = lines[first_dropped.line - 1]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect();
+    for i0 in (first_dropped.line - 1)..last_line.min(lines.len()) {
+        lines[i0].clear();
+    }
+    lines[first_dropped.line - 1] = format!("{indent}sorry");
+    let mut out = lines.join("\n");
+    if text.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// Prefix the command owning `decl` with a scoped option (on the same line).
+pub fn with_scoped_option(text: &str, decl: &IndexedDecl, prefix: &str) -> String {
+    let orig: Vec<&str> = text.lines().collect();
+    if decl.line == 0 || decl.line > orig.len() {
+        return text.to_string();
+    }
+    let i0 = crate::profile::command_start_line0(&orig, decl.line - 1);
+    let mut lines: Vec<String> = orig.iter().map(|s| s.to_string()).collect();
+    let ln = &lines[i0];
+    let indent_len = ln.len() - ln.trim_start().len();
+    let (indent, rest) = ln.split_at(indent_len);
+    lines[i0] = format!("{indent}{prefix} {rest}");
+    let mut out = lines.join("\n");
+    if text.ends_with('\n') {
+        out.push('\n');
+    }
+    out
+}
+
+/// Cheaper variants to try in place of an expensive tactic (head token swapped, args kept).
+pub fn cheaper_tactic_alternatives(tactic: &str) -> Vec<String> {
+    let t = tactic.trim();
+    let (head, rest) = match t.find(|c: char| c.is_whitespace() || c == '[') {
+        Some(i) => (&t[..i], &t[i..]),
+        None => (t, ""),
+    };
+    let swap = |h: &str| format!("{h}{rest}");
+    match head {
+        // `simp?` / `simp_all?` / `aesop?` report the (much cheaper) explicit call to paste back.
+        "simp" if !rest.trim_start().starts_with("only") => {
+            vec![swap("simp?")]
+        }
+        "simp_all" if !rest.trim_start().starts_with("only") => vec![swap("simp_all?")],
+        "aesop" => vec![swap("aesop?")],
+        "nlinarith" | "polyrith" => vec![swap("linarith"), "positivity".to_string()],
+        "exact?" | "apply?" | "rw?" => vec!["(paste the `Try this:` suggestion)".to_string()],
+        "decide" => vec!["norm_num".to_string()],
+        "norm_num" if !rest.trim_start().starts_with('[') => vec!["norm_num1".to_string()],
+        _ => Vec::new(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetAttempt {
+    pub value: u64,
+    /// No resource-limit error inside the decl with this budget.
+    pub resolved: bool,
+    pub verify_ok: bool,
+    pub timeout: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Culprit {
+    /// 0-based index of the tactic in the top-level block.
+    pub index: usize,
+    pub line: usize,
+    pub tactic: String,
+    /// Verify runs spent bisecting.
+    pub probes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proposal {
+    /// `scoped_set_option` | `cheaper_tactic`
+    pub kind: String,
+    pub line: usize,
+    /// Text to insert before the command (`scoped_set_option`) or to replace the tactic with.
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeartbeatsTriage {
+    pub file: String,
+    pub decl: Option<String>,
+    pub decl_line: Option<usize>,
+    /// `heartbeats` | `recursion` | `none`
+    pub kind: String,
+    pub errors: Vec<ResourceLimitError>,
+    pub current_limit: Option<u64>,
+    pub attempts: Vec<BudgetAttempt>,
+    /// Smallest tried budget that resolved the failure.
+    pub needed: Option<u64>,
+    pub culprit: Option<Culprit>,
+    pub proposals: Vec<Proposal>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TriageOptions {
+    pub max_heartbeats: u64,
+    pub max_rec_depth: u64,
+    pub bisect: bool,
+}
+
+impl Default for TriageOptions {
+    fn default() -> Self {
+        Self {
+            max_heartbeats: 3_200_000,
+            max_rec_depth: 8192,
+            bisect: true,
+        }
+    }
+}
+
+fn errors_in_decl<'a>(
+    errs: &'a [ResourceLimitError],
+    text: &str,
+    decl: &IndexedDecl,
+) -> impl Iterator<Item = &'a ResourceLimitError> {
+    let lines: Vec<&str> = text.lines().collect();
+    let start = if decl.line >= 1 && decl.line <= lines.len() {
+        crate::profile::command_start_line0(&lines, decl.line - 1) + 1
+    } else {
+        decl.line
+    };
+    let end = decl.end_line.max(decl.line);
+    errs.iter()
+        .filter(move |e| start <= e.line && e.line <= end)
+}
+
+/// Triage a heartbeat / recursion-limit failure in `file_rel`.
+///
+/// The target decl is `decl_name` if given, else the decl containing `line`, else the decl
+/// containing the first resource-limit error.
+pub async fn triage_resource_limits(
+    repo_root: &Path,
+    file_rel: &str,
+    decl_name: Option<&str>,
+    line: Option<usize>,
+    opts: &TriageOptions,
+    timeout_s: Duration,
+) -> Result<HeartbeatsTriage, String> {
+    let p = repo_root.join(file_rel);
+    if !p.exists() {
+        return Err(format!("File not found: {}", p.display()));
+    }
+    let text = std::fs::read_to_string(&p).map_err(|e| format!("read {}: {e}", p.display()))?;
+    let decls = crate::index_decls_in_text(&text);
+
+    let base = crate::verify_lean_text(repo_root, &text, timeout_s).await?;
+    let all_errs = parse_resource_limit_errors(&base.stdout, &base.stderr);
+
+    let containing = |l: usize| {
+        decls
+            .iter()
+            .find(|d| d.line <= l && l <= d.end_line.max(d.line))
+            .cloned()
+    };
+    let decl: Option<IndexedDecl> = if let Some(n) = decl_name {
+        Some(
+            decls
+                .iter()
+                .find(|d| d.full_name == n || d.name == n)
+                .cloned()
+                .ok_or_else(|| format!("decl not found: {n}"))?,
+        )
+    } else if let Some(l) = line {
+        containing(l)
+    } else {
+        all_errs.first().and_then(|e| containing(e.line))
+    };
+
+    let mut out = HeartbeatsTriage {
+        file: file_rel.to_string(),
+        decl: decl.as_ref().map(|d| d.full_name.clone()),
+        decl_line: decl.as_ref().map(|d| d.line),
+        kind: "none".to_string(),
+        errors: Vec::new(),
+        current_limit: None,
+        attempts: Vec::new(),
+        needed: None,
+        culprit: None,
+        proposals: Vec::new(),
+    };
+    let Some(decl) = decl else {
+        out.errors = all_errs;
+        return Ok(out);
+    };
+    let errs: Vec<ResourceLimitError> = errors_in_decl(&all_errs, &text, &decl).cloned().collect();
+    let Some(first) = errs.first().cloned() else {
+        return Ok(out);
+    };
+    let kind = first.kind.clone();
+    let current = if kind == "heartbeats" {
+        reported_heartbeat_limit(&first.message).unwrap_or(DEFAULT_MAX_HEARTBEATS)
+    } else {
+        DEFAULT_MAX_REC_DEPTH
+    };
+    out.kind = kind.clone();
+    out.errors = errs;
+    out.current_limit = Some(current);
+
+    // 1) Budget: smallest doubling that makes the decl's resource error go away.
+    let cap = if kind == "heartbeats" {
+        opts.max_heartbeats
+    } else {
+        opts.max_rec_depth
+    };
+    for v in budget_ladder(current, cap) {
+        let t = with_scoped_option(&text, &decl, &scoped_set_option(&kind, v));
+        let r = crate::verify_lean_text(repo_root, &t, timeout_s).await?;
+        let e = parse_resource_limit_errors(&r.stdout, &r.stderr);
+        let resolved = !r.timeout && errors_in_decl(&e, &t, &decl).next().is_none();
+        out.attempts.push(BudgetAttempt {
+            value: v,
+            resolved,
+            verify_ok: r.ok,
+            timeout: r.timeout,
+        });
+        if resolved {
+            out.needed = Some(v);
+            break;
+        }
+    }
+
+    // 2) Localize: smallest tactic prefix that still hits the limit (at the default budget).
+    if opts.bisect {
+        let tactics = top_level_tactics(&text, &decl);
+        if !tactics.is_empty() {
+            let (mut lo, mut hi) = (0usize, tactics.len());
+            let mut probes = 0usize;
+            while hi - lo > 1 {
+                let mid = (lo + hi) / 2;
+                let t = truncate_tactic_block(&text, &tactics, mid);
+                let r = crate::verify_lean_text(repo_root, &t, timeout_s).await?;
+                probes += 1;
+                let e = parse_resource_limit_errors(&r.stdout, &r.stderr);
+                if r.timeout || errors_in_decl(&e, &t, &decl).next().is_some() {
+                    hi = mid;
+                } else {
+                    lo = mid;
+                }
+            }
+            let c = &tactics[hi - 1];
+            out.culprit = Some(Culprit {
+                index: hi - 1,
+                line: c.line,
+                tactic: c.text.clone(),
+                probes,
+            });
+        }
+    }
+
+    // 3) Proposals: cheaper tactic first (a fix), then the scoped budget (a workaround).
+    if let Some(c) = out.culprit.as_ref() {
+        let head_line = c.tactic.lines().next().unwrap_or("");
+        for alt in cheaper_tactic_alternatives(head_line) {
+            out.proposals.push(Proposal {
+                kind: "cheaper_tactic".to_string(),
+                line: c.line,
+                text: alt,
+            });
+        }
+    }
+    if let Some(v) = out.needed {
+        let lines: Vec<&str> = text.lines().collect();
+        let start = crate::profile::command_start_line0(&lines, decl.line - 1) + 1;
+        out.proposals.push(Proposal {
+            kind: "scoped_set_option".to_string(),
+            line: start,
+            text: scoped_set_option(&kind, v),
+        });
+    }
+    Ok(out)
+}
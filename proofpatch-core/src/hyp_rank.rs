@@ -0,0 +1,329 @@
+//! Hypothesis relevance ranking for one goal (`rank_hypotheses`).
+//!
+//! Goals deep in a development often carry dozens of locals, most of them unrelated to the
+//! target. Prompts (rubberduck, region patch, planner evidence) list the relevant ones and say
+//! how many were left out, instead of pasting the whole context.
+//!
+//! Scores come from cheap, solver-free signals:
+//! - variable overlap: how many hops a hypothesis is from the target through shared local names
+//!   (the connectivity `--smt-depth` uses to select SMT constraints, without the depth cutoff)
+//! - unsat-core membership: hypotheses a difference-logic certificate for the target uses, or
+//!   those of a core the caller already has (`rank_hypotheses_with_core`)
+//! - type: declarations of target variables and connected arithmetic relations (for an
+//!   arithmetic target) rank up; instances and inaccessible (`✝`) locals rank down
+//!
+//! The ranking only trims context. Nothing is dropped from the goal Lean sees.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Hypotheses kept in a trimmed goal snapshot by default (see `goal_snapshot`).
+pub const DEFAULT_MAX_HYPS: usize = 12;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RankedHypothesis {
+    /// Position in `goals[0].hyps`.
+    pub index: usize,
+    pub text: String,
+    pub score: f64,
+    /// Hops from the target through shared local names (1: mentions a target variable);
+    /// `None` when unconnected.
+    pub distance: Option<usize>,
+    pub in_core: bool,
+    /// `decl`, `instance`, `arith` (parses as a linear relation) or `prop`.
+    pub kind: &'static str,
+}
+
+/// Rank the hypotheses of the first goal of a `pp_dump`, most relevant first (ties keep the
+/// goal's order). Empty when there is no goal.
+pub fn rank_hypotheses(pp_dump: &Value) -> Vec<RankedHypothesis> {
+    rank_hypotheses_with_core(pp_dump, &[])
+}
+
+/// `rank_hypotheses`, also counting the hypotheses of a known unsat core as core members.
+/// Core entries match a hypothesis by name (`h`) or by type text (the part after `h :`).
+pub fn rank_hypotheses_with_core(pp_dump: &Value, core: &[String]) -> Vec<RankedHypothesis> {
+    let Some(goal) = first_goal(pp_dump) else {
+        return Vec::new();
+    };
+    let texts = hyp_texts(goal);
+    let target = goal_target(goal);
+    let hyps: Vec<(Vec<String>, String)> = texts.iter().map(|t| split_hyp(t)).collect();
+
+    let locals: BTreeSet<String> = hyps.iter().flat_map(|(ns, _)| ns.iter().cloned()).collect();
+    let target_vars: BTreeSet<String> = idents(&target)
+        .into_iter()
+        .filter(|x| locals.contains(x))
+        .collect();
+    let kinds: Vec<&'static str> = hyps.iter().map(|(ns, ty)| kind_of(ns, ty)).collect();
+
+    let arith_info = crate::smt_lia::arith_hyp_srcs(goal);
+    let target_is_arith = arith_info.is_some();
+    let (arith, mut core_srcs) = arith_info.unwrap_or_default();
+    core_srcs.extend(core.iter().cloned());
+
+    // Breadth-first from the target variables. Declarations and instances are reached through
+    // their binder names but do not extend the frontier through their types: `x : α` would
+    // otherwise connect everything over `α`.
+    let mentions: Vec<BTreeSet<String>> = hyps
+        .iter()
+        .map(|(ns, ty)| {
+            let mut m: BTreeSet<String> = ns.iter().cloned().collect();
+            m.extend(idents(ty).into_iter().filter(|x| locals.contains(x)));
+            m
+        })
+        .collect();
+    let mut distance: Vec<Option<usize>> = vec![None; hyps.len()];
+    let mut frontier = target_vars.clone();
+    let mut hop = 0usize;
+    while !frontier.is_empty() {
+        hop += 1;
+        let mut next: BTreeSet<String> = BTreeSet::new();
+        for (i, m) in mentions.iter().enumerate() {
+            if distance[i].is_some() || m.is_disjoint(&frontier) {
+                continue;
+            }
+            distance[i] = Some(hop);
+            if matches!(kinds[i], "decl" | "instance") {
+                continue;
+            }
+            next.extend(m.iter().cloned());
+        }
+        frontier = next;
+    }
+
+    let mut out: Vec<RankedHypothesis> = Vec::new();
+    for (i, (names, ty)) in hyps.iter().enumerate() {
+        let kind = if kinds[i] == "prop" && arith.contains(ty) {
+            "arith"
+        } else {
+            kinds[i]
+        };
+        let in_core = core_srcs.contains(ty) || names.iter().any(|n| core_srcs.contains(n));
+        let mut score = distance[i].map(|d| 3.0 / d as f64).unwrap_or(0.0);
+        if in_core {
+            score += 4.0;
+        }
+        if kind == "decl" && names.iter().any(|n| target_vars.contains(n)) {
+            score += 2.0;
+        }
+        if kind == "arith" && target_is_arith && distance[i].is_some() {
+            score += 1.0;
+        }
+        if kind == "instance" {
+            score -= 1.0;
+        }
+        if names.iter().any(|n| n.contains('✝')) {
+            score -= 0.5;
+        }
+        out.push(RankedHypothesis {
+            index: i,
+            text: texts[i].clone(),
+            score,
+            distance: distance[i],
+            in_core,
+            kind,
+        });
+    }
+    out.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.index.cmp(&b.index)));
+    out
+}
+
+/// The first goal of a `pp_dump` as prompt text, keeping at most `max_hyps` hypotheses.
+///
+/// Small goals (and `max_hyps == 0`) come back as Lean printed them. Otherwise the
+/// best-ranked hypotheses with a positive score are listed in their original order, followed by
+/// a note counting the omitted ones and the target.
+pub fn goal_snapshot(pp_dump: &Value, max_hyps: usize) -> Option<String> {
+    let goal = first_goal(pp_dump)?;
+    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
+    let texts = hyp_texts(goal);
+    let target = goal_target(goal);
+    if max_hyps == 0 || texts.len() <= max_hyps || target.is_empty() {
+        return (!pretty.trim().is_empty()).then(|| pretty.to_string());
+    }
+    let mut keep: Vec<usize> = rank_hypotheses(pp_dump)
+        .into_iter()
+        .filter(|h| h.score > 0.0)
+        .take(max_hyps)
+        .map(|h| h.index)
+        .collect();
+    keep.sort_unstable();
+    let mut out = String::new();
+    for &i in &keep {
+        out.push_str(&texts[i]);
+        out.push('\n');
+    }
+    let omitted = texts.len() - keep.len();
+    out.push_str(&format!(
+        "-- {omitted} of {} hypotheses omitted (ranked unrelated to the target)\n",
+        texts.len()
+    ));
+    out.push_str("⊢ ");
+    out.push_str(&target);
+    Some(out)
+}
+
+fn first_goal(pp_dump: &Value) -> Option<&Value> {
+    pp_dump
+        .get("goals")
+        .and_then(|v| v.as_array())
+        .and_then(|a| a.first())
+}
+
+fn hyp_texts(goal: &Value) -> Vec<String> {
+    goal.get("hyps")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|h| h.get("text").and_then(|v| v.as_str()))
+        .map(String::from)
+        .collect()
+}
+
+/// Everything after `⊢` in the goal's pretty form (targets can span lines).
+fn goal_target(goal: &Value) -> String {
+    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
+    match pretty.find('⊢') {
+        Some(i) => pretty[i + '⊢'.len_utf8()..].trim().to_string(),
+        None => String::new(),
+    }
+}
+
+/// `x y : T` into binder names and type text (`h : a ≤ b` gives `["h"]`, `a ≤ b`).
+fn split_hyp(text: &str) -> (Vec<String>, String) {
+    let Some((lhs, rhs)) = text.split_once(" : ").or_else(|| text.split_once(':')) else {
+        return (Vec::new(), text.trim().to_string());
+    };
+    let names = lhs.split_whitespace().map(String::from).collect();
+    (names, rhs.trim().to_string())
+}
+
+/// Identifier-like tokens of a Lean expression, with projections split off (`h.1`, `x.succ`).
+fn idents(s: &str) -> BTreeSet<String> {
+    s.split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '\'' || c == '✝'))
+        .filter(|w| w.chars().next().is_some_and(|c| !c.is_numeric()))
+        .map(String::from)
+        .collect()
+}
+
+fn kind_of(names: &[String], ty: &str) -> &'static str {
+    const PROP_MARKERS: &[&str] = &[
+        "=", "≠", "≤", "<", "≥", ">", "∣", "∈", "∉", "⊆", "⊂", "∧", "∨", "↔", "¬", "∀", "∃",
+        "True", "False",
+    ];
+    if names.iter().any(|n| n.starts_with("inst")) {
+        "instance"
+    } else if PROP_MARKERS.iter().any(|m| ty.contains(m)) {
+        "prop"
+    } else {
+        "decl"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn goal(target: &str, hyps: &[&str]) -> Value {
+        serde_json::json!({
+            "goals": [{
+                "pretty": format!("{}\n⊢ {target}", hyps.join("\n")),
+                "hyps": hyps.iter().map(|h| serde_json::json!({ "text": h })).collect::<Vec<_>>(),
+            }]
+        })
+    }
+
+    #[test]
+    fn ranks_by_overlap_core_and_kind_and_trims_snapshots() {
+        let hyps = [
+            "α : Type u_1",
+            "inst✝ : DecidableEq α",
+            "s t : Finset α",
+            "hs : s ⊆ t",
+            "a b c : ℕ",
+            "hab : a ≤ b",
+            "hbc : b ≤ c",
+            "k : ℕ",
+            "hk : k ≤ c",
+            "f : ℕ → ℕ",
+            "hf : ∀ n, f n ≤ f (n + 1)",
+            "m : ℕ",
+            "hm : m ≤ 5",
+        ];
+        let pp = goal("a ≤ c", &hyps);
+        let ranked = rank_hypotheses(&pp);
+        let top: Vec<&str> = ranked.iter().take(2).map(|h| h.text.as_str()).collect();
+        assert_eq!(top, ["hab : a ≤ b", "hbc : b ≤ c"]);
+        assert!(ranked[0].in_core && ranked[0].kind == "arith");
+        let by_text = |t: &str| ranked.iter().find(|h| h.text == t).unwrap();
+        assert_eq!(by_text("a b c : ℕ").kind, "decl");
+        assert_eq!(by_text("hk : k ≤ c").distance, Some(1));
+        assert_eq!(by_text("k : ℕ").distance, Some(2));
+        assert!(!by_text("hk : k ≤ c").in_core);
+        for unrelated in ["hs : s ⊆ t", "hf : ∀ n, f n ≤ f (n + 1)", "hm : m ≤ 5"] {
+            assert_eq!(by_text(unrelated).distance, None, "{unrelated}");
+            assert_eq!(by_text(unrelated).score, 0.0, "{unrelated}");
+        }
+        assert!(by_text("inst✝ : DecidableEq α").score < 0.0);
+
+        // A core from elsewhere (names or types) counts too.
+        let with_core = rank_hypotheses_with_core(&pp, &["hk".to_string()]);
+        assert!(
+            with_core
+                .iter()
+                .find(|h| h.text == "hk : k ≤ c")
+                .unwrap()
+                .in_core
+        );
+
+        let snap = goal_snapshot(&pp, 4).unwrap();
+        assert_eq!(
+            snap,
+            "a b c : ℕ\nhab : a ≤ b\nhbc : b ≤ c\nhk : k ≤ c\n\
+             -- 9 of 13 hypotheses omitted (ranked unrelated to the target)\n⊢ a ≤ c"
+        );
+        // Small goals are left alone.
+        assert_eq!(
+            goal_snapshot(&pp, 20).as_deref(),
+            pp["goals"][0]["pretty"].as_str()
+        );
+        assert!(goal_snapshot(&serde_json::json!({ "goals": [] }), 4).is_none());
+    }
+
+    #[test]
+    fn snapshot_ties_keep_goal_order_and_limits_are_inclusive() {
+        let hyps = [
+            "α : Type",
+            "x : α",
+            "h1 : Q x",
+            "h2 : R x",
+            "h3 : S x",
+            "z : α",
+            "hz : Q z",
+        ];
+        let pp = goal("P x", &hyps);
+        let ranked = rank_hypotheses(&pp);
+        let order: Vec<usize> = ranked.iter().map(|h| h.index).collect();
+        assert_eq!(order[..4], [1, 2, 3, 4]);
+        assert_eq!(ranked[1].score, ranked[3].score);
+
+        // Three of the tied `h*` compete for two slots: the earlier ones win.
+        assert_eq!(
+            goal_snapshot(&pp, 3).unwrap(),
+            "x : α\nh1 : Q x\nh2 : R x\n\
+             -- 4 of 7 hypotheses omitted (ranked unrelated to the target)\n⊢ P x"
+        );
+        // Unrelated hypotheses are dropped even when there is room for them.
+        assert_eq!(
+            goal_snapshot(&pp, 6).unwrap(),
+            "x : α\nh1 : Q x\nh2 : R x\nh3 : S x\n\
+             -- 3 of 7 hypotheses omitted (ranked unrelated to the target)\n⊢ P x"
+        );
+        // A goal exactly at the limit, or no limit, is left as Lean printed it.
+        let pretty = pp["goals"][0]["pretty"].as_str();
+        assert_eq!(goal_snapshot(&pp, 7).as_deref(), pretty);
+        assert_eq!(goal_snapshot(&pp, 0).as_deref(), pretty);
+    }
+}
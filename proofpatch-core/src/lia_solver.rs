@@ -0,0 +1,847 @@
+//! A small in-process decision procedure for the linear fragments `smt_lia` builds.
+//!
+//! Input is the same SMT-LIB assertions an external solver would get (relations over `+ - *`
+//! by constants, `/` by constants, `to_real`, combined with `and or not => ite`), so it can
+//! stand in when no solver is installed and cross-check one when there is.
+//!
+//! Method: case-split disjunctions (bounded, pruned by the rational relaxation), decide each
+//! conjunction with Fourier–Motzkin over exact rationals, and branch-and-bound on integer
+//! variables. Every answer is conservative: `Sat` only with a point that satisfies all
+//! assertions exactly, `Unsat` only when every case is infeasible over the rationals after
+//! sound integer tightening, and `Unknown` on anything unsupported, overflow, budget exhaustion
+//! or a passed deadline.
+
+use smtkit::session::Status;
+use smtkit::sexp::Sexp;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Instant;
+
+/// Bounds the search: rational feasibility checks across all cases and B&B nodes.
+const MAX_CHECKS: usize = 4_000;
+/// Bounds Fourier–Motzkin: constraints alive during one elimination.
+const MAX_CONSTRAINTS: usize = 2_000;
+
+/// Unsupported input, arithmetic overflow or budget exhaustion: the answer is `Unknown`.
+#[derive(Debug)]
+struct GiveUp;
+
+type R<T> = Result<T, GiveUp>;
+
+/// Work done so far, against `MAX_CHECKS` and the caller's deadline.
+struct Budget {
+    checks: usize,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    /// Count one rational feasibility check.
+    fn tick(&mut self) -> R<()> {
+        self.checks += 1;
+        if self.spent() {
+            return Err(GiveUp);
+        }
+        Ok(())
+    }
+
+    fn spent(&self) -> bool {
+        self.checks > MAX_CHECKS || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+}
+
+/// An exact rational `n / d` with `d > 0` and `gcd(n, d) = 1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Q {
+    n: i128,
+    d: i128,
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl Q {
+    const ZERO: Q = Q { n: 0, d: 1 };
+    const ONE: Q = Q { n: 1, d: 1 };
+
+    fn int(n: i128) -> Q {
+        Q { n, d: 1 }
+    }
+
+    fn new(n: i128, d: i128) -> R<Q> {
+        // Excluding `i128::MIN` keeps `neg` and sign flips overflow-free.
+        if d == 0 || n == i128::MIN || d == i128::MIN {
+            return Err(GiveUp);
+        }
+        let g = gcd(n, d).max(1);
+        let s = if d < 0 { -1 } else { 1 };
+        Ok(Q {
+            n: s * (n / g),
+            d: s * (d / g),
+        })
+    }
+
+    fn add(self, o: Q) -> R<Q> {
+        let n = (self.n.checked_mul(o.d))
+            .and_then(|a| o.n.checked_mul(self.d).and_then(|b| a.checked_add(b)))
+            .ok_or(GiveUp)?;
+        Q::new(n, self.d.checked_mul(o.d).ok_or(GiveUp)?)
+    }
+
+    fn neg(self) -> Q {
+        Q {
+            n: -self.n,
+            d: self.d,
+        }
+    }
+
+    fn sub(self, o: Q) -> R<Q> {
+        self.add(o.neg())
+    }
+
+    fn mul(self, o: Q) -> R<Q> {
+        Q::new(
+            self.n.checked_mul(o.n).ok_or(GiveUp)?,
+            self.d.checked_mul(o.d).ok_or(GiveUp)?,
+        )
+    }
+
+    fn div(self, o: Q) -> R<Q> {
+        if o.n == 0 {
+            return Err(GiveUp);
+        }
+        self.mul(Q { n: o.d, d: o.n }.normalized()?)
+    }
+
+    fn normalized(self) -> R<Q> {
+        Q::new(self.n, self.d)
+    }
+
+    fn is_zero(self) -> bool {
+        self.n == 0
+    }
+
+    fn is_int(self) -> bool {
+        self.d == 1
+    }
+
+    fn floor(self) -> Q {
+        Q::int(self.n.div_euclid(self.d))
+    }
+
+    fn ceil(self) -> Q {
+        Q::int(-(-self.n).div_euclid(self.d))
+    }
+
+    fn cmp(self, o: Q) -> R<std::cmp::Ordering> {
+        let a = self.n.checked_mul(o.d).ok_or(GiveUp)?;
+        let b = o.n.checked_mul(self.d).ok_or(GiveUp)?;
+        Ok(a.cmp(&b))
+    }
+}
+
+/// `Σ coeffs·x + c`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Lin {
+    coeffs: BTreeMap<String, Q>,
+    c: Q,
+}
+
+impl Lin {
+    fn constant(c: Q) -> Lin {
+        Lin {
+            coeffs: BTreeMap::new(),
+            c,
+        }
+    }
+
+    fn var(name: &str) -> Lin {
+        Lin {
+            coeffs: BTreeMap::from([(name.to_string(), Q::ONE)]),
+            c: Q::ZERO,
+        }
+    }
+
+    fn add(&self, o: &Lin) -> R<Lin> {
+        let mut out = self.clone();
+        for (v, a) in &o.coeffs {
+            let e = out.coeffs.entry(v.clone()).or_insert(Q::ZERO);
+            *e = e.add(*a)?;
+        }
+        out.coeffs.retain(|_, a| !a.is_zero());
+        out.c = out.c.add(o.c)?;
+        Ok(out)
+    }
+
+    fn scale(&self, k: Q) -> R<Lin> {
+        if k.is_zero() {
+            return Ok(Lin::constant(Q::ZERO));
+        }
+        let mut coeffs = BTreeMap::new();
+        for (v, a) in &self.coeffs {
+            coeffs.insert(v.clone(), a.mul(k)?);
+        }
+        Ok(Lin {
+            coeffs,
+            c: self.c.mul(k)?,
+        })
+    }
+
+    fn eval(&self, point: &BTreeMap<String, Q>) -> R<Q> {
+        let mut acc = self.c;
+        for (v, a) in &self.coeffs {
+            acc = acc.add(a.mul(*point.get(v).unwrap_or(&Q::ZERO))?)?;
+        }
+        Ok(acc)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Le,
+    Lt,
+    Eq,
+}
+
+/// `lin (≤ | < | =) 0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cons {
+    lin: Lin,
+    kind: Kind,
+}
+
+impl Cons {
+    fn holds(&self, point: &BTreeMap<String, Q>) -> R<bool> {
+        let v = self.lin.eval(point)?;
+        Ok(match self.kind {
+            Kind::Le => v.n <= 0,
+            Kind::Lt => v.n < 0,
+            Kind::Eq => v.n == 0,
+        })
+    }
+}
+
+/// Negation normal form over linear atoms.
+#[derive(Debug, Clone)]
+enum Nnf {
+    Atom(Cons),
+    And(Vec<Nnf>),
+    Or(Vec<Nnf>),
+}
+
+fn is_numeral(a: &str) -> bool {
+    a.starts_with(|c: char| c.is_ascii_digit()) && a.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+fn numeral(a: &str) -> R<Q> {
+    let (int, frac) = a.split_once('.').unwrap_or((a, ""));
+    let digits = format!("{int}{frac}");
+    let n: i128 = digits.parse().map_err(|_| GiveUp)?;
+    let d = 10i128.checked_pow(frac.len() as u32).ok_or(GiveUp)?;
+    Q::new(n, d)
+}
+
+fn head_args(s: &Sexp) -> Option<(&str, &[Sexp])> {
+    match s {
+        Sexp::List(xs) => match xs.split_first() {
+            Some((Sexp::Atom(h), args)) => Some((h.as_str(), args)),
+            _ => None,
+        },
+        Sexp::Atom(_) => None,
+    }
+}
+
+fn term(s: &Sexp) -> R<Lin> {
+    match s {
+        Sexp::Atom(a) if is_numeral(a) => Ok(Lin::constant(numeral(a)?)),
+        Sexp::Atom(a) if a == "true" || a == "false" => Err(GiveUp),
+        Sexp::Atom(a) => Ok(Lin::var(a)),
+        Sexp::List(_) => {
+            let (op, args) = head_args(s).ok_or(GiveUp)?;
+            match (op, args) {
+                ("+", _) if !args.is_empty() => {
+                    let mut acc = Lin::constant(Q::ZERO);
+                    for a in args {
+                        acc = acc.add(&term(a)?)?;
+                    }
+                    Ok(acc)
+                }
+                ("-", [x]) => term(x)?.scale(Q::int(-1)),
+                ("-", [x, rest @ ..]) => {
+                    let mut acc = term(x)?;
+                    for a in rest {
+                        acc = acc.add(&term(a)?.scale(Q::int(-1))?)?;
+                    }
+                    Ok(acc)
+                }
+                ("*", _) if !args.is_empty() => {
+                    // Linear: at most one factor may mention variables.
+                    let mut acc = Lin::constant(Q::ONE);
+                    for a in args {
+                        let t = term(a)?;
+                        acc = match (acc.coeffs.is_empty(), t.coeffs.is_empty()) {
+                            (true, _) => t.scale(acc.c)?,
+                            (false, true) => acc.scale(t.c)?,
+                            (false, false) => return Err(GiveUp),
+                        };
+                    }
+                    Ok(acc)
+                }
+                ("/", [x, y]) => {
+                    let y = term(y)?;
+                    if !y.coeffs.is_empty() {
+                        return Err(GiveUp);
+                    }
+                    term(x)?.scale(Q::ONE.div(y.c)?)
+                }
+                ("to_real", [x]) => term(x),
+                _ => Err(GiveUp),
+            }
+        }
+    }
+}
+
+/// `lhs op rhs` as a constraint on `lhs - rhs`, or its negation.
+fn relation(op: &str, lhs: &Sexp, rhs: &Sexp, positive: bool) -> R<Nnf> {
+    let d = term(lhs)?.add(&term(rhs)?.scale(Q::int(-1))?)?;
+    let neg = d.scale(Q::int(-1))?;
+    let atom = |lin: Lin, kind: Kind| Nnf::Atom(Cons { lin, kind });
+    Ok(match (op, positive) {
+        ("<=", true) | (">", false) => atom(d, Kind::Le),
+        ("<", true) | (">=", false) => atom(d, Kind::Lt),
+        (">=", true) | ("<", false) => atom(neg, Kind::Le),
+        (">", true) | ("<=", false) => atom(neg, Kind::Lt),
+        ("=", true) => atom(d, Kind::Eq),
+        ("=", false) => Nnf::Or(vec![atom(d, Kind::Lt), atom(neg, Kind::Lt)]),
+        _ => return Err(GiveUp),
+    })
+}
+
+fn is_bool_term(s: &Sexp) -> bool {
+    match s {
+        Sexp::Atom(a) => a == "true" || a == "false",
+        Sexp::List(_) => matches!(
+            head_args(s).map(|(h, _)| h),
+            Some("and" | "or" | "not" | "=>" | "<=" | "<" | ">=" | ">" | "=" | "distinct" | "!")
+        ),
+    }
+}
+
+/// `s` (or `¬s` when `positive` is false) in negation normal form.
+fn formula(s: &Sexp, positive: bool) -> R<Nnf> {
+    let truth = |b: bool| {
+        if b {
+            Nnf::And(Vec::new())
+        } else {
+            Nnf::Or(Vec::new())
+        }
+    };
+    if let Sexp::Atom(a) = s {
+        return match a.as_str() {
+            "true" => Ok(truth(positive)),
+            "false" => Ok(truth(!positive)),
+            _ => Err(GiveUp),
+        };
+    }
+    let (op, args) = head_args(s).ok_or(GiveUp)?;
+    let all =
+        |fs: &[Sexp], pol: bool| -> R<Vec<Nnf>> { fs.iter().map(|f| formula(f, pol)).collect() };
+    match (op, args) {
+        ("!", [x, ..]) => formula(x, positive),
+        ("not", [x]) => formula(x, !positive),
+        ("and", _) if positive => Ok(Nnf::And(all(args, true)?)),
+        ("and", _) => Ok(Nnf::Or(all(args, false)?)),
+        ("or", _) if positive => Ok(Nnf::Or(all(args, true)?)),
+        ("or", _) => Ok(Nnf::And(all(args, false)?)),
+        ("=>", [a, b]) if positive => Ok(Nnf::Or(vec![formula(a, false)?, formula(b, true)?])),
+        ("=>", [a, b]) => Ok(Nnf::And(vec![formula(a, true)?, formula(b, false)?])),
+        ("ite", [c, a, b]) => Ok(Nnf::Or(vec![
+            Nnf::And(vec![formula(c, true)?, formula(a, positive)?]),
+            Nnf::And(vec![formula(c, false)?, formula(b, positive)?]),
+        ])),
+        ("=", [a, b]) if is_bool_term(a) || is_bool_term(b) => {
+            // `a ↔ b`, or its negation `a ↔ ¬b`.
+            Ok(Nnf::Or(vec![
+                Nnf::And(vec![formula(a, true)?, formula(b, positive)?]),
+                Nnf::And(vec![formula(a, false)?, formula(b, !positive)?]),
+            ]))
+        }
+        ("distinct", [a, b]) => relation("=", a, b, !positive),
+        ("<=" | "<" | ">=" | ">" | "=", [_, _, ..]) => {
+            // Chains `(<= a b c)` are pairwise conjunctions.
+            let parts: Vec<Nnf> = args
+                .windows(2)
+                .map(|w| relation(op, &w[0], &w[1], positive))
+                .collect::<R<_>>()?;
+            Ok(match (parts.len(), positive) {
+                (1, _) => parts.into_iter().next().ok_or(GiveUp)?,
+                (_, true) => Nnf::And(parts),
+                (_, false) => Nnf::Or(parts),
+            })
+        }
+        _ => Err(GiveUp),
+    }
+}
+
+/// Integer tightening of a constraint over integer variables only: integral coefficients with
+/// gcd 1, and `<` as `≤ -1`. Sound for integer points, which is all that matters for them.
+fn tighten(c: &Cons, ints: &BTreeSet<String>) -> R<Cons> {
+    if !c.lin.coeffs.keys().all(|v| ints.contains(v)) {
+        return Ok(c.clone());
+    }
+    let mut lcm: i128 = c.lin.c.d;
+    for a in c.lin.coeffs.values() {
+        lcm = (lcm / gcd(lcm, a.d)).checked_mul(a.d).ok_or(GiveUp)?;
+    }
+    let mut lin = c.lin.scale(Q::int(lcm))?;
+    let mut kind = c.kind;
+    if kind == Kind::Lt {
+        lin.c = lin.c.add(Q::ONE)?;
+        kind = Kind::Le;
+    }
+    let g = lin.coeffs.values().fold(0, |g, a| gcd(g, a.n));
+    if g > 1 {
+        let k = Q::new(1, g)?;
+        let rest = lin.c.mul(k)?;
+        if kind == Kind::Eq && !rest.is_int() {
+            // `g·(…) = -c` with `g ∤ c`: no integer solution.
+            return Ok(Cons {
+                lin: Lin::constant(Q::ONE),
+                kind: Kind::Eq,
+            });
+        }
+        lin = Lin {
+            coeffs: lin.scale(k)?.coeffs,
+            c: rest.ceil(),
+        };
+    }
+    Ok(Cons { lin, kind })
+}
+
+/// One elimination step, replayed backwards to build a point.
+enum Step {
+    /// `x = expr` (from an equality).
+    Subst(String, Lin),
+    /// The constraints on `x` when it was eliminated.
+    Bounds(String, Vec<Cons>),
+}
+
+/// A rational point satisfying `cons`, or `None` when there is none.
+fn solve_rational(cons: &[Cons]) -> R<Option<BTreeMap<String, Q>>> {
+    let mut cons: Vec<Cons> = cons.to_vec();
+    let mut steps: Vec<Step> = Vec::new();
+
+    // Equalities first: solve for one variable and substitute it everywhere.
+    while let Some(i) = cons
+        .iter()
+        .position(|c| c.kind == Kind::Eq && !c.lin.coeffs.is_empty())
+    {
+        let eq = cons.swap_remove(i);
+        let (x, a) = eq
+            .lin
+            .coeffs
+            .iter()
+            .next()
+            .map(|(x, a)| (x.clone(), *a))
+            .ok_or(GiveUp)?;
+        let mut rest = eq.lin.clone();
+        rest.coeffs.remove(&x);
+        let expr = rest.scale(Q::int(-1).div(a)?)?;
+        for c in cons.iter_mut() {
+            if let Some(b) = c.lin.coeffs.remove(&x) {
+                c.lin = c.lin.add(&expr.scale(b)?)?;
+            }
+        }
+        steps.push(Step::Subst(x, expr));
+    }
+
+    // Fourier–Motzkin on the inequalities.
+    loop {
+        let mut live: Vec<Cons> = Vec::new();
+        for c in cons {
+            if c.lin.coeffs.is_empty() {
+                let v = c.lin.c;
+                let ok = match c.kind {
+                    Kind::Le => v.n <= 0,
+                    Kind::Lt => v.n < 0,
+                    Kind::Eq => v.n == 0,
+                };
+                if !ok {
+                    return Ok(None);
+                }
+            } else if !live.contains(&c) {
+                live.push(c);
+            }
+        }
+        if live.len() > MAX_CONSTRAINTS {
+            return Err(GiveUp);
+        }
+        // Eliminate the variable with the fewest new constraints.
+        let vars: BTreeSet<&String> = live.iter().flat_map(|c| c.lin.coeffs.keys()).collect();
+        let Some(x) = vars
+            .into_iter()
+            .min_by_key(|x| {
+                let pos = live
+                    .iter()
+                    .filter(|c| c.lin.coeffs.get(*x).is_some_and(|a| a.n > 0));
+                let neg = live
+                    .iter()
+                    .filter(|c| c.lin.coeffs.get(*x).is_some_and(|a| a.n < 0));
+                pos.count() * neg.count()
+            })
+            .cloned()
+        else {
+            break;
+        };
+        let (with_x, without): (Vec<Cons>, Vec<Cons>) = live
+            .into_iter()
+            .partition(|c| c.lin.coeffs.contains_key(&x));
+        let mut next = without;
+        for p in with_x.iter().filter(|c| c.lin.coeffs[&x].n > 0) {
+            for n in with_x.iter().filter(|c| c.lin.coeffs[&x].n < 0) {
+                let (a, b) = (p.lin.coeffs[&x], n.lin.coeffs[&x].neg());
+                let mut lin = p.lin.scale(b)?.add(&n.lin.scale(a)?)?;
+                lin.coeffs.remove(&x);
+                let kind = if p.kind == Kind::Lt || n.kind == Kind::Lt {
+                    Kind::Lt
+                } else {
+                    Kind::Le
+                };
+                next.push(Cons { lin, kind });
+            }
+        }
+        steps.push(Step::Bounds(x, with_x));
+        cons = next;
+    }
+
+    // Back-substitution, preferring integral values.
+    let mut point: BTreeMap<String, Q> = BTreeMap::new();
+    for step in steps.into_iter().rev() {
+        match step {
+            Step::Subst(x, expr) => {
+                let v = expr.eval(&point)?;
+                point.insert(x, v);
+            }
+            Step::Bounds(x, cs) => {
+                let mut lo: Option<(Q, bool)> = None;
+                let mut hi: Option<(Q, bool)> = None;
+                for c in &cs {
+                    let a = c.lin.coeffs[&x];
+                    let mut rest = c.lin.clone();
+                    rest.coeffs.remove(&x);
+                    let bound = rest.eval(&point)?.neg().div(a)?;
+                    let strict = c.kind == Kind::Lt;
+                    if a.n > 0 {
+                        if hi.map_or(Ok(true), |(h, hs)| {
+                            bound
+                                .cmp(h)
+                                .map(|o| o.is_lt() || (o.is_eq() && strict && !hs))
+                        })? {
+                            hi = Some((bound, strict));
+                        }
+                    } else if lo.map_or(Ok(true), |(l, ls)| {
+                        bound
+                            .cmp(l)
+                            .map(|o| o.is_gt() || (o.is_eq() && strict && !ls))
+                    })? {
+                        lo = Some((bound, strict));
+                    }
+                }
+                let above_lo = |v: Q| -> R<bool> {
+                    lo.map_or(Ok(true), |(l, s)| {
+                        v.cmp(l).map(|o| o.is_gt() || (!s && o.is_eq()))
+                    })
+                };
+                let below_hi = |v: Q| -> R<bool> {
+                    hi.map_or(Ok(true), |(h, s)| {
+                        v.cmp(h).map(|o| o.is_lt() || (!s && o.is_eq()))
+                    })
+                };
+                let mut cands: Vec<Q> = Vec::new();
+                match (lo, hi) {
+                    (Some((l, _)), _) => cands.extend([l.ceil(), l.ceil().add(Q::ONE)?]),
+                    (None, Some((h, _))) => cands.extend([h.floor(), h.floor().sub(Q::ONE)?]),
+                    (None, None) => cands.push(Q::ZERO),
+                }
+                if let (Some((l, _)), Some((h, _))) = (lo, hi) {
+                    cands.push(l.add(h)?.mul(Q::new(1, 2)?)?);
+                }
+                let mut chosen = None;
+                for v in cands {
+                    if above_lo(v)? && below_hi(v)? {
+                        chosen = Some(v);
+                        break;
+                    }
+                }
+                // Feasibility of the eliminated system guarantees a value; anything else is a bug.
+                point.insert(x, chosen.ok_or(GiveUp)?);
+            }
+        }
+    }
+    Ok(Some(point))
+}
+
+/// Decide a conjunction over integers (`ints`) and rationals (everything else).
+fn solve_mixed(
+    cons: &[Cons],
+    ints: &BTreeSet<String>,
+    budget: &mut Budget,
+) -> R<Option<BTreeMap<String, Q>>> {
+    let mut stack: Vec<Vec<Cons>> = vec![cons
+        .iter()
+        .map(|c| tighten(c, ints))
+        .collect::<R<Vec<_>>>()?];
+    let mut gave_up = false;
+    while let Some(cs) = stack.pop() {
+        budget.tick()?;
+        let point = match solve_rational(&cs) {
+            Ok(Some(p)) => p,
+            Ok(None) => continue,
+            Err(GiveUp) => {
+                gave_up = true;
+                continue;
+            }
+        };
+        let frac = point
+            .iter()
+            .find(|(v, q)| ints.contains(*v) && !q.is_int())
+            .map(|(v, q)| (v.clone(), *q));
+        let Some((x, q)) = frac else {
+            return Ok(Some(point));
+        };
+        // x ≤ ⌊q⌋  ∨  x ≥ ⌈q⌉.
+        let mut le = cs.clone();
+        le.push(Cons {
+            lin: Lin::var(&x).add(&Lin::constant(q.floor().neg()))?,
+            kind: Kind::Le,
+        });
+        let mut ge = cs;
+        ge.push(Cons {
+            lin: Lin::var(&x)
+                .scale(Q::int(-1))?
+                .add(&Lin::constant(q.ceil()))?,
+            kind: Kind::Le,
+        });
+        stack.push(ge);
+        stack.push(le);
+    }
+    if gave_up {
+        Err(GiveUp)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Depth-first case split over the disjunctions in `todo`, deciding each complete case.
+fn search(
+    mut todo: Vec<Nnf>,
+    mut acc: Vec<Cons>,
+    ints: &BTreeSet<String>,
+    budget: &mut Budget,
+) -> R<Option<BTreeMap<String, Q>>> {
+    // Absorb conjunctions and atoms; keep the disjunctions for splitting.
+    let mut ors: Vec<Vec<Nnf>> = Vec::new();
+    while let Some(f) = todo.pop() {
+        match f {
+            Nnf::Atom(c) => acc.push(c),
+            Nnf::And(fs) => todo.extend(fs),
+            Nnf::Or(fs) => ors.push(fs),
+        }
+    }
+    let Some(split) = ors.pop() else {
+        return solve_mixed(&acc, ints, budget);
+    };
+    // Prune: if the case is already infeasible over the rationals, so is every extension.
+    budget.tick()?;
+    let tightened: Vec<Cons> = acc.iter().map(|c| tighten(c, ints)).collect::<R<_>>()?;
+    if solve_rational(&tightened)?.is_none() {
+        return Ok(None);
+    }
+    let rest: Vec<Nnf> = ors.into_iter().map(Nnf::Or).collect();
+    let mut gave_up = false;
+    for branch in split {
+        let mut next = rest.clone();
+        next.push(branch);
+        match search(next, acc.clone(), ints, budget) {
+            Ok(Some(p)) => return Ok(Some(p)),
+            Ok(None) => {}
+            Err(GiveUp) if !budget.spent() => gave_up = true,
+            Err(GiveUp) => return Err(GiveUp),
+        }
+    }
+    if gave_up {
+        Err(GiveUp)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Decide the conjunction of `assertions`; symbols in `int_vars` are integers, every other
+/// symbol is real. Returns `Unknown` when the input is outside the fragment or too large, or
+/// once `deadline` passes.
+pub fn check_sat(
+    assertions: &[Sexp],
+    int_vars: &BTreeSet<String>,
+    deadline: Option<Instant>,
+) -> Status {
+    let run = || -> R<Status> {
+        let fs: Vec<Nnf> = assertions
+            .iter()
+            .map(|a| formula(a, true))
+            .collect::<R<_>>()?;
+        let mut budget = Budget {
+            checks: 0,
+            deadline,
+        };
+        match search(fs.clone(), Vec::new(), int_vars, &mut budget)? {
+            None => Ok(Status::Unsat),
+            Some(point) => {
+                // Independent check of the model against the original formulas.
+                if fs.iter().all(|f| holds(f, &point).unwrap_or(false)) {
+                    Ok(Status::Sat)
+                } else {
+                    Err(GiveUp)
+                }
+            }
+        }
+    };
+    run().unwrap_or(Status::Unknown)
+}
+
+fn holds(f: &Nnf, point: &BTreeMap<String, Q>) -> R<bool> {
+    Ok(match f {
+        Nnf::Atom(c) => c.holds(point)?,
+        Nnf::And(fs) => {
+            for f in fs {
+                if !holds(f, point)? {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+        Nnf::Or(fs) => {
+            for f in fs {
+                if holds(f, point)? {
+                    return Ok(true);
+                }
+            }
+            false
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(script: &str, ints: &[&str]) -> Status {
+        let asserts = crate::smt_minimize::parse_script(script).unwrap();
+        let ints: BTreeSet<String> = ints.iter().map(|s| s.to_string()).collect();
+        check_sat(&asserts, &ints, None)
+    }
+
+    #[test]
+    fn decides_integer_rational_and_disjunctive_fragments() {
+        // Parity: no integer solution, plenty of rational ones.
+        let parity = "(= (* 2 x) (+ (* 2 y) 1))";
+        assert_eq!(check(parity, &["x", "y"]), Status::Unsat);
+        assert_eq!(check(parity, &[]), Status::Sat);
+
+        // Mixed: `2x = r + 1` with `0 < r < 1` needs branch-and-bound on `x`.
+        let mixed = "(= (* 2 x) (+ r 1)) (< 0 r) (< r 1)";
+        assert_eq!(check(mixed, &["x"]), Status::Unsat);
+        assert_eq!(
+            check("(= (* 2 x) (+ r 1)) (< 0 r) (< r 2)", &["x"]),
+            Status::Sat
+        );
+        assert_eq!(check("(<= 0.5 r) (< r (/ 1 2))", &[]), Status::Unsat);
+
+        // Disjunctions, implications and `ite` (as the side constraints produce them).
+        let gap = "(or (<= x 0) (>= x 10)) (<= 3 x) (<= x N)";
+        assert_eq!(check(&gap.replace('N', "7"), &["x"]), Status::Unsat);
+        assert_eq!(check(&gap.replace('N', "12"), &["x"]), Status::Sat);
+        let congruence = "(=> (= a b) (= fa fb)) (= a b) (not (= fa fb))";
+        assert_eq!(check(congruence, &["a", "b", "fa", "fb"]), Status::Unsat);
+        let ite = "(ite (<= a b) (= m b) (= m a)) (< m a)";
+        assert_eq!(check(ite, &["a", "b", "m"]), Status::Unsat);
+        assert_eq!(
+            check("(distinct x y) (<= x y) (<= y x)", &["x", "y"]),
+            Status::Unsat
+        );
+
+        // Outside the fragment.
+        assert_eq!(check("(<= (* x y) 3)", &["x", "y"]), Status::Unknown);
+        assert_eq!(check("(<= (div x 2) 3)", &["x"]), Status::Unknown);
+    }
+
+    #[test]
+    fn branch_and_bound_splits_fractional_relaxations() {
+        // The relaxation of `3x + 5y = 1` is fractional and no single constraint tightens it
+        // away; the integer answers come from splitting on `x` and `y`.
+        let run = |script: &str| {
+            let asserts = crate::smt_minimize::parse_script(script).unwrap();
+            let fs: Vec<Nnf> = asserts.iter().map(|a| formula(a, true).unwrap()).collect();
+            let ints: BTreeSet<String> = ["x", "y"].iter().map(|s| s.to_string()).collect();
+            let mut budget = Budget {
+                checks: 0,
+                deadline: None,
+            };
+            let point = search(fs, Vec::new(), &ints, &mut budget).unwrap();
+            (point, budget.checks)
+        };
+        let (point, checks) = run("(= (+ (* 3 x) (* 5 y)) 1) (<= 0 x) (<= x 10)");
+        let point = point.unwrap();
+        assert!(point.values().all(|q| q.is_int()), "{point:?}");
+        assert!(checks > 1);
+        let (point, checks) = run("(= (+ (* 3 x) (* 5 y)) 1) (<= 0 x) (<= x 1) (<= 0 y)");
+        assert!(point.is_none());
+        assert!(checks > 1);
+    }
+
+    #[test]
+    fn strict_bounds_tighten_over_int_but_not_real() {
+        let open = "(< 0 x) (< x 1)";
+        assert_eq!(check(open, &["x"]), Status::Unsat);
+        assert_eq!(check(open, &[]), Status::Sat);
+        // `2 < x ≤ 3` keeps exactly `x = 3` over the integers.
+        assert_eq!(check("(< 2 x) (<= x 3)", &["x"]), Status::Sat);
+        assert_eq!(check("(< 2 x) (< x 3)", &["x"]), Status::Unsat);
+        assert_eq!(check("(< 2 x) (< x 3)", &[]), Status::Sat);
+        // `2x > 3` tightens to `x ≥ 2` over the integers.
+        assert_eq!(check("(> (* 2 x) 3) (<= x 1)", &["x"]), Status::Unsat);
+        assert_eq!(check("(> (* 2 x) 3) (<= x 2)", &[]), Status::Sat);
+    }
+
+    #[test]
+    fn gives_up_on_overflow_budget_and_deadline() {
+        // Coefficients beyond `i128`.
+        let big = "(<= (* 100000000000000000000 (* 100000000000000000000 x)) 1)";
+        assert_eq!(check(big, &["x"]), Status::Unknown);
+
+        // Unsat only after all 2^k cases: decided for k = 3, past `MAX_CHECKS` for k = 13.
+        let cube = |k: usize| {
+            let vars: Vec<String> = (0..k).map(|i| format!("x{i}")).collect();
+            let mut script: String = vars
+                .iter()
+                .map(|v| format!("(or (= {v} 0) (= {v} 1)) "))
+                .collect();
+            script.push_str(&format!("(= (+ {}) 20)", vars.join(" ")));
+            let ints: Vec<&str> = vars.iter().map(|s| s.as_str()).collect();
+            check(&script, &ints)
+        };
+        assert_eq!(cube(3), Status::Unsat);
+        assert_eq!(cube(13), Status::Unknown);
+
+        // A passed deadline gives up even on an easy query.
+        let asserts = crate::smt_minimize::parse_script("(< 0 x) (< x 1)").unwrap();
+        let ints: BTreeSet<String> = ["x".to_string()].into();
+        assert_eq!(check_sat(&asserts, &ints, None), Status::Unsat);
+        assert_eq!(
+            check_sat(&asserts, &ints, Some(Instant::now())),
+            Status::Unknown
+        );
+    }
+}
@@ -30,16 +30,35 @@ use std::sync::{Mutex, OnceLock};
 use std::time::Duration;
 use tokio::process::Command;
 
+#[cfg(feature = "axi-agent")]
+pub mod agent;
 pub mod arxiv;
+pub mod autofix;
+pub mod axiom_audit;
 pub mod config;
+pub mod deprecations;
+pub mod heartbeats;
+pub mod hyp_rank;
 pub mod json_extract;
+pub mod lia_solver;
+pub mod lint;
 pub mod llm;
+pub mod llm_cache;
+pub mod llm_usage;
+pub mod llm_wire;
 #[cfg(feature = "lsp")]
 mod lsp_client;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock_llm;
 #[cfg(feature = "planner")]
 pub mod planner;
+pub mod profile;
+pub mod prompts;
+pub mod repair;
 pub mod review;
+pub mod sampling;
 pub mod smt_lia;
+pub mod smt_minimize;
 pub mod tree_search;
 
 #[derive(Debug, Clone)]
@@ -833,6 +852,9 @@ pub fn load_cursor_mcp_env_if_present() {
 }
 
 /// Load dotenv with covolume-style “super-workspace” convenience:
+/// - load the `[llm]` provider registry from `<repo_root>/proofpatch.toml`
+/// - install the repo's prompt templates (`[prompts]`, see `prompts`)
+/// - point the LLM response cache at `<repo_root>/.generated/`
 /// - load `<repo_root>/.env`
 /// - if still no API keys, optionally scan sibling dirs (one-level deep) for a `.env`
 ///   containing `OPENROUTER_API_KEY` or `OPENAI_API_KEY`
@@ -841,6 +863,21 @@ pub fn load_cursor_mcp_env_if_present() {
 /// - `PROOFPATCH_DOTENV_SEARCH` (default: on): set to 0/false/off to disable
 /// - `PROOFPATCH_DOTENV_SEARCH_ROOT` (default: repo_root.parent): override search root
 pub fn load_dotenv_smart(repo_root: &Path) {
+    // Provider registry (`[llm]` in proofpatch.toml). LLM selection falls back to the built-ins
+    // on a broken config, which also drops `[llm.budget]` and `[llm.prices]`: say so, since a typo
+    // in any section would otherwise turn off the spend limit silently.
+    if let Err(e) = llm::load_provider_registry(repo_root) {
+        eprintln!(
+            "proofpatch: ignoring proofpatch.toml for LLM calls (no [llm] providers, prices or budget): {e}"
+        );
+        llm::set_provider_registry(None);
+    }
+    if let Err(e) = prompts::load_templates(repo_root) {
+        eprintln!("proofpatch: using the built-in prompt templates: {e}");
+        prompts::set_templates(None);
+    }
+    llm_cache::set_repo_root(repo_root);
+
     // Base: repo-local .env
     load_dotenv_if_present(repo_root);
 
@@ -2115,25 +2152,15 @@ pub fn build_proof_prompt(
 /// System prompt for proof suggestion (reused across CLI/MCP).
 ///
 /// Public invariant: this prompt is meant to be stable-ish, since it affects tool behavior.
+/// Rendered from the `proof_system` template (see `prompts`).
 pub fn proof_system_prompt() -> String {
-    [
-        "You are a Lean 4 proof assistant.",
-        "Return ONLY Lean code that replaces a single `sorry` (or `admit`) inside a `by` block.",
-        "No markdown fences. No commentary. No surrounding `theorem`/`lemma` header.",
-        "Prefer short tactic proofs (`simp`, `aesop`, `nlinarith`, `omega`, `ring_nf`) and existing lemmas.",
-        "If you cannot complete the proof, return a minimal partial proof with the smallest remaining goal(s).",
-    ]
-    .join("\n")
+    prompts::render("proof_system", &prompts::PromptVars::new())
 }
 
 /// User prompt for proof suggestion, given an excerpt (reused across CLI/MCP).
+/// Rendered from the `proof_user` template.
 pub fn proof_user_prompt(excerpt: &str) -> String {
-    format!(
-        "We are working in a Lean 4 + Mathlib project.\n\
-Here is the declaration context (excerpt):\n\n\
-{excerpt}\n\n\
-Task: provide the Lean proof code that replaces the `sorry`/`admit` (the proof term only)."
-    )
+    prompts::render("proof_user", &prompts::vars([("excerpt", excerpt)]))
 }
 
 pub fn build_rubberduck_prompt(
@@ -2141,6 +2168,7 @@ pub fn build_rubberduck_prompt(
     file_rel: &str,
     decl: &str,
     diagnostics: Option<&str>,
+    extras: &prompts::PromptExtras,
 ) -> Result<PromptPayload, String> {
     let repo_root = find_lean_repo_root(repo_root)?;
     load_dotenv_smart(&repo_root);
@@ -2152,30 +2180,7 @@ pub fn build_rubberduck_prompt(
         .map_err(|e| format!("failed to read {}: {}", p.display(), e))?;
     let excerpt = extract_decl_block(&txt, decl)?;
 
-    let system = [
-        "You are a Lean 4 proof assistant helping a human/agent debug and plan.",
-        "Do NOT write Lean code.",
-        "Return a structured plan for how to eliminate the next `sorry` / error, with small verifiable steps.",
-        "Prefer tactics that create tight feedback loops: `simp`, `simp?`, `aesop?`, `exact?`, `apply?`, `have`, `rcases`, `cases`, `by_cases`.",
-        "If a goal looks arithmetic/decidable, suggest `omega`, `nlinarith`, `linarith`, `ring_nf`, `norm_num` (only when applicable).",
-        "Output format (strict):",
-        "1) Key goal-shape guess (1-2 sentences).",
-        "2) 3 candidate next steps (each 1-3 lines), including what to inspect in goals/hypotheses.",
-        "3) If stuck: 5 lemma-name search keywords (Lean identifiers) likely to exist in mathlib.",
-    ]
-    .join("\n");
-
-    let mut user = String::new();
-    user.push_str("Context: we are editing a Lean 4 + mathlib file.\n");
-    if let Some(d) = diagnostics {
-        if !d.trim().is_empty() {
-            user.push_str("\nRecent Lean diagnostics (raw):\n");
-            user.push_str(d.trim());
-            user.push('\n');
-        }
-    }
-    user.push_str("\nDeclaration excerpt:\n\n");
-    user.push_str(&excerpt);
+    let system = prompts::render("rubberduck_system", &prompts::PromptVars::new());
 
     // Research hooks (optional).
     //
@@ -2190,8 +2195,6 @@ pub fn build_rubberduck_prompt(
         "Lean 4 proof strategy for arithmetic lemmas"
     };
     let web_q = format!("{q} mathlib Lean");
-    user.push_str("\n\nResearch (optional):\n");
-    user.push_str("- Suggested research plan (machine-readable JSON):\n");
     // Keep this as JSON so it can be copy/pasted into an agent runner or a script safely.
     let plan = serde_json::json!({
         "goal": "Collect a reliable statement and a Lean mapping plan.",
@@ -2228,8 +2231,16 @@ pub fn build_rubberduck_prompt(
             }
         }
     });
-    user.push_str(&plan.to_string());
-    user.push('\n');
+    let plan = plan.to_string();
+    let mut vars = prompts::vars([
+        ("excerpt", excerpt.as_str()),
+        ("excerpt_label", "Declaration excerpt"),
+        ("focus_label", decl),
+        ("diagnostics", diagnostics.unwrap_or("").trim()),
+        ("research_plan", plan.as_str()),
+    ]);
+    extras.add_to(&mut vars);
+    let user = prompts::render("rubberduck_user", &vars);
 
     let prompt_combined = format!("{system}\n\n{user}");
     let prompt_combined_chars = prompt_combined.chars().count();
@@ -2259,6 +2270,7 @@ pub fn build_rubberduck_prompt_from_excerpt(
     focus_label: &str,
     excerpt: &str,
     diagnostics: Option<&str>,
+    extras: &prompts::PromptExtras,
 ) -> Result<PromptPayload, String> {
     let repo_root = find_lean_repo_root(repo_root)?;
     load_dotenv_smart(&repo_root);
@@ -2267,38 +2279,13 @@ pub fn build_rubberduck_prompt_from_excerpt(
         return Err(format!("File not found: {}", p.display()));
     }
 
-    let system = [
-        "You are a Lean 4 proof assistant helping a human/agent debug and plan.",
-        "Do NOT write Lean code.",
-        "Return a structured plan for how to eliminate the next `sorry` / error, with small verifiable steps.",
-        "Prefer tactics that create tight feedback loops: `simp`, `simp?`, `aesop?`, `exact?`, `apply?`, `have`, `rcases`, `cases`, `by_cases`.",
-        "If a goal looks arithmetic/decidable, suggest `omega`, `nlinarith`, `linarith`, `ring_nf`, `norm_num` (only when applicable).",
-        "Output format (strict):",
-        "1) Key goal-shape guess (1-2 sentences).",
-        "2) 3 candidate next steps (each 1-3 lines), including what to inspect in goals/hypotheses.",
-        "3) If stuck: 5 lemma-name search keywords (Lean identifiers) likely to exist in mathlib.",
-    ]
-    .join("\n");
-
-    let mut user = String::new();
-    user.push_str("Context: we are editing a Lean 4 + mathlib file.\n");
-    if let Some(d) = diagnostics {
-        if !d.trim().is_empty() {
-            user.push_str("\nRecent Lean diagnostics (raw):\n");
-            user.push_str(d.trim());
-            user.push('\n');
-        }
-    }
-    user.push_str("\nFocused excerpt:\n\n");
-    user.push_str(excerpt);
+    let system = prompts::render("rubberduck_system", &prompts::PromptVars::new());
 
     // Research hooks (optional).
     //
     // Note: these are suggestions embedded into the prompt; proofpatch does not execute them.
     let q = focus_label;
     let web_q = format!("{q} mathlib Lean");
-    user.push_str("\n\nResearch (optional):\n");
-    user.push_str("- Suggested research plan (machine-readable JSON):\n");
     let plan = serde_json::json!({
         "goal": "Collect a reliable statement and a Lean mapping plan.",
         "steps": [
@@ -2328,8 +2315,16 @@ pub fn build_rubberduck_prompt_from_excerpt(
             }
         }
     });
-    user.push_str(&plan.to_string());
-    user.push('\n');
+    let plan = plan.to_string();
+    let mut vars = prompts::vars([
+        ("excerpt", excerpt),
+        ("excerpt_label", "Focused excerpt"),
+        ("focus_label", focus_label),
+        ("diagnostics", diagnostics.unwrap_or("").trim()),
+        ("research_plan", plan.as_str()),
+    ]);
+    extras.add_to(&mut vars);
+    let user = prompts::render("rubberduck_user", &vars);
 
     let prompt_combined = format!("{system}\n\n{user}");
     let prompt_combined_chars = prompt_combined.chars().count();
@@ -2359,6 +2354,7 @@ pub fn build_region_patch_prompt(
     start_line_1: usize,
     end_line_1_inclusive: usize,
     diagnostics: Option<&str>,
+    extras: &prompts::PromptExtras,
 ) -> Result<PromptPayload, String> {
     let repo_root = find_lean_repo_root(repo_root)?;
     load_dotenv_smart(&repo_root);
@@ -2389,33 +2385,16 @@ pub fn build_region_patch_prompt(
     let end0 = usize::min(end_line_1_inclusive, lines.len());
     let excerpt = lines[(start_line_1 - 1)..end0].join("\n");
 
-    let system = [
-        "You are a Lean 4 proof assistant.",
-        "Return ONLY Lean code that replaces a single `sorry` inside the provided region.",
-        "No markdown fences. No commentary.",
-        "Do not repeat the surrounding `theorem`/`lemma` header.",
-        "Prefer short tactic proofs (`simp`, `aesop`, `nlinarith`, `omega`, `ring_nf`) and existing lemmas.",
-        "If you cannot complete the proof, return a minimal partial proof with the smallest remaining goal(s).",
-    ]
-    .join("\n");
-
-    let mut user = String::new();
-    user.push_str("We are working in a Lean 4 + Mathlib project.\n");
-    if let Some(d) = diagnostics {
-        if !d.trim().is_empty() {
-            user.push_str("\nRecent Lean diagnostics (raw):\n");
-            user.push_str(d.trim());
-            user.push('\n');
-        }
-    }
-    user.push_str(&format!(
-        "\nHere is a focused region (lines {}..={}):\n\n",
-        start_line_1, end_line_1_inclusive
-    ));
-    user.push_str(&excerpt);
-    user.push_str(
-        "\n\nTask: provide the Lean proof code that replaces the `sorry` (the proof term only).",
-    );
+    let system = prompts::render("region_patch_system", &prompts::PromptVars::new());
+    let (start, end) = (start_line_1.to_string(), end_line_1_inclusive.to_string());
+    let mut vars = prompts::vars([
+        ("excerpt", excerpt.as_str()),
+        ("diagnostics", diagnostics.unwrap_or("").trim()),
+        ("start_line", start.as_str()),
+        ("end_line", end.as_str()),
+    ]);
+    extras.add_to(&mut vars);
+    let user = prompts::render("region_patch_user", &vars);
 
     let prompt_combined = format!("{system}\n\n{user}");
     let prompt_combined_chars = prompt_combined.chars().count();
@@ -2488,27 +2467,10 @@ fn any_decl_header_regex() -> Result<Regex, String> {
         .map_err(|e| format!("invalid decl-header regex: {}", e))
 }
 
-/// Best-effort: find the nearest declaration header at/above `focus_line_1`.
+/// Best-effort block comment masking (`/- ... -/`, nested) for line-oriented scanners.
 ///
-/// This is intentionally shallow (regex-based) and only meant to support UX flows like
-/// `goal-dump-nearest --allow-sorry-free --focus-line ...`, where we need a decl name to
-/// synthesize a shadow declaration.
-pub fn nearest_decl_header_in_text(
-    text: &str,
-    focus_line_1: usize,
-    max_scan_lines: usize,
-) -> Option<NearbyDecl> {
-    let max_scan_lines = max_scan_lines.max(1).min(20_000);
-    let lines: Vec<&str> = text.lines().collect();
-    if lines.is_empty() {
-        return None;
-    }
-    let focus0 = focus_line_1.saturating_sub(1).min(lines.len() - 1);
-
-    let decl_pat = any_decl_header_regex().ok()?;
-
-    // Best-effort block comment masking (`/- ... -/`, nested). This is not a full Lean lexer;
-    // it is only intended to avoid obvious false positives.
+/// This is not a full Lean lexer; it is only intended to avoid obvious false positives.
+pub(crate) fn block_comment_mask(lines: &[&str]) -> Vec<bool> {
     let mut in_block: Vec<bool> = vec![false; lines.len()];
     let mut depth: usize = 0;
     for (idx, ln) in lines.iter().enumerate() {
@@ -2544,6 +2506,28 @@ pub fn nearest_decl_header_in_text(
         // (This is conservative and fine for our purposes.)
         in_block[idx] = depth > 0;
     }
+    in_block
+}
+
+/// Best-effort: find the nearest declaration header at/above `focus_line_1`.
+///
+/// This is intentionally shallow (regex-based) and only meant to support UX flows like
+/// `goal-dump-nearest --allow-sorry-free --focus-line ...`, where we need a decl name to
+/// synthesize a shadow declaration.
+pub fn nearest_decl_header_in_text(
+    text: &str,
+    focus_line_1: usize,
+    max_scan_lines: usize,
+) -> Option<NearbyDecl> {
+    let max_scan_lines = max_scan_lines.max(1).min(20_000);
+    let lines: Vec<&str> = text.lines().collect();
+    if lines.is_empty() {
+        return None;
+    }
+    let focus0 = focus_line_1.saturating_sub(1).min(lines.len() - 1);
+
+    let decl_pat = any_decl_header_regex().ok()?;
+    let in_block = block_comment_mask(&lines);
 
     let mut steps = 0usize;
     let mut i0 = focus0;
@@ -2573,6 +2557,119 @@ pub fn nearest_decl_header_in_text(
     None
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedDecl {
+    /// 1-based line number where the declaration header begins.
+    pub line: usize,
+    /// 1-based last line of the declaration (best-effort: up to the next header/scope command).
+    pub end_line: usize,
+    /// The keyword: theorem|lemma|def|abbrev|instance.
+    pub kind: String,
+    /// The identifier token after the keyword, as written.
+    pub name: String,
+    /// `name` qualified by the enclosing `namespace` blocks (`_root_.` stripped).
+    pub full_name: String,
+    /// The full header line text.
+    pub header: String,
+}
+
+/// Best-effort declaration index for a Lean file.
+///
+/// Like `nearest_decl_header_in_text`, this is regex/line based. It additionally tracks
+/// `namespace`/`section`/`end` scopes so callers can refer to declarations by their fully
+/// qualified name from *outside* those scopes (e.g. commands appended at the end of the file).
+pub fn index_decls_in_text(text: &str) -> Vec<IndexedDecl> {
+    let lines: Vec<&str> = text.lines().collect();
+    let Ok(decl_pat) = any_decl_header_regex() else {
+        return Vec::new();
+    };
+    let Ok(scope_pat) = Regex::new(
+        r"^\s*(?:(namespace)\s+(\S+)|(?:noncomputable\s+)?(section)\b\s*(\S+)?|(end)\b\s*(\S+)?)",
+    ) else {
+        return Vec::new();
+    };
+    let in_block = block_comment_mask(&lines);
+
+    // (is_namespace, name)
+    let mut scopes: Vec<(bool, String)> = Vec::new();
+    let mut out: Vec<IndexedDecl> = Vec::new();
+    // Index into `out` of the decl whose span is still open.
+    let mut open: Option<usize> = None;
+    let close_open = |out: &mut Vec<IndexedDecl>, open: &mut Option<usize>, end0_excl: usize| {
+        if let Some(k) = open.take() {
+            let mut end0 = end0_excl.saturating_sub(1).max(out[k].line - 1);
+            // Trailing blank lines and comments (typically the next decl's docstring) are not
+            // part of this declaration.
+            while end0 > out[k].line - 1 {
+                let t = lines[end0].trim();
+                let is_comment = t.starts_with("--") || t.starts_with("/-") || t.ends_with("-/");
+                if !(t.is_empty() || is_comment || in_block[end0]) {
+                    break;
+                }
+                end0 -= 1;
+            }
+            out[k].end_line = end0 + 1;
+        }
+    };
+
+    for (i0, ln) in lines.iter().enumerate() {
+        let t = ln.trim_start();
+        if t.is_empty() || t.starts_with("--") || in_block[i0] {
+            continue;
+        }
+        if let Some(cap) = scope_pat.captures(ln) {
+            close_open(&mut out, &mut open, i0);
+            if cap.get(1).is_some() {
+                let nm = cap.get(2).map(|m| m.as_str()).unwrap_or("").to_string();
+                scopes.push((true, nm));
+            } else if cap.get(3).is_some() {
+                let nm = cap.get(4).map(|m| m.as_str()).unwrap_or("").to_string();
+                scopes.push((false, nm));
+            } else if cap.get(5).is_some() {
+                let nm = cap.get(6).map(|m| m.as_str()).unwrap_or("");
+                if nm.is_empty() {
+                    scopes.pop();
+                } else if let Some(pos) = scopes.iter().rposition(|(_, s)| s == nm) {
+                    scopes.truncate(pos);
+                }
+            }
+            continue;
+        }
+        let Some(cap) = decl_pat.captures(ln) else {
+            continue;
+        };
+        let kind = cap.get(1).map(|m| m.as_str()).unwrap_or("").to_string();
+        let name = cap.get(2).map(|m| m.as_str()).unwrap_or("").to_string();
+        // Anonymous instances (`instance : Foo`, `instance [Bar] : Foo`) have no usable name.
+        if kind.is_empty() || name.is_empty() || name.starts_with(['[', '{', '⦃']) {
+            continue;
+        }
+        close_open(&mut out, &mut open, i0);
+        let full_name = if let Some(root) = name.strip_prefix("_root_.") {
+            root.to_string()
+        } else {
+            let mut parts: Vec<&str> = scopes
+                .iter()
+                .filter(|(is_ns, _)| *is_ns)
+                .map(|(_, s)| s.as_str())
+                .collect();
+            parts.push(&name);
+            parts.join(".")
+        };
+        out.push(IndexedDecl {
+            line: i0 + 1,
+            end_line: i0 + 1,
+            kind,
+            name,
+            full_name,
+            header: ln.to_string(),
+        });
+        open = Some(out.len() - 1);
+    }
+    close_open(&mut out, &mut open, lines.len());
+    out
+}
+
 fn extract_decl_span(lines: &[&str], decl_name: &str) -> Result<(usize, usize, String), String> {
     let pat = decl_header_regex(decl_name)?;
     let start0 = lines
@@ -2661,6 +2758,33 @@ pub fn build_context_pack(
 
     let txt = std::fs::read_to_string(&p)
         .map_err(|e| format!("failed to read {}: {}", p.display(), e))?;
+    let mut pack = build_context_pack_in_text(
+        &txt,
+        decl,
+        line_1,
+        context_lines,
+        nearby_lines,
+        max_nearby_decls,
+        max_imports,
+    )?;
+    pack.repo_root = repo_root.display().to_string();
+    pack.file_rel = file_rel.to_string();
+    pack.file_abs = p.display().to_string();
+    Ok(pack)
+}
+
+/// Like [`build_context_pack`], but over in-memory file text (e.g. an unsaved working copy).
+///
+/// `repo_root`, `file_rel` and `file_abs` are left empty for the caller to fill in.
+pub fn build_context_pack_in_text(
+    txt: &str,
+    decl: Option<&str>,
+    line_1: Option<usize>,
+    context_lines: usize,
+    nearby_lines: usize,
+    max_nearby_decls: usize,
+    max_imports: usize,
+) -> Result<ContextPack, String> {
     let lines: Vec<&str> = txt.lines().collect();
     let file_lines = lines.len();
     let file_bytes = txt.as_bytes().len();
@@ -2732,9 +2856,9 @@ pub fn build_context_pack(
     }
 
     Ok(ContextPack {
-        repo_root: repo_root.display().to_string(),
-        file_rel: file_rel.to_string(),
-        file_abs: p.display().to_string(),
+        repo_root: String::new(),
+        file_rel: String::new(),
+        file_abs: String::new(),
         file_lines,
         file_bytes,
         imports,
@@ -3507,19 +3631,25 @@ pub async fn verify_lean_file(
     })
 }
 
-fn insert_after_imports(text: &str, insert: &str) -> String {
-    let mut lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
-    if lines.is_empty() {
-        return insert.to_string();
-    }
-
+/// 0-based line index just past the last `import` line (scanning the first 200 lines).
+pub(crate) fn import_block_end<S: AsRef<str>>(lines: &[S]) -> usize {
     let mut insert_at = 0usize;
     for (i, ln) in lines.iter().take(200).enumerate() {
-        let t = ln.trim_start();
+        let t = ln.as_ref().trim_start();
         if t.starts_with("import ") || t == "import" {
             insert_at = i + 1;
         }
     }
+    insert_at
+}
+
+pub(crate) fn insert_after_imports(text: &str, insert: &str) -> String {
+    let mut lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
+    if lines.is_empty() {
+        return insert.to_string();
+    }
+
+    let insert_at = import_block_end(&lines);
 
     let insert = insert.trim_matches('\n');
     if !insert.trim().is_empty() {
@@ -3609,7 +3739,7 @@ pub fn derive_candidates_from_goal_pretty(goal_pretty: &str) -> Vec<String> {
     derive_candidates_from_goal_pretty_with_hint_rules(goal_pretty, &[])
 }
 
-fn is_lean_ident_char(ch: char) -> bool {
+pub(crate) fn is_lean_ident_char(ch: char) -> bool {
     ch.is_alphanumeric() || ch == '_' || ch == '\''
 }
 
@@ -0,0 +1,346 @@
+//! Style lint findings (`lint-style`).
+//!
+//! Two sources feed the same `LintFinding` shape:
+//!
+//! - `lake exe lint-style` output (Mathlib's text linter), in any of the formats it has used:
+//!   - `Mathlib/Foo.lean:12: ERR_LIN: Line has more than 100 characters`
+//!   - `::ERR file=Mathlib/Foo.lean,line=12,code=ERR_LIN::...` (`--github`)
+//!   - `Mathlib/Foo.lean : line 12 : ERR_LIN : ...` (older Python linter)
+//! - a small native checker for the mechanical rules in `RULES`, which works without a
+//!   `lint-style` executable and is what the fixes in `autofix` are driven by.
+
+use crate::config::LintConfig;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// Native rules, in the order fixes are applied.
+pub const RULES: &[&str] = &[
+    "trailing_whitespace",
+    "fun_arrow",
+    "dollar",
+    "long_line",
+    "copyright",
+];
+
+/// Rules checked when `[lint] rules` is unset: the mechanical ones `lint-style` itself reports.
+///
+/// `fun_arrow`, `dollar` and `copyright` are project conventions rather than linter errors, so
+/// they only run when listed in `[lint] rules`.
+pub const DEFAULT_RULES: &[&str] = &["trailing_whitespace", "long_line"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LintFinding {
+    pub file: String,
+    /// 1-based line.
+    pub line: usize,
+    /// 0-based column (Unicode scalar values), when known.
+    pub col: Option<usize>,
+    /// One of `RULES`, or a lowercased linter code for rules we do not know.
+    pub rule: String,
+    /// The linter's own code (e.g. `ERR_LIN`), for findings parsed from `lint-style` output.
+    pub code: Option<String>,
+    pub message: String,
+    /// `"proofpatch"` (native check) or `"lint-style"`.
+    pub source: String,
+    pub fixable: bool,
+}
+
+/// Map a linter code / message to one of `RULES` when possible.
+pub fn rule_for(code: Option<&str>, message: &str) -> String {
+    match code {
+        Some("ERR_TWS") => return "trailing_whitespace".to_string(),
+        Some("ERR_COP") => return "copyright".to_string(),
+        Some("ERR_LIN") => return "long_line".to_string(),
+        _ => {}
+    }
+    let m = message.to_lowercase();
+    if m.contains("trailing whitespace") {
+        "trailing_whitespace".to_string()
+    } else if m.contains("copyright") {
+        "copyright".to_string()
+    } else if m.contains("more than") && m.contains("character") {
+        "long_line".to_string()
+    } else if m.contains("`$`") || m.contains("<|") {
+        "dollar".to_string()
+    } else if m.contains("fun") && (m.contains("↦") || m.contains("=>")) {
+        "fun_arrow".to_string()
+    } else if let Some(c) = code {
+        c.to_lowercase()
+    } else {
+        "unknown".to_string()
+    }
+}
+
+/// Parse `lake exe lint-style` output into findings (`fixable` is filled in by the caller).
+pub fn parse_lint_style_output(text: &str) -> Vec<LintFinding> {
+    let (Ok(gh), Ok(plain), Ok(legacy)) = (
+        Regex::new(
+            r"^::\w+\s+file=([^,]+),line=(\d+)(?:,col=(\d+))?(?:,[^:]*?code=(\w+))?[^:]*::(.*)$",
+        ),
+        Regex::new(
+            r"^(?:(?:error|warning):\s*)?(\S[^:]*\.lean):(\d+)(?::(\d+))?:?\s*(?:(ERR_[A-Z_]+)\s*:?\s*)?(.*)$",
+        ),
+        Regex::new(r"^(\S[^:]*\.lean)\s*:\s*line\s+(\d+)\s*:\s*(ERR_[A-Z_]+)\s*:\s*(.*)$"),
+    ) else {
+        return Vec::new();
+    };
+    let mut out: Vec<LintFinding> = Vec::new();
+    let mut push = |file: &str, line: &str, col: Option<&str>, code: Option<&str>, msg: &str| {
+        let Ok(line) = line.parse::<usize>() else {
+            return;
+        };
+        let msg = msg.trim();
+        out.push(LintFinding {
+            file: file.trim().to_string(),
+            line,
+            col: col.and_then(|c| c.parse().ok()),
+            rule: rule_for(code, msg),
+            code: code.map(|c| c.to_string()),
+            message: msg.to_string(),
+            source: "lint-style".to_string(),
+            fixable: false,
+        });
+    };
+    for ln in text.lines() {
+        let ln = ln.trim_end();
+        if let Some(c) = gh.captures(ln) {
+            let code = c.get(4).map(|m| m.as_str());
+            let msg = c[5].to_string();
+            // The message often repeats `file:line` (and the code) in front; keep the tail.
+            let msg = match code.and_then(|k| msg.find(k).map(|i| i + k.len())) {
+                Some(i) => msg[i..].trim_start_matches([':', ' ']).to_string(),
+                None => msg,
+            };
+            push(&c[1], &c[2], c.get(3).map(|m| m.as_str()), code, &msg);
+        } else if let Some(c) = legacy.captures(ln) {
+            push(&c[1], &c[2], None, Some(&c[3]), &c[4]);
+        } else if let Some(c) = plain.captures(ln) {
+            // Lean diagnostics (`file:line:col: error: ...`) are not lint findings.
+            let msg = &c[5];
+            if c.get(4).is_none()
+                && (c.get(3).is_some() || msg.starts_with("error") || msg.starts_with("warning"))
+            {
+                continue;
+            }
+            push(
+                &c[1],
+                &c[2],
+                c.get(3).map(|m| m.as_str()),
+                c.get(4).map(|m| m.as_str()),
+                msg,
+            );
+        }
+    }
+    out
+}
+
+/// Per-line masks of which chars are code (not inside a comment or string literal).
+pub(crate) fn code_masks(lines: &[&str]) -> Vec<Vec<bool>> {
+    let mut depth: usize = 0;
+    let mut out = Vec::with_capacity(lines.len());
+    for ln in lines {
+        let chars: Vec<char> = ln.chars().collect();
+        let mut mask = vec![false; chars.len()];
+        let mut in_str = false;
+        let mut i = 0usize;
+        while i < chars.len() {
+            let c = chars[i];
+            let next = chars.get(i + 1).copied();
+            if depth > 0 {
+                if c == '/' && next == Some('-') {
+                    depth += 1;
+                    i += 2;
+                } else if c == '-' && next == Some('/') {
+                    depth -= 1;
+                    i += 2;
+                } else {
+                    i += 1;
+                }
+                continue;
+            }
+            if in_str {
+                if c == '\\' {
+                    i += 2;
+                    continue;
+                }
+                if c == '"' {
+                    in_str = false;
+                }
+                i += 1;
+                continue;
+            }
+            match (c, next) {
+                ('-', Some('-')) => break,
+                ('/', Some('-')) => {
+                    depth += 1;
+                    i += 2;
+                }
+                ('"', _) => {
+                    in_str = true;
+                    i += 1;
+                }
+                _ => {
+                    mask[i] = true;
+                    i += 1;
+                }
+            }
+        }
+        out.push(mask);
+    }
+    out
+}
+
+/// 0-based char columns of `fun <binders> =>` arrows on a line (code only).
+///
+/// `fun | pat => ...` (pattern-matching lambdas) and binders spanning lines are left alone.
+pub(crate) fn fun_arrow_cols(chars: &[char], mask: &[bool]) -> Vec<usize> {
+    let is_code = |i: usize| mask.get(i).copied().unwrap_or(false);
+    let mut out = Vec::new();
+    let mut i = 0usize;
+    while i + 3 <= chars.len() {
+        let starts_word = i == 0 || !crate::is_lean_ident_char(chars[i - 1]);
+        let ends_word = chars
+            .get(i + 3)
+            .map(|c| !crate::is_lean_ident_char(*c))
+            .unwrap_or(true);
+        if !(is_code(i) && starts_word && ends_word && chars[i..i + 3] == ['f', 'u', 'n']) {
+            i += 1;
+            continue;
+        }
+        let mut j = i + 3;
+        while j < chars.len() && chars[j].is_whitespace() {
+            j += 1;
+        }
+        if j == i + 3 || j >= chars.len() || chars[j] == '|' {
+            i = j.max(i + 1);
+            continue;
+        }
+        while j < chars.len() {
+            if !is_code(j) {
+                break;
+            }
+            if chars[j] == '↦' {
+                break;
+            }
+            if chars[j] == '=' && chars.get(j + 1) == Some(&'>') {
+                out.push(j);
+                break;
+            }
+            j += 1;
+        }
+        i += 3;
+    }
+    out
+}
+
+/// 0-based char columns of a free-standing `$` (function application) on a line (code only).
+pub(crate) fn dollar_cols(chars: &[char], mask: &[bool]) -> Vec<usize> {
+    (0..chars.len())
+        .filter(|&i| {
+            chars[i] == '$'
+                && mask.get(i).copied().unwrap_or(false)
+                && (i == 0 || chars[i - 1].is_whitespace())
+                && chars.get(i + 1).map(|c| c.is_whitespace()).unwrap_or(true)
+        })
+        .collect()
+}
+
+/// Does the file start with a copyright header comment?
+pub(crate) fn has_copyright_header(text: &str) -> bool {
+    let head = text.trim_start();
+    head.starts_with("/-")
+        && head
+            .find("-/")
+            .map(|end| head[..end].contains("Copyright"))
+            .unwrap_or(false)
+}
+
+fn rule_enabled(cfg: &LintConfig, rule: &str) -> bool {
+    match &cfg.rules {
+        Some(rs) => rs.iter().any(|r| r == rule),
+        None => DEFAULT_RULES.contains(&rule),
+    }
+}
+
+/// Run the native checks on `text`.
+pub fn lint_text(file: &str, text: &str, cfg: &LintConfig) -> Vec<LintFinding> {
+    let lines: Vec<&str> = text.lines().collect();
+    let masks = code_masks(&lines);
+    let mut out: Vec<LintFinding> = Vec::new();
+    let mut push = |line0: usize, col: usize, rule: &str, message: String, fixable: bool| {
+        out.push(LintFinding {
+            file: file.to_string(),
+            line: line0 + 1,
+            col: Some(col),
+            rule: rule.to_string(),
+            code: None,
+            message,
+            source: "proofpatch".to_string(),
+            fixable,
+        });
+    };
+
+    if rule_enabled(cfg, "copyright") && !has_copyright_header(text) {
+        push(
+            0,
+            0,
+            "copyright",
+            "missing copyright header".to_string(),
+            cfg.copyright_header.is_some(),
+        );
+    }
+    for (i0, ln) in lines.iter().enumerate() {
+        let chars: Vec<char> = ln.chars().collect();
+        let mask = &masks[i0];
+        let trimmed_len = ln.trim_end().chars().count();
+        if rule_enabled(cfg, "trailing_whitespace") && trimmed_len < chars.len() {
+            push(
+                i0,
+                trimmed_len,
+                "trailing_whitespace",
+                "trailing whitespace".to_string(),
+                true,
+            );
+        }
+        if rule_enabled(cfg, "fun_arrow") {
+            for col in fun_arrow_cols(&chars, mask) {
+                push(
+                    i0,
+                    col,
+                    "fun_arrow",
+                    "use `fun x ↦ ...` instead of `fun x => ...`".to_string(),
+                    true,
+                );
+            }
+        }
+        if rule_enabled(cfg, "dollar") {
+            for col in dollar_cols(&chars, mask) {
+                push(
+                    i0,
+                    col,
+                    "dollar",
+                    "use `<|` instead of `$`".to_string(),
+                    true,
+                );
+            }
+        }
+        if rule_enabled(cfg, "long_line")
+            && trimmed_len > cfg.max_line_length
+            && !ln.contains("http://")
+            && !ln.contains("https://")
+        {
+            let fixable = crate::autofix::wrap_long_line(ln, mask, cfg.max_line_length).is_some();
+            push(
+                i0,
+                cfg.max_line_length,
+                "long_line",
+                format!(
+                    "line has {trimmed_len} characters (max {})",
+                    cfg.max_line_length
+                ),
+                fixable,
+            );
+        }
+    }
+    out
+}
@@ -1,6 +1,11 @@
+use crate::config::{LlmConfig, LlmProviderConfig, WireFormat};
+use crate::llm_cache::{self, LlmCacheMode};
+use crate::llm_usage::{self, TokenUsage};
+use crate::llm_wire;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::borrow::Cow;
+use std::path::Path;
 use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +21,8 @@ pub struct StructuredChatResult<T> {
     /// - "content_json": parsed from `message.content` as JSON
     /// - "content_extract": extracted JSON blob from `message.content`
     pub mode: String,
+    /// Token counts (and cost, when priced) for the call that produced `value`.
+    pub usage: TokenUsage,
     /// Raw provider response (OpenAI-compatible envelope), with injected provider/model metadata.
     pub raw: Value,
 }
@@ -29,6 +36,7 @@ pub struct ChatCompletionResult {
     /// Which env var was used for the provider's model (when applicable).
     pub model_env: String,
     pub content: String,
+    pub usage: TokenUsage,
     pub raw: Value,
 }
 
@@ -40,80 +48,258 @@ pub struct SelectedProviderInfo {
     pub model: String,
     pub model_source: String,
     pub model_env: String,
+    /// `openai` (chat completions) or `anthropic` (Messages API).
+    pub wire_format: String,
+    /// Where the provider entry came from: `builtin`, `config`, or `builtin+config`.
+    pub source: String,
+    /// Where the order came from: `env` (`PROOFPATCH_PROVIDER_ORDER`), `config`, or `default`.
+    pub order_source: String,
+    pub order: Vec<String>,
+    /// Human-readable explanation of the pick.
+    pub reason: String,
+    /// Providers tried before the pick, and why each was passed over.
+    pub skipped: Vec<SkippedProvider>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedProvider {
+    pub provider: String,
+    pub reason: String,
 }
 
 #[derive(Debug, Clone)]
 struct Provider {
-    name: &'static str,
+    name: String,
+    base_url: String,
+    api_key_env: Option<String>,
+    model_env: String,
+    /// Model pinned in `proofpatch.toml` (below the model env var, above defaults).
+    model: Option<String>,
+    headers: Vec<(String, String)>,
+    temperature: f64,
+    max_tokens: Option<u64>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    /// Check the endpoint answers before selecting it (local servers).
+    probe: bool,
+    source: &'static str,
+    wire: WireFormat,
+}
+
+fn env_value(key: &str) -> Option<String> {
+    std::env::var(key)
+        .ok()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+fn builtin_provider(
+    name: &str,
     base_url: String,
-    api_key_env: Option<&'static str>,
-    model_env: &'static str,
+    api_key_env: Option<&str>,
+    model_env: &str,
+) -> Provider {
+    Provider {
+        name: name.to_string(),
+        base_url: base_url.trim_end_matches('/').to_string(),
+        api_key_env: api_key_env.map(|s| s.to_string()),
+        model_env: model_env.to_string(),
+        model: None,
+        headers: Vec::new(),
+        temperature: 0.2,
+        max_tokens: None,
+        timeout: None,
+        connect_timeout: None,
+        probe: name == "ollama",
+        source: "builtin",
+        wire: WireFormat::OpenAi,
+    }
 }
 
 fn providers_from_env() -> Vec<Provider> {
+    let mut openrouter = builtin_provider(
+        "openrouter",
+        env_value("OPENROUTER_BASE_URL").unwrap_or_else(|| "https://openrouter.ai/api/v1".into()),
+        Some("OPENROUTER_API_KEY"),
+        "OPENROUTER_MODEL",
+    );
+    if let Some(site) = env_value("OPENROUTER_SITE_URL") {
+        openrouter.headers.push(("HTTP-Referer".to_string(), site));
+    }
+    if let Some(app) = env_value("OPENROUTER_APP_NAME") {
+        openrouter.headers.push(("X-Title".to_string(), app));
+    }
     vec![
-        Provider {
-            name: "ollama",
-            base_url: std::env::var("OLLAMA_HOST")
-                .ok()
-                .filter(|s| !s.trim().is_empty())
-                .unwrap_or_else(|| "http://localhost:11434".to_string())
-                .trim_end_matches('/')
-                .to_string(),
-            api_key_env: None,
-            model_env: "OLLAMA_MODEL",
-        },
-        Provider {
-            name: "groq",
-            base_url: "https://api.groq.com/openai/v1".to_string(),
-            api_key_env: Some("GROQ_API_KEY"),
-            model_env: "GROQ_MODEL",
-        },
-        Provider {
-            name: "openrouter",
-            base_url: std::env::var("OPENROUTER_BASE_URL")
-                .ok()
-                .filter(|s| !s.trim().is_empty())
-                .unwrap_or_else(|| "https://openrouter.ai/api/v1".to_string())
-                .trim_end_matches('/')
-                .to_string(),
-            api_key_env: Some("OPENROUTER_API_KEY"),
-            model_env: "OPENROUTER_MODEL",
-        },
+        builtin_provider(
+            "ollama",
+            env_value("OLLAMA_HOST").unwrap_or_else(|| "http://localhost:11434".into()),
+            None,
+            "OLLAMA_MODEL",
+        ),
+        builtin_provider(
+            "groq",
+            "https://api.groq.com/openai/v1".to_string(),
+            Some("GROQ_API_KEY"),
+            "GROQ_MODEL",
+        ),
+        openrouter,
         // Not in the original Python router, but used by covolume's llm_review.
+        builtin_provider(
+            "openai",
+            env_value("OPENAI_BASE_URL").unwrap_or_else(|| "https://api.openai.com/v1".into()),
+            Some("OPENAI_API_KEY"),
+            "OPENAI_MODEL",
+        ),
+        // Native Messages API. Not in the default order: name it in `PROOFPATCH_PROVIDER_ORDER`,
+        // `[llm] provider_order`, or add an `[llm.providers.anthropic]` entry.
         Provider {
-            name: "openai",
-            base_url: std::env::var("OPENAI_BASE_URL")
-                .ok()
-                .filter(|s| !s.trim().is_empty())
-                .unwrap_or_else(|| "https://api.openai.com/v1".to_string())
-                .trim_end_matches('/')
-                .to_string(),
-            api_key_env: Some("OPENAI_API_KEY"),
-            model_env: "OPENAI_MODEL",
+            wire: WireFormat::Anthropic,
+            ..builtin_provider(
+                "anthropic",
+                env_value("ANTHROPIC_BASE_URL")
+                    .unwrap_or_else(|| "https://api.anthropic.com/v1".into()),
+                Some("ANTHROPIC_API_KEY"),
+                "ANTHROPIC_MODEL",
+            )
         },
     ]
 }
 
-fn provider_order() -> Vec<String> {
-    for k in ["PROOFPATCH_PROVIDER_ORDER"] {
-        if let Ok(v) = std::env::var(k) {
-            let v = v.trim().to_string();
-            if !v.is_empty() {
-                return v
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect();
+fn provider_registry_slot() -> &'static std::sync::RwLock<Option<LlmConfig>> {
+    static SLOT: std::sync::OnceLock<std::sync::RwLock<Option<LlmConfig>>> =
+        std::sync::OnceLock::new();
+    SLOT.get_or_init(|| std::sync::RwLock::new(None))
+}
+
+/// Install the `[llm]` section used for provider selection (`None`: built-ins only).
+///
+/// Also installs `[llm.prices]` and `[llm.budget]` for usage accounting (`llm_usage`).
+pub fn set_provider_registry(cfg: Option<LlmConfig>) {
+    let c = cfg.clone().unwrap_or_default();
+    llm_usage::configure(c.prices, c.budget);
+    if let Ok(mut g) = provider_registry_slot().write() {
+        *g = cfg;
+    }
+}
+
+/// Load the `[llm]` section of `<repo_root>/proofpatch.toml` into the provider registry.
+pub fn load_provider_registry(repo_root: &Path) -> Result<(), String> {
+    let cfg = crate::config::load_from_repo_root(repo_root)?.map(|c| c.llm);
+    set_provider_registry(cfg);
+    Ok(())
+}
+
+fn provider_registry() -> Option<LlmConfig> {
+    provider_registry_slot().read().ok().and_then(|g| g.clone())
+}
+
+/// Apply a `[llm.providers.<name>]` entry on top of `p` (a built-in, or a blank provider).
+fn apply_provider_config(mut p: Provider, c: &LlmProviderConfig) -> Provider {
+    if let Some(u) = c
+        .base_url_env
+        .as_deref()
+        .and_then(env_value)
+        .or_else(|| c.base_url.clone())
+    {
+        p.base_url = u.trim().trim_end_matches('/').to_string();
+    }
+    if let Some(k) = c.api_key_env.as_ref() {
+        p.api_key_env = Some(k.clone()).filter(|k| !k.trim().is_empty());
+    }
+    if let Some(m) = c.model_env.as_ref() {
+        p.model_env = m.clone();
+    }
+    if let Some(w) = c.wire_format {
+        p.wire = w;
+    }
+    if let Some(m) = c.model.as_ref() {
+        p.model = Some(m.trim().to_string()).filter(|m| !m.is_empty());
+    }
+    for (k, v) in &c.headers {
+        p.headers.retain(|(kk, _)| !kk.eq_ignore_ascii_case(k));
+        p.headers.push((k.clone(), v.clone()));
+    }
+    for (k, env) in &c.headers_env {
+        if let Some(v) = env_value(env) {
+            p.headers.retain(|(kk, _)| !kk.eq_ignore_ascii_case(k));
+            p.headers.push((k.clone(), v));
+        }
+    }
+    if let Some(t) = c.temperature {
+        p.temperature = t;
+    }
+    if c.max_tokens.is_some() {
+        p.max_tokens = c.max_tokens;
+    }
+    if let Some(t) = c.timeout_s {
+        p.timeout = Some(Duration::from_secs(t));
+    }
+    if let Some(t) = c.connect_timeout_s {
+        p.connect_timeout = Some(Duration::from_secs(t));
+    }
+    p.probe = c.probe.unwrap_or(p.probe || p.api_key_env.is_none());
+    p
+}
+
+/// Built-in providers merged with the registry (disabled entries removed).
+fn providers() -> Vec<Provider> {
+    let mut out = providers_from_env();
+    let Some(cfg) = provider_registry() else {
+        return out;
+    };
+    for (name, c) in &cfg.providers {
+        let base = match out.iter().position(|p| &p.name == name) {
+            Some(i) => {
+                let mut p = out.remove(i);
+                p.source = "builtin+config";
+                p
+            }
+            None => {
+                let model_env = format!("{}_MODEL", name.to_uppercase().replace(['-', '.'], "_"));
+                let mut p = builtin_provider(name, String::new(), None, &model_env);
+                p.source = "config";
+                p
             }
+        };
+        if c.enabled == Some(false) {
+            continue;
         }
+        out.push(apply_provider_config(base, c));
     }
-    vec![
-        "ollama".into(),
-        "groq".into(),
-        "openai".into(),
-        "openrouter".into(),
-    ]
+    out
+}
+
+/// Provider order, and where it came from (`env`, `config`, `default`).
+fn provider_order_with_source() -> (Vec<String>, &'static str) {
+    let split = |v: &str| -> Vec<String> {
+        v.split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    };
+    if let Some(v) = env_value("PROOFPATCH_PROVIDER_ORDER") {
+        return (split(&v), "env");
+    }
+    let builtin = ["ollama", "groq", "openai", "openrouter"];
+    let reg = provider_registry();
+    if let Some(order) = reg.as_ref().and_then(|c| c.provider_order.clone()) {
+        return (order, "config");
+    }
+    let mut out: Vec<String> = reg
+        .map(|c| {
+            c.providers
+                .into_keys()
+                .filter(|k| !builtin.contains(&k.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+    out.extend(builtin.iter().map(|s| s.to_string()));
+    (out, "default")
+}
+
+#[cfg(test)]
+fn provider_order() -> Vec<String> {
+    provider_order_with_source().0
 }
 
 fn env_nonempty(key: &str) -> bool {
@@ -159,6 +345,7 @@ fn hardcoded_default_model(provider_name: &str) -> Option<&'static str> {
         // See: https://openrouter.ai/rankings
         "openrouter" => Some("anthropic/claude-opus-4.5"),
         "openai" => Some("gpt-4o-mini"),
+        "anthropic" => Some("claude-sonnet-4-5"),
         "groq" => Some("llama-3.1-8b-instant"),
         _ => None,
     }
@@ -190,151 +377,281 @@ async fn is_ollama_reachable(base_url: &str, timeout: Duration) -> bool {
     false
 }
 
-async fn select_provider(timeout: Duration) -> Result<(Provider, String, String), String> {
-    let provs = providers_from_env();
-    for name in provider_order() {
-        let Some(p) = provs.iter().find(|pp| pp.name == name).cloned() else {
+struct Selection {
+    provider: Provider,
+    model: String,
+    model_source: String,
+    order: Vec<String>,
+    order_source: &'static str,
+    skipped: Vec<SkippedProvider>,
+}
+
+async fn select_provider_explained(timeout: Duration) -> Result<Selection, String> {
+    // Cache replay sends nothing, so neither keys nor reachability matter.
+    let offline = llm_cache::mode() == LlmCacheMode::ReadOnly;
+    let provs = providers();
+    let (order, order_source) = provider_order_with_source();
+    let mut skipped: Vec<SkippedProvider> = Vec::new();
+    let mut skip = |name: &str, reason: String| {
+        skipped.push(SkippedProvider {
+            provider: name.to_string(),
+            reason,
+        })
+    };
+    for name in &order {
+        let Some(p) = provs.iter().find(|pp| &pp.name == name).cloned() else {
+            skip(name, "not defined (or disabled)".to_string());
             continue;
         };
-        let mut model = std::env::var(p.model_env).ok().unwrap_or_default();
-        model = model.trim().to_string();
-        if let Some(k) = p.api_key_env {
-            if !env_nonempty(k) {
+        if p.base_url.is_empty() {
+            skip(name, "no base_url".to_string());
+            continue;
+        }
+        if let Some(k) = p.api_key_env.as_deref() {
+            if !offline && !env_nonempty(k) {
+                skip(name, format!("{k} is not set"));
                 continue;
             }
         }
+        let mut model = std::env::var(&p.model_env).ok().unwrap_or_default();
+        model = model.trim().to_string();
         let mut model_source = "env".to_string();
+        if model.is_empty() {
+            if let Some(m) = p.model.clone() {
+                model = m;
+                model_source = "config".to_string();
+            }
+        }
         if model.is_empty() {
             // Defaults are enabled unless explicitly disabled.
             let defaults_enabled = env_truthy("PROOFPATCH_MODEL_DEFAULTS", true);
             if !defaults_enabled {
+                skip(
+                    name,
+                    format!("no model ({} unset, defaults disabled)", p.model_env),
+                );
                 continue;
             }
-            if let Some((m, key)) = default_model_from_env(p.name) {
+            if let Some((m, key)) = default_model_from_env(&p.name) {
                 model = m;
                 model_source = format!("default_env_override({})", key);
-            } else if let Some(m) = hardcoded_default_model(p.name) {
+            } else if let Some(m) = hardcoded_default_model(&p.name) {
                 model = m.to_string();
                 model_source = "default_hardcoded".to_string();
-            } else {
-                model = String::new();
             }
             if model.is_empty() {
+                skip(name, format!("no model (set {} or `model`)", p.model_env));
                 continue;
             }
         }
-        if p.name == "ollama" && !is_ollama_reachable(&p.base_url, timeout).await {
+        if p.probe && !offline && !is_ollama_reachable(&p.base_url, timeout).await {
+            skip(name, format!("{} is not reachable", p.base_url));
             continue;
         }
-        return Ok((p, model, model_source));
+        return Ok(Selection {
+            provider: p,
+            model,
+            model_source,
+            order,
+            order_source,
+            skipped,
+        });
     }
-    Err("No usable provider found. Set one of:\n\
+    let tried = skipped
+        .iter()
+        .map(|s| format!("- {}: {}", s.provider, s.reason))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(format!(
+        "No usable provider found. Set one of:\n\
 - OLLAMA_MODEL (+ optional OLLAMA_HOST)\n\
 - GROQ_API_KEY and GROQ_MODEL\n\
 - OPENROUTER_API_KEY and OPENROUTER_MODEL\n\
 - OPENAI_API_KEY and OPENAI_MODEL\n\
+- an [llm.providers.<name>] entry in proofpatch.toml\n\
 Optionally set:\n\
 - PROOFPATCH_PROVIDER_ORDER\n\
 - PROOFPATCH_DEFAULT_MODEL / PROOFPATCH_DEFAULT_MODEL_<PROVIDER>\n\
-- PROOFPATCH_MODEL_DEFAULTS=0 to disable built-in defaults"
-        .to_string())
+- PROOFPATCH_MODEL_DEFAULTS=0 to disable built-in defaults\n\
+Tried ({order_source} order):\n{tried}"
+    ))
+}
+
+async fn select_provider(timeout: Duration) -> Result<(Provider, String, String), String> {
+    let s = select_provider_explained(timeout).await?;
+    Ok((s.provider, s.model, s.model_source))
 }
 
 pub async fn select_provider_info(timeout: Duration) -> Result<SelectedProviderInfo, String> {
-    let (p, model, model_source) = select_provider(timeout).await?;
-    let api_key = p.api_key_env.and_then(|k| {
-        std::env::var(k)
-            .ok()
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-    });
+    let s = select_provider_explained(timeout).await?;
+    let p = s.provider;
+    let api_key = p.api_key_env.as_deref().and_then(env_value);
+    let mut reason = format!(
+        "first usable provider in {} order [{}]; model from {}",
+        s.order_source,
+        s.order.join(", "),
+        s.model_source
+    );
+    if s.model_source == "env" {
+        reason.push_str(&format!(" ({})", p.model_env));
+    }
     Ok(SelectedProviderInfo {
-        provider: p.name.to_string(),
+        provider: p.name,
         base_url: p.base_url,
         api_key,
-        model,
-        model_source,
-        model_env: p.model_env.to_string(),
+        model: s.model,
+        model_source: s.model_source,
+        model_env: p.model_env,
+        wire_format: llm_wire::wire_format_name(p.wire).to_string(),
+        source: p.source.to_string(),
+        order_source: s.order_source.to_string(),
+        order: s.order,
+        reason,
+        skipped: s.skipped,
     })
 }
 
-#[derive(Debug, Deserialize)]
-struct ChatCompletionChoice {
-    message: Value,
-}
-
-#[derive(Debug, Deserialize)]
-struct ChatCompletionResponse {
-    choices: Vec<ChatCompletionChoice>,
-}
-
-/// OpenAI-compatible chat completions, with provider selection matching the legacy Python CLI.
+/// HTTP client carrying the provider's auth and extra headers, and its timeouts.
 ///
-/// Invariants (should not change lightly):
-/// - request path is `POST <base_url>/chat/completions`
-/// - uses `Authorization: Bearer <key>` when provider requires a key
-/// - OpenRouter adds `HTTP-Referer` and `X-Title` when configured
-/// - default temperature is 0.2
-pub async fn chat_completion(
-    system: &str,
-    user: &str,
-    timeout: Duration,
-) -> Result<ChatCompletionResult, String> {
-    let (provider, model, model_source) = select_provider(Duration::from_secs(3)).await?;
-
+/// `timeout: None` leaves the overall request timeout unset (streaming enforces its own deadline).
+fn http_client(provider: &Provider, timeout: Option<Duration>) -> Result<reqwest::Client, String> {
     let mut headers = reqwest::header::HeaderMap::new();
     headers.insert(
         reqwest::header::CONTENT_TYPE,
         reqwest::header::HeaderValue::from_static("application/json"),
     );
-    if let Some(k) = provider.api_key_env {
-        let key = std::env::var(k).ok().unwrap_or_default();
-        let key = key.trim().to_string();
-        if key.is_empty() {
-            return Err(format!("missing {}", k));
-        }
-        let v = format!("Bearer {}", key);
-        let hv = reqwest::header::HeaderValue::from_str(&v)
-            .map_err(|e| format!("invalid Authorization header: {e}"))?;
-        headers.insert(reqwest::header::AUTHORIZATION, hv);
-    }
-    if provider.name == "openrouter" {
-        if let Ok(site) = std::env::var("OPENROUTER_SITE_URL") {
-            let site = site.trim().to_string();
-            if !site.is_empty() {
-                if let Ok(hv) = reqwest::header::HeaderValue::from_str(&site) {
-                    headers.insert("HTTP-Referer", hv);
-                }
+    if let Some(k) = provider.api_key_env.as_deref() {
+        let key = env_value(k).ok_or_else(|| format!("missing {}", k))?;
+        match provider.wire {
+            WireFormat::OpenAi => {
+                let v = format!("Bearer {}", key);
+                let hv = reqwest::header::HeaderValue::from_str(&v)
+                    .map_err(|e| format!("invalid Authorization header: {e}"))?;
+                headers.insert(reqwest::header::AUTHORIZATION, hv);
             }
-        }
-        if let Ok(app) = std::env::var("OPENROUTER_APP_NAME") {
-            let app = app.trim().to_string();
-            if !app.is_empty() {
-                if let Ok(hv) = reqwest::header::HeaderValue::from_str(&app) {
-                    headers.insert("X-Title", hv);
-                }
+            WireFormat::Anthropic => {
+                let hv = reqwest::header::HeaderValue::from_str(&key)
+                    .map_err(|e| format!("invalid x-api-key header: {e}"))?;
+                headers.insert("x-api-key", hv);
             }
         }
     }
+    if provider.wire == WireFormat::Anthropic {
+        headers.insert(
+            "anthropic-version",
+            reqwest::header::HeaderValue::from_static(llm_wire::ANTHROPIC_VERSION),
+        );
+    }
+    for (k, v) in &provider.headers {
+        let name = reqwest::header::HeaderName::from_bytes(k.as_bytes())
+            .map_err(|e| format!("invalid header name {k}: {e}"))?;
+        let hv = reqwest::header::HeaderValue::from_str(v)
+            .map_err(|e| format!("invalid header value for {k}: {e}"))?;
+        headers.insert(name, hv);
+    }
 
-    let payload = serde_json::json!({
-        "model": model,
-        "messages": [
-            { "role": "system", "content": system },
-            { "role": "user", "content": user }
-        ],
-        "temperature": 0.2
-    });
-
-    let url = format!("{}/chat/completions", provider.base_url);
-    let client = reqwest::Client::builder()
-        .timeout(timeout)
-        .default_headers(headers)
+    let mut builder = reqwest::Client::builder().default_headers(headers);
+    if let Some(t) = timeout {
+        builder = builder.timeout(t);
+    }
+    if let Some(t) = provider.connect_timeout {
+        builder = builder.connect_timeout(t);
+    }
+    builder
         .build()
-        .map_err(|e| format!("http client build: {e}"))?;
+        .map_err(|e| format!("http client build: {e}"))
+}
+
+/// Request URL and body in the provider's wire format.
+fn wire_request(provider: &Provider, payload: &Value) -> (String, Value) {
+    let url = format!(
+        "{}{}",
+        provider.base_url,
+        llm_wire::request_path(provider.wire)
+    );
+    let body = match provider.wire {
+        WireFormat::OpenAi => payload.clone(),
+        WireFormat::Anthropic => llm_wire::openai_to_anthropic_request(payload),
+    };
+    (url, body)
+}
+
+/// Cache lookup for a request about to be sent: a hit, a read-only miss (error), or `None` (send).
+fn cached_response(
+    provider: &Provider,
+    payload: &Value,
+) -> Result<Option<(Value, TokenUsage)>, String> {
+    let mode = llm_cache::mode();
+    if mode == LlmCacheMode::Off {
+        return Ok(None);
+    }
+    let key = llm_cache::cache_key(&provider.name, payload);
+    if let Some(hit) = llm_cache::lookup(&key) {
+        llm_usage::record_cache_hit();
+        let usage = TokenUsage {
+            cached: true,
+            ..hit.usage
+        };
+        return Ok(Some((hit.response, usage)));
+    }
+    if mode == LlmCacheMode::ReadOnly {
+        return Err(format!(
+            "{}: key {key} for provider {}",
+            llm_cache::READ_ONLY_MISS,
+            provider.name
+        ));
+    }
+    Ok(None)
+}
+
+/// Record usage for a response (estimating when the provider sent none) and, for a complete one
+/// (`cache`), store it when cache writing is enabled.
+fn record_response(
+    provider: &Provider,
+    payload: &Value,
+    raw: &Value,
+    usage: Option<TokenUsage>,
+    cache: bool,
+) -> TokenUsage {
+    let usage = usage
+        .or_else(|| llm_usage::usage_from_response(raw))
+        .unwrap_or_else(|| llm_usage::estimate_usage(payload, raw));
+    let model = payload.get("model").and_then(|m| m.as_str()).unwrap_or("");
+    let usage = llm_usage::record(&provider.name, model, usage);
+    if cache && llm_cache::mode() == LlmCacheMode::ReadWrite {
+        // Best-effort: a failed cache write should not fail the call.
+        let _ = llm_cache::store(&llm_cache::CachedResponse {
+            key: llm_cache::cache_key(&provider.name, payload),
+            provider: provider.name.clone(),
+            model: model.to_string(),
+            created_unix_ms: llm_cache::now_unix_ms(),
+            usage,
+            response: raw.clone(),
+        });
+    }
+    usage
+}
+
+/// Send an OpenAI-shaped chat request with the provider's headers, key and timeouts, translating
+/// to and from the provider's wire format. Always returns an OpenAI-shaped envelope.
+///
+/// Answered from `llm_cache` when enabled; otherwise refused up front when the run's LLM budget
+/// is spent, and the response's usage is recorded.
+async fn post_chat_completions(
+    provider: &Provider,
+    payload: &Value,
+    timeout: Duration,
+) -> Result<(Value, TokenUsage), String> {
+    if let Some(hit) = cached_response(provider, payload)? {
+        return Ok(hit);
+    }
+    let model = payload.get("model").and_then(|m| m.as_str()).unwrap_or("");
+    llm_usage::check_budget(&provider.name, model)?;
+    let client = http_client(provider, Some(provider.timeout.unwrap_or(timeout)))?;
+    let (url, body) = wire_request(provider, payload);
     let resp = client
         .post(url)
-        .json(&payload)
+        .json(&body)
         .send()
         .await
         .map_err(|e| format!("http request failed: {e}"))?;
@@ -352,6 +669,64 @@ pub async fn chat_completion(
             raw
         ));
     }
+    let raw = match provider.wire {
+        WireFormat::OpenAi => raw,
+        WireFormat::Anthropic => llm_wire::anthropic_to_openai_response(&raw),
+    };
+    let usage = record_response(provider, payload, &raw, None, true);
+    Ok((raw, usage))
+}
+
+fn chat_payload(provider: &Provider, model: &str, messages: Value) -> Value {
+    let mut payload = serde_json::json!({
+        "model": model,
+        "messages": messages,
+        "temperature": provider.temperature
+    });
+    if let Some(n) = provider.max_tokens {
+        payload["max_tokens"] = serde_json::json!(n);
+    }
+    payload
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionChoice {
+    message: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatCompletionResponse {
+    choices: Vec<ChatCompletionChoice>,
+}
+
+/// Chat completion, with provider selection matching the legacy Python CLI.
+///
+/// Invariants (should not change lightly):
+/// - request path is `POST <base_url>/chat/completions` (`/messages` for Anthropic wire format)
+/// - uses `Authorization: Bearer <key>` when provider requires a key (`x-api-key` for Anthropic)
+/// - OpenRouter adds `HTTP-Referer` and `X-Title` when configured
+/// - default temperature is 0.2 (per-provider `temperature` in `proofpatch.toml` overrides it)
+pub async fn chat_completion(
+    system: &str,
+    user: &str,
+    timeout: Duration,
+) -> Result<ChatCompletionResult, String> {
+    let messages = [
+        serde_json::json!({ "role": "system", "content": system }),
+        serde_json::json!({ "role": "user", "content": user }),
+    ];
+    chat_completion_messages(&messages, timeout).await
+}
+
+/// `chat_completion` over a full message list (multi-turn conversations).
+pub async fn chat_completion_messages(
+    messages: &[serde_json::Value],
+    timeout: Duration,
+) -> Result<ChatCompletionResult, String> {
+    let (provider, model, model_source) = select_provider(Duration::from_secs(3)).await?;
+
+    let payload = chat_payload(&provider, &model, serde_json::json!(messages));
+    let (raw, usage) = post_chat_completions(&provider, &payload, timeout).await?;
 
     let parsed: ChatCompletionResponse =
         serde_json::from_value(raw.clone()).map_err(|e| format!("invalid chat response: {e}"))?;
@@ -368,66 +743,398 @@ pub async fn chat_completion(
         .to_string();
 
     Ok(ChatCompletionResult {
-        provider: provider.name.to_string(),
+        provider: provider.name,
         model,
         model_source,
-        model_env: provider.model_env.to_string(),
+        model_env: provider.model_env,
         content,
+        usage,
         raw,
     })
 }
 
-/// Lower-level entrypoint: send an OpenAI-compatible request with an explicit `messages` array and optional `tools`.
+/// What a streaming callback wants next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamControl {
+    Continue,
+    /// Drop the connection and return what has arrived so far (`finish: "cancelled"`).
+    Stop,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamedCompletion {
+    pub provider: String,
+    pub model: String,
+    pub model_source: String,
+    pub model_env: String,
+    /// Text received (all of it, or the salvaged prefix when `partial`).
+    pub content: String,
+    /// - "complete": the provider finished the response
+    /// - "cancelled": the callback returned `StreamControl::Stop`
+    /// - "timeout": the deadline passed mid-stream
+    /// - "error": the connection or the provider failed mid-stream
+    /// - "truncated": the stream ended without the provider's terminal event (`[DONE]` or
+    ///   `message_stop`), e.g. a dropped connection
+    pub finish: String,
+    /// `content` is a prefix of the response (`finish` is not "complete").
+    pub partial: bool,
+    /// Provider finish reason (OpenAI naming), when sent.
+    pub finish_reason: Option<String>,
+    /// Streamed text deltas received.
+    pub chunks: u64,
+    pub error: Option<String>,
+    /// First closed ```lean block in `content`, if any (see `complete_lean_code_block`).
+    pub code_block: Option<String>,
+    pub usage: TokenUsage,
+    pub elapsed_ms: u64,
+}
+
+/// First complete fenced Lean block (```lean, ```lean4, or a bare ```) in `text`, without fences.
 ///
-/// Returns the raw JSON response plus injected `provider`/`model` fields for callers that want tool loops.
-pub async fn chat_completion_raw(
-    messages: &[serde_json::Value],
-    tools: Option<&serde_json::Value>,
-    tool_choice: Option<&serde_json::Value>,
+/// `None` while the closing fence has not arrived yet.
+pub fn complete_lean_code_block(text: &str) -> Option<String> {
+    let mut rest = text;
+    while let Some(i) = rest.find("```") {
+        let after = &rest[i + 3..];
+        let nl = after.find('\n')?;
+        let lang = after[..nl].trim();
+        let body = &after[nl + 1..];
+        let close = body.find("```")?;
+        if matches!(lang, "lean" | "lean4" | "") {
+            return Some(body[..close].trim_end().to_string());
+        }
+        rest = &body[close + 3..];
+    }
+    None
+}
+
+/// Streaming chat completion: same request as `chat_completion`, sent with `stream: true`.
+///
+/// `on_delta(delta, text_so_far)` runs for each text delta and may stop the stream early (e.g.
+/// once a complete Lean block has arrived). When `timeout` passes mid-stream, the text received
+/// so far is returned with `finish: "timeout"` instead of an error; only a timeout with no text at
+/// all is an error. Usage is recorded for partial responses too, but only complete ones are cached.
+/// A cache hit is delivered to `on_delta` as a single delta.
+pub async fn chat_completion_stream<F>(
+    system: &str,
+    user: &str,
     timeout: Duration,
-) -> Result<serde_json::Value, String> {
+    mut on_delta: F,
+) -> Result<StreamedCompletion, String>
+where
+    F: FnMut(&str, &str) -> StreamControl,
+{
+    let started = std::time::Instant::now();
     let (provider, model, model_source) = select_provider(Duration::from_secs(3)).await?;
-
-    let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert(
-        reqwest::header::CONTENT_TYPE,
-        reqwest::header::HeaderValue::from_static("application/json"),
+    let mut payload = chat_payload(
+        &provider,
+        &model,
+        serde_json::json!([
+            { "role": "system", "content": system },
+            { "role": "user", "content": user }
+        ]),
     );
-    if let Some(k) = provider.api_key_env {
-        let key = std::env::var(k).ok().unwrap_or_default();
-        let key = key.trim().to_string();
-        if key.is_empty() {
-            return Err(format!("missing {}", k));
-        }
-        let v = format!("Bearer {}", key);
-        let hv = reqwest::header::HeaderValue::from_str(&v)
-            .map_err(|e| format!("invalid Authorization header: {e}"))?;
-        headers.insert(reqwest::header::AUTHORIZATION, hv);
-    }
-    if provider.name == "openrouter" {
-        if let Ok(site) = std::env::var("OPENROUTER_SITE_URL") {
-            let site = site.trim().to_string();
-            if !site.is_empty() {
-                if let Ok(hv) = reqwest::header::HeaderValue::from_str(&site) {
-                    headers.insert("HTTP-Referer", hv);
+    let done = |content: String,
+                finish: &str,
+                finish_reason: Option<String>,
+                chunks: u64,
+                error: Option<String>,
+                usage: TokenUsage| StreamedCompletion {
+        provider: provider.name.clone(),
+        model: model.clone(),
+        model_source: model_source.clone(),
+        model_env: provider.model_env.clone(),
+        code_block: complete_lean_code_block(&content),
+        partial: finish != "complete",
+        content,
+        finish: finish.to_string(),
+        finish_reason,
+        chunks,
+        error,
+        usage,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    };
+
+    if let Some((raw, usage)) = cached_response(&provider, &payload)? {
+        let content = extract_message_content(&raw).unwrap_or("").to_string();
+        on_delta(&content, &content);
+        return Ok(done(content, "complete", None, 1, None, usage));
+    }
+    llm_usage::check_budget(&provider.name, &model)?;
+
+    payload["stream"] = Value::Bool(true);
+    if provider.wire == WireFormat::OpenAi {
+        payload["stream_options"] = serde_json::json!({ "include_usage": true });
+    }
+    let deadline = tokio::time::Instant::now() + provider.timeout.unwrap_or(timeout);
+    let client = http_client(&provider, None)?;
+    let (url, body) = wire_request(&provider, &payload);
+    let resp = tokio::time::timeout_at(deadline, client.post(url).json(&body).send())
+        .await
+        .map_err(|_| format!("llm stream timed out before the response started ({timeout:?})"))?
+        .map_err(|e| format!("http request failed: {e}"))?;
+    let status = resp.status();
+    if !status.is_success() {
+        let text = resp.text().await.unwrap_or_default();
+        return Err(format!(
+            "provider {} returned {}: {}",
+            provider.name,
+            status.as_u16(),
+            text
+        ));
+    }
+
+    let mut resp = resp;
+    let mut sse = llm_wire::SseParser::default();
+    let mut content = String::new();
+    let mut chunks = 0u64;
+    let mut prompt_tokens = None;
+    let mut completion_tokens = None;
+    let mut finish_reason = None;
+    let mut error = None;
+    let finish = 'read: loop {
+        let mut eof = false;
+        let events = match tokio::time::timeout_at(deadline, resp.chunk()).await {
+            Err(_) => break 'read "timeout",
+            Ok(Err(e)) => {
+                error = Some(format!("stream read failed: {e}"));
+                break 'read "error";
+            }
+            Ok(Ok(Some(bytes))) => sse.push(&bytes),
+            Ok(Ok(None)) => {
+                eof = true;
+                sse.finish()
+            }
+        };
+        for data in events {
+            let d = llm_wire::stream_delta(provider.wire, &data);
+            prompt_tokens = d.prompt_tokens.or(prompt_tokens);
+            completion_tokens = d.completion_tokens.or(completion_tokens);
+            finish_reason = d.finish_reason.or(finish_reason);
+            if let Some(e) = d.error {
+                error = Some(e);
+                break 'read "error";
+            }
+            if !d.text.is_empty() {
+                chunks += 1;
+                content.push_str(&d.text);
+                if on_delta(&d.text, &content) == StreamControl::Stop {
+                    break 'read "cancelled";
                 }
             }
+            if d.done {
+                break 'read "complete";
+            }
+        }
+        if eof {
+            error = Some("stream ended before the provider finished the response".to_string());
+            break 'read "truncated";
+        }
+    };
+
+    if finish == "timeout" && content.is_empty() {
+        return Err(format!(
+            "llm stream timed out after {timeout:?} with no output"
+        ));
+    }
+    if matches!(finish, "error" | "truncated") && content.is_empty() {
+        return Err(error.unwrap_or_else(|| "llm stream failed".to_string()));
+    }
+    let raw = serde_json::json!({
+        "object": "chat.completion",
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": finish_reason,
+        }],
+    });
+    // Cancelled or cut-off streams usually lack the final usage event; those get estimated.
+    let reported = match (prompt_tokens, completion_tokens) {
+        (Some(p), Some(c)) => Some(TokenUsage {
+            prompt_tokens: p,
+            completion_tokens: c,
+            total_tokens: p + c,
+            ..TokenUsage::default()
+        }),
+        _ => None,
+    };
+    let usage = record_response(&provider, &payload, &raw, reported, finish == "complete");
+    Ok(done(content, finish, finish_reason, chunks, error, usage))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SampledCompletions {
+    pub provider: String,
+    pub model: String,
+    pub model_source: String,
+    pub model_env: String,
+    /// One entry per sample received, in request order.
+    pub samples: Vec<String>,
+    /// - "native": one request with `n` (OpenAI wire format)
+    /// - "parallel": one request per sample
+    /// - "native+parallel": the provider returned fewer than `n` choices; the rest were requested
+    ///   in parallel
+    pub mode: String,
+    pub requests: u64,
+    /// Failed requests (the call only fails when no sample arrived at all).
+    pub errors: Vec<String>,
+    /// Summed over all requests.
+    pub usage: TokenUsage,
+}
+
+fn add_usage(total: &mut TokenUsage, u: &TokenUsage) {
+    total.prompt_tokens += u.prompt_tokens;
+    total.completion_tokens += u.completion_tokens;
+    total.total_tokens += u.total_tokens;
+    total.estimated |= u.estimated;
+    total.cached |= u.cached;
+    if let Some(c) = u.cost_usd {
+        total.cost_usd = Some(total.cost_usd.unwrap_or(0.0) + c);
+    }
+}
+
+fn choice_contents(raw: &Value) -> Vec<String> {
+    raw.get("choices")
+        .and_then(|c| c.as_array())
+        .into_iter()
+        .flatten()
+        .map(|c| {
+            c.get("message")
+                .and_then(|m| m.get("content"))
+                .and_then(|t| t.as_str())
+                .unwrap_or("")
+                .to_string()
+        })
+        .collect()
+}
+
+/// `n` completions for the same prompt.
+///
+/// OpenAI-compatible providers are asked for all `n` in one request (`n`); providers that ignore
+/// it (or speak another wire format) get one request per missing sample, sent concurrently (one
+/// at a time while an LLM budget is set). Each such request carries a distinct `seed`, so samples
+/// stay distinct (and separately cached).
+/// `temperature` overrides the provider's for these requests.
+pub async fn chat_completion_samples(
+    system: &str,
+    user: &str,
+    n: usize,
+    temperature: Option<f64>,
+    timeout: Duration,
+) -> Result<SampledCompletions, String> {
+    if n == 0 {
+        return Err("samples must be >= 1".to_string());
+    }
+    let (provider, model, model_source) = select_provider(Duration::from_secs(3)).await?;
+    let mut payload = chat_payload(
+        &provider,
+        &model,
+        serde_json::json!([
+            { "role": "system", "content": system },
+            { "role": "user", "content": user }
+        ]),
+    );
+    if let Some(t) = temperature {
+        payload["temperature"] = serde_json::json!(t);
+    }
+
+    let mut samples: Vec<String> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    let mut usage = TokenUsage::default();
+    let mut requests = 0u64;
+    let mut native = false;
+    if provider.wire == WireFormat::OpenAi && n > 1 {
+        let mut p = payload.clone();
+        p["n"] = serde_json::json!(n);
+        requests += 1;
+        match post_chat_completions(&provider, &p, timeout).await {
+            Ok((raw, u)) => {
+                native = true;
+                add_usage(&mut usage, &u);
+                samples.extend(choice_contents(&raw).into_iter().take(n));
+            }
+            Err(e) => errors.push(e),
+        }
+    }
+
+    let missing = n - samples.len();
+    // Under a budget the requests go one at a time, so each is checked against the usage of the
+    // ones before it and the run still overshoots by at most one response.
+    let jobs = if llm_usage::budget_limited() {
+        1
+    } else {
+        missing
+    };
+    let permits = std::sync::Arc::new(tokio::sync::Semaphore::new(jobs.max(1)));
+    let mut tasks = tokio::task::JoinSet::new();
+    for i in 0..missing {
+        let provider = provider.clone();
+        let permits = permits.clone();
+        let mut p = payload.clone();
+        if n > 1 {
+            p["seed"] = serde_json::json!(samples.len() + i);
         }
-        if let Ok(app) = std::env::var("OPENROUTER_APP_NAME") {
-            let app = app.trim().to_string();
-            if !app.is_empty() {
-                if let Ok(hv) = reqwest::header::HeaderValue::from_str(&app) {
-                    headers.insert("X-Title", hv);
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            (i, post_chat_completions(&provider, &p, timeout).await)
+        });
+    }
+    requests += missing as u64;
+    let mut extra: Vec<(usize, String)> = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((i, Ok((raw, u)))) => {
+                add_usage(&mut usage, &u);
+                if let Some(c) = choice_contents(&raw).into_iter().next() {
+                    extra.push((i, c));
                 }
             }
+            Ok((_, Err(e))) => errors.push(e),
+            Err(e) => errors.push(format!("sample task failed: {e}")),
         }
     }
+    extra.sort_by_key(|(i, _)| *i);
+    let parallel = !extra.is_empty();
+    samples.extend(extra.into_iter().map(|(_, c)| c));
 
-    let mut payload = serde_json::json!({
-        "model": model,
-        "messages": messages,
-        "temperature": 0.2
-    });
+    if samples.is_empty() {
+        return Err(errors
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| "no samples returned".to_string()));
+    }
+    let mode = match (native, parallel) {
+        (true, true) => "native+parallel",
+        (true, false) => "native",
+        _ => "parallel",
+    };
+    Ok(SampledCompletions {
+        provider: provider.name,
+        model,
+        model_source,
+        model_env: provider.model_env,
+        samples,
+        mode: mode.to_string(),
+        requests,
+        errors,
+        usage,
+    })
+}
+
+/// Lower-level entrypoint: send an OpenAI-compatible request with an explicit `messages` array and optional `tools`.
+///
+/// Returns the raw JSON response plus injected `provider`/`model` (and `token_usage`) fields for callers that want tool loops.
+/// Providers with another wire format are translated both ways, so the envelope is always OpenAI-shaped.
+pub async fn chat_completion_raw(
+    messages: &[serde_json::Value],
+    tools: Option<&serde_json::Value>,
+    tool_choice: Option<&serde_json::Value>,
+    timeout: Duration,
+) -> Result<serde_json::Value, String> {
+    let (provider, model, model_source) = select_provider(Duration::from_secs(3)).await?;
+
+    let mut payload = chat_payload(&provider, &model, serde_json::json!(messages));
     if let Some(t) = tools {
         payload["tools"] = t.clone();
         if let Some(tc) = tool_choice {
@@ -436,38 +1143,12 @@ pub async fn chat_completion_raw(
             payload["tool_choice"] = serde_json::json!("auto");
         }
     }
-
-    let url = format!("{}/chat/completions", provider.base_url);
-    let client = reqwest::Client::builder()
-        .timeout(timeout)
-        .default_headers(headers)
-        .build()
-        .map_err(|e| format!("http client build: {e}"))?;
-    let resp = client
-        .post(url)
-        .json(&payload)
-        .send()
-        .await
-        .map_err(|e| format!("http request failed: {e}"))?;
-
-    let status = resp.status();
-    let mut raw: serde_json::Value = resp
-        .json()
-        .await
-        .map_err(|e| format!("http json decode: {e}"))?;
-    if !status.is_success() {
-        return Err(format!(
-            "provider {} returned {}: {}",
-            provider.name,
-            status.as_u16(),
-            raw
-        ));
-    }
+    let (mut raw, usage) = post_chat_completions(&provider, &payload, timeout).await?;
     // Inject selection metadata so callers don't have to re-run selection.
     if let Some(obj) = raw.as_object_mut() {
         obj.insert(
             "provider".to_string(),
-            serde_json::Value::String(provider.name.to_string()),
+            serde_json::Value::String(provider.name),
         );
         obj.insert("model".to_string(), serde_json::Value::String(model));
         obj.insert(
@@ -476,7 +1157,15 @@ pub async fn chat_completion_raw(
         );
         obj.insert(
             "model_env".to_string(),
-            serde_json::Value::String(provider.model_env.to_string()),
+            serde_json::Value::String(provider.model_env),
+        );
+        obj.insert(
+            "wire_format".to_string(),
+            serde_json::Value::String(llm_wire::wire_format_name(provider.wire).to_string()),
+        );
+        obj.insert(
+            "token_usage".to_string(),
+            serde_json::to_value(usage).unwrap_or(serde_json::Value::Null),
         );
     }
     Ok(raw)
@@ -513,6 +1202,13 @@ fn extract_tool_call_arguments<'a>(
     tc
 }
 
+/// The `token_usage` injected by `chat_completion_raw`.
+fn injected_usage(raw: &serde_json::Value) -> TokenUsage {
+    raw.get("token_usage")
+        .and_then(|u| serde_json::from_value(u.clone()).ok())
+        .unwrap_or_default()
+}
+
 fn extract_message_content<'a>(raw: &'a serde_json::Value) -> Option<&'a str> {
     raw.get("choices")?
         .as_array()?
@@ -582,6 +1278,7 @@ where
                 model_env: done.model_env,
                 value,
                 mode,
+                usage: done.usage,
                 raw: done.raw,
             });
         }
@@ -616,6 +1313,7 @@ where
                 .to_string(),
             value,
             mode: "tool_call".to_string(),
+            usage: injected_usage(&raw),
             raw,
         });
     }
@@ -646,6 +1344,7 @@ where
                     .to_string(),
                 value: v,
                 mode: "content_json".to_string(),
+                usage: injected_usage(&raw),
                 raw,
             });
         }
@@ -675,6 +1374,7 @@ where
                     .to_string(),
                 value: v,
                 mode: "content_extract".to_string(),
+                usage: injected_usage(&raw),
                 raw,
             });
         }
@@ -881,4 +1581,120 @@ mod tests {
         let err = select_provider(Duration::from_millis(10)).await.unwrap_err();
         assert!(err.contains("No usable provider found"));
     }
+
+    const ALL_PROVIDER_ENVS: &[&str] = &[
+        "PROOFPATCH_PROVIDER_ORDER",
+        "OLLAMA_MODEL",
+        "GROQ_API_KEY",
+        "GROQ_MODEL",
+        "OPENROUTER_API_KEY",
+        "OPENROUTER_MODEL",
+        "OPENAI_API_KEY",
+        "OPENAI_MODEL",
+        "OPENAI_BASE_URL",
+        "VLLM_MODEL",
+        "VLLM_KEY",
+    ];
+
+    struct RegistryGuard;
+
+    impl Drop for RegistryGuard {
+        fn drop(&mut self) {
+            set_provider_registry(None);
+        }
+    }
+
+    fn registry(toml_src: &str) -> RegistryGuard {
+        let cfg: crate::config::ProofpatchConfig = toml::from_str(toml_src).expect("config");
+        set_provider_registry(Some(cfg.llm));
+        RegistryGuard
+    }
+
+    #[tokio::test]
+    async fn registry_providers_come_first_and_use_config_model() {
+        let _lock = env_lock().lock().await;
+        let _g = EnvGuard::new(ALL_PROVIDER_ENVS);
+        for k in ALL_PROVIDER_ENVS {
+            std::env::remove_var(k);
+        }
+        std::env::set_var("OPENAI_API_KEY", "test_key");
+        std::env::set_var("VLLM_KEY", "local");
+        let _r = registry(
+            r#"
+[llm.providers.vllm]
+base_url = "http://127.0.0.1:8000/v1/"
+api_key_env = "VLLM_KEY"
+model = "Qwen/Qwen2.5-Coder-32B-Instruct"
+temperature = 0.0
+max_tokens = 2048
+headers = { "X-Team" = "lean" }
+"#,
+        );
+        assert_eq!(provider_order()[0], "vllm");
+
+        let info = select_provider_info(Duration::from_millis(10))
+            .await
+            .expect("expected provider");
+        assert_eq!(info.provider, "vllm");
+        assert_eq!(info.base_url, "http://127.0.0.1:8000/v1");
+        assert_eq!(info.model, "Qwen/Qwen2.5-Coder-32B-Instruct");
+        assert_eq!(info.model_source, "config");
+        assert_eq!(info.model_env, "VLLM_MODEL");
+        assert_eq!(info.source, "config");
+        assert_eq!(info.order_source, "default");
+        assert!(info.skipped.is_empty());
+
+        let p = providers().into_iter().find(|p| p.name == "vllm").unwrap();
+        assert_eq!(p.temperature, 0.0);
+        assert_eq!(p.max_tokens, Some(2048));
+        assert_eq!(p.headers, vec![("X-Team".to_string(), "lean".to_string())]);
+        assert!(!p.probe);
+    }
+
+    #[tokio::test]
+    async fn registry_overrides_builtins_and_explains_skips() {
+        let _lock = env_lock().lock().await;
+        let _g = EnvGuard::new(ALL_PROVIDER_ENVS);
+        for k in ALL_PROVIDER_ENVS {
+            std::env::remove_var(k);
+        }
+        std::env::set_var("OPENAI_API_KEY", "test_key");
+        let _r = registry(
+            r#"
+[llm]
+provider_order = ["groq", "llamacpp", "openai"]
+
+[llm.providers.groq]
+enabled = false
+
+[llm.providers.llamacpp]
+model = "local"
+
+[llm.providers.openai]
+base_url = "http://proxy.internal/v1"
+model = "gpt-4.1-mini"
+"#,
+        );
+        let info = select_provider_info(Duration::from_millis(10))
+            .await
+            .expect("expected provider");
+        assert_eq!(info.provider, "openai");
+        assert_eq!(info.base_url, "http://proxy.internal/v1");
+        assert_eq!(info.model, "gpt-4.1-mini");
+        assert_eq!(info.source, "builtin+config");
+        assert_eq!(info.order_source, "config");
+        let skipped: Vec<(&str, &str)> = info
+            .skipped
+            .iter()
+            .map(|s| (s.provider.as_str(), s.reason.as_str()))
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                ("groq", "not defined (or disabled)"),
+                ("llamacpp", "no base_url"),
+            ]
+        );
+        assert!(info.reason.contains("config order"));
+    }
 }
@@ -0,0 +1,190 @@
+//! Content-addressed LLM response cache, with offline replay.
+//!
+//! Entries live under `<repo_root>/.generated/proofpatch-llm-cache/` (or
+//! `PROOFPATCH_LLM_CACHE_DIR`), one JSON file per request, keyed by a SHA-256 over the provider
+//! name and the OpenAI-shaped request: model, messages, tools, tool choice, temperature,
+//! `max_tokens`, and (when set) the sampling fields `n` and `seed`.
+//!
+//! Modes (`PROOFPATCH_LLM_CACHE`, or `set_mode_override` from `--llm-cache`):
+//! - `off` (default): no reads or writes
+//! - `read-write`: serve hits from disk, store fresh responses
+//! - `read-only`: replay only; a miss is an error and nothing is sent. Provider selection skips
+//!   API-key and reachability checks, so replay works without keys or network as long as the
+//!   provider order and model resolve the same way as when the cache was recorded.
+
+use crate::llm_usage::TokenUsage;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+/// Prefix of the error returned for a miss in `read-only` mode.
+pub const READ_ONLY_MISS: &str = "llm cache miss (read-only replay)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LlmCacheMode {
+    ReadWrite,
+    ReadOnly,
+    Off,
+}
+
+impl LlmCacheMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_lowercase().replace('_', "-").as_str() {
+            "read-write" | "rw" | "on" => Ok(Self::ReadWrite),
+            "read-only" | "ro" | "replay" => Ok(Self::ReadOnly),
+            "off" | "" => Ok(Self::Off),
+            other => Err(format!(
+                "invalid llm cache mode: {other} (expected read-write, read-only, or off)"
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ReadWrite => "read-write",
+            Self::ReadOnly => "read-only",
+            Self::Off => "off",
+        }
+    }
+}
+
+/// One cached response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub key: String,
+    pub provider: String,
+    pub model: String,
+    pub created_unix_ms: u64,
+    /// Usage of the original call.
+    pub usage: TokenUsage,
+    /// OpenAI-shaped response, as returned by the provider (after wire translation).
+    pub response: Value,
+}
+
+#[derive(Default)]
+struct State {
+    mode_override: Option<LlmCacheMode>,
+    root: Option<PathBuf>,
+}
+
+fn state() -> &'static Mutex<State> {
+    static SLOT: OnceLock<Mutex<State>> = OnceLock::new();
+    SLOT.get_or_init(|| Mutex::new(State::default()))
+}
+
+/// Override `PROOFPATCH_LLM_CACHE` for this process (`None`: use the env var).
+pub fn set_mode_override(mode: Option<LlmCacheMode>) {
+    if let Ok(mut g) = state().lock() {
+        g.mode_override = mode;
+    }
+}
+
+/// Repo whose `.generated/` holds the cache (set by `load_dotenv_smart`).
+pub fn set_repo_root(repo_root: &Path) {
+    if let Ok(mut g) = state().lock() {
+        g.root = Some(repo_root.to_path_buf());
+    }
+}
+
+/// Effective mode; an unparseable `PROOFPATCH_LLM_CACHE` counts as `off`.
+pub fn mode() -> LlmCacheMode {
+    if let Some(m) = state().lock().ok().and_then(|g| g.mode_override) {
+        return m;
+    }
+    std::env::var("PROOFPATCH_LLM_CACHE")
+        .ok()
+        .and_then(|s| LlmCacheMode::parse(&s).ok())
+        .unwrap_or(LlmCacheMode::Off)
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(d) = std::env::var("PROOFPATCH_LLM_CACHE_DIR")
+        .ok()
+        .filter(|s| !s.trim().is_empty())
+    {
+        return Some(PathBuf::from(d.trim()));
+    }
+    let root = state().lock().ok().and_then(|g| g.root.clone())?;
+    Some(root.join(".generated").join("proofpatch-llm-cache"))
+}
+
+/// Recursively sort object keys so the key does not depend on construction order.
+fn canonical(v: &Value) -> Value {
+    match v {
+        Value::Object(m) => {
+            let mut keys: Vec<&String> = m.keys().collect();
+            keys.sort();
+            let mut out = serde_json::Map::new();
+            for k in keys {
+                out.insert(k.clone(), canonical(&m[k]));
+            }
+            Value::Object(out)
+        }
+        Value::Array(xs) => Value::Array(xs.iter().map(canonical).collect()),
+        other => other.clone(),
+    }
+}
+
+/// Cache key (hex SHA-256) for an OpenAI-shaped request sent to `provider`.
+pub fn cache_key(provider: &str, payload: &Value) -> String {
+    use sha2::{Digest, Sha256};
+    let field = |k: &str| payload.get(k).cloned().unwrap_or(Value::Null);
+    let mut keyed = serde_json::json!({
+        "v": 1,
+        "provider": provider,
+        "model": field("model"),
+        "messages": field("messages"),
+        "tools": field("tools"),
+        "tool_choice": field("tool_choice"),
+        "temperature": field("temperature"),
+        "max_tokens": field("max_tokens"),
+    });
+    // Sampling fields only when set, so keys of single-sample requests are unchanged.
+    for k in ["n", "seed"] {
+        if let Some(v) = payload.get(k) {
+            keyed[k] = v.clone();
+        }
+    }
+    let bytes = canonical(&keyed).to_string();
+    let mut h = Sha256::new();
+    h.update(bytes.as_bytes());
+    hex::encode(h.finalize())
+}
+
+fn entry_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(&key[..2]).join(format!("{key}.json"))
+}
+
+/// Read the entry for `key`, if present and readable.
+pub fn lookup(key: &str) -> Option<CachedResponse> {
+    let dir = cache_dir()?;
+    let bytes = std::fs::read(entry_path(&dir, key)).ok()?;
+    serde_json::from_slice::<CachedResponse>(&bytes)
+        .ok()
+        .filter(|e| e.key == key)
+}
+
+/// Write an entry (atomically: temp file + rename).
+pub fn store(entry: &CachedResponse) -> Result<PathBuf, String> {
+    let dir = cache_dir().ok_or_else(|| "llm cache: no repo root or cache dir".to_string())?;
+    let path = entry_path(&dir, &entry.key);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("llm cache: create {}: {e}", parent.display()))?;
+    }
+    let data = serde_json::to_vec_pretty(entry).map_err(|e| format!("llm cache: encode: {e}"))?;
+    let tmp = path.with_extension(format!("json.tmp.{}", std::process::id()));
+    std::fs::write(&tmp, data).map_err(|e| format!("llm cache: write {}: {e}", tmp.display()))?;
+    std::fs::rename(&tmp, &path)
+        .map_err(|e| format!("llm cache: rename to {}: {e}", path.display()))?;
+    Ok(path)
+}
+
+pub fn now_unix_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,368 @@
+//! Token usage, cost accounting, and run-level LLM budgets.
+//!
+//! Every request sent through `llm` is recorded in a process-wide ledger. Token counts come from
+//! the response's `usage` block (Anthropic's is normalized by `llm_wire`); when a provider omits
+//! it, counts are estimated from request/response sizes and flagged as such. Costs use the
+//! `[llm.prices]` table.
+//!
+//! The budget (`[llm.budget]`, overridden per field by `set_budget_override`) is checked before
+//! each request. Once it is spent, requests fail with an error starting with `BUDGET_EXCEEDED`.
+//! The request that crosses a limit still completes, so a run overshoots by at most one response;
+//! requests that would otherwise run in parallel (`llm::chat_completion_samples`) are sent one at
+//! a time while a limit is set. A cost limit cannot count calls to models without a price, so
+//! while one is set those calls are refused up front (`UNPRICED_MODEL`).
+
+use crate::config::{LlmBudget, ModelPrice};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+/// Prefix of the error returned for requests refused by the budget.
+pub const BUDGET_EXCEEDED: &str = "llm budget exceeded";
+
+/// Prefix of the error returned for requests to unpriced models while a cost limit is set.
+pub const UNPRICED_MODEL: &str = "llm cost limit set but model has no price";
+
+/// Where a budget field came from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetSource {
+    /// No limit.
+    #[default]
+    None,
+    /// `[llm.budget]` in proofpatch.toml.
+    Config,
+    /// A CLI flag (`--llm-max-tokens`, `--llm-max-cost`).
+    Flag,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetSources {
+    pub max_tokens: BudgetSource,
+    pub max_cost_usd: BudgetSource,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    /// The provider reported no usage; counts are estimated (about 4 chars per token).
+    pub estimated: bool,
+    /// USD, when the model has a `[llm.prices]` entry.
+    pub cost_usd: Option<f64>,
+    /// Served from the response cache (`llm_cache`); counts are those of the original call and
+    /// are not added to the run's totals.
+    #[serde(default)]
+    pub cached: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub provider: String,
+    pub model: String,
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub cost_usd: Option<f64>,
+}
+
+/// Run-level totals, as included in JSON reports under `llm_usage`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageSummary {
+    pub calls: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    /// Sum over priced calls only (see `unpriced_calls`).
+    pub cost_usd: f64,
+    /// Calls whose token counts were estimated.
+    pub estimated_calls: u64,
+    /// Calls to models without a `[llm.prices]` entry (not included in `cost_usd`).
+    pub unpriced_calls: u64,
+    /// Requests refused because the budget was spent, or because a cost limit is set and the
+    /// model has no price.
+    pub refused_calls: u64,
+    /// Requests answered from the response cache (not included in `calls`).
+    pub cache_hits: u64,
+    pub budget: LlmBudget,
+    /// Where each field of `budget` came from. A `[llm]` section that fails to parse leaves
+    /// `none` here (and a warning on stderr).
+    #[serde(default)]
+    pub budget_source: BudgetSources,
+    pub budget_exhausted: bool,
+    pub by_model: Vec<ModelUsage>,
+}
+
+#[derive(Default)]
+struct Ledger {
+    by_model: BTreeMap<(String, String), ModelUsage>,
+    estimated_calls: u64,
+    unpriced_calls: u64,
+    refused_calls: u64,
+    cache_hits: u64,
+    prices: BTreeMap<String, ModelPrice>,
+    config_budget: LlmBudget,
+    budget_override: LlmBudget,
+}
+
+impl Ledger {
+    fn budget(&self) -> LlmBudget {
+        LlmBudget {
+            max_tokens: self
+                .budget_override
+                .max_tokens
+                .or(self.config_budget.max_tokens),
+            max_cost_usd: self
+                .budget_override
+                .max_cost_usd
+                .or(self.config_budget.max_cost_usd),
+        }
+    }
+
+    fn budget_source(&self) -> BudgetSources {
+        let source = |flag: bool, config: bool| {
+            if flag {
+                BudgetSource::Flag
+            } else if config {
+                BudgetSource::Config
+            } else {
+                BudgetSource::None
+            }
+        };
+        BudgetSources {
+            max_tokens: source(
+                self.budget_override.max_tokens.is_some(),
+                self.config_budget.max_tokens.is_some(),
+            ),
+            max_cost_usd: source(
+                self.budget_override.max_cost_usd.is_some(),
+                self.config_budget.max_cost_usd.is_some(),
+            ),
+        }
+    }
+
+    fn totals(&self) -> (u64, f64) {
+        let tokens = self.by_model.values().map(|m| m.total_tokens).sum();
+        let cost = self.by_model.values().filter_map(|m| m.cost_usd).sum();
+        (tokens, cost)
+    }
+
+    fn exhausted(&self) -> Option<String> {
+        let b = self.budget();
+        let (tokens, cost) = self.totals();
+        if let Some(max) = b.max_tokens {
+            if tokens >= max {
+                return Some(format!("{tokens} tokens used (max {max})"));
+            }
+        }
+        if let Some(max) = b.max_cost_usd {
+            if cost >= max {
+                return Some(format!("${cost:.4} spent (max ${max:.4})"));
+            }
+        }
+        None
+    }
+}
+
+fn ledger() -> &'static Mutex<Ledger> {
+    static SLOT: OnceLock<Mutex<Ledger>> = OnceLock::new();
+    SLOT.get_or_init(|| Mutex::new(Ledger::default()))
+}
+
+/// Install the price table and the config budget (from `[llm]`).
+pub fn configure(prices: BTreeMap<String, ModelPrice>, budget: LlmBudget) {
+    if let Ok(mut g) = ledger().lock() {
+        g.prices = prices;
+        g.config_budget = budget;
+    }
+}
+
+/// Override budget fields for this run (CLI flags); unset fields fall back to `[llm.budget]`.
+pub fn set_budget_override(budget: LlmBudget) {
+    if let Ok(mut g) = ledger().lock() {
+        g.budget_override = budget;
+    }
+}
+
+/// Forget all recorded usage (the price table and budgets are kept).
+pub fn reset() {
+    if let Ok(mut g) = ledger().lock() {
+        g.by_model.clear();
+        g.estimated_calls = 0;
+        g.unpriced_calls = 0;
+        g.refused_calls = 0;
+        g.cache_hits = 0;
+    }
+}
+
+/// Price for `model`, preferring a `"<provider>/<model>"` entry.
+pub fn price_for(
+    prices: &BTreeMap<String, ModelPrice>,
+    provider: &str,
+    model: &str,
+) -> Option<ModelPrice> {
+    prices
+        .get(&format!("{provider}/{model}"))
+        .or_else(|| prices.get(model))
+        .copied()
+}
+
+pub fn cost_usd(price: ModelPrice, usage: &TokenUsage) -> f64 {
+    (usage.prompt_tokens as f64 * price.input_per_mtok
+        + usage.completion_tokens as f64 * price.output_per_mtok)
+        / 1_000_000.0
+}
+
+/// Token counts from an OpenAI-shaped response's `usage` block.
+pub fn usage_from_response(raw: &Value) -> Option<TokenUsage> {
+    let u = raw.get("usage").filter(|u| u.is_object())?;
+    let field = |k: &str| u.get(k).and_then(|v| v.as_u64());
+    let prompt = field("prompt_tokens").or_else(|| field("input_tokens"));
+    let completion = field("completion_tokens").or_else(|| field("output_tokens"));
+    let total = field("total_tokens");
+    if prompt.is_none() && completion.is_none() && total.is_none() {
+        return None;
+    }
+    let prompt = prompt.unwrap_or(0);
+    let completion = completion.unwrap_or(0);
+    Some(TokenUsage {
+        prompt_tokens: prompt,
+        completion_tokens: completion,
+        total_tokens: total.unwrap_or(prompt + completion),
+        estimated: false,
+        cost_usd: None,
+        cached: false,
+    })
+}
+
+/// Rough token counts (about 4 chars per token) for providers that report no usage.
+pub fn estimate_usage(payload: &Value, raw: &Value) -> TokenUsage {
+    let tokens = |chars: usize| (chars as u64).div_ceil(4);
+    let message = raw
+        .get("choices")
+        .and_then(|c| c.get(0))
+        .and_then(|c| c.get("message"));
+    let completion_chars = message
+        .map(|m| {
+            m.get("content")
+                .and_then(|c| c.as_str())
+                .unwrap_or("")
+                .len()
+                + m.get("tool_calls")
+                    .map(|t| t.to_string().len())
+                    .unwrap_or(0)
+        })
+        .unwrap_or(0);
+    let prompt = tokens(payload.to_string().len());
+    let completion = tokens(completion_chars);
+    TokenUsage {
+        prompt_tokens: prompt,
+        completion_tokens: completion,
+        total_tokens: prompt + completion,
+        estimated: true,
+        cost_usd: None,
+        cached: false,
+    }
+}
+
+/// Refuse the next request to `model` if the budget is spent, or if a cost limit is set and the
+/// model has no price (its calls would not count towards the limit).
+pub fn check_budget(provider: &str, model: &str) -> Result<(), String> {
+    let Ok(mut g) = ledger().lock() else {
+        return Ok(());
+    };
+    if let Some(why) = g.exhausted() {
+        g.refused_calls += 1;
+        return Err(format!("{BUDGET_EXCEEDED}: {why}"));
+    }
+    if g.budget().max_cost_usd.is_some() && price_for(&g.prices, provider, model).is_none() {
+        g.refused_calls += 1;
+        return Err(format!(
+            "{UNPRICED_MODEL}: add `[llm.prices.\"{model}\"]` (or `\"{provider}/{model}\"`) to proofpatch.toml, \
+             zero for a free model, or drop the cost limit"
+        ));
+    }
+    Ok(())
+}
+
+/// Has the budget been spent (i.e. would the next request be refused)?
+pub fn budget_exhausted() -> bool {
+    ledger()
+        .lock()
+        .map(|g| g.exhausted().is_some())
+        .unwrap_or(false)
+}
+
+/// Is a token or cost limit in effect?
+pub fn budget_limited() -> bool {
+    ledger()
+        .lock()
+        .map(|g| {
+            let b = g.budget();
+            b.max_tokens.is_some() || b.max_cost_usd.is_some()
+        })
+        .unwrap_or(false)
+}
+
+/// Record one response; fills in `cost_usd` from the price table and returns the final usage.
+pub fn record(provider: &str, model: &str, mut usage: TokenUsage) -> TokenUsage {
+    let Ok(mut g) = ledger().lock() else {
+        return usage;
+    };
+    usage.cost_usd = price_for(&g.prices, provider, model).map(|p| cost_usd(p, &usage));
+    if usage.estimated {
+        g.estimated_calls += 1;
+    }
+    if usage.cost_usd.is_none() {
+        g.unpriced_calls += 1;
+    }
+    let m = g
+        .by_model
+        .entry((provider.to_string(), model.to_string()))
+        .or_insert_with(|| ModelUsage {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            ..ModelUsage::default()
+        });
+    m.calls += 1;
+    m.prompt_tokens += usage.prompt_tokens;
+    m.completion_tokens += usage.completion_tokens;
+    m.total_tokens += usage.total_tokens;
+    if let Some(c) = usage.cost_usd {
+        m.cost_usd = Some(m.cost_usd.unwrap_or(0.0) + c);
+    }
+    usage
+}
+
+/// Count a response served from the cache.
+pub fn record_cache_hit() {
+    if let Ok(mut g) = ledger().lock() {
+        g.cache_hits += 1;
+    }
+}
+
+pub fn summary() -> UsageSummary {
+    let Ok(g) = ledger().lock() else {
+        return UsageSummary::default();
+    };
+    let by_model: Vec<ModelUsage> = g.by_model.values().cloned().collect();
+    let (total_tokens, cost_usd) = g.totals();
+    UsageSummary {
+        calls: by_model.iter().map(|m| m.calls).sum(),
+        prompt_tokens: by_model.iter().map(|m| m.prompt_tokens).sum(),
+        completion_tokens: by_model.iter().map(|m| m.completion_tokens).sum(),
+        total_tokens,
+        cost_usd,
+        estimated_calls: g.estimated_calls,
+        unpriced_calls: g.unpriced_calls,
+        refused_calls: g.refused_calls,
+        cache_hits: g.cache_hits,
+        budget: g.budget(),
+        budget_source: g.budget_source(),
+        budget_exhausted: g.exhausted().is_some(),
+        by_model,
+    }
+}
@@ -0,0 +1,379 @@
+//! Provider wire formats.
+//!
+//! Internally every request and response uses the OpenAI chat-completions shape: `messages`
+//! (with `system` / `user` / `assistant` / `tool` roles), `tools` as `{type:"function", function}`,
+//! and responses as `choices[0].message` with optional `tool_calls`. Providers that speak something
+//! else are translated at the edge, so tool-calling callers (`chat_completion_structured`, tool
+//! loops) work the same across formats.
+//!
+//! Supported: OpenAI-compatible `/chat/completions`, and Anthropic `/messages` (tools become
+//! `tool_use` / `tool_result` content blocks).
+
+use crate::config::WireFormat;
+use serde_json::{json, Map, Value};
+
+/// Anthropic API version header value.
+pub const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Anthropic requires `max_tokens`; used when the provider does not set one.
+pub const ANTHROPIC_DEFAULT_MAX_TOKENS: u64 = 4096;
+
+pub fn wire_format_name(w: WireFormat) -> &'static str {
+    match w {
+        WireFormat::OpenAi => "openai",
+        WireFormat::Anthropic => "anthropic",
+    }
+}
+
+/// Path appended to the provider's `base_url`.
+pub fn request_path(w: WireFormat) -> &'static str {
+    match w {
+        WireFormat::OpenAi => "/chat/completions",
+        WireFormat::Anthropic => "/messages",
+    }
+}
+
+fn text_of(content: &Value) -> String {
+    match content {
+        Value::String(s) => s.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|p| p.get("text").and_then(|t| t.as_str()))
+            .collect::<Vec<_>>()
+            .join(""),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Append `block` to the last message if it has the same role, else start a new message.
+///
+/// Anthropic requires alternating roles; consecutive tool results must share one user turn.
+fn push_block(out: &mut Vec<Value>, role: &str, block: Value) {
+    if let Some(last) = out.last_mut() {
+        if last.get("role").and_then(|r| r.as_str()) == Some(role) {
+            if let Some(arr) = last.get_mut("content").and_then(|c| c.as_array_mut()) {
+                arr.push(block);
+                return;
+            }
+        }
+    }
+    out.push(json!({ "role": role, "content": [block] }));
+}
+
+/// Translate an OpenAI-shaped chat request into an Anthropic Messages request.
+pub fn openai_to_anthropic_request(payload: &Value) -> Value {
+    let mut system: Vec<String> = Vec::new();
+    let mut messages: Vec<Value> = Vec::new();
+    let empty = Vec::new();
+    for m in payload
+        .get("messages")
+        .and_then(|v| v.as_array())
+        .unwrap_or(&empty)
+    {
+        let role = m.get("role").and_then(|r| r.as_str()).unwrap_or("user");
+        let content = m.get("content").cloned().unwrap_or(Value::Null);
+        match role {
+            "system" | "developer" => system.push(text_of(&content)),
+            "tool" => push_block(
+                &mut messages,
+                "user",
+                json!({
+                    "type": "tool_result",
+                    "tool_use_id": m.get("tool_call_id").cloned().unwrap_or(Value::Null),
+                    "content": text_of(&content),
+                }),
+            ),
+            "assistant" => {
+                let text = text_of(&content);
+                if !text.is_empty() {
+                    push_block(
+                        &mut messages,
+                        "assistant",
+                        json!({ "type": "text", "text": text }),
+                    );
+                }
+                for tc in m
+                    .get("tool_calls")
+                    .and_then(|v| v.as_array())
+                    .unwrap_or(&empty)
+                {
+                    let f = tc.get("function").cloned().unwrap_or(Value::Null);
+                    let input = f
+                        .get("arguments")
+                        .and_then(|a| a.as_str())
+                        .and_then(|a| serde_json::from_str::<Value>(a).ok())
+                        .unwrap_or_else(|| json!({}));
+                    push_block(
+                        &mut messages,
+                        "assistant",
+                        json!({
+                            "type": "tool_use",
+                            "id": tc.get("id").cloned().unwrap_or(Value::Null),
+                            "name": f.get("name").cloned().unwrap_or(Value::Null),
+                            "input": input,
+                        }),
+                    );
+                }
+            }
+            _ => push_block(
+                &mut messages,
+                "user",
+                json!({ "type": "text", "text": text_of(&content) }),
+            ),
+        }
+    }
+
+    let mut out = Map::new();
+    out.insert(
+        "model".to_string(),
+        payload.get("model").cloned().unwrap_or(Value::Null),
+    );
+    out.insert(
+        "max_tokens".to_string(),
+        payload
+            .get("max_tokens")
+            .cloned()
+            .unwrap_or_else(|| json!(ANTHROPIC_DEFAULT_MAX_TOKENS)),
+    );
+    if !system.is_empty() {
+        out.insert("system".to_string(), json!(system.join("\n\n")));
+    }
+    out.insert("messages".to_string(), Value::Array(messages));
+    if let Some(t) = payload.get("temperature") {
+        out.insert("temperature".to_string(), t.clone());
+    }
+    if let Some(st) = payload.get("stream") {
+        out.insert("stream".to_string(), st.clone());
+    }
+    if let Some(tools) = payload.get("tools").and_then(|v| v.as_array()) {
+        let tools: Vec<Value> = tools
+            .iter()
+            .filter_map(|t| {
+                let f = t.get("function")?;
+                Some(json!({
+                    "name": f.get("name")?,
+                    "description": f.get("description").cloned().unwrap_or(json!("")),
+                    "input_schema": f.get("parameters").cloned().unwrap_or(json!({"type": "object"})),
+                }))
+            })
+            .collect();
+        out.insert("tools".to_string(), Value::Array(tools));
+        let choice = match payload.get("tool_choice") {
+            Some(Value::String(s)) if s == "none" => json!({ "type": "none" }),
+            Some(Value::String(s)) if s == "required" => json!({ "type": "any" }),
+            Some(Value::Object(o)) => match o
+                .get("function")
+                .and_then(|f| f.get("name"))
+                .and_then(|n| n.as_str())
+            {
+                Some(name) => json!({ "type": "tool", "name": name }),
+                None => json!({ "type": "auto" }),
+            },
+            _ => json!({ "type": "auto" }),
+        };
+        out.insert("tool_choice".to_string(), choice);
+    }
+    Value::Object(out)
+}
+
+/// OpenAI `finish_reason` for an Anthropic `stop_reason`.
+fn openai_finish_reason(stop_reason: &str) -> &str {
+    match stop_reason {
+        "end_turn" | "stop_sequence" => "stop",
+        "tool_use" => "tool_calls",
+        "max_tokens" => "length",
+        other => other,
+    }
+}
+
+/// Translate an Anthropic Messages response into an OpenAI chat-completion envelope.
+///
+/// The original response is kept under `wire_raw`.
+pub fn anthropic_to_openai_response(raw: &Value) -> Value {
+    let empty = Vec::new();
+    let blocks = raw
+        .get("content")
+        .and_then(|c| c.as_array())
+        .unwrap_or(&empty);
+    let text: String = blocks
+        .iter()
+        .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("text"))
+        .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+        .collect::<Vec<_>>()
+        .join("");
+    let tool_calls: Vec<Value> = blocks
+        .iter()
+        .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("tool_use"))
+        .map(|b| {
+            json!({
+                "id": b.get("id").cloned().unwrap_or(Value::Null),
+                "type": "function",
+                "function": {
+                    "name": b.get("name").cloned().unwrap_or(Value::Null),
+                    "arguments": b.get("input").cloned().unwrap_or_else(|| json!({})).to_string(),
+                }
+            })
+        })
+        .collect();
+    let finish_reason = raw
+        .get("stop_reason")
+        .and_then(|s| s.as_str())
+        .map_or(Value::Null, |s| json!(openai_finish_reason(s)));
+    let mut message = json!({
+        "role": "assistant",
+        "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) },
+    });
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+    let mut out = json!({
+        "id": raw.get("id").cloned().unwrap_or(Value::Null),
+        "object": "chat.completion",
+        "model": raw.get("model").cloned().unwrap_or(Value::Null),
+        "choices": [{ "index": 0, "message": message, "finish_reason": finish_reason }],
+        "wire_raw": raw,
+    });
+    if let Some(u) = raw.get("usage") {
+        let input = u.get("input_tokens").and_then(|v| v.as_u64()).unwrap_or(0);
+        let output = u.get("output_tokens").and_then(|v| v.as_u64()).unwrap_or(0);
+        out["usage"] = json!({
+            "prompt_tokens": input,
+            "completion_tokens": output,
+            "total_tokens": input + output,
+        });
+    }
+    out
+}
+
+/// Incremental parser for a `text/event-stream` body: feed it raw chunks, get back the `data:`
+/// payload of each complete event (multi-line data joined with `\n`).
+///
+/// Chunks are buffered as bytes and only complete lines are decoded, so a multi-byte character
+/// split across two chunks (`ℕ`, `⊢`, ...) comes through intact.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buf: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buf.extend_from_slice(bytes);
+        let mut out = Vec::new();
+        while let Some(i) = self.buf.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=i).collect();
+            let line = String::from_utf8_lossy(&line);
+            self.line(line.trim_end_matches(['\n', '\r']), &mut out);
+        }
+        out
+    }
+
+    /// Flush a trailing event that was not terminated by a blank line.
+    pub fn finish(&mut self) -> Vec<String> {
+        let mut out = Vec::new();
+        let rest = std::mem::take(&mut self.buf);
+        if !rest.is_empty() {
+            let rest = String::from_utf8_lossy(&rest);
+            self.line(rest.trim_end_matches('\r'), &mut out);
+        }
+        self.line("", &mut out);
+        out
+    }
+
+    fn line(&mut self, line: &str, out: &mut Vec<String>) {
+        if line.is_empty() {
+            if !self.data.is_empty() {
+                out.push(self.data.join("\n"));
+                self.data.clear();
+            }
+        } else if let Some(d) = line.strip_prefix("data:") {
+            self.data.push(d.strip_prefix(' ').unwrap_or(d).to_string());
+        }
+        // `event:`, `id:`, `retry:` and `:` comments carry nothing we need (Anthropic repeats the
+        // event name as `type` in the data).
+    }
+}
+
+/// What one streamed event contributes to the response.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamDelta {
+    pub text: String,
+    /// The stream is finished (`[DONE]`, or Anthropic's `message_stop`).
+    pub done: bool,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    /// OpenAI-style finish reason.
+    pub finish_reason: Option<String>,
+    /// An error event sent mid-stream.
+    pub error: Option<String>,
+}
+
+/// Decode one SSE `data:` payload in the provider's wire format.
+pub fn stream_delta(w: WireFormat, data: &str) -> StreamDelta {
+    let mut d = StreamDelta::default();
+    if data.trim() == "[DONE]" {
+        d.done = true;
+        return d;
+    }
+    let Ok(v) = serde_json::from_str::<Value>(data) else {
+        return d;
+    };
+    let u64_at = |v: &Value, path: &[&str]| {
+        path.iter()
+            .try_fold(v, |v, k| v.get(*k))
+            .and_then(|x| x.as_u64())
+    };
+    if let Some(e) = v.get("error") {
+        d.error = Some(
+            e.get("message")
+                .and_then(|m| m.as_str())
+                .map(str::to_string)
+                .unwrap_or_else(|| e.to_string()),
+        );
+        return d;
+    }
+    match w {
+        WireFormat::OpenAi => {
+            let choice = v.get("choices").and_then(|c| c.get(0));
+            if let Some(t) = choice
+                .and_then(|c| c.get("delta"))
+                .and_then(|m| m.get("content"))
+                .and_then(|t| t.as_str())
+            {
+                d.text = t.to_string();
+            }
+            d.finish_reason = choice
+                .and_then(|c| c.get("finish_reason"))
+                .and_then(|f| f.as_str())
+                .map(str::to_string);
+            d.prompt_tokens = u64_at(&v, &["usage", "prompt_tokens"]);
+            d.completion_tokens = u64_at(&v, &["usage", "completion_tokens"]);
+        }
+        WireFormat::Anthropic => match v.get("type").and_then(|t| t.as_str()) {
+            Some("message_start") => {
+                d.prompt_tokens = u64_at(&v, &["message", "usage", "input_tokens"]);
+            }
+            Some("content_block_delta") => {
+                if let Some(t) = v
+                    .get("delta")
+                    .and_then(|x| x.get("text"))
+                    .and_then(|t| t.as_str())
+                {
+                    d.text = t.to_string();
+                }
+            }
+            Some("message_delta") => {
+                d.completion_tokens = u64_at(&v, &["usage", "output_tokens"]);
+                d.finish_reason = v
+                    .get("delta")
+                    .and_then(|x| x.get("stop_reason"))
+                    .and_then(|s| s.as_str())
+                    .map(|s| openai_finish_reason(s).to_string());
+            }
+            Some("message_stop") => d.done = true,
+            _ => {}
+        },
+    }
+    d
+}
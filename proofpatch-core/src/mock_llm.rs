@@ -0,0 +1,662 @@
+//! Scripted OpenAI-compatible LLM server for tests (`proofpatch-mock-llm`).
+//!
+//! Serves, on localhost:
+//! - `GET  <prefix>/models` (what provider probes hit)
+//! - `POST <prefix>/chat/completions`
+//! - `POST <prefix>/messages` (Anthropic wire format; replies are translated)
+//!
+//! Replies come from a `MockScript`, usually a JSON fixture file:
+//!
+//! ```json
+//! {
+//!   "models": ["mock-model"],
+//!   "rules": [
+//!     { "when": { "tool": "emit" },
+//!       "reply": { "tool_calls": [{ "name": "emit", "arguments": { "confidence": 0.5 } }] } },
+//!     { "when": { "contains": "theorem foo" }, "times": 1,
+//!       "reply": { "status": 503, "error": "overloaded", "delay_ms": 50 } },
+//!     { "reply": { "body": "{not json" } },
+//!     { "reply": { "content": "by simp" } }
+//!   ]
+//! }
+//! ```
+//!
+//! Rules are tried in order; the first one whose `when` matches and that has uses left (`times`,
+//! default unlimited) answers. Unmatched requests get a 500.
+//!
+//! Requests with `"stream": true` get the reply's text as server-sent events (OpenAI chunks, or
+//! Anthropic `content_block_delta` events), `chunk_chars` characters at a time with
+//! `chunk_delay_ms` between events; `drop_after_events` cuts the stream off before its terminal
+//! event. Tool calls are not streamed.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockScript {
+    /// Ids listed by `/models` (default: `["mock-model"]`).
+    #[serde(default)]
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub rules: Vec<MockRule>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockRule {
+    #[serde(default)]
+    pub when: MockMatch,
+    /// How many requests this rule answers (default: unlimited).
+    #[serde(default)]
+    pub times: Option<u64>,
+    pub reply: MockReply,
+}
+
+/// All set fields must match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockMatch {
+    /// Substring of any message's text.
+    #[serde(default)]
+    pub contains: Option<String>,
+    /// The request offers a tool with this name.
+    #[serde(default)]
+    pub tool: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Substring of the request path (e.g. `/messages`).
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockReply {
+    /// Assistant text.
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Vec<MockToolCall>,
+    /// HTTP status (default 200). With `error`, the body is `{"error": {"message": ...}}`.
+    #[serde(default)]
+    pub status: Option<u16>,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Sent verbatim instead of a generated body (e.g. malformed JSON).
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Wait this long before answering.
+    #[serde(default)]
+    pub delay_ms: Option<u64>,
+    #[serde(default)]
+    pub usage: Option<MockUsage>,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+    /// Characters of `content` per streamed event (default 8).
+    #[serde(default)]
+    pub chunk_chars: Option<usize>,
+    /// Wait this long between streamed events.
+    #[serde(default)]
+    pub chunk_delay_ms: Option<u64>,
+    /// Close the connection after this many streamed events, before the terminal one (a dropped
+    /// connection mid-stream).
+    #[serde(default)]
+    pub drop_after_events: Option<usize>,
+    /// Honor the request's `n`: reply with this many choices (`content` repeated), up to `n`.
+    /// Unset: always one choice, like providers that ignore `n`.
+    #[serde(default)]
+    pub n_choices: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockToolCall {
+    pub name: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MockUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// One request as received.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    /// Lowercased names.
+    pub headers: Vec<(String, String)>,
+    pub body: Value,
+    /// Index of the rule that answered, if any.
+    pub rule: Option<usize>,
+}
+
+impl MockRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers
+            .iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+pub fn load_script(path: &Path) -> Result<MockScript, String> {
+    let s = std::fs::read_to_string(path)
+        .map_err(|e| format!("read fixture {}: {e}", path.display()))?;
+    serde_json::from_str(&s).map_err(|e| format!("parse fixture {}: {e}", path.display()))
+}
+
+struct Shared {
+    script: MockScript,
+    used: Vec<u64>,
+    requests: Vec<MockRequest>,
+    log: Option<PathBuf>,
+}
+
+/// A running mock server; stops when dropped.
+pub struct MockLlmServer {
+    pub addr: SocketAddr,
+    /// `http://<addr>/v1`, for a provider's `base_url`.
+    pub base_url: String,
+    shared: Arc<Mutex<Shared>>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl MockLlmServer {
+    /// Serve `script` on an ephemeral localhost port.
+    pub async fn start(script: MockScript) -> Result<Self, String> {
+        Self::bind("127.0.0.1:0", script, None).await
+    }
+
+    /// Serve `script` on `addr`, appending each request to `log` (JSONL) when set.
+    pub async fn bind(
+        addr: &str,
+        script: MockScript,
+        log: Option<PathBuf>,
+    ) -> Result<Self, String> {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|e| format!("bind {addr}: {e}"))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| format!("local_addr: {e}"))?;
+        let shared = Arc::new(Mutex::new(Shared {
+            used: vec![0; script.rules.len()],
+            script,
+            requests: Vec::new(),
+            log,
+        }));
+        let shared2 = shared.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let Ok((sock, _)) = listener.accept().await else {
+                    return;
+                };
+                tokio::spawn(handle_conn(sock, shared2.clone()));
+            }
+        });
+        Ok(Self {
+            addr,
+            base_url: format!("http://{addr}/v1"),
+            shared,
+            task,
+        })
+    }
+
+    /// Requests received so far, in arrival order.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.shared
+            .lock()
+            .map(|g| g.requests.clone())
+            .unwrap_or_default()
+    }
+
+    /// Serve until the task ends (i.e. forever, for the standalone binary).
+    pub async fn wait(mut self) {
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for MockLlmServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+fn message_text(body: &Value) -> String {
+    let mut out = String::new();
+    for m in body
+        .get("messages")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+    {
+        match m.get("content") {
+            Some(Value::String(s)) => out.push_str(s),
+            Some(Value::Array(parts)) => {
+                for p in parts {
+                    for k in ["text", "content"] {
+                        if let Some(s) = p.get(k).and_then(|t| t.as_str()) {
+                            out.push_str(s);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        out.push('\n');
+    }
+    if let Some(s) = body.get("system").and_then(|s| s.as_str()) {
+        out.push_str(s);
+    }
+    out
+}
+
+fn offers_tool(body: &Value, name: &str) -> bool {
+    body.get("tools")
+        .and_then(|t| t.as_array())
+        .into_iter()
+        .flatten()
+        .any(|t| {
+            let n = t
+                .get("function")
+                .and_then(|f| f.get("name"))
+                .or_else(|| t.get("name"));
+            n.and_then(|n| n.as_str()) == Some(name)
+        })
+}
+
+fn matches(m: &MockMatch, path: &str, body: &Value) -> bool {
+    m.contains
+        .as_deref()
+        .is_none_or(|s| message_text(body).contains(s))
+        && m.tool.as_deref().is_none_or(|t| offers_tool(body, t))
+        && m.model
+            .as_deref()
+            .is_none_or(|x| body.get("model").and_then(|v| v.as_str()) == Some(x))
+        && m.path.as_deref().is_none_or(|p| path.contains(p))
+}
+
+fn openai_body(reply: &MockReply, model: &Value, n: usize) -> Value {
+    let tool_calls: Vec<Value> = reply
+        .tool_calls
+        .iter()
+        .enumerate()
+        .map(|(i, tc)| {
+            json!({
+                "id": format!("call_{i}"),
+                "type": "function",
+                "function": { "name": tc.name, "arguments": tc.arguments.to_string() },
+            })
+        })
+        .collect();
+    let mut message = json!({ "role": "assistant", "content": reply.content });
+    if !tool_calls.is_empty() {
+        message["tool_calls"] = Value::Array(tool_calls);
+    }
+    let finish = reply.finish_reason.clone().unwrap_or_else(|| {
+        if reply.tool_calls.is_empty() {
+            "stop".to_string()
+        } else {
+            "tool_calls".to_string()
+        }
+    });
+    let choices: Vec<Value> = (0..reply.n_choices.map_or(1, |c| c.min(n).max(1)))
+        .map(|i| json!({ "index": i, "message": message, "finish_reason": finish }))
+        .collect();
+    let mut out = json!({
+        "id": "mock-cmpl",
+        "object": "chat.completion",
+        "model": model,
+        "choices": choices,
+    });
+    if let Some(u) = reply.usage {
+        out["usage"] = json!({
+            "prompt_tokens": u.prompt_tokens,
+            "completion_tokens": u.completion_tokens,
+            "total_tokens": u.prompt_tokens + u.completion_tokens,
+        });
+    }
+    out
+}
+
+fn anthropic_body(reply: &MockReply, model: &Value) -> Value {
+    let mut content: Vec<Value> = Vec::new();
+    if let Some(t) = reply.content.as_ref() {
+        content.push(json!({ "type": "text", "text": t }));
+    }
+    for (i, tc) in reply.tool_calls.iter().enumerate() {
+        content.push(json!({
+            "type": "tool_use",
+            "id": format!("toolu_{i}"),
+            "name": tc.name,
+            "input": tc.arguments,
+        }));
+    }
+    let stop = reply.finish_reason.clone().unwrap_or_else(|| {
+        if reply.tool_calls.is_empty() {
+            "end_turn".to_string()
+        } else {
+            "tool_use".to_string()
+        }
+    });
+    let u = reply.usage.unwrap_or(MockUsage {
+        prompt_tokens: 0,
+        completion_tokens: 0,
+    });
+    json!({
+        "id": "msg_mock",
+        "type": "message",
+        "role": "assistant",
+        "model": model,
+        "content": content,
+        "stop_reason": stop,
+        "usage": { "input_tokens": u.prompt_tokens, "output_tokens": u.completion_tokens },
+    })
+}
+
+fn text_chunks(reply: &MockReply) -> Vec<String> {
+    let chars: Vec<char> = reply.content.as_deref().unwrap_or("").chars().collect();
+    chars
+        .chunks(reply.chunk_chars.unwrap_or(8).max(1))
+        .map(|c| c.iter().collect())
+        .collect()
+}
+
+fn sse(event: Option<&str>, data: &Value) -> String {
+    match event {
+        Some(e) => format!("event: {e}\ndata: {data}\n\n"),
+        None => format!("data: {data}\n\n"),
+    }
+}
+
+/// The reply as OpenAI `chat.completion.chunk` events, ending with `[DONE]`.
+fn openai_stream(reply: &MockReply, model: &Value) -> Vec<String> {
+    let chunk = |delta: Value, finish: Value| {
+        json!({
+            "id": "mock-cmpl",
+            "object": "chat.completion.chunk",
+            "model": model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish }],
+        })
+    };
+    let mut out = vec![sse(
+        None,
+        &chunk(json!({ "role": "assistant" }), Value::Null),
+    )];
+    for t in text_chunks(reply) {
+        out.push(sse(None, &chunk(json!({ "content": t }), Value::Null)));
+    }
+    let finish = reply.finish_reason.as_deref().unwrap_or("stop");
+    out.push(sse(None, &chunk(json!({}), json!(finish))));
+    if let Some(u) = reply.usage {
+        let usage = json!({
+            "id": "mock-cmpl",
+            "object": "chat.completion.chunk",
+            "model": model,
+            "choices": [],
+            "usage": {
+                "prompt_tokens": u.prompt_tokens,
+                "completion_tokens": u.completion_tokens,
+                "total_tokens": u.prompt_tokens + u.completion_tokens,
+            },
+        });
+        out.push(sse(None, &usage));
+    }
+    out.push("data: [DONE]\n\n".to_string());
+    out
+}
+
+/// The reply as Anthropic Messages stream events.
+fn anthropic_stream(reply: &MockReply, model: &Value) -> Vec<String> {
+    let u = reply.usage.unwrap_or(MockUsage {
+        prompt_tokens: 0,
+        completion_tokens: 0,
+    });
+    let start = json!({
+        "type": "message_start",
+        "message": {
+            "id": "msg_mock", "type": "message", "role": "assistant", "model": model,
+            "content": [], "usage": { "input_tokens": u.prompt_tokens, "output_tokens": 0 },
+        },
+    });
+    let mut out = vec![
+        sse(Some("message_start"), &start),
+        sse(
+            Some("content_block_start"),
+            &json!({ "type": "content_block_start", "index": 0,
+                     "content_block": { "type": "text", "text": "" } }),
+        ),
+    ];
+    for t in text_chunks(reply) {
+        out.push(sse(
+            Some("content_block_delta"),
+            &json!({ "type": "content_block_delta", "index": 0,
+                     "delta": { "type": "text_delta", "text": t } }),
+        ));
+    }
+    let stop = reply.finish_reason.as_deref().unwrap_or("end_turn");
+    out.push(sse(
+        Some("content_block_stop"),
+        &json!({ "type": "content_block_stop", "index": 0 }),
+    ));
+    out.push(sse(
+        Some("message_delta"),
+        &json!({ "type": "message_delta", "delta": { "stop_reason": stop },
+                 "usage": { "output_tokens": u.completion_tokens } }),
+    ));
+    out.push(sse(
+        Some("message_stop"),
+        &json!({ "type": "message_stop" }),
+    ));
+    out
+}
+
+/// What to send back for one request.
+struct Response {
+    status: u16,
+    body: String,
+    delay_ms: u64,
+    /// Server-sent events, sent one at a time instead of `body`.
+    events: Option<Vec<String>>,
+    event_delay_ms: u64,
+}
+
+impl Response {
+    fn json(status: u16, body: String, delay_ms: u64) -> Self {
+        Self {
+            status,
+            body,
+            delay_ms,
+            events: None,
+            event_delay_ms: 0,
+        }
+    }
+}
+
+/// The response for one request; records it.
+fn respond(shared: &Mutex<Shared>, req: MockRequest) -> Response {
+    let Ok(mut g) = shared.lock() else {
+        return Response::json(500, "{}".to_string(), 0);
+    };
+    let mut req = req;
+    let out = if req.method == "GET" && req.path.ends_with("/models") {
+        let models = if g.script.models.is_empty() {
+            vec!["mock-model".to_string()]
+        } else {
+            g.script.models.clone()
+        };
+        let data: Vec<Value> = models
+            .iter()
+            .map(|m| json!({ "id": m, "object": "model" }))
+            .collect();
+        Response::json(
+            200,
+            json!({ "object": "list", "data": data }).to_string(),
+            0,
+        )
+    } else if req.method == "POST"
+        && (req.path.ends_with("/chat/completions") || req.path.ends_with("/messages"))
+    {
+        let pick = g.script.rules.iter().enumerate().position(|(i, r)| {
+            r.times.is_none_or(|n| g.used[i] < n) && matches(&r.when, &req.path, &req.body)
+        });
+        match pick {
+            Some(i) => {
+                g.used[i] += 1;
+                req.rule = Some(i);
+                let reply = g.script.rules[i].reply.clone();
+                let delay = reply.delay_ms.unwrap_or(0);
+                let status = reply.status.unwrap_or(200);
+                let anthropic = req.path.ends_with("/messages");
+                let streamed = req.body.get("stream").and_then(|v| v.as_bool()) == Some(true);
+                if let Some(b) = reply.body.as_ref() {
+                    Response::json(status, b.clone(), delay)
+                } else if let Some(e) = reply.error.as_ref() {
+                    let body = json!({ "error": { "message": e, "type": "mock_error" } });
+                    Response::json(status, body.to_string(), delay)
+                } else if streamed {
+                    let mut events = if anthropic {
+                        anthropic_stream(&reply, &req.body["model"])
+                    } else {
+                        openai_stream(&reply, &req.body["model"])
+                    };
+                    if let Some(n) = reply.drop_after_events {
+                        events.truncate(n.min(events.len().saturating_sub(1)));
+                    }
+                    Response {
+                        events: Some(events),
+                        event_delay_ms: reply.chunk_delay_ms.unwrap_or(0),
+                        ..Response::json(status, String::new(), delay)
+                    }
+                } else if anthropic {
+                    let body = anthropic_body(&reply, &req.body["model"]);
+                    Response::json(status, body.to_string(), delay)
+                } else {
+                    let n = req.body.get("n").and_then(|v| v.as_u64()).unwrap_or(1);
+                    let body = openai_body(&reply, &req.body["model"], n as usize);
+                    Response::json(status, body.to_string(), delay)
+                }
+            }
+            None => Response::json(
+                500,
+                json!({ "error": { "message": "mock: no scripted reply for this request" } })
+                    .to_string(),
+                0,
+            ),
+        }
+    } else {
+        Response::json(
+            404,
+            json!({ "error": { "message": "mock: unknown route" } }).to_string(),
+            0,
+        )
+    };
+    if let Some(log) = g.log.as_ref() {
+        if let Ok(line) = serde_json::to_string(&req) {
+            use std::io::Write;
+            if let Ok(mut f) = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(log)
+            {
+                let _ = writeln!(f, "{line}");
+            }
+        }
+    }
+    g.requests.push(req);
+    out
+}
+
+async fn handle_conn(mut sock: tokio::net::TcpStream, shared: Arc<Mutex<Shared>>) {
+    let mut buf: Vec<u8> = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let head_end = loop {
+        let n = sock.read(&mut chunk).await.unwrap_or(0);
+        if n == 0 {
+            return;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.lines();
+    let mut first = lines.next().unwrap_or("").split_whitespace();
+    let method = first.next().unwrap_or("").to_string();
+    let path = first.next().unwrap_or("").to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+        .collect();
+    let len: usize = headers
+        .iter()
+        .find(|(k, _)| k == "content-length")
+        .and_then(|(_, v)| v.parse().ok())
+        .unwrap_or(0);
+    while buf.len() < head_end + len {
+        let n = sock.read(&mut chunk).await.unwrap_or(0);
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    let body: Value = serde_json::from_slice(&buf[head_end..]).unwrap_or(Value::Null);
+    let r = respond(
+        &shared,
+        MockRequest {
+            method,
+            path,
+            headers,
+            body,
+            rule: None,
+        },
+    );
+    if r.delay_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(r.delay_ms)).await;
+    }
+    let reason = if r.status < 400 { "OK" } else { "Error" };
+    match r.events {
+        Some(events) => {
+            // Close-delimited body, so events can be written as they are produced.
+            let head = format!(
+                "HTTP/1.1 {} {reason}\r\ncontent-type: text/event-stream\r\ncache-control: no-cache\r\nconnection: close\r\n\r\n",
+                r.status,
+            );
+            if sock.write_all(head.as_bytes()).await.is_err() {
+                return;
+            }
+            for (i, ev) in events.iter().enumerate() {
+                if i > 0 && r.event_delay_ms > 0 {
+                    tokio::time::sleep(Duration::from_millis(r.event_delay_ms)).await;
+                }
+                // The client may hang up early (cancellation); stop quietly.
+                if sock.write_all(ev.as_bytes()).await.is_err() || sock.flush().await.is_err() {
+                    return;
+                }
+            }
+        }
+        None => {
+            let resp = format!(
+                "HTTP/1.1 {} {reason}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                r.status,
+                r.body.len(),
+                r.body,
+            );
+            let _ = sock.write_all(resp.as_bytes()).await;
+        }
+    }
+    let _ = sock.shutdown().await;
+}
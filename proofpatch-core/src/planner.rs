@@ -58,6 +58,9 @@ pub struct PlannerGoalSummary {
     pub target: String,
     pub n_goals: u64,
     pub hyps_total: u64,
+    /// The hypotheses most relevant to the target, best first (bounded; see `hyp_rank`).
+    #[serde(default)]
+    pub relevant_hyps: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
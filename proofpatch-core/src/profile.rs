@@ -0,0 +1,460 @@
+//! Elaboration profiling via `set_option profiler true`.
+//!
+//! Lean reports profiler output as `<what> took <time>` lines, usually as `info:` messages
+//! positioned at the command that produced them. This module:
+//! - injects the profiler options after the import block (and optionally `count_heartbeats in`
+//!   in front of each declaration, without shifting line numbers)
+//! - parses the timings / heartbeat counts out of the verifier output
+//! - attributes them to declarations (via `index_decls_in_text`) and to individual tactics
+//! - profiles just one declaration in place (`profiled_decl_source` / `decl_profile_ms`), which
+//!   tree search uses to penalize slow candidates
+//!
+//! Everything here is best-effort: unrecognized lines are ignored, and timings that cannot be
+//! tied to a declaration are kept under `unattributed`.
+
+use crate::IndexedDecl;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// One `<what> took <time>` line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileItem {
+    /// 1-based line of the message position (in the profiled text), if the line had one.
+    pub line: Option<usize>,
+    /// e.g. `elaboration`, `type checking`, `tactic execution`, `typeclass inference`.
+    pub category: String,
+    /// The part after ` of ` (tactic parser kind, class name, ...), if any.
+    pub detail: Option<String>,
+    pub ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TacticTime {
+    /// Short tactic name (last component of the parser kind, e.g. `simp`).
+    pub name: String,
+    pub ms: f64,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeclProfile {
+    pub name: String,
+    pub kind: String,
+    pub line: usize,
+    pub end_line: usize,
+    /// Sum of the profiler items attributed to this decl (categories may nest, so this is a
+    /// ranking signal rather than exact wall-clock time).
+    pub total_ms: f64,
+    /// From `count_heartbeats in` (only when requested).
+    pub heartbeats: Option<u64>,
+    pub categories: BTreeMap<String, f64>,
+    /// Sorted slowest-first.
+    pub tactics: Vec<TacticTime>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileProfile {
+    pub file: String,
+    pub verify_ok: bool,
+    pub timeout: bool,
+    /// Wall-clock time of the whole verify run.
+    pub wall_ms: u64,
+    pub threshold_ms: u64,
+    pub heartbeats: bool,
+    /// Ranked slowest-first (by `total_ms`, then heartbeats).
+    pub decls: Vec<DeclProfile>,
+    /// Per-category time that could not be tied to a declaration (imports, `#eval`, ...).
+    pub unattributed: BTreeMap<String, f64>,
+    /// Lean's end-of-run `cumulative profiling times:` block, if printed.
+    pub cumulative: BTreeMap<String, f64>,
+}
+
+/// Source text prepared for a profiling run.
+#[derive(Debug, Clone)]
+pub struct ProfiledSource {
+    pub text: String,
+    /// 0-based line index where the `set_option` lines were inserted.
+    pub inserted_at: usize,
+    /// Number of inserted lines (positions after `inserted_at` are shifted by this much).
+    pub inserted_lines: usize,
+}
+
+impl ProfiledSource {
+    /// Map a 1-based line in the profiled text back to the original file.
+    pub fn original_line(&self, line_1: usize) -> Option<usize> {
+        if line_1 <= self.inserted_at {
+            Some(line_1)
+        } else if line_1 > self.inserted_at + self.inserted_lines {
+            Some(line_1 - self.inserted_lines)
+        } else {
+            None
+        }
+    }
+}
+
+/// 0-based line where the command owning the decl header at `decl_line0` starts, i.e. including
+/// any attribute lines and a doc comment directly above it.
+pub(crate) fn command_start_line0(lines: &[&str], decl_line0: usize) -> usize {
+    let mut i = decl_line0;
+    while i > 0 {
+        let prev = lines[i - 1].trim();
+        if prev.starts_with("@[") {
+            i -= 1;
+            continue;
+        }
+        if prev.ends_with("-/") {
+            // Walk back to the start of the doc comment. A plain `/- ... -/` comment is not part
+            // of the command; without stopping there the walk would run on into the docstring of
+            // the declaration above.
+            let mut j = i - 1;
+            loop {
+                let l = lines[j].trim_start();
+                if l.starts_with("/--") {
+                    break;
+                }
+                if l.starts_with("/-") || j == 0 || decl_line0 - j > 200 {
+                    return i;
+                }
+                j -= 1;
+            }
+            i = j;
+            continue;
+        }
+        break;
+    }
+    i
+}
+
+/// Put `prefix` in front of the command starting on line `i0` (0-based), after its indentation.
+fn prefix_command(lines: &mut [String], i0: usize, prefix: &str) {
+    let ln = &lines[i0];
+    let indent_len = ln.len() - ln.trim_start().len();
+    let (indent, rest) = ln.split_at(indent_len);
+    lines[i0] = format!("{indent}{prefix}{rest}");
+}
+
+/// The declaration whose span contains `line_1` (1-based).
+fn decl_at(decls: &[IndexedDecl], line_1: usize) -> Option<&IndexedDecl> {
+    decls
+        .iter()
+        .rfind(|d| d.line <= line_1 && line_1 <= d.end_line.max(d.line))
+}
+
+/// Prepare `text` for profiling.
+///
+/// The profiler options go right after the imports. With `heartbeats`, each declaration's
+/// command is prefixed with `count_heartbeats in ` on its first line (so the decl index and
+/// message positions stay aligned). `count_heartbeats` comes from Mathlib.
+pub fn profiled_source(
+    text: &str,
+    decls: &[IndexedDecl],
+    threshold_ms: u64,
+    heartbeats: bool,
+) -> ProfiledSource {
+    let mut lines: Vec<String> = text.lines().map(|s| s.to_string()).collect();
+    if heartbeats {
+        let orig: Vec<&str> = text.lines().collect();
+        let mut starts: Vec<usize> = decls
+            .iter()
+            .filter(|d| d.line >= 1 && d.line <= orig.len())
+            .map(|d| command_start_line0(&orig, d.line - 1))
+            .collect();
+        starts.sort_unstable();
+        starts.dedup();
+        for i0 in starts {
+            prefix_command(&mut lines, i0, "count_heartbeats in ");
+        }
+    }
+    let inserted_at = crate::import_block_end(&lines);
+    let opts = [
+        "set_option profiler true".to_string(),
+        format!("set_option profiler.threshold {threshold_ms}"),
+    ];
+    let inserted_lines = opts.len();
+    lines.splice(inserted_at..inserted_at, opts);
+    let mut out = lines.join("\n");
+    out.push('\n');
+    ProfiledSource {
+        text: out,
+        inserted_at,
+        inserted_lines,
+    }
+}
+
+/// `text` with the profiler on for the declaration containing `line_1` only.
+///
+/// The options go in front of that declaration's command as `set_option ... in`, on its first
+/// line, so no line moves and the verify output can be read as usual. `None` when no declaration
+/// contains `line_1`.
+pub fn profiled_decl_source(text: &str, line_1: usize, threshold_ms: u64) -> Option<String> {
+    let decls = crate::index_decls_in_text(text);
+    let d = decl_at(&decls, line_1)?;
+    let orig: Vec<&str> = text.lines().collect();
+    let i0 = command_start_line0(&orig, d.line - 1);
+    let mut lines: Vec<String> = orig.iter().map(|s| s.to_string()).collect();
+    prefix_command(
+        &mut lines,
+        i0,
+        &format!("set_option profiler true in set_option profiler.threshold {threshold_ms} in "),
+    );
+    let mut out = lines.join("\n");
+    out.push('\n');
+    Some(out)
+}
+
+/// Profiled time (`DeclProfile::total_ms`) of the declaration containing `line_1`, read from the
+/// verifier output of a `profiled_decl_source` run on `text`.
+pub fn decl_profile_ms(text: &str, line_1: usize, output: &str) -> Option<f64> {
+    let decls = crate::index_decls_in_text(text);
+    let d = decl_at(&decls, line_1)?.clone();
+    let (items, _) = parse_profiler_output(output);
+    let (ranked, _) = attribute_profile(text, &[d], &items, &[]);
+    ranked.first().map(|p| p.total_ms)
+}
+
+/// Split a positioned Lean message line (`path:line:col: info: msg`) into `(line, msg)`.
+///
+/// Parses right-to-left so Windows drive letters don't confuse it.
+fn split_positioned(line: &str) -> Option<(usize, &str)> {
+    let (idx, tag) = [": info:", ": information:", ": warning:", ": error:"]
+        .iter()
+        .filter_map(|t| line.find(t).map(|i| (i, *t)))
+        .min_by_key(|(i, _)| *i)?;
+    let prefix = line[..idx].trim_end();
+    let mut it = prefix.rsplitn(3, ':');
+    let _col = it.next()?.trim().parse::<usize>().ok()?;
+    let line_no = it.next()?.trim().parse::<usize>().ok()?;
+    it.next()?;
+    Some((line_no, line[idx + tag.len()..].trim()))
+}
+
+fn to_ms(num: &str, unit: &str) -> Option<f64> {
+    let x: f64 = num.parse().ok()?;
+    Some(match unit {
+        "s" => x * 1000.0,
+        "ms" => x,
+        "us" | "μs" => x / 1000.0,
+        "ns" => x / 1_000_000.0,
+        _ => return None,
+    })
+}
+
+fn split_category(what: &str) -> (String, Option<String>) {
+    match what.split_once(" of ") {
+        Some((cat, detail)) => (cat.trim().to_string(), Some(detail.trim().to_string())),
+        None => (what.trim().to_string(), None),
+    }
+}
+
+/// Short display name for a tactic parser kind (`Lean.Parser.Tactic.simp` -> `simp`).
+pub fn short_tactic_name(kind: &str) -> String {
+    let k = kind.trim().trim_matches(['«', '»']);
+    k.rsplit('.').next().unwrap_or(k).to_string()
+}
+
+/// Parse profiler lines (`<what> took <time>`) out of verifier output.
+///
+/// Unpositioned lines directly following a positioned message inherit its position (Lean prints
+/// multi-line info messages that way). The end-of-run `cumulative profiling times:` block is
+/// returned separately.
+pub fn parse_profiler_output(text: &str) -> (Vec<ProfileItem>, BTreeMap<String, f64>) {
+    let mut items = Vec::new();
+    let mut cumulative: BTreeMap<String, f64> = BTreeMap::new();
+    let Ok(took) = Regex::new(r"^(.+?) took (\d+(?:\.\d+)?)\s*(ms|s|us|μs|ns)\s*$") else {
+        return (items, cumulative);
+    };
+    let Ok(cum) = Regex::new(r"^\s+(.+?)\s+(\d+(?:\.\d+)?)\s*(ms|s|us|μs|ns)\s*$") else {
+        return (items, cumulative);
+    };
+    let mut pos: Option<usize> = None;
+    let mut in_cumulative = false;
+    for raw in text.lines() {
+        if raw.trim() == "cumulative profiling times:" {
+            in_cumulative = true;
+            pos = None;
+            continue;
+        }
+        if in_cumulative {
+            if let Some(c) = cum.captures(raw) {
+                if let Some(ms) = to_ms(&c[2], &c[3]) {
+                    *cumulative.entry(c[1].to_string()).or_default() += ms;
+                }
+                continue;
+            }
+            in_cumulative = false;
+        }
+        let msg = match split_positioned(raw) {
+            Some((l, m)) => {
+                pos = Some(l);
+                m
+            }
+            None => {
+                if raw.trim().is_empty() {
+                    pos = None;
+                }
+                raw.trim()
+            }
+        };
+        let Some(c) = took.captures(msg) else {
+            continue;
+        };
+        let Some(ms) = to_ms(&c[2], &c[3]) else {
+            continue;
+        };
+        let (category, detail) = split_category(&c[1]);
+        items.push(ProfileItem {
+            line: pos,
+            category,
+            detail,
+            ms,
+        });
+    }
+    (items, cumulative)
+}
+
+/// Parse `count_heartbeats in` results: `(line, heartbeats)` per positioned `Used N heartbeats`.
+pub fn parse_heartbeats_output(text: &str) -> Vec<(usize, u64)> {
+    let Ok(re) = Regex::new(r"Used (\d+) heartbeats") else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    for raw in text.lines() {
+        let Some((line, msg)) = split_positioned(raw) else {
+            continue;
+        };
+        if let Some(c) = re.captures(msg) {
+            if let Ok(n) = c[1].parse::<u64>() {
+                out.push((line, n));
+            }
+        }
+    }
+    out
+}
+
+/// Attribute parsed items to declarations and rank them slowest-first.
+///
+/// `items`/`heartbeats` lines must already be mapped back to original-file lines. A message is
+/// owned by the decl whose command span (attributes + docstring + body) contains its line.
+pub fn attribute_profile(
+    text: &str,
+    decls: &[IndexedDecl],
+    items: &[ProfileItem],
+    heartbeats: &[(usize, u64)],
+) -> (Vec<DeclProfile>, BTreeMap<String, f64>) {
+    let lines: Vec<&str> = text.lines().collect();
+    let spans: Vec<(usize, usize)> = decls
+        .iter()
+        .map(|d| {
+            let start = if d.line >= 1 && d.line <= lines.len() {
+                command_start_line0(&lines, d.line - 1) + 1
+            } else {
+                d.line
+            };
+            (start, d.end_line.max(d.line))
+        })
+        .collect();
+    let owner = |line: usize| spans.iter().position(|(s, e)| *s <= line && line <= *e);
+
+    let mut out: Vec<DeclProfile> = decls
+        .iter()
+        .map(|d| DeclProfile {
+            name: d.full_name.clone(),
+            kind: d.kind.clone(),
+            line: d.line,
+            end_line: d.end_line,
+            total_ms: 0.0,
+            heartbeats: None,
+            categories: BTreeMap::new(),
+            tactics: Vec::new(),
+        })
+        .collect();
+    let mut tactics: Vec<BTreeMap<String, (f64, usize)>> = vec![BTreeMap::new(); decls.len()];
+    let mut unattributed: BTreeMap<String, f64> = BTreeMap::new();
+
+    for it in items {
+        let Some(k) = it.line.and_then(owner) else {
+            *unattributed.entry(it.category.clone()).or_default() += it.ms;
+            continue;
+        };
+        let d = &mut out[k];
+        d.total_ms += it.ms;
+        *d.categories.entry(it.category.clone()).or_default() += it.ms;
+        if it.category == "tactic execution" {
+            if let Some(detail) = it.detail.as_deref() {
+                let e = tactics[k]
+                    .entry(short_tactic_name(detail))
+                    .or_insert((0.0, 0));
+                e.0 += it.ms;
+                e.1 += 1;
+            }
+        }
+    }
+    for (line, n) in heartbeats {
+        if let Some(k) = owner(*line) {
+            let h = out[k].heartbeats.get_or_insert(0);
+            *h = (*h).max(*n);
+        }
+    }
+    for (d, ts) in out.iter_mut().zip(tactics) {
+        d.tactics = ts
+            .into_iter()
+            .map(|(name, (ms, count))| TacticTime { name, ms, count })
+            .collect();
+        d.tactics
+            .sort_by(|a, b| b.ms.total_cmp(&a.ms).then_with(|| a.name.cmp(&b.name)));
+    }
+    out.sort_by(|a, b| {
+        b.total_ms
+            .total_cmp(&a.total_ms)
+            .then_with(|| b.heartbeats.cmp(&a.heartbeats))
+            .then_with(|| a.line.cmp(&b.line))
+    });
+    (out, unattributed)
+}
+
+/// Profile `file_rel`: one verify run with the profiler enabled, attributed per declaration.
+pub async fn profile_lean_file(
+    repo_root: &Path,
+    file_rel: &str,
+    threshold_ms: u64,
+    heartbeats: bool,
+    timeout_s: Duration,
+) -> Result<FileProfile, String> {
+    let p = repo_root.join(file_rel);
+    if !p.exists() {
+        return Err(format!("File not found: {}", p.display()));
+    }
+    let text = std::fs::read_to_string(&p).map_err(|e| format!("read {}: {e}", p.display()))?;
+    let decls = crate::index_decls_in_text(&text);
+    let src = profiled_source(&text, &decls, threshold_ms, heartbeats);
+
+    let t0 = std::time::Instant::now();
+    let raw = crate::verify_lean_text(repo_root, &src.text, timeout_s).await?;
+    let wall_ms = t0.elapsed().as_millis() as u64;
+
+    let output = format!("{}\n{}", raw.stdout, raw.stderr);
+    let (mut items, cumulative) = parse_profiler_output(&output);
+    for it in items.iter_mut() {
+        it.line = it.line.and_then(|l| src.original_line(l));
+    }
+    let hb: Vec<(usize, u64)> = parse_heartbeats_output(&output)
+        .into_iter()
+        .filter_map(|(l, n)| src.original_line(l).map(|l| (l, n)))
+        .collect();
+    let (decls, unattributed) = attribute_profile(&text, &decls, &items, &hb);
+
+    Ok(FileProfile {
+        file: file_rel.to_string(),
+        verify_ok: raw.ok,
+        timeout: raw.timeout,
+        wall_ms,
+        threshold_ms,
+        heartbeats,
+        decls,
+        unattributed,
+        cumulative,
+    })
+}
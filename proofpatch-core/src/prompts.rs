@@ -0,0 +1,472 @@
+//! Prompt templates with named variables.
+//!
+//! Every prompt proofpatch sends is rendered from a named template. Built-in defaults match the
+//! historical hard-coded prompts; a project overrides any of them without forking the crate:
+//! - inline, in `proofpatch.toml` under `[prompts.templates]` (wins), or
+//! - as `<name>.txt` files in `[prompts] dir` (default `.proofpatch/prompts`).
+//!
+//! Syntax:
+//! - `{{var}}` inserts a variable (unset variables render as empty)
+//! - `{{#var}}...{{/var}}` renders its body only when `var` is set and not blank
+//!
+//! Each template accepts its own variables plus the optional extras `goal`, `context_pack`,
+//! `research_notes` and `hints`. Overrides are checked when loaded: unknown template names,
+//! unknown variables, and malformed tags are errors.
+//!
+//! `load_dotenv_smart` installs the repo's templates for the process (a broken set falls back to
+//! the built-ins; `prompt render` reports the error).
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Variable values by name.
+pub type PromptVars = BTreeMap<String, String>;
+
+/// Optional variables every template accepts.
+pub const EXTRA_VARS: &[&str] = &["goal", "context_pack", "research_notes", "hints"];
+
+/// Values for the `EXTRA_VARS` a caller has on hand. Unset or blank ones leave their template
+/// sections out.
+#[derive(Debug, Clone, Default)]
+pub struct PromptExtras {
+    /// Goal state, usually a `hyp_rank::goal_snapshot`.
+    pub goal: Option<String>,
+    /// `context_pack_text` of a pack around the focus.
+    pub context_pack: Option<String>,
+    pub research_notes: Option<String>,
+    pub hints: Option<String>,
+}
+
+impl PromptExtras {
+    pub fn with_goal(goal: Option<&str>) -> Self {
+        Self {
+            goal: goal.map(String::from),
+            ..Self::default()
+        }
+    }
+
+    /// Insert the set extras into `vars`, trimmed.
+    pub fn add_to(&self, vars: &mut PromptVars) {
+        for (k, v) in [
+            ("goal", &self.goal),
+            ("context_pack", &self.context_pack),
+            ("research_notes", &self.research_notes),
+            ("hints", &self.hints),
+        ] {
+            if let Some(v) = v {
+                vars.insert(k.to_string(), v.trim().to_string());
+            }
+        }
+    }
+}
+
+pub struct TemplateSpec {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Template-specific variables (`EXTRA_VARS` are accepted too).
+    pub vars: &'static [&'static str],
+    pub default: &'static str,
+}
+
+pub const TEMPLATES: &[TemplateSpec] = &[
+    TemplateSpec {
+        name: "proof_system",
+        description: "System prompt for proof suggestions (`suggest`, `loop`, `prompt`).",
+        vars: &[],
+        default: "You are a Lean 4 proof assistant.\n\
+Return ONLY Lean code that replaces a single `sorry` (or `admit`) inside a `by` block.\n\
+No markdown fences. No commentary. No surrounding `theorem`/`lemma` header.\n\
+Prefer short tactic proofs (`simp`, `aesop`, `nlinarith`, `omega`, `ring_nf`) and existing lemmas.\n\
+If you cannot complete the proof, return a minimal partial proof with the smallest remaining goal(s).",
+    },
+    TemplateSpec {
+        name: "proof_user",
+        description: "User prompt for proof suggestions, around the lemma excerpt.",
+        vars: &["excerpt"],
+        default: "We are working in a Lean 4 + Mathlib project.\n\
+Here is the declaration context (excerpt):\n\n\
+{{excerpt}}\
+{{#goal}}\n\nGoal state:\n{{goal}}{{/goal}}\
+{{#context_pack}}\n\nContext pack:\n{{context_pack}}{{/context_pack}}\
+{{#research_notes}}\n\nResearch notes:\n{{research_notes}}{{/research_notes}}\
+{{#hints}}\n\nHints:\n{{hints}}{{/hints}}\n\n\
+Task: provide the Lean proof code that replaces the `sorry`/`admit` (the proof term only).",
+    },
+    TemplateSpec {
+        name: "rubberduck_system",
+        description: "System prompt for planning help (`rubberduck-prompt`, triage).",
+        vars: &[],
+        default: "You are a Lean 4 proof assistant helping a human/agent debug and plan.\n\
+Do NOT write Lean code.\n\
+Return a structured plan for how to eliminate the next `sorry` / error, with small verifiable steps.\n\
+Prefer tactics that create tight feedback loops: `simp`, `simp?`, `aesop?`, `exact?`, `apply?`, `have`, `rcases`, `cases`, `by_cases`.\n\
+If a goal looks arithmetic/decidable, suggest `omega`, `nlinarith`, `linarith`, `ring_nf`, `norm_num` (only when applicable).\n\
+Output format (strict):\n\
+1) Key goal-shape guess (1-2 sentences).\n\
+2) 3 candidate next steps (each 1-3 lines), including what to inspect in goals/hypotheses.\n\
+3) If stuck: 5 lemma-name search keywords (Lean identifiers) likely to exist in mathlib.",
+    },
+    TemplateSpec {
+        name: "rubberduck_user",
+        description: "User prompt for planning help: diagnostics, excerpt, and a research plan.",
+        vars: &[
+            "excerpt",
+            "excerpt_label",
+            "focus_label",
+            "diagnostics",
+            "research_plan",
+        ],
+        default: "Context: we are editing a Lean 4 + mathlib file.\n\
+{{#diagnostics}}\nRecent Lean diagnostics (raw):\n{{diagnostics}}\n{{/diagnostics}}\
+\n{{excerpt_label}}:\n\n\
+{{excerpt}}\
+{{#goal}}\n\nGoal state:\n{{goal}}{{/goal}}\
+{{#context_pack}}\n\nContext pack:\n{{context_pack}}{{/context_pack}}\
+{{#research_notes}}\n\nResearch notes:\n{{research_notes}}{{/research_notes}}\
+{{#hints}}\n\nHints:\n{{hints}}{{/hints}}\n\n\
+Research (optional):\n\
+- Suggested research plan (machine-readable JSON):\n\
+{{research_plan}}\n",
+    },
+    TemplateSpec {
+        name: "region_patch_system",
+        description: "System prompt for patching a `sorry` inside a line region.",
+        vars: &[],
+        default: "You are a Lean 4 proof assistant.\n\
+Return ONLY Lean code that replaces a single `sorry` inside the provided region.\n\
+No markdown fences. No commentary.\n\
+Do not repeat the surrounding `theorem`/`lemma` header.\n\
+Prefer short tactic proofs (`simp`, `aesop`, `nlinarith`, `omega`, `ring_nf`) and existing lemmas.\n\
+If you cannot complete the proof, return a minimal partial proof with the smallest remaining goal(s).",
+    },
+    TemplateSpec {
+        name: "region_patch_user",
+        description: "User prompt for patching a `sorry` inside a line region.",
+        vars: &["excerpt", "diagnostics", "start_line", "end_line"],
+        default: "We are working in a Lean 4 + Mathlib project.\n\
+{{#diagnostics}}\nRecent Lean diagnostics (raw):\n{{diagnostics}}\n{{/diagnostics}}\
+\nHere is a focused region (lines {{start_line}}..={{end_line}}):\n\n\
+{{excerpt}}\
+{{#goal}}\n\nGoal state:\n{{goal}}{{/goal}}\
+{{#context_pack}}\n\nContext pack:\n{{context_pack}}{{/context_pack}}\
+{{#research_notes}}\n\nResearch notes:\n{{research_notes}}{{/research_notes}}\
+{{#hints}}\n\nHints:\n{{hints}}{{/hints}}\n\n\
+Task: provide the Lean proof code that replaces the `sorry` (the proof term only).",
+    },
+];
+
+pub fn spec(name: &str) -> Option<&'static TemplateSpec> {
+    TEMPLATES.iter().find(|t| t.name == name)
+}
+
+fn known_names() -> String {
+    TEMPLATES
+        .iter()
+        .map(|t| t.name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Var(String),
+    Section(String, Vec<Node>),
+}
+
+fn parse(src: &str) -> Result<Vec<Node>, String> {
+    // Stack of open sections: (name, nodes so far). The bottom entry is the top level.
+    let mut stack: Vec<(String, Vec<Node>)> = vec![(String::new(), Vec::new())];
+    let mut rest = src;
+    while let Some(open) = rest.find("{{") {
+        if open > 0 {
+            stack
+                .last_mut()
+                .expect("stack is never empty")
+                .1
+                .push(Node::Text(rest[..open].to_string()));
+        }
+        let after = &rest[open + 2..];
+        let close = after
+            .find("}}")
+            .ok_or_else(|| format!("unclosed `{{{{` near `{}`", snippet(&rest[open..])))?;
+        let tag = after[..close].trim();
+        rest = &after[close + 2..];
+        let (kind, name) = match tag.chars().next() {
+            Some('#') | Some('/') => (tag.chars().next(), tag[1..].trim()),
+            _ => (None, tag),
+        };
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+        {
+            return Err(format!(
+                "bad tag `{{{{{tag}}}}}` (variables are lower_snake_case)"
+            ));
+        }
+        match kind {
+            Some('#') => stack.push((name.to_string(), Vec::new())),
+            Some('/') => {
+                if stack.len() == 1 || stack.last().map(|s| s.0.as_str()) != Some(name) {
+                    return Err(format!("`{{{{/{name}}}}}` does not close an open section"));
+                }
+                let (n, body) = stack.pop().expect("checked above");
+                stack
+                    .last_mut()
+                    .expect("stack is never empty")
+                    .1
+                    .push(Node::Section(n, body));
+            }
+            _ => stack
+                .last_mut()
+                .expect("stack is never empty")
+                .1
+                .push(Node::Var(name.to_string())),
+        }
+    }
+    if !rest.is_empty() {
+        stack
+            .last_mut()
+            .expect("stack is never empty")
+            .1
+            .push(Node::Text(rest.to_string()));
+    }
+    if stack.len() > 1 {
+        let open = stack.last().map(|s| s.0.clone()).unwrap_or_default();
+        return Err(format!("section `{{{{#{open}}}}}` is never closed"));
+    }
+    Ok(stack.pop().map(|s| s.1).unwrap_or_default())
+}
+
+fn snippet(s: &str) -> String {
+    s.chars().take(30).collect()
+}
+
+fn collect_vars(nodes: &[Node], out: &mut Vec<String>) {
+    for n in nodes {
+        match n {
+            Node::Text(_) => {}
+            Node::Var(v) => out.push(v.clone()),
+            Node::Section(v, body) => {
+                out.push(v.clone());
+                collect_vars(body, out);
+            }
+        }
+    }
+}
+
+fn render_nodes(nodes: &[Node], vars: &PromptVars, out: &mut String) {
+    for n in nodes {
+        match n {
+            Node::Text(t) => out.push_str(t),
+            Node::Var(v) => out.push_str(vars.get(v).map(String::as_str).unwrap_or("")),
+            Node::Section(v, body) => {
+                if vars.get(v).is_some_and(|x| !x.trim().is_empty()) {
+                    render_nodes(body, vars, out);
+                }
+            }
+        }
+    }
+}
+
+/// Variables referenced by `src` (deduplicated, in first-use order).
+pub fn template_vars(src: &str) -> Result<Vec<String>, String> {
+    let mut all = Vec::new();
+    collect_vars(&parse(src)?, &mut all);
+    let mut out: Vec<String> = Vec::new();
+    for v in all {
+        if !out.contains(&v) {
+            out.push(v);
+        }
+    }
+    Ok(out)
+}
+
+/// Render a template source directly (no name checks).
+pub fn render_source(src: &str, vars: &PromptVars) -> Result<String, String> {
+    let mut out = String::new();
+    render_nodes(&parse(src)?, vars, &mut out);
+    Ok(out)
+}
+
+/// Check `src` as the template `name`: it parses and uses only the variables `name` accepts.
+pub fn validate(name: &str, src: &str) -> Result<(), String> {
+    let s = spec(name).ok_or_else(|| {
+        format!(
+            "unknown prompt template `{name}` (known: {})",
+            known_names()
+        )
+    })?;
+    for v in template_vars(src).map_err(|e| format!("template `{name}`: {e}"))? {
+        if !s.vars.contains(&v.as_str()) && !EXTRA_VARS.contains(&v.as_str()) {
+            let mut allowed: Vec<&str> = s.vars.to_vec();
+            allowed.extend_from_slice(EXTRA_VARS);
+            return Err(format!(
+                "template `{name}`: unknown variable `{v}` (allowed: {})",
+                allowed.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct TemplateOverride {
+    pub text: String,
+    /// `proofpatch.toml` or the template file's path.
+    pub source: String,
+}
+
+/// A project's overrides; anything not overridden uses the built-in default.
+#[derive(Debug, Clone, Default)]
+pub struct PromptTemplates {
+    pub overrides: BTreeMap<String, TemplateOverride>,
+}
+
+impl PromptTemplates {
+    /// Load `[prompts]` from `<repo_root>/proofpatch.toml` and the templates directory.
+    pub fn load(repo_root: &Path) -> Result<Self, String> {
+        let cfg = crate::config::load_from_repo_root(repo_root)?
+            .map(|c| c.prompts)
+            .unwrap_or_default();
+        let mut overrides = BTreeMap::new();
+
+        let dir = templates_dir(repo_root, cfg.dir.as_deref());
+        if dir.is_dir() {
+            let rd = std::fs::read_dir(&dir).map_err(|e| format!("read {}: {e}", dir.display()))?;
+            let mut files: Vec<PathBuf> = rd
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().and_then(|x| x.to_str()) == Some("txt"))
+                .collect();
+            files.sort();
+            for p in files {
+                let name = p
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or_default()
+                    .to_string();
+                // Other files (a README, notes) may live next to the templates; skip them rather
+                // than failing the whole load.
+                if spec(&name).is_none() {
+                    eprintln!(
+                        "proofpatch: ignoring {} (not a prompt template; known: {})",
+                        p.display(),
+                        known_names()
+                    );
+                    continue;
+                }
+                let text = std::fs::read_to_string(&p)
+                    .map_err(|e| format!("read {}: {e}", p.display()))?;
+                validate(&name, &text).map_err(|e| format!("{}: {e}", p.display()))?;
+                overrides.insert(
+                    name,
+                    TemplateOverride {
+                        text,
+                        source: p.display().to_string(),
+                    },
+                );
+            }
+        } else if cfg.dir.is_some() {
+            return Err(format!("[prompts] dir not found: {}", dir.display()));
+        }
+
+        for (name, text) in cfg.templates {
+            validate(&name, &text)
+                .map_err(|e| format!("proofpatch.toml [prompts.templates]: {e}"))?;
+            overrides.insert(
+                name,
+                TemplateOverride {
+                    text,
+                    source: "proofpatch.toml".to_string(),
+                },
+            );
+        }
+        Ok(Self { overrides })
+    }
+
+    /// Template source and where it came from (`builtin` when not overridden).
+    pub fn get(&self, name: &str) -> Option<(&str, &str)> {
+        if let Some(o) = self.overrides.get(name) {
+            return Some((o.text.as_str(), o.source.as_str()));
+        }
+        spec(name).map(|s| (s.default, "builtin"))
+    }
+
+    pub fn render(&self, name: &str, vars: &PromptVars) -> Result<String, String> {
+        let (src, _) = self.get(name).ok_or_else(|| {
+            format!(
+                "unknown prompt template `{name}` (known: {})",
+                known_names()
+            )
+        })?;
+        render_source(src, vars)
+    }
+}
+
+fn templates_dir(repo_root: &Path, dir: Option<&str>) -> PathBuf {
+    match dir {
+        Some(d) if Path::new(d).is_absolute() => PathBuf::from(d),
+        Some(d) => repo_root.join(d),
+        None => repo_root.join(".proofpatch").join("prompts"),
+    }
+}
+
+fn installed_slot() -> &'static std::sync::RwLock<PromptTemplates> {
+    static SLOT: std::sync::OnceLock<std::sync::RwLock<PromptTemplates>> =
+        std::sync::OnceLock::new();
+    SLOT.get_or_init(|| std::sync::RwLock::new(PromptTemplates::default()))
+}
+
+/// Install the templates used by the prompt builders (`None`: built-ins only).
+pub fn set_templates(t: Option<PromptTemplates>) {
+    if let Ok(mut g) = installed_slot().write() {
+        *g = t.unwrap_or_default();
+    }
+}
+
+/// Load the repo's templates and install them.
+pub fn load_templates(repo_root: &Path) -> Result<(), String> {
+    set_templates(Some(PromptTemplates::load(repo_root)?));
+    Ok(())
+}
+
+/// Render `name` with the installed templates. Overrides were validated when loaded; a template
+/// that still fails to render falls back to the built-in default.
+pub fn render(name: &str, vars: &PromptVars) -> String {
+    let installed = installed_slot()
+        .read()
+        .ok()
+        .and_then(|g| g.render(name, vars).ok());
+    installed.unwrap_or_else(|| {
+        spec(name)
+            .and_then(|s| render_source(s.default, vars).ok())
+            .unwrap_or_default()
+    })
+}
+
+/// `PromptVars` from `(name, value)` pairs.
+pub fn vars<'a>(pairs: impl IntoIterator<Item = (&'a str, &'a str)>) -> PromptVars {
+    pairs
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// Text for the `context_pack` variable: imports and nearby declaration headers.
+pub fn context_pack_text(pack: &crate::ContextPack) -> String {
+    let mut out = String::new();
+    if !pack.imports.is_empty() {
+        out.push_str("Imports:\n");
+        for i in &pack.imports {
+            out.push_str(&format!("- {}\n", i.trim()));
+        }
+    }
+    if !pack.nearby_decls.is_empty() {
+        out.push_str("Nearby declarations:\n");
+        for d in &pack.nearby_decls {
+            out.push_str(&format!("- line {}: {}\n", d.line, d.header.trim()));
+        }
+    }
+    out.trim_end().to_string()
+}
@@ -0,0 +1,348 @@
+//! Multi-turn repair conversations for `loop --conversation`.
+//!
+//! Instead of a fresh prompt per iteration, the model sees its earlier replies as assistant turns,
+//! each followed by a user turn with what that reply did: the structured first error (location and
+//! full message), the goal state at the error (as Lean printed it, or from a goal dump when the
+//! message has none), and the diff the patch made to the lemma. When the rendered history exceeds a byte budget, the oldest turns are folded into a
+//! one-line-per-attempt summary; the latest attempt is always kept in full.
+
+use crate::{DiagnosticLoc, VerifyResult};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use similar::TextDiff;
+use std::path::Path;
+use std::time::Duration;
+
+/// Default byte budget for the rendered attempt history.
+pub const DEFAULT_HISTORY_BYTES: usize = 12_000;
+
+/// What one attempt did, as fed back to the model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttemptFeedback {
+    /// 1-based loop iteration.
+    pub iter: usize,
+    /// The model's replacement for the `sorry`.
+    pub replacement: String,
+    pub ok: bool,
+    pub lemma_still_contains_sorry: bool,
+    pub first_error_loc: Option<DiagnosticLoc>,
+    /// The first error's full message (header line plus continuation lines).
+    pub first_error: Option<String>,
+    /// Hypotheses and `⊢` lines from the first error's message, when Lean printed them;
+    /// otherwise from a goal dump at the error (`goal_at_error`), when the caller ran one.
+    pub goal: Option<String>,
+    /// Unified diff of the lemma before and after the patch.
+    pub diff: String,
+}
+
+fn is_diagnostic_header(line: &str) -> bool {
+    [": error", ": warning", ": info"].iter().any(|k| {
+        line.find(k).is_some_and(|i| {
+            // `<path>:<line>:<col>: error...`
+            let mut parts = line[..i].rsplitn(3, ':');
+            let col = parts.next().unwrap_or("");
+            let ln = parts.next().unwrap_or("");
+            parts.next().is_some()
+                && col.trim().parse::<usize>().is_ok()
+                && ln.trim().parse::<usize>().is_ok()
+        })
+    })
+}
+
+/// The first error diagnostic with its continuation lines (stdout first, then stderr).
+pub fn first_error_message(stdout: &str, stderr: &str) -> Option<String> {
+    fn scan(out: &str) -> Option<String> {
+        let lines: Vec<&str> = out.lines().collect();
+        let start = lines.iter().position(|l| {
+            is_diagnostic_header(l) && (l.contains(": error:") || l.contains(": error("))
+        })?;
+        let mut block = vec![lines[start]];
+        for l in &lines[start + 1..] {
+            if is_diagnostic_header(l) {
+                break;
+            }
+            block.push(l);
+        }
+        Some(block.join("\n").trim_end().to_string())
+    }
+    scan(stdout).or_else(|| scan(stderr))
+}
+
+/// Goal state inside an error message (e.g. `unsolved goals`): the hypothesis and `⊢` lines.
+pub fn goal_from_error(message: &str) -> Option<String> {
+    let body: Vec<&str> = message.lines().skip(1).collect();
+    let first = body.iter().position(|l| {
+        let t = l.trim_start();
+        t.starts_with('⊢') || t.starts_with("case ") || t.contains(" : ")
+    })?;
+    let last = body.iter().rposition(|l| l.trim_start().starts_with('⊢'))?;
+    if last < first {
+        return None;
+    }
+    // Continuation lines of the final `⊢` goal (indented) belong to it too.
+    let end = body[last + 1..]
+        .iter()
+        .position(|l| !l.starts_with("  "))
+        .map_or(body.len(), |n| last + 1 + n);
+    Some(body[first..end].join("\n").trim_end().to_string())
+}
+
+/// `after` with a `sorry` line inserted above line `line_1` (1-based), at that line's indentation,
+/// so the goal dump can stop where the failing tactic started.
+///
+/// `None` unless `line_1` is an indented, non-blank line (a tactic rather than a declaration
+/// header).
+pub fn goal_probe_text(after: &str, line_1: usize) -> Option<String> {
+    let lines: Vec<&str> = after.lines().collect();
+    let i0 = line_1.checked_sub(1)?;
+    let l = *lines.get(i0)?;
+    let t = l.trim_start();
+    let indent = &l[..l.len() - t.len()];
+    if indent.is_empty() || t.is_empty() {
+        return None;
+    }
+    let probe = format!("{indent}sorry");
+    let mut out: Vec<&str> = lines[..i0].to_vec();
+    out.push(&probe);
+    out.extend_from_slice(&lines[i0..]);
+    Some(out.join("\n") + "\n")
+}
+
+/// The goals of a `pp_dump` result (as returned by `goal_dump_in_text_at`), one block each.
+pub fn goal_from_pp_dump(pp_dump: &Value) -> Option<String> {
+    let goals: Vec<&str> = pp_dump
+        .get("goals")?
+        .as_array()?
+        .iter()
+        .filter_map(|g| g.get("pretty").and_then(|v| v.as_str()))
+        .collect();
+    if goals.is_empty() {
+        return None;
+    }
+    Some(goals.join("\n\n").trim_end().to_string())
+}
+
+/// Goal state at an error whose message carries none (`goal_from_error` found nothing): run the
+/// goal dump on `after` at line `line_1`. Best-effort; `None` when the probe does not apply or Lean
+/// prints no dump.
+pub async fn goal_at_error(
+    repo_root: &Path,
+    file_rel: &str,
+    after: &str,
+    line_1: usize,
+    timeout: Duration,
+) -> Option<String> {
+    let probe = goal_probe_text(after, line_1)?;
+    let dump =
+        crate::goal_dump_in_text_at(repo_root, file_rel, &probe, timeout, Some(line_1), None)
+            .await
+            .ok()?;
+    goal_from_pp_dump(dump.get("pp_dump")?)
+}
+
+/// Unified diff of the lemma between two versions of the file (empty if it did not change).
+pub fn decl_diff(before: &str, after: &str, lemma: &str) -> String {
+    let a = crate::extract_decl_block(before, lemma).unwrap_or_else(|_| before.to_string());
+    let b = crate::extract_decl_block(after, lemma).unwrap_or_else(|_| after.to_string());
+    if a == b {
+        return String::new();
+    }
+    // Excerpts lack a final newline; without one the diff grows `\ No newline` markers.
+    let (a, b) = (format!("{}\n", a.trim_end()), format!("{}\n", b.trim_end()));
+    TextDiff::from_lines(&a, &b)
+        .unified_diff()
+        .context_radius(2)
+        .header("before", "after")
+        .to_string()
+}
+
+/// Build the feedback for one attempt from its patched text and verify result.
+pub fn attempt_feedback(
+    iter: usize,
+    replacement: &str,
+    before: &str,
+    after: &str,
+    lemma: &str,
+    verify: &VerifyResult,
+) -> AttemptFeedback {
+    let first_error = first_error_message(&verify.stdout, &verify.stderr);
+    AttemptFeedback {
+        iter,
+        replacement: replacement.to_string(),
+        ok: verify.ok,
+        lemma_still_contains_sorry: crate::decl_block_contains_sorry(after, lemma).unwrap_or(true),
+        first_error_loc: crate::parse_first_error_loc(&verify.stdout, &verify.stderr),
+        goal: first_error.as_deref().and_then(goal_from_error),
+        first_error,
+        diff: decl_diff(before, after, lemma),
+    }
+}
+
+impl AttemptFeedback {
+    /// The user turn that follows this attempt's assistant turn.
+    pub fn render(&self) -> String {
+        let mut out = format!("Attempt {} result: ", self.iter);
+        if self.ok && !self.lemma_still_contains_sorry {
+            out.push_str("compiled and closed the lemma.\n");
+        } else if self.ok {
+            out.push_str("compiled, but the lemma still contains `sorry`.\n");
+        } else if self.first_error.is_some() {
+            out.push_str("failed to compile.\n");
+        } else {
+            out.push_str("failed (no error message; possibly a timeout).\n");
+        }
+        if let Some(loc) = &self.first_error_loc {
+            out.push_str(&format!(
+                "First error at line {}, column {}:\n",
+                loc.line, loc.col
+            ));
+        }
+        if let Some(e) = &self.first_error {
+            // The header repeats a temp-file path; keep only the message.
+            let msg = e
+                .split_once(": error")
+                .map(|(_, rest)| format!("error{rest}"))
+                .unwrap_or_else(|| e.clone());
+            out.push_str(&format!("```\n{msg}\n```\n"));
+        }
+        if let Some(g) = &self.goal {
+            out.push_str(&format!("Goal state at the error:\n```\n{g}\n```\n"));
+        }
+        if !self.diff.is_empty() {
+            out.push_str(&format!(
+                "Your patch changed the lemma:\n```diff\n{}```\n",
+                self.diff
+            ));
+        }
+        out.push_str(if self.ok {
+            "Reply with Lean code for the remaining `sorry` (Lean code only)."
+        } else {
+            "The patch was discarded. Reply with a corrected replacement for the original `sorry` (Lean code only)."
+        });
+        out
+    }
+
+    /// One-line summary used once the turn has been folded out of the full history.
+    pub fn summary_line(&self) -> String {
+        let proof = self
+            .replacement
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let proof: String = proof.chars().take(120).collect();
+        let outcome = if self.ok && !self.lemma_still_contains_sorry {
+            "closed the lemma".to_string()
+        } else if self.ok {
+            "compiled, `sorry` left".to_string()
+        } else {
+            let msg = self
+                .first_error
+                .as_deref()
+                .and_then(|e| e.lines().next())
+                .and_then(|l| {
+                    l.split_once(": error")
+                        .map(|(_, r)| r.trim_start_matches(':').trim())
+                })
+                .unwrap_or("failed");
+            let msg: String = msg.chars().take(160).collect();
+            match &self.first_error_loc {
+                Some(loc) => format!("error at line {}: {msg}", loc.line),
+                None => msg,
+            }
+        };
+        format!("- attempt {}: `{proof}` -> {outcome}", self.iter)
+    }
+}
+
+/// The conversation: a fixed system/task prefix and the attempt history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairConversation {
+    pub system: String,
+    /// First user turn (the task and the original excerpt).
+    pub task: String,
+    pub attempts: Vec<AttemptFeedback>,
+    /// Budget for the rendered history (assistant + feedback turns), in bytes.
+    pub max_bytes: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryStats {
+    pub attempts: usize,
+    /// Oldest attempts folded into the summary turn.
+    pub summarized: usize,
+    /// Bytes of rendered history sent (summary included).
+    pub history_bytes: usize,
+    pub max_bytes: usize,
+}
+
+impl RepairConversation {
+    pub fn new(system: String, task: String, max_bytes: usize) -> Self {
+        Self {
+            system,
+            task,
+            attempts: Vec::new(),
+            max_bytes,
+        }
+    }
+
+    pub fn push(&mut self, attempt: AttemptFeedback) {
+        self.attempts.push(attempt);
+    }
+
+    fn turn_bytes(a: &AttemptFeedback) -> usize {
+        a.replacement.len() + a.render().len()
+    }
+
+    /// How many of the oldest attempts must be summarized to fit the budget.
+    fn summarized_count(&self) -> usize {
+        let n = self.attempts.len();
+        let mut total: usize = self.attempts.iter().map(Self::turn_bytes).sum();
+        let mut k = 0;
+        // The latest attempt always stays in full.
+        while k + 1 < n && total > self.max_bytes {
+            total -= Self::turn_bytes(&self.attempts[k]);
+            total += self.attempts[k].summary_line().len() + 1;
+            k += 1;
+        }
+        k
+    }
+
+    /// OpenAI-shaped messages for the next request.
+    pub fn messages(&self) -> Vec<Value> {
+        let k = self.summarized_count();
+        let mut out = vec![
+            json!({ "role": "system", "content": self.system }),
+            json!({ "role": "user", "content": self.task }),
+        ];
+        if k > 0 {
+            let lines: Vec<String> = self.attempts[..k]
+                .iter()
+                .map(|a| a.summary_line())
+                .collect();
+            out.push(json!({
+                "role": "user",
+                "content": format!("Earlier attempts (summarized):\n{}", lines.join("\n")),
+            }));
+        }
+        for a in &self.attempts[k..] {
+            out.push(json!({ "role": "assistant", "content": a.replacement }));
+            out.push(json!({ "role": "user", "content": a.render() }));
+        }
+        out
+    }
+
+    pub fn stats(&self) -> HistoryStats {
+        let k = self.summarized_count();
+        let summary: usize = self.attempts[..k]
+            .iter()
+            .map(|a| a.summary_line().len() + 1)
+            .sum();
+        let full: usize = self.attempts[k..].iter().map(Self::turn_bytes).sum();
+        HistoryStats {
+            attempts: self.attempts.len(),
+            summarized: k,
+            history_bytes: summary + full,
+            max_bytes: self.max_bytes,
+        }
+    }
+}
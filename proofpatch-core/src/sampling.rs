@@ -0,0 +1,231 @@
+//! Multi-sample proof generation: dedupe proposals, rank them by cross-sample agreement, and verify
+//! the top candidates in parallel.
+//!
+//! Samples come from `llm::chat_completion_samples`. Two samples count as the same proposal when
+//! they agree after `normalize_proof` (code fences, a leading `:= by` / `by`, and whitespace
+//! differences are ignored). Candidates are ranked by how many samples proposed them (ties: first
+//! seen wins), and each survivor is spliced into the lemma with `patch_first_sorry_in_decl` and
+//! checked with `verify_lean_text`.
+
+use crate::{PatchResult, VerifyResult};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// One distinct proposal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candidate {
+    /// Text of the first sample with this proposal (fences stripped), as spliced into the lemma.
+    pub proof: String,
+    pub normalized: String,
+    /// Samples that proposed it.
+    pub votes: usize,
+    /// Indices into the sample list.
+    pub samples: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandidateCheck {
+    /// 0-based position in the agreement ranking.
+    pub rank: usize,
+    pub proof: String,
+    pub votes: usize,
+    /// The file compiled with the proof spliced in.
+    pub ok: bool,
+    /// `ok`, and the lemma has no `sorry`/`admit` left.
+    pub solved: bool,
+    pub patch: Option<PatchResult>,
+    pub verify: Option<VerifyResult>,
+    /// Patching or verification could not run.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SampleStats {
+    pub samples: usize,
+    pub distinct: usize,
+    /// Candidates that were verified (the top `max_verify`).
+    pub verified: usize,
+    /// Verified candidates that compiled / that also closed the lemma.
+    pub compiled: usize,
+    pub solved: usize,
+    /// Samples whose proposal compiled (candidate votes summed), and their share of all samples.
+    pub compiled_samples: usize,
+    pub compiled_rate: f64,
+}
+
+/// The sample's proof text: the first fenced Lean block if there is one, else the text without
+/// stray fence lines.
+pub fn clean_proof(sample: &str) -> String {
+    if let Some(block) = crate::llm::complete_lean_code_block(sample) {
+        return block.trim().to_string();
+    }
+    sample
+        .lines()
+        .filter(|l| !l.trim_start().starts_with("```"))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Comparison key: `clean_proof`, minus a leading `:=` / `by`, with whitespace runs collapsed.
+pub fn normalize_proof(sample: &str) -> String {
+    let cleaned = clean_proof(sample);
+    let mut s = cleaned.trim();
+    loop {
+        let before = s;
+        if let Some(rest) = s.strip_prefix(":=") {
+            s = rest.trim_start();
+        }
+        if let Some(rest) = s.strip_prefix("by") {
+            if rest.is_empty() || rest.starts_with(char::is_whitespace) {
+                s = rest.trim_start();
+            }
+        }
+        if s == before {
+            break;
+        }
+    }
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Distinct non-empty proposals, most-agreed-upon first.
+pub fn rank_candidates(samples: &[String]) -> Vec<Candidate> {
+    let mut out: Vec<Candidate> = Vec::new();
+    for (i, s) in samples.iter().enumerate() {
+        let normalized = normalize_proof(s);
+        if normalized.is_empty() {
+            continue;
+        }
+        match out.iter_mut().find(|c| c.normalized == normalized) {
+            Some(c) => {
+                c.votes += 1;
+                c.samples.push(i);
+            }
+            None => out.push(Candidate {
+                proof: clean_proof(s),
+                normalized,
+                votes: 1,
+                samples: vec![i],
+            }),
+        }
+    }
+    // Stable: equal votes keep first-seen order.
+    out.sort_by_key(|c| std::cmp::Reverse(c.votes));
+    out
+}
+
+async fn check_candidate(
+    repo_root: &Path,
+    text: &str,
+    lemma: &str,
+    rank: usize,
+    c: &Candidate,
+    timeout: Duration,
+) -> CandidateCheck {
+    let mut out = CandidateCheck {
+        rank,
+        proof: c.proof.clone(),
+        votes: c.votes,
+        ok: false,
+        solved: false,
+        patch: None,
+        verify: None,
+        error: None,
+    };
+    let patched = match crate::patch_first_sorry_in_decl(text, lemma, &c.proof) {
+        Ok(p) => p,
+        Err(e) => {
+            out.error = Some(format!("patch: {e}"));
+            return out;
+        }
+    };
+    match crate::verify_lean_text(repo_root, &patched.text, timeout).await {
+        Ok(v) => {
+            out.ok = v.ok;
+            out.solved =
+                v.ok && !crate::decl_block_contains_sorry(&patched.text, lemma).unwrap_or(true);
+            out.verify = Some(v);
+        }
+        Err(e) => out.error = Some(format!("verify: {e}")),
+    }
+    out.patch = Some(patched);
+    out
+}
+
+/// Verify the first `max_verify` candidates against `text`, at most `jobs` at a time.
+///
+/// Results are in rank order.
+pub async fn verify_candidates(
+    repo_root: &Path,
+    text: &str,
+    lemma: &str,
+    candidates: &[Candidate],
+    max_verify: usize,
+    jobs: usize,
+    timeout: Duration,
+) -> Vec<CandidateCheck> {
+    // Local tasks: verification futures are not `Send` with the LSP backend, and the work is
+    // waiting on Lean subprocesses anyway.
+    let permits = Arc::new(tokio::sync::Semaphore::new(jobs.max(1)));
+    let local = tokio::task::LocalSet::new();
+    let mut out = local
+        .run_until(async {
+            let mut tasks = tokio::task::JoinSet::new();
+            for (rank, c) in candidates.iter().take(max_verify).enumerate() {
+                let permits = permits.clone();
+                let (root, text, lemma, c) = (
+                    repo_root.to_path_buf(),
+                    text.to_string(),
+                    lemma.to_string(),
+                    c.clone(),
+                );
+                tasks.spawn_local(async move {
+                    let _permit = permits.acquire_owned().await;
+                    check_candidate(&root, &text, &lemma, rank, &c, timeout).await
+                });
+            }
+            let mut out = Vec::new();
+            while let Some(joined) = tasks.join_next().await {
+                if let Ok(check) = joined {
+                    out.push(check);
+                }
+            }
+            out
+        })
+        .await;
+    out.sort_by_key(|c| c.rank);
+    out
+}
+
+/// Best check: the highest-ranked one that closed the lemma, else the highest-ranked one that
+/// compiled.
+pub fn best_check(checks: &[CandidateCheck]) -> Option<&CandidateCheck> {
+    checks
+        .iter()
+        .find(|c| c.solved)
+        .or_else(|| checks.iter().find(|c| c.ok))
+}
+
+pub fn sample_stats(
+    samples: usize,
+    candidates: &[Candidate],
+    checks: &[CandidateCheck],
+) -> SampleStats {
+    let compiled_samples: usize = checks.iter().filter(|c| c.ok).map(|c| c.votes).sum();
+    SampleStats {
+        samples,
+        distinct: candidates.len(),
+        verified: checks.len(),
+        compiled: checks.iter().filter(|c| c.ok).count(),
+        solved: checks.iter().filter(|c| c.solved).count(),
+        compiled_samples,
+        compiled_rate: if samples == 0 {
+            0.0
+        } else {
+            compiled_samples as f64 / samples as f64
+        },
+    }
+}
@@ -1,4 +1,4 @@
-//! Minimal SMT-based entailment checks for linear integer arithmetic (LIA).
+//! Minimal SMT-based entailment checks for linear arithmetic over `ℕ`/`ℤ` (LIA) and `ℝ`/`ℚ` (LRA).
 //!
 //! This is intentionally conservative and best-effort:
 //! - If we cannot confidently parse/sort variables, return `Ok(None)`.
@@ -7,7 +7,6 @@
 //! Soundness posture: this is a *heuristic signal* for ranking / candidate selection.
 //! It must never be used as a proof of a Lean goal without verification.
 
-use regex::Regex;
 use serde_json::Value;
 use std::hash::{Hash, Hasher};
 use std::sync::OnceLock;
@@ -27,6 +26,10 @@ pub fn smt_solver_probe() -> Value {
                 serde_json::json!({
                     "available": true,
                     "used": used,
+                    "portfolio": portfolio_solvers_on_path()
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .collect::<Vec<_>>(),
                     "caps": {
                         "check_sat_assuming": caps.check_sat_assuming,
                         "get_model": caps.get_model,
@@ -44,13 +47,37 @@ pub fn smt_solver_probe() -> Value {
         .clone()
 }
 
+/// Solvers a portfolio session races against each other: `(name, SMTKIT_SOLVER command line)`.
+const PORTFOLIO_SOLVERS: [(&str, &str); 3] = [
+    ("z3", "z3 -in -smt2"),
+    ("cvc5", "cvc5 --lang smt2 --incremental"),
+    ("yices", "yices-smt2 --incremental"),
+];
+
+/// The portfolio solvers whose binary is on `PATH` (cheap; nothing is spawned).
+pub(crate) fn portfolio_solvers_on_path() -> Vec<(&'static str, &'static str)> {
+    let Some(path) = std::env::var_os("PATH") else {
+        return Vec::new();
+    };
+    let dirs: Vec<std::path::PathBuf> = std::env::split_paths(&path).collect();
+    PORTFOLIO_SOLVERS
+        .iter()
+        .filter(|(_, cmdline)| {
+            let bin = cmdline.split_whitespace().next().unwrap_or_default();
+            dirs.iter().any(|d| d.join(bin).is_file())
+        })
+        .copied()
+        .collect()
+}
+
 /// A reusable “warm” SMT session for repeated entailment checks.
 ///
 /// This is intentionally **best-effort**:
 /// - If no solver is available, callers should fall back to non-SMT heuristics.
 /// - We only use `check-sat-assuming` to avoid accumulating assertions.
 pub struct ReusableSmtSession {
-    sess: smtkit::session::SmtlibSession,
+    // `None` for a portfolio coordinator, which only dispatches to `portfolio`.
+    sess: Option<smtkit::session::SmtlibSession>,
     solver_used: String,
     // vars we already declared in the session
     declared: std::collections::BTreeSet<String>,
@@ -73,6 +100,81 @@ pub struct ReusableSmtSession {
     disabled: bool,
     disabled_reason: Option<String>,
     errors: u64,
+    // Portfolio mode: one warm session per solver, raced per query. A slot is `None` while its
+    // session is still answering an earlier query on a background thread.
+    portfolio: Vec<Option<ReusableSmtSession>>,
+    // Answers (with their sessions) coming back from portfolio threads.
+    portfolio_chan: Option<(
+        std::sync::mpsc::Sender<PortfolioReturn>,
+        std::sync::mpsc::Receiver<PortfolioReturn>,
+    )>,
+    // Queries with answers still outstanding, by query number.
+    portfolio_open: std::collections::BTreeMap<u64, OpenPortfolioQuery>,
+    portfolio_queries: u64,
+    // Per-solver portfolio outcomes, keyed by solver command line (empty outside portfolio mode).
+    solver_stats: std::collections::BTreeMap<String, PortfolioSolverStats>,
+    // Queries where one solver said `sat` and another `unsat` (bounded; a solver bug signal).
+    disagreements: Vec<Value>,
+    disagreements_total: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PortfolioSolverStats {
+    queries: u64,
+    /// First definitive answer to a query.
+    wins: u64,
+    /// `unknown` answers (almost always the per-query timeout).
+    timeouts: u64,
+    errors: u64,
+}
+
+/// The portfolio name of the in-process procedure (`lia_solver`).
+const BUILTIN_SOLVER: &str = "builtin";
+
+/// One solver's answer to a portfolio query.
+struct PortfolioAnswer {
+    solver: String,
+    entails: Result<Option<bool>, String>,
+}
+
+/// A portfolio thread's answer, handed back with its session.
+struct PortfolioReturn {
+    query: u64,
+    /// Slot in `portfolio`; `None` for the in-process procedure.
+    slot: Option<usize>,
+    session: Option<ReusableSmtSession>,
+    answer: PortfolioAnswer,
+}
+
+/// A portfolio query whose solvers have not all answered yet.
+struct OpenPortfolioQuery {
+    target: String,
+    hyps: Vec<String>,
+    /// In arrival order.
+    answers: Vec<PortfolioAnswer>,
+    outstanding: usize,
+    /// A disagreement was already recorded.
+    flagged: bool,
+}
+
+/// How long `ReusableSmtSession::settle` waits for any one outstanding portfolio answer.
+const PORTFOLIO_SETTLE_WAIT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Pick the portfolio result from answers in arrival order.
+///
+/// The first definitive answer wins. Returns `(winner index, disagreement)`; when two solvers
+/// give opposite answers, at least one of them is wrong, so callers should not trust either.
+fn portfolio_verdict(answers: &[PortfolioAnswer]) -> (Option<usize>, bool) {
+    let winner = answers
+        .iter()
+        .position(|a| matches!(a.entails, Ok(Some(_))));
+    let disagreement = winner.is_some_and(|w| {
+        let first = answers[w].entails.clone().ok().flatten();
+        answers
+            .iter()
+            .any(|a| matches!(a.entails, Ok(Some(r)) if Some(r) != first))
+    });
+    (winner, disagreement)
 }
 
 fn reuse_fragment_key(
@@ -103,16 +205,26 @@ impl ReusableSmtSession {
         sess.set_logic("QF_LIA").map_err(|e| e.to_string())?;
         sess.set_print_success(false).map_err(|e| e.to_string())?;
         sess.set_produce_models(false).map_err(|e| e.to_string())?;
+        Ok(Some(Self::with_session(
+            Some(sess),
+            used,
+            caps.check_sat_assuming,
+        )))
+    }
 
-        let supports_assuming = caps.check_sat_assuming;
+    fn with_session(
+        sess: Option<smtkit::session::SmtlibSession>,
+        solver_used: String,
+        supports_assuming: bool,
+    ) -> Self {
         // Reuse requires `check-sat-assuming` (we use it to avoid accumulating assertions).
         // Historically we disabled reuse for some solvers by name due to session brittleness,
         // but we now prefer a capability-first posture and allow reuse to self-disable on errors.
-        let reuse_disabled_for_solver = !supports_assuming;
+        let reuse_disabled_for_solver = sess.is_some() && !supports_assuming;
 
-        Ok(Some(Self {
+        Self {
             sess,
-            solver_used: used,
+            solver_used,
             declared: std::collections::BTreeSet::new(),
             nat_vars: std::collections::BTreeSet::new(),
             supports_assuming,
@@ -134,28 +246,104 @@ impl ReusableSmtSession {
                 None
             },
             errors: 0,
-        }))
+            portfolio: Vec::new(),
+            portfolio_chan: None,
+            portfolio_open: std::collections::BTreeMap::new(),
+            portfolio_queries: 0,
+            solver_stats: std::collections::BTreeMap::new(),
+            disagreements: Vec::new(),
+            disagreements_total: 0,
+        }
+    }
+
+    /// Spawn one warm session per solver on `PATH` (z3, cvc5, yices) and race them per query,
+    /// together with the in-process procedure (`lia_solver`) as a cross-check.
+    ///
+    /// The returned session only coordinates; each solver runs on its own thread. Falls back to
+    /// `new()` when fewer than two portfolio solvers can be spawned.
+    pub fn new_portfolio() -> Result<Option<Self>, String> {
+        let mut sessions: Vec<Self> = Vec::new();
+        for (_, cmdline) in portfolio_solvers_on_path() {
+            // `smtkit` picks its solver from `SMTKIT_SOLVER`; restore it after each spawn.
+            let old = std::env::var_os("SMTKIT_SOLVER");
+            std::env::set_var("SMTKIT_SOLVER", cmdline);
+            let spawned = Self::new();
+            match old {
+                Some(v) => std::env::set_var("SMTKIT_SOLVER", v),
+                None => std::env::remove_var("SMTKIT_SOLVER"),
+            }
+            if let Ok(Some(s)) = spawned {
+                sessions.push(s);
+            }
+        }
+        // One external solver plus `builtin` is not a portfolio: use the plain warm session.
+        if sessions.len() < 2 {
+            return Self::new();
+        }
+        let mut lead = Self::with_session(None, "portfolio".to_string(), false);
+        let solvers = sessions
+            .iter()
+            .map(|s| s.solver_used.clone())
+            .chain([BUILTIN_SOLVER.to_string()]);
+        for solver in solvers {
+            lead.solver_stats.entry(solver).or_default();
+        }
+        lead.portfolio = sessions.into_iter().map(Some).collect();
+        lead.portfolio_chan = Some(std::sync::mpsc::channel());
+        Ok(Some(lead))
+    }
+
+    /// Wait for portfolio answers still outstanding from earlier queries, so that late
+    /// disagreements show up in `disagreements` and `stats`.
+    pub fn settle(&mut self) {
+        self.collect_portfolio_answers(true);
+    }
+
+    /// `sat`/`unsat` disagreements seen so far in portfolio mode (bounded).
+    pub fn disagreements(&self) -> &[Value] {
+        &self.disagreements
     }
 
     pub fn stats(&self) -> Value {
+        // A portfolio coordinator has no session of its own: counters are summed over the
+        // members (those not still busy on a background thread).
+        let all: Vec<&Self> = std::iter::once(self)
+            .chain(self.portfolio.iter().flatten())
+            .collect();
+        let sum = |f: fn(&Self) -> u64| all.iter().map(|s| f(s)).sum::<u64>();
+        let max = |f: fn(&Self) -> usize| all.iter().map(|s| f(s)).max().unwrap_or(0);
         serde_json::json!({
             "solver": self.solver_used,
             "supports_check_sat_assuming": self.supports_assuming,
-            "declared_vars": self.declared.len(),
-            "nat_vars": self.nat_vars.len(),
-            "decls_added": self.decls_added,
-            "checks_assuming": self.checks_assuming,
-            "checks_pushpop": self.checks_pushpop,
-            "fragment_hits": self.fragment_hits,
-            "fragment_misses": self.fragment_misses,
-            "fragment_resets": self.fragment_resets,
-            "max_fragment_assert_terms": self.max_fragment_assert_terms,
-            "max_assumptions_terms": self.max_assumptions_terms,
-            "unknowns": self.unknowns,
+            "declared_vars": max(|s| s.declared.len()),
+            "nat_vars": max(|s| s.nat_vars.len()),
+            "decls_added": sum(|s| s.decls_added),
+            "checks_assuming": sum(|s| s.checks_assuming),
+            "checks_pushpop": sum(|s| s.checks_pushpop),
+            "fragment_hits": sum(|s| s.fragment_hits),
+            "fragment_misses": sum(|s| s.fragment_misses),
+            "fragment_resets": sum(|s| s.fragment_resets),
+            "max_fragment_assert_terms": max(|s| s.max_fragment_assert_terms),
+            "max_assumptions_terms": max(|s| s.max_assumptions_terms),
+            "unknowns": sum(|s| s.unknowns),
             "last_reason_unknown": self.last_reason_unknown,
             "disabled": self.disabled,
             "disabled_reason": self.disabled_reason,
-            "errors": self.errors,
+            "errors": sum(|s| s.errors),
+            "portfolio": if self.solver_stats.is_empty() {
+                Value::Null
+            } else {
+                serde_json::json!({
+                    "solvers": self.solver_stats.iter().map(|(solver, st)| serde_json::json!({
+                        "solver": solver,
+                        "queries": st.queries,
+                        "wins": st.wins,
+                        "timeouts": st.timeouts,
+                        "errors": st.errors,
+                    })).collect::<Vec<_>>(),
+                    "disagreements": self.disagreements_total,
+                })
+            },
         })
     }
 
@@ -164,7 +352,15 @@ impl ReusableSmtSession {
         self.disabled = true;
         self.disabled_reason = Some(reason);
         // Best-effort: kill the underlying process so we don't keep a wedged solver around.
-        self.sess.kill();
+        if let Some(sess) = self.sess.as_mut() {
+            sess.kill();
+        }
+    }
+
+    fn solver(&mut self) -> Result<&mut smtkit::session::SmtlibSession, String> {
+        self.sess
+            .as_mut()
+            .ok_or_else(|| "portfolio coordinator has no solver session".to_string())
     }
 
     fn ensure_declared(
@@ -176,7 +372,7 @@ impl ReusableSmtSession {
             if self.declared.contains(name) {
                 continue;
             }
-            self.sess
+            self.solver()?
                 .declare_const(name, &smtkit::smt2::Sort::Int.to_smt2())
                 .map_err(|e| e.to_string())?;
             self.declared.insert(name.clone());
@@ -215,23 +411,29 @@ impl ReusableSmtSession {
 
         // Reset the active fragment frame (if any).
         if self.fragment_active_key.is_some() {
-            self.sess.pop(1).map_err(|e| e.to_string())?;
+            self.solver()?.pop(1).map_err(|e| e.to_string())?;
             self.fragment_resets = self.fragment_resets.saturating_add(1);
             self.fragment_active_key = None;
         }
 
         // Install a new fragment frame with Nat constraints + hypotheses asserted.
-        self.sess.push().map_err(|e| e.to_string())?;
+        self.solver()?.push().map_err(|e| e.to_string())?;
 
         let mut asserted_terms: usize = 0;
         for v in nat_in_use.iter() {
-            self.sess
+            self.solver()?
                 .assert_sexp(&t::ge(t::sym(v.clone()), t::int_lit(0)))
                 .map_err(|e| e.to_string())?;
             asserted_terms = asserted_terms.saturating_add(1);
         }
         for h in hyps.iter() {
-            self.sess.assert_sexp(&h.sexp).map_err(|e| e.to_string())?;
+            self.solver()?
+                .assert_sexp(&h.sexp)
+                .map_err(|e| e.to_string())?;
+            asserted_terms = asserted_terms.saturating_add(1);
+        }
+        for s in side_constraints(hyps) {
+            self.solver()?.assert_sexp(&s).map_err(|e| e.to_string())?;
             asserted_terms = asserted_terms.saturating_add(1);
         }
 
@@ -240,6 +442,233 @@ impl ReusableSmtSession {
         Ok(())
     }
 
+    /// One entailment check on this warm session, using the solver's preferred strategy.
+    fn check_entails_warm(
+        &mut self,
+        timeout_ms: u64,
+        seed: u64,
+        hyps: &[ParsedRelConstraint],
+        target: &ParsedRelConstraint,
+        used_vars: &std::collections::BTreeSet<String>,
+        var_kinds: &std::collections::BTreeMap<String, VarKind>,
+    ) -> Result<Option<bool>, String> {
+        if self.disabled {
+            return Ok(None);
+        }
+        self.ensure_declared(used_vars, var_kinds)?;
+        // Solver-specific strategy:
+        // - Z3: push/pop + cached fragment is fast/stable.
+        // - cvc5: prefer `check-sat-assuming` (avoids some stdout oddities), then fall back to push/pop.
+        if self.solver_used.trim_start().starts_with("cvc5") {
+            if let Some(r) =
+                self.check_entails_assuming(timeout_ms, seed, hyps, target, used_vars)?
+            {
+                return Ok(Some(r));
+            }
+            self.check_entails_pushpop(timeout_ms, seed, hyps, target, used_vars)
+        } else {
+            // Prefer fragment caching + push/pop (smaller per-check traffic).
+            if let Some(r) =
+                self.check_entails_pushpop(timeout_ms, seed, hyps, target, used_vars)?
+            {
+                return Ok(Some(r));
+            }
+            // Fallback to assuming-mode if push/pop path yields unknown.
+            self.check_entails_assuming(timeout_ms, seed, hyps, target, used_vars)
+        }
+    }
+
+    /// Race the check across the portfolio and return the first definitive answer.
+    ///
+    /// Each idle session checks on its own thread, next to the in-process procedure; sessions
+    /// still busy with an earlier query sit this one out. The caller does not wait for the slower
+    /// solvers: their answers are collected on later calls (or by `settle`) and compared with the
+    /// first, so a `sat`/`unsat` disagreement is still recorded. A disagreement seen before
+    /// returning yields `None`. A session that errors is disabled on its own; the rest of the
+    /// portfolio keeps going.
+    fn check_entails_portfolio(
+        &mut self,
+        timeout_ms: u64,
+        seed: u64,
+        hyps: &[ParsedRelConstraint],
+        target: &ParsedRelConstraint,
+        used_vars: &std::collections::BTreeSet<String>,
+        var_kinds: &std::collections::BTreeMap<String, VarKind>,
+    ) -> Result<Option<bool>, String> {
+        if self.solver_stats.is_empty() {
+            return self.check_entails_warm(timeout_ms, seed, hyps, target, used_vars, var_kinds);
+        }
+        self.collect_portfolio_answers(false);
+        let Some(tx) = self.portfolio_chan.as_ref().map(|(tx, _)| tx.clone()) else {
+            return Ok(None);
+        };
+        let query = self.portfolio_queries;
+        self.portfolio_queries = self.portfolio_queries.saturating_add(1);
+        let job = std::sync::Arc::new((
+            hyps.to_vec(),
+            target.clone(),
+            used_vars.clone(),
+            var_kinds.clone(),
+        ));
+
+        let (builtin_tx, builtin_job) = (tx.clone(), job.clone());
+        std::thread::spawn(move || {
+            let (hyps, target, _, var_kinds) = &*builtin_job;
+            let _ = builtin_tx.send(PortfolioReturn {
+                query,
+                slot: None,
+                session: None,
+                answer: PortfolioAnswer {
+                    solver: BUILTIN_SOLVER.to_string(),
+                    entails: Ok(builtin_entails(target, hyps, var_kinds, timeout_ms)),
+                },
+            });
+        });
+        let mut outstanding = 1usize;
+        for (i, slot) in self.portfolio.iter_mut().enumerate() {
+            let mut sess = match slot.take() {
+                Some(s) if !s.disabled => s,
+                other => {
+                    *slot = other;
+                    continue;
+                }
+            };
+            let (tx, job) = (tx.clone(), job.clone());
+            std::thread::spawn(move || {
+                let (hyps, target, used_vars, var_kinds) = &*job;
+                let entails =
+                    sess.check_entails_warm(timeout_ms, seed, hyps, target, used_vars, var_kinds);
+                if let Err(e) = &entails {
+                    sess.disable(e.clone());
+                }
+                let answer = PortfolioAnswer {
+                    solver: sess.solver_used.clone(),
+                    entails,
+                };
+                let _ = tx.send(PortfolioReturn {
+                    query,
+                    slot: Some(i),
+                    session: Some(sess),
+                    answer,
+                });
+            });
+            outstanding += 1;
+        }
+        self.portfolio_open.insert(
+            query,
+            OpenPortfolioQuery {
+                target: target.src.clone(),
+                hyps: hyps.iter().map(|h| h.src.clone()).collect(),
+                answers: Vec::new(),
+                outstanding,
+                flagged: false,
+            },
+        );
+
+        // Every solver, `builtin` included, stops at `timeout_ms`; the slack covers process and
+        // thread overhead.
+        let deadline = std::time::Instant::now()
+            + std::time::Duration::from_millis(timeout_ms.saturating_add(1_000));
+        Ok(self.await_portfolio_answer(query, deadline))
+    }
+
+    /// Wait for the first definitive answer to `query`, until every solver has answered or
+    /// `deadline` passes. Answers to earlier queries arriving meanwhile are taken in as well.
+    fn await_portfolio_answer(&mut self, query: u64, deadline: std::time::Instant) -> Option<bool> {
+        let result = loop {
+            let Some(q) = self.portfolio_open.get(&query) else {
+                break None;
+            };
+            let (winner, disagreement) = portfolio_verdict(&q.answers);
+            if disagreement {
+                break None;
+            }
+            if let Some(w) = winner {
+                break q.answers[w].entails.clone().ok().flatten();
+            }
+            if q.outstanding == 0 {
+                break None;
+            }
+            let wait = deadline.saturating_duration_since(std::time::Instant::now());
+            let next = self
+                .portfolio_chan
+                .as_ref()
+                .and_then(|(_, rx)| rx.recv_timeout(wait).ok());
+            match next {
+                Some(r) => self.absorb_portfolio_answer(r),
+                None => break None,
+            }
+        };
+        self.portfolio_open.retain(|_, q| q.outstanding > 0);
+        result
+    }
+
+    /// Take in portfolio answers that have arrived; with `wait`, until none is outstanding.
+    fn collect_portfolio_answers(&mut self, wait: bool) {
+        loop {
+            let pending = wait && self.portfolio_open.values().any(|q| q.outstanding > 0);
+            let next = self.portfolio_chan.as_ref().and_then(|(_, rx)| {
+                if pending {
+                    rx.recv_timeout(PORTFOLIO_SETTLE_WAIT).ok()
+                } else {
+                    rx.try_recv().ok()
+                }
+            });
+            match next {
+                Some(r) => self.absorb_portfolio_answer(r),
+                None => break,
+            }
+        }
+        self.portfolio_open.retain(|_, q| q.outstanding > 0);
+    }
+
+    /// Record one portfolio answer and put its session back in its slot.
+    fn absorb_portfolio_answer(&mut self, r: PortfolioReturn) {
+        if let (Some(i), Some(sess)) = (r.slot, r.session) {
+            if matches!(r.answer.entails, Ok(None)) && sess.last_reason_unknown.is_some() {
+                self.last_reason_unknown = sess.last_reason_unknown.clone();
+            }
+            if let Some(slot) = self.portfolio.get_mut(i) {
+                *slot = Some(sess);
+            }
+        }
+        let st = self
+            .solver_stats
+            .entry(r.answer.solver.clone())
+            .or_default();
+        st.queries = st.queries.saturating_add(1);
+        let Some(q) = self.portfolio_open.get_mut(&r.query) else {
+            return;
+        };
+        q.outstanding = q.outstanding.saturating_sub(1);
+        let first = portfolio_verdict(&q.answers).0.is_none();
+        match r.answer.entails {
+            Ok(Some(_)) if first => st.wins = st.wins.saturating_add(1),
+            Ok(Some(_)) => {}
+            Ok(None) => st.timeouts = st.timeouts.saturating_add(1),
+            Err(_) => st.errors = st.errors.saturating_add(1),
+        }
+        q.answers.push(r.answer);
+        if q.flagged || !portfolio_verdict(&q.answers).1 {
+            return;
+        }
+        q.flagged = true;
+        self.disagreements_total = self.disagreements_total.saturating_add(1);
+        if self.disagreements.len() < 16 {
+            self.disagreements.push(serde_json::json!({
+                "target": q.target,
+                "hyps": q.hyps,
+                "answers": q.answers.iter().filter_map(|a| match a.entails {
+                    Ok(Some(entails)) => Some(serde_json::json!({
+                        "solver": a.solver,
+                        "result": if entails { "unsat" } else { "sat" },
+                    })),
+                    _ => None,
+                }).collect::<Vec<_>>(),
+            }));
+        }
+    }
+
     fn check_entails_pushpop(
         &mut self,
         timeout_ms: u64,
@@ -253,21 +682,26 @@ impl ReusableSmtSession {
             return Ok(None);
         }
 
-        self.sess
+        self.solver()?
             .set_timeout_ms(timeout_ms)
             .map_err(|e| e.to_string())?;
-        self.sess.set_random_seed(seed).map_err(|e| e.to_string())?;
+        self.solver()?
+            .set_random_seed(seed)
+            .map_err(|e| e.to_string())?;
 
         self.ensure_fragment_asserted(hyps, used_vars)?;
 
-        // Push an inner frame for just `¬target`, then pop it.
-        self.sess.push().map_err(|e| e.to_string())?;
-        self.sess
+        // Push an inner frame for just `¬target` (and its side constraints), then pop it.
+        self.solver()?.push().map_err(|e| e.to_string())?;
+        for s in &target.side {
+            self.solver()?.assert_sexp(s).map_err(|e| e.to_string())?;
+        }
+        self.solver()?
             .assert_sexp(&t::not(target.sexp.clone()))
             .map_err(|e| e.to_string())?;
         self.checks_pushpop = self.checks_pushpop.saturating_add(1);
-        let st = self.sess.check_sat().map_err(|e| e.to_string())?;
-        self.sess.pop(1).map_err(|e| e.to_string())?;
+        let st = self.solver()?.check_sat().map_err(|e| e.to_string())?;
+        self.solver()?.pop(1).map_err(|e| e.to_string())?;
 
         Ok(match st {
             smtkit::session::Status::Unsat => Some(true),
@@ -276,8 +710,8 @@ impl ReusableSmtSession {
                 self.unknowns = self.unknowns.saturating_add(1);
                 self.last_reason_unknown = self
                     .sess
-                    .get_info(":reason-unknown")
-                    .ok()
+                    .as_mut()
+                    .and_then(|s| s.get_info(":reason-unknown").ok())
                     .map(|s| s.to_string());
                 None
             }
@@ -299,10 +733,12 @@ impl ReusableSmtSession {
         if !self.supports_assuming {
             return Ok(None);
         }
-        self.sess
+        self.solver()?
             .set_timeout_ms(timeout_ms)
             .map_err(|e| e.to_string())?;
-        self.sess.set_random_seed(seed).map_err(|e| e.to_string())?;
+        self.solver()?
+            .set_random_seed(seed)
+            .map_err(|e| e.to_string())?;
 
         // Assumptions: Nat constraints + all hyp constraints + ¬target.
         let mut assumptions: Vec<smtkit::sexp::Sexp> = Vec::new();
@@ -314,12 +750,13 @@ impl ReusableSmtSession {
         for h in hyps {
             assumptions.push(h.sexp.clone());
         }
+        assumptions.extend(side_constraints(hyps.iter().chain([target])));
         assumptions.push(t::not(target.sexp.clone()));
         self.checks_assuming = self.checks_assuming.saturating_add(1);
         self.max_assumptions_terms = self.max_assumptions_terms.max(assumptions.len());
 
         let st = self
-            .sess
+            .solver()?
             .check_sat_assuming(&assumptions)
             .map_err(|e| e.to_string())?;
         Ok(match st {
@@ -329,8 +766,8 @@ impl ReusableSmtSession {
                 self.unknowns = self.unknowns.saturating_add(1);
                 self.last_reason_unknown = self
                     .sess
-                    .get_info(":reason-unknown")
-                    .ok()
+                    .as_mut()
+                    .and_then(|s| s.get_info(":reason-unknown").ok())
                     .map(|s| s.to_string());
                 None
             }
@@ -339,9 +776,64 @@ impl ReusableSmtSession {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum VarKind {
+pub(crate) enum VarKind {
     Int,
     Nat,
+    /// `ℝ`; `ℚ` is `Rat`. Both are declared with SMT sort `Real`.
+    Real,
+    Rat,
+    /// `Fin n`: an `Int` in `[0, n)`. The bound is `None` when `n` is not a literal (`Fin n`
+    /// gets a derived `↑i < n` hypothesis instead, and no wrap-around arithmetic).
+    Fin(Option<i64>),
+    /// `UInt8` … `UInt64`: `(_ BitVec w)`, with shift amounts taken modulo `w`.
+    UInt(u32),
+    /// `BitVec w` for a literal `w`.
+    BitVec(u32),
+}
+
+impl VarKind {
+    fn is_real(self) -> bool {
+        matches!(self, VarKind::Real | VarKind::Rat)
+    }
+
+    /// The width of a bitvector-sorted kind.
+    fn bv_width(self) -> Option<u32> {
+        match self {
+            VarKind::UInt(w) | VarKind::BitVec(w) => Some(w),
+            _ => None,
+        }
+    }
+}
+
+fn smt_sort(kind: VarKind) -> smtkit::smt2::Sort {
+    match kind {
+        k if k.is_real() => smtkit::smt2::Sort::Real,
+        VarKind::UInt(w) | VarKind::BitVec(w) => smtkit::smt2::Sort::BitVec(w),
+        _ => smtkit::smt2::Sort::Int,
+    }
+}
+
+/// `QF_BV` for bitvector fragments (which never mix in other sorts); otherwise `QF_LIA`,
+/// `QF_LRA`, or `QF_LIRA` when integer variables are cast into a real fragment.
+fn fragment_logic(
+    used_vars: &std::collections::BTreeSet<String>,
+    var_kinds: &std::collections::BTreeMap<String, VarKind>,
+) -> &'static str {
+    if used_vars
+        .iter()
+        .any(|v| var_kinds.get(v).is_some_and(|k| k.bv_width().is_some()))
+    {
+        return "QF_BV";
+    }
+    let real = used_vars
+        .iter()
+        .filter(|v| var_kinds.get(*v).is_some_and(|k| k.is_real()))
+        .count();
+    match real {
+        0 => "QF_LIA",
+        n if n == used_vars.len() => "QF_LRA",
+        _ => "QF_LIRA",
+    }
 }
 
 fn sanitize_name(s: &str) -> String {
@@ -367,27 +859,68 @@ fn sanitize_name(s: &str) -> String {
     out
 }
 
-fn extract_decl_kind(hyp_text: &str) -> Option<(String, VarKind)> {
+pub(crate) fn extract_decl_kind(hyp_text: &str) -> Option<(Vec<String>, VarKind)> {
     // Recognize tiny declaration shapes like:
     // - `n : ℕ` / `n : Nat`
     // - `m : ℤ` / `m : Int`
+    // - `x : ℝ` / `x : Real`, `q : ℚ` / `q : Rat`
+    // - `w : UInt32`, `b : BitVec 8` (literal widths), `i : Fin 5` / `i : Fin n`
+    // - several names at once: `x y : ℝ`
+    // - functions, by their result type: `f : ℕ → ℤ` (see `ArithParser::uf_app`)
+    //
+    // Other types (`l : List ℕ`, `s : Finset ℝ`) are not arithmetic sorts.
     let (name, ty) = hyp_text.split_once(':')?;
     let name = name.trim();
     let ty = ty.trim();
     if name.is_empty() || ty.is_empty() {
         return None;
     }
-    let kind = if ty.contains('ℕ') || ty.contains("Nat") {
-        VarKind::Nat
-    } else if ty.contains('ℤ') || ty.contains("Int") {
-        VarKind::Int
-    } else {
+    let (args, result) = ty.rsplit_once('→').unwrap_or(("", ty));
+    if !args.is_empty() && args.split('→').any(|a| a.trim().is_empty()) {
         return None;
+    }
+    let kind = match result.trim() {
+        "ℕ" | "Nat" => VarKind::Nat,
+        "ℤ" | "Int" => VarKind::Int,
+        "ℝ" | "Real" => VarKind::Real,
+        "ℚ" | "Rat" => VarKind::Rat,
+        "UInt8" => VarKind::UInt(8),
+        "UInt16" => VarKind::UInt(16),
+        "UInt32" => VarKind::UInt(32),
+        "UInt64" => VarKind::UInt(64),
+        ty => {
+            let (head, arg) = ty.split_once(' ')?;
+            let lit = arg.trim().parse::<u32>().ok();
+            match (head, lit) {
+                ("BitVec", Some(w)) if (1..=MAX_BV_WIDTH).contains(&w) => VarKind::BitVec(w),
+                ("Fin", Some(n)) if n > 0 => VarKind::Fin(Some(i64::from(n))),
+                ("Fin", None) => VarKind::Fin(None),
+                _ => return None,
+            }
+        }
     };
-    Some((sanitize_name(name), kind))
+    Some((name.split_whitespace().map(sanitize_name).collect(), kind))
 }
 
-#[derive(Debug, Clone)]
+/// Widest `BitVec w` the encoding takes on.
+const MAX_BV_WIDTH: u32 = 1024;
+
+/// `i < n` for a declaration `i : Fin n` with a symbolic bound `n` (a plain name).
+fn fin_bound_fact(hyp_text: &str) -> Option<Vec<String>> {
+    let (names, ty) = hyp_text.split_once(':')?;
+    let bound = ty.trim().strip_prefix("Fin ")?.trim();
+    if bound.parse::<u64>().is_ok() || !bound.chars().all(is_ident_char) || bound.is_empty() {
+        return None;
+    }
+    Some(
+        names
+            .split_whitespace()
+            .map(|i| format!("↑{i} < {bound}"))
+            .collect(),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
 struct LinearExpr {
     // var -> coefficient
     coeffs: std::collections::BTreeMap<String, i64>,
@@ -401,6 +934,9 @@ enum RelOp {
     Lt,
     Gt,
     Eq,
+    /// A bitvector relation (see `parse_bv_constraint`): only its SMT term is meaningful, so the
+    /// linear shortcuts and the in-process procedure leave it alone.
+    Bv,
 }
 
 #[derive(Debug, Clone)]
@@ -410,136 +946,1056 @@ struct ParsedRel {
     rhs: LinearExpr,
 }
 
-fn parse_linear_expr_int(s: &str) -> Option<LinearExpr> {
-    // Small parser: sums/differences of identifiers and integer literals.
-    // Rejects obvious non-LIA operators.
-    let bad = ['*', '/', '^', '·', '↑', '∑', '∏'];
-    if s.chars().any(|c| bad.contains(&c)) {
-        return None;
-    }
-    let mut coeffs: std::collections::BTreeMap<String, i64> = std::collections::BTreeMap::new();
-    let mut c0: i64 = 0;
-    let mut i = 0usize;
-    let chars: Vec<char> = s.chars().collect();
-    let mut sign: i64 = 1;
-    while i < chars.len() {
-        let ch = chars[i];
-        if ch.is_whitespace() {
-            i += 1;
-            continue;
+impl LinearExpr {
+    fn constant(c0: i64) -> Self {
+        LinearExpr {
+            coeffs: std::collections::BTreeMap::new(),
+            c0,
         }
-        if ch == '+' {
-            sign = 1;
-            i += 1;
-            continue;
-        }
-        if ch == '-' {
-            sign = -1;
-            i += 1;
-            continue;
+    }
+
+    fn var(name: String) -> Self {
+        LinearExpr {
+            coeffs: std::iter::once((name, 1)).collect(),
+            c0: 0,
         }
-        if ch.is_ascii_digit() {
-            let mut j = i + 1;
-            while j < chars.len() && chars[j].is_ascii_digit() {
-                j += 1;
+    }
+
+    fn as_constant(&self) -> Option<i64> {
+        self.coeffs.is_empty().then_some(self.c0)
+    }
+
+    /// `self + k * other`, or `None` on overflow.
+    fn add_scaled(&self, other: &LinearExpr, k: i64) -> Option<LinearExpr> {
+        let mut coeffs = self.coeffs.clone();
+        for (v, c) in other.coeffs.iter() {
+            let next = coeffs
+                .get(v)
+                .copied()
+                .unwrap_or(0)
+                .checked_add(c.checked_mul(k)?)?;
+            if next == 0 {
+                coeffs.remove(v);
+            } else {
+                coeffs.insert(v.clone(), next);
             }
-            let lit: String = chars[i..j].iter().collect();
-            let v: i64 = lit.parse().ok()?;
-            c0 = c0.saturating_add(sign.saturating_mul(v));
-            i = j;
-            continue;
         }
-        if ch.is_alphanumeric() || ch == '_' || ch == '.' {
-            let mut j = i + 1;
-            while j < chars.len()
-                && (chars[j].is_alphanumeric() || chars[j] == '_' || chars[j] == '.')
-            {
-                j += 1;
+        Some(LinearExpr {
+            coeffs,
+            c0: self.c0.checked_add(other.c0.checked_mul(k)?)?,
+        })
+    }
+
+    fn scale(&self, k: i64) -> Option<LinearExpr> {
+        LinearExpr::constant(0).add_scaled(self, k)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(i64),
+    /// Decimal literal `num / den` (`0.25` is `Dec(25, 100)`).
+    Dec(i64, i64),
+    Ident(String),
+    Sym(&'static str),
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '\'' | '!' | '?' | '✝')
+}
+
+fn tokenize_arith(s: &str) -> Option<Vec<Tok>> {
+    // Longer spellings first; ASCII comparisons map onto the unicode ones.
+    const SYMS: [(&str, &str); 30] = [
+        ("&&&", "&&&"),
+        ("|||", "|||"),
+        ("^^^", "^^^"),
+        ("<<<", "<<<"),
+        (">>>", ">>>"),
+        ("~~~", "~~~"),
+        ("#", "#"),
+        ("<=", "≤"),
+        (">=", "≥"),
+        ("!=", "≠"),
+        ("≤", "≤"),
+        ("≥", "≥"),
+        ("≠", "≠"),
+        ("<", "<"),
+        (">", ">"),
+        ("=", "="),
+        ("+", "+"),
+        ("-", "-"),
+        ("*", "*"),
+        ("•", "*"),
+        ("/", "/"),
+        ("%", "%"),
+        ("^", "^"),
+        ("(", "("),
+        (")", ")"),
+        (":", ":"),
+        ("↑", "↑"),
+        ("¬", "¬"),
+        ("∧", "∧"),
+        ("∨", "∨"),
+    ];
+    let mut out = Vec::new();
+    let mut rest = s;
+    while let Some(ch) = rest.chars().next() {
+        if ch.is_whitespace() {
+            rest = &rest[ch.len_utf8()..];
+        } else if let Some((radix, digits)) = rest
+            .strip_prefix("0x")
+            .map(|r| (16, r))
+            .or_else(|| rest.strip_prefix("0b").map(|r| (2, r)))
+        {
+            // `0xff`, `0b1010` (bitvector and `UInt` literals).
+            let end = digits
+                .find(|c: char| !c.is_digit(radix) && c != '_')
+                .unwrap_or(digits.len());
+            let n = i64::from_str_radix(&digits[..end].replace('_', ""), radix).ok()?;
+            out.push(Tok::Num(n));
+            rest = &digits[end..];
+        } else if ch.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let frac = rest[end..]
+                .strip_prefix('.')
+                .map(|f| f.find(|c: char| !c.is_ascii_digit()).unwrap_or(f.len()))
+                .filter(|n| *n > 0);
+            match frac {
+                Some(n) => {
+                    let digits = format!("{}{}", &rest[..end], &rest[end + 1..end + 1 + n]);
+                    let den = 10i64.checked_pow(u32::try_from(n).ok()?)?;
+                    out.push(Tok::Dec(digits.parse().ok()?, den));
+                    rest = &rest[end + 1 + n..];
+                }
+                None => {
+                    out.push(Tok::Num(rest[..end].parse().ok()?));
+                    rest = &rest[end..];
+                }
             }
-            let raw: String = chars[i..j].iter().collect();
-            let name = sanitize_name(&raw);
-            *coeffs.entry(name.clone()).or_insert(0) =
-                coeffs.get(&name).copied().unwrap_or(0).saturating_add(sign);
-            i = j;
-            continue;
+        } else if is_ident_char(ch) {
+            let end = rest.find(|c| !is_ident_char(c)).unwrap_or(rest.len());
+            out.push(Tok::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            let (lit, sym) = SYMS.iter().find(|(lit, _)| rest.starts_with(lit))?;
+            out.push(Tok::Sym(sym));
+            rest = &rest[lit.len()..];
         }
-        return None;
     }
-    Some(LinearExpr { coeffs, c0 })
+    Some(out)
+}
+
+/// Sort of a parsed term. Literals fit either sort until combined with a variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TermSort {
+    Const,
+    Int,
+    Real,
+}
+
+/// Precedence-climbing parser for Lean pretty-printed arithmetic over `ℕ`/`ℤ` and `ℝ`/`ℚ`.
+///
+/// Terms outside linear arithmetic are encoded with fresh variables whose definitions go to `side`:
+/// - `↑e` in an integer relation (read as the `ℕ → ℤ` cast) asserts the atoms of `e` non-negative;
+///   in a real relation an integer `e` becomes a fresh real equal to `(to_real e)`, non-negative
+///   when `e` only mentions `ℕ` variables
+/// - `a - b` of `ℕ` terms (in a cast argument, or in a relation without casts) truncates: a fresh
+///   `t` with `t = ite(a ≥ b, a - b, 0)`, so `↑(n - 1)` is not `↑n - 1`
+/// - `e / k`, `e % k` for a literal `k` over the integers introduce a quotient `q` with
+///   `k*q ≤ e ≤ k*q + |k| - 1` (Lean's `/` and `%` round like SMT-LIB `div`/`mod`); over the reals
+///   `e / k` is a fresh `q` with `k*q = e`, and decimals like `0.5` are handled the same way
+/// - `min a b`, `max a b` and `if c then a else b` introduce a variable equal to the chosen branch
+///
+/// A relation is real when it mentions a declared real variable, a real type, or a decimal
+/// literal; undeclared variables then default to `Real` instead of `Int`. Fresh variables are
+/// named after a hash of the term they stand for, so the same subterm in the target and in a
+/// hypothesis maps to the same variable. Other applications (`f x`, `List.length l`, `l.length`)
+/// are opaque atoms of the same kind, recorded in `apps` for congruence (see `add_congruence`).
+///
+/// `Fin n` variables are integers in `[0, n)`; `+ - *` on them wrap around modulo a literal `n`
+/// (through `%` as above), and literals compared with them are reduced. `↑i`, `i.val` and `i.1`
+/// read the value without wrapping. A bitvector `x` only occurs as `x.toNat` or `↑x`, an atom in
+/// `[0, 2^w)` not related to `x` itself; relations over `x` go through `parse_bv_constraint`.
+/// Anything else (products of variables, sums mixing integer and real terms) makes the parse fail.
+struct ArithParser<'a> {
+    toks: Vec<Tok>,
+    pos: usize,
+    side: Vec<smtkit::sexp::Sexp>,
+    vars: std::collections::BTreeSet<String>,
+    kinds: &'a std::collections::BTreeMap<String, VarKind>,
+    /// Whether literals and undeclared variables are read as reals at this point.
+    real: bool,
+    real_vars: std::collections::BTreeSet<String>,
+    apps: Vec<UfApp>,
+    /// Fresh variables holding wrapped `Fin n` results, with their `n`.
+    fin: std::collections::BTreeMap<String, i64>,
+    /// Set when a bitvector variable occurs outside `toNat`; the parse is then rejected.
+    bad: bool,
+    /// Whether subtraction of `ℕ` terms truncates at this point: in a cast argument, or anywhere
+    /// in a relation without casts.
+    nat_sub: bool,
+    /// Whether undeclared variables are `ℕ` at this point (the argument of a `ℕ → ℤ` cast).
+    undeclared_nat: bool,
+    /// Fresh variables with values in `ℕ` (`ℕ`-valued applications, quotients of `ℕ` terms, ...).
+    nat_fresh: std::collections::BTreeSet<String>,
 }
 
+/// An uninterpreted application `func args…`, abstracted as the fresh variable `sym`.
 #[derive(Debug, Clone)]
-struct ParsedRelConstraint {
-    rel: ParsedRel,
-    sexp: smtkit::sexp::Sexp,
+struct UfApp {
+    func: String,
+    /// Rendered arguments with whether each is real-sorted.
+    args: Vec<(smtkit::sexp::Sexp, bool)>,
+    sym: String,
+    real: bool,
+    /// `sym` and the variables of the arguments, and the real-sorted ones among them.
     vars: std::collections::BTreeSet<String>,
-    src: String,
+    real_vars: std::collections::BTreeSet<String>,
 }
 
-fn select_constraints_by_var_depth(
-    target_vars: &std::collections::BTreeSet<String>,
-    hyps: &[ParsedRelConstraint],
-    depth: usize,
-) -> Vec<ParsedRelConstraint> {
-    // Depth semantics (deterministic, bounded):
-    // - depth == 0: return all hyps (status quo behavior)
-    // - depth == 1: include only hyps mentioning a target var
-    // - depth == 2+: expand by variable connectivity up to `depth` passes
-    //
-    // This is a performance/robustness knob, not a soundness boundary: SMT here is only a heuristic signal.
-    if depth == 0 {
-        return hyps.to_vec();
+/// Functions with values in `ℕ` (their applications are asserted non-negative), and the
+/// projections `x.length`, `x.card`, ... that stand for them.
+const NAT_FUNCTIONS: [(&str, &str); 12] = [
+    ("List.length", "length"),
+    ("Finset.card", "card"),
+    ("Multiset.card", ""),
+    ("Fintype.card", ""),
+    ("Nat.card", ""),
+    ("Array.size", "size"),
+    ("String.length", ""),
+    ("Int.natAbs", "natAbs"),
+    ("Int.toNat", "toNat"),
+    ("Nat.factorial", "factorial"),
+    ("Nat.fib", ""),
+    ("Nat.sqrt", ""),
+];
+
+type ArithRel = (&'static str, LinearExpr, LinearExpr);
+
+impl<'a> ArithParser<'a> {
+    fn new(s: &str, kinds: &'a std::collections::BTreeMap<String, VarKind>) -> Option<Self> {
+        let toks = tokenize_arith(s)?;
+        let real = toks.iter().any(|t| match t {
+            Tok::Dec(..) => true,
+            Tok::Ident(x) => {
+                matches!(x.as_str(), "ℝ" | "ℚ" | "Real" | "Rat")
+                    || kinds.get(&sanitize_name(x)).is_some_and(|k| k.is_real())
+            }
+            _ => false,
+        });
+        // `↑`, cast functions and `(e : ℤ)` ascriptions move arithmetic out of `ℕ`.
+        let casts = toks.iter().enumerate().any(|(i, t)| match t {
+            Tok::Sym("↑") => true,
+            Tok::Sym(":") => matches!(
+                toks.get(i + 1),
+                Some(Tok::Ident(ty)) if ty != "ℕ" && ty != "Nat"
+            ),
+            Tok::Ident(x) => matches!(
+                x.as_str(),
+                "Nat.cast" | "Int.ofNat" | "Int.cast" | "Rat.cast"
+            ),
+            _ => false,
+        });
+        Some(ArithParser {
+            toks,
+            pos: 0,
+            side: Vec::new(),
+            vars: std::collections::BTreeSet::new(),
+            kinds,
+            real,
+            real_vars: std::collections::BTreeSet::new(),
+            apps: Vec::new(),
+            fin: std::collections::BTreeMap::new(),
+            bad: false,
+            nat_sub: !casts && !real,
+            undeclared_nat: false,
+            nat_fresh: std::collections::BTreeSet::new(),
+        })
     }
-    if target_vars.is_empty() || hyps.is_empty() {
-        return Vec::new();
+
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos)
     }
 
-    let mut included_vars: std::collections::BTreeSet<String> = target_vars.clone();
-    let mut picked: Vec<bool> = vec![false; hyps.len()];
+    fn next(&mut self) -> Option<Tok> {
+        let t = self.toks.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
 
-    // One pass per "hop" from the target vars.
-    for _ in 0..depth {
-        // Snapshot the current frontier so we don't chain within one hop.
-        // (We only expand `included_vars` *after* finishing the pass.)
-        let frontier = included_vars.clone();
-        let mut pending_vars: std::collections::BTreeSet<String> =
-            std::collections::BTreeSet::new();
-        let mut changed = false;
-        for (i, h) in hyps.iter().enumerate() {
-            if picked[i] {
-                continue;
-            }
-            let touches = h.vars.iter().any(|v| frontier.contains(v));
-            if touches {
-                picked[i] = true;
-                pending_vars.extend(h.vars.iter().cloned());
-                changed = true;
-            }
-        }
-        included_vars.extend(pending_vars);
-        if !changed {
-            break;
+    fn eat_sym(&mut self, s: &str) -> bool {
+        if matches!(self.peek(), Some(Tok::Sym(x)) if *x == s) {
+            self.pos += 1;
+            return true;
         }
+        false
     }
 
-    let mut out: Vec<ParsedRelConstraint> = Vec::new();
-    for (i, h) in hyps.iter().enumerate() {
-        if picked[i] {
-            out.push(h.clone());
+    fn eat_kw(&mut self, kw: &str) -> bool {
+        if matches!(self.peek(), Some(Tok::Ident(x)) if x == kw) {
+            self.pos += 1;
+            return true;
         }
+        false
     }
-    out
-}
 
-fn linear_expr_to_smt_sexp(e: &LinearExpr) -> smtkit::sexp::Sexp {
-    use smtkit::smt2::t;
-    let mut terms: Vec<smtkit::sexp::Sexp> = Vec::new();
-    if e.c0 != 0 {
-        terms.push(t::int_lit(e.c0));
+    fn is_kw(s: &str) -> bool {
+        matches!(s, "if" | "then" | "else")
     }
-    for (v, c) in e.coeffs.iter() {
-        if *c == 0 {
-            continue;
+
+    fn starts_arg(&self) -> bool {
+        match self.peek() {
+            Some(Tok::Num(_) | Tok::Dec(..)) => true,
+            Some(Tok::Ident(x)) => !Self::is_kw(x),
+            Some(Tok::Sym(s)) => matches!(*s, "(" | "↑"),
+            None => false,
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.toks.len()
+    }
+
+    fn var(&mut self, raw: &str) -> LinearExpr {
+        use smtkit::smt2::t;
+        let name = sanitize_name(raw);
+        match self.kinds.get(&name) {
+            Some(k) if k.bv_width().is_some() => self.bad = true,
+            Some(VarKind::Fin(n)) => {
+                self.add_side(t::le(t::int_lit(0), t::sym(name.clone())));
+                if let Some(n) = n {
+                    self.add_side(t::lt(t::sym(name.clone()), t::int_lit(*n)));
+                }
+            }
+            _ => {}
+        }
+        let real = match self.kinds.get(&name) {
+            Some(k) => k.is_real(),
+            // Undeclared: the context of its first occurrence decides.
+            None => self.real_vars.contains(&name) || (self.real && !self.vars.contains(&name)),
+        };
+        if real {
+            self.real_vars.insert(name.clone());
+        }
+        self.vars.insert(name.clone());
+        LinearExpr::var(name)
+    }
+
+    fn add_side(&mut self, s: smtkit::sexp::Sexp) {
+        let key = s.to_string();
+        if !self.side.iter().any(|x| x.to_string() == key) {
+            self.side.push(s);
+        }
+    }
+
+    fn fresh(&mut self, kind: &str, key: &str, real: bool) -> String {
+        let mut h = std::collections::hash_map::DefaultHasher::new();
+        kind.hash(&mut h);
+        key.hash(&mut h);
+        real.hash(&mut h);
+        let name = format!("_{kind}_{:08x}", h.finish() as u32);
+        if real {
+            self.real_vars.insert(name.clone());
+        }
+        self.vars.insert(name.clone());
+        name
+    }
+
+    fn sort_of(&self, e: &LinearExpr) -> Option<TermSort> {
+        let real = e
+            .coeffs
+            .keys()
+            .filter(|v| self.real_vars.contains(*v))
+            .count();
+        match real {
+            0 if e.coeffs.is_empty() => Some(TermSort::Const),
+            0 => Some(TermSort::Int),
+            n if n == e.coeffs.len() => Some(TermSort::Real),
+            _ => None,
+        }
+    }
+
+    /// Whether `es` are rendered together as reals; `None` if they mix integer and real terms.
+    fn joint_real(&self, es: &[&LinearExpr]) -> Option<bool> {
+        let mut sort = TermSort::Const;
+        for e in es {
+            match (sort, self.sort_of(e)?) {
+                (_, TermSort::Const) => {}
+                (TermSort::Const, s) => sort = s,
+                (a, b) if a == b => {}
+                _ => return None,
+            }
+        }
+        Some(match sort {
+            TermSort::Const => self.real,
+            s => s == TermSort::Real,
+        })
+    }
+
+    /// `lhs op rhs` with `op` one of `≤ ≥ < > = ≠`.
+    fn rel(&mut self) -> Option<ArithRel> {
+        let lhs = self.expr(0)?;
+        let op = match self.next()? {
+            Tok::Sym(s @ ("≤" | "≥" | "<" | ">" | "=" | "≠")) => s,
+            _ => return None,
+        };
+        let rhs = self.expr(0)?;
+        let rhs = self.fin_literal(&lhs, rhs)?;
+        let lhs = self.fin_literal(&rhs, lhs)?;
+        Some((op, lhs, rhs))
+    }
+
+    /// The modulus of the first `Fin n` atom among `es` (`Some(None)` for a symbolic `n`), or
+    /// `None` when there is none.
+    fn fin_modulus(&self, es: &[&LinearExpr]) -> Option<Option<i64>> {
+        es.iter().find_map(|e| {
+            let (v, c) = e.coeffs.iter().next()?;
+            if e.c0 != 0 || e.coeffs.len() != 1 || *c != 1 {
+                return None;
+            }
+            match self.kinds.get(v) {
+                Some(VarKind::Fin(n)) => Some(*n),
+                _ => self.fin.get(v).map(|n| Some(*n)),
+            }
+        })
+    }
+
+    /// A literal `e` next to a `Fin n` atom is itself in `Fin n` (`i = 7` is `i = 2` in `Fin 5`).
+    fn fin_literal(&self, atom: &LinearExpr, e: LinearExpr) -> Option<LinearExpr> {
+        match (self.fin_modulus(&[atom]), e.as_constant()) {
+            (Some(n), Some(c)) => Some(LinearExpr::constant(c.rem_euclid(n?))),
+            _ => Some(e),
+        }
+    }
+
+    /// `e mod n` as a fresh `Fin n` variable.
+    fn fin_wrap(&mut self, e: LinearExpr, n: i64) -> Option<LinearExpr> {
+        use smtkit::smt2::t;
+        let r = self.div_mod("%", e, n)?;
+        if r.as_constant().is_some() {
+            return Some(r);
+        }
+        let sr = linear_expr_to_smt_sexp(&r);
+        let v = self.fresh("fin", &format!("{sr} {n}"), false);
+        self.add_side(t::eq(t::sym(v.clone()), sr));
+        self.fin.insert(v.clone(), n);
+        Some(LinearExpr::var(v))
+    }
+
+    /// The value of a `Fin n` atom as a plain integer (no wrap-around from here on).
+    fn fin_val(&mut self, e: LinearExpr) -> LinearExpr {
+        use smtkit::smt2::t;
+        if self.fin_modulus(&[&e]).is_none() {
+            return e;
+        }
+        let se = linear_expr_to_smt_sexp(&e);
+        let v = self.fresh("val", &se.to_string(), false);
+        self.nat_fresh.insert(v.clone());
+        self.add_side(t::eq(t::sym(v.clone()), se));
+        LinearExpr::var(v)
+    }
+
+    /// Whether every variable of `e` is `ℕ`-valued.
+    fn is_nat(&self, e: &LinearExpr) -> bool {
+        e.coeffs.keys().all(|v| match self.kinds.get(v) {
+            Some(k) => *k == VarKind::Nat,
+            None => {
+                self.nat_fresh.contains(v)
+                    || (self.undeclared_nat && !v.starts_with('_') && !self.real_vars.contains(v))
+            }
+        })
+    }
+
+    /// Truncated `ℕ` subtraction: a fresh `t` with `t = ite(a ≥ b, a - b, 0)`.
+    fn nat_sub(&mut self, a: &LinearExpr, b: &LinearExpr) -> Option<LinearExpr> {
+        use smtkit::smt2::t;
+        let d = a.add_scaled(b, -1)?;
+        if let Some(c) = d.as_constant() {
+            return Some(LinearExpr::constant(c.max(0)));
+        }
+        let sd = linear_expr_to_smt_sexp(&d);
+        let v = self.fresh("natsub", &sd.to_string(), false);
+        let sv = t::sym(v.clone());
+        self.add_side(t::app(
+            "ite",
+            vec![
+                t::ge(sd.clone(), t::int_lit(0)),
+                t::eq(sv.clone(), sd),
+                t::eq(sv, t::int_lit(0)),
+            ],
+        ));
+        self.nat_fresh.insert(v.clone());
+        Some(LinearExpr::var(v))
+    }
+
+    /// `x.toNat` for a bitvector `x` of width `w`: a natural-number atom below `2^w`.
+    fn bv_nat(&mut self, raw: &str, w: u32) -> LinearExpr {
+        use smtkit::smt2::t;
+        let name = sanitize_name(raw);
+        let sym = self.fresh("bvnat", &name, false);
+        self.nat_fresh.insert(sym.clone());
+        self.add_side(t::ge(t::sym(sym.clone()), t::int_lit(0)));
+        if w <= 62 {
+            self.add_side(t::le(t::sym(sym.clone()), t::int_lit((1i64 << w) - 1)));
+        }
+        // Recorded as an opaque application so counterexamples over it are not `complete`.
+        self.apps.push(UfApp {
+            func: format!("{name}.toNat"),
+            args: Vec::new(),
+            sym: sym.clone(),
+            real: false,
+            vars: std::iter::once(sym.clone()).collect(),
+            real_vars: std::collections::BTreeSet::new(),
+        });
+        LinearExpr::var(sym)
+    }
+
+    fn rel_sexp(&self, op: &str, a: &LinearExpr, b: &LinearExpr) -> Option<smtkit::sexp::Sexp> {
+        use smtkit::smt2::t;
+        let real = self.joint_real(&[a, b])?;
+        let (a, b) = (
+            linear_expr_to_smt_sexp_sorted(a, real),
+            linear_expr_to_smt_sexp_sorted(b, real),
+        );
+        Some(match op {
+            "≤" => t::le(a, b),
+            "≥" => t::ge(a, b),
+            "<" => t::lt(a, b),
+            ">" => t::app(">", vec![a, b]),
+            "=" => t::eq(a, b),
+            "≠" => t::not(t::eq(a, b)),
+            _ => return None,
+        })
+    }
+
+    fn expr(&mut self, min_prec: u8) -> Option<LinearExpr> {
+        let mut lhs = self.unary()?;
+        loop {
+            let (op, prec, right_assoc) = match self.peek() {
+                Some(Tok::Sym(s @ ("+" | "-"))) => (*s, 65, false),
+                Some(Tok::Sym(s @ ("*" | "/" | "%"))) => (*s, 70, false),
+                Some(Tok::Sym("^")) => ("^", 75, true),
+                _ => break,
+            };
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expr(if right_assoc { prec } else { prec + 1 })?;
+            lhs = self.binary(op, lhs, rhs)?;
+        }
+        Some(lhs)
+    }
+
+    fn unary(&mut self) -> Option<LinearExpr> {
+        if self.eat_sym("-") {
+            let e = self.expr(75)?;
+            return match self.fin_modulus(&[&e]) {
+                Some(n) => self.fin_wrap(e.scale(-1)?, n?),
+                None => e.scale(-1),
+            };
+        }
+        if matches!(self.peek(), Some(Tok::Ident(x)) if x == "if") {
+            return self.ite();
+        }
+        self.app()
+    }
+
+    /// Function application (`min a b`, `Nat.succ n`, ...) or an atom.
+    fn app(&mut self) -> Option<LinearExpr> {
+        let name = match self.peek() {
+            Some(Tok::Ident(x)) if !Self::is_kw(x) => x.clone(),
+            _ => return self.arg(),
+        };
+        self.pos += 1;
+        match name.as_str() {
+            "min" | "max" => {
+                let a = self.arg()?;
+                let b = self.arg()?;
+                self.min_max(&name, a, b)
+            }
+            "Nat.succ" | "Int.succ" => self.arg()?.add_scaled(&LinearExpr::constant(1), 1),
+            "Nat.cast" | "Int.ofNat" | "Int.cast" | "Rat.cast" => self.cast_arg(),
+            _ if self.starts_arg() => {
+                let mut args = Vec::new();
+                while self.starts_arg() {
+                    args.push(self.arg()?);
+                }
+                self.uf_app(&name, args)
+            }
+            _ => self.ident(&name),
+        }
+    }
+
+    fn ident(&mut self, name: &str) -> Option<LinearExpr> {
+        if let Some((base, proj)) = name.rsplit_once('.') {
+            let kind = self.kinds.get(&sanitize_name(base)).copied();
+            if let (Some(w), "toNat") = (kind.and_then(VarKind::bv_width), proj) {
+                return Some(self.bv_nat(base, w));
+            }
+            if let (Some(VarKind::Fin(_)), "val" | "1" | "toNat") = (kind, proj) {
+                let e = self.var(base);
+                return Some(self.fin_val(e));
+            }
+        }
+        if let Some(base) = name.strip_suffix(".succ").filter(|b| !b.is_empty()) {
+            let mut e = self.var(base);
+            e.c0 = 1;
+            return Some(e);
+        }
+        let projection = name.rsplit_once('.').and_then(|(base, proj)| {
+            let (func, _) = NAT_FUNCTIONS
+                .iter()
+                .find(|(_, p)| !p.is_empty() && *p == proj)?;
+            (!base.is_empty() && !NAT_FUNCTIONS.iter().any(|(f, _)| *f == name))
+                .then_some((base, *func))
+        });
+        match projection {
+            Some((base, func)) => {
+                let arg = self.var(base);
+                self.uf_app(func, vec![arg])
+            }
+            None => Some(self.var(name)),
+        }
+    }
+
+    /// `func args…` as a fresh variable named after the function and its (normalized) arguments,
+    /// so equal applications share it. The result is real when `func` is declared with a real
+    /// codomain, or by context if it is not declared at all; `ℕ`-valued functions get `≥ 0`.
+    fn uf_app(&mut self, func: &str, args: Vec<LinearExpr>) -> Option<LinearExpr> {
+        use smtkit::smt2::t;
+        let kind = if NAT_FUNCTIONS.iter().any(|(f, _)| *f == func) {
+            Some(VarKind::Nat)
+        } else {
+            self.kinds.get(&sanitize_name(func)).copied()
+        };
+        // Bitvector- and `Fin`-valued functions have no integer encoding here.
+        if kind.is_some_and(|k| k.bv_width().is_some() || matches!(k, VarKind::Fin(_))) {
+            return None;
+        }
+        let real = kind.map_or(self.real, |k| k.is_real());
+        let mut rendered = Vec::new();
+        let mut vars = std::collections::BTreeSet::new();
+        for a in &args {
+            let r = self.joint_real(&[a])?;
+            rendered.push((linear_expr_to_smt_sexp_sorted(a, r), r));
+            vars.extend(a.coeffs.keys().cloned());
+        }
+        let key = std::iter::once(func.to_string())
+            .chain(rendered.iter().map(|(s, _)| s.to_string()))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let sym = self.fresh("app", &key, real);
+        if kind == Some(VarKind::Nat) {
+            self.add_side(t::ge(t::sym(sym.clone()), t::int_lit(0)));
+            self.nat_fresh.insert(sym.clone());
+        }
+        vars.insert(sym.clone());
+        let real_vars = vars.intersection(&self.real_vars).cloned().collect();
+        self.apps.push(UfApp {
+            func: func.to_string(),
+            args: rendered,
+            sym: sym.clone(),
+            real,
+            vars,
+            real_vars,
+        });
+        Some(LinearExpr::var(sym))
+    }
+
+    /// An application argument: a literal, a name, a cast, or a parenthesized term.
+    fn arg(&mut self) -> Option<LinearExpr> {
+        match self.next()? {
+            Tok::Num(v) => Some(LinearExpr::constant(v)),
+            Tok::Dec(num, den) => self.decimal(num, den),
+            Tok::Ident(x) if !Self::is_kw(&x) => self.ident(&x),
+            Tok::Sym("↑") => self.cast_arg(),
+            Tok::Sym("(") => {
+                let e = self.expr(0)?;
+                // Type ascription: `(e : ℤ)`, or `(e : ℝ)` in a real relation.
+                if self.eat_sym(":") {
+                    let ok = match self.next()? {
+                        Tok::Ident(ty) if self.real => {
+                            matches!(ty.as_str(), "ℝ" | "ℚ" | "Real" | "Rat")
+                        }
+                        Tok::Ident(ty) => matches!(ty.as_str(), "ℤ" | "ℕ" | "Int" | "Nat"),
+                        _ => false,
+                    };
+                    if !ok {
+                        return None;
+                    }
+                }
+                self.eat_sym(")").then_some(e)
+            }
+            _ => None,
+        }
+    }
+
+    fn decimal(&mut self, num: i64, den: i64) -> Option<LinearExpr> {
+        use smtkit::smt2::t;
+        if !self.real {
+            return None;
+        }
+        if num % den == 0 {
+            return Some(LinearExpr::constant(num / den));
+        }
+        let q = self.fresh("dec", &format!("{num} {den}"), true);
+        let den_q = LinearExpr::var(q.clone()).scale(den)?;
+        self.add_side(t::eq(
+            linear_expr_to_smt_sexp_sorted(&den_q, true),
+            real_lit(num),
+        ));
+        Some(LinearExpr::var(q))
+    }
+
+    /// The argument of a cast is read in an integer context (`↑n` with `n : ℕ` inside `ℝ`).
+    fn cast_arg(&mut self) -> Option<LinearExpr> {
+        // `↑x` of a bitvector `x` is `x.toNat`.
+        let bv = match self.peek() {
+            Some(Tok::Ident(x)) => self
+                .kinds
+                .get(&sanitize_name(x))
+                .and_then(|k| k.bv_width())
+                .map(|w| (x.clone(), w)),
+            _ => None,
+        };
+        if let Some((x, w)) = bv {
+            self.pos += 1;
+            let e = self.bv_nat(&x, w);
+            return self.cast(e);
+        }
+        let outer = (self.real, self.nat_sub, self.undeclared_nat);
+        (self.real, self.nat_sub, self.undeclared_nat) = (false, true, !outer.0);
+        let a = self.arg();
+        (self.real, self.nat_sub, self.undeclared_nat) = outer;
+        self.cast(a?)
+    }
+
+    fn cast(&mut self, e: LinearExpr) -> Option<LinearExpr> {
+        use smtkit::smt2::t;
+        let e = self.fin_val(e);
+        match (self.sort_of(&e)?, self.real) {
+            (TermSort::Const, _) => (e.c0 >= 0).then_some(e),
+            // `ℚ → ℝ`: nothing to encode.
+            (TermSort::Real, true) => Some(e),
+            (TermSort::Real, false) => None,
+            // The atoms of a `ℕ` term are non-negative. The term itself may have been built with
+            // (truncated) subtraction, which `nat_sub` already encodes.
+            (TermSort::Int, false) => {
+                let atoms: Vec<String> = e.coeffs.keys().cloned().collect();
+                for v in atoms {
+                    self.add_side(t::ge(t::sym(v), t::int_lit(0)));
+                }
+                Some(e)
+            }
+            (TermSort::Int, true) => {
+                let ei = linear_expr_to_smt_sexp(&e);
+                if self.is_nat(&e) {
+                    self.add_side(t::ge(ei.clone(), t::int_lit(0)));
+                }
+                let r = self.fresh("cast", &ei.to_string(), true);
+                self.add_side(t::eq(t::sym(r.clone()), t::app("to_real", vec![ei])));
+                Some(LinearExpr::var(r))
+            }
+        }
+    }
+
+    fn binary(&mut self, op: &str, a: LinearExpr, b: LinearExpr) -> Option<LinearExpr> {
+        let fin = match op {
+            "+" | "-" | "*" => self.fin_modulus(&[&a, &b]),
+            _ => None,
+        };
+        let e = match op {
+            // Literal-only differences are only known to be `ℕ` in a cast argument.
+            "-" if self.nat_sub
+                && !self.real
+                && self.is_nat(&a)
+                && self.is_nat(&b)
+                && (self.undeclared_nat
+                    || a.as_constant().is_none()
+                    || b.as_constant().is_none()) =>
+            {
+                self.nat_sub(&a, &b)
+            }
+            "+" | "-" => {
+                self.joint_real(&[&a, &b])?;
+                a.add_scaled(&b, if op == "+" { 1 } else { -1 })
+            }
+            "*" => match (a.as_constant(), b.as_constant()) {
+                (Some(k), _) => b.scale(k),
+                (_, Some(k)) => a.scale(k),
+                _ => None,
+            },
+            "/" | "%" => self.div_mod(op, a, b.as_constant()?),
+            "^" => {
+                let exp = u32::try_from(b.as_constant()?).ok()?;
+                match a.as_constant() {
+                    Some(base) => Some(LinearExpr::constant(base.checked_pow(exp)?)),
+                    None if exp == 1 => Some(a),
+                    None if exp == 0 => Some(LinearExpr::constant(1)),
+                    None => None,
+                }
+            }
+            _ => None,
+        }?;
+        match fin {
+            Some(n) => self.fin_wrap(e, n?),
+            None => Some(e),
+        }
+    }
+
+    fn div_mod(&mut self, op: &str, a: LinearExpr, k: i64) -> Option<LinearExpr> {
+        use smtkit::smt2::t;
+        let real = self.joint_real(&[&a])?;
+        // Lean: `e / 0 = 0` and `e % 0 = e`.
+        if k == 0 {
+            return Some(if op == "/" {
+                LinearExpr::constant(0)
+            } else {
+                a
+            });
+        }
+        if real {
+            if op == "%" {
+                return None;
+            }
+            if a.as_constant().is_some_and(|c| c % k == 0) {
+                return Some(LinearExpr::constant(a.c0 / k));
+            }
+            let e = linear_expr_to_smt_sexp_sorted(&a, true);
+            let q = self.fresh("div", &format!("{e} {k}"), true);
+            let kq = LinearExpr::var(q.clone()).scale(k)?;
+            self.add_side(t::eq(linear_expr_to_smt_sexp_sorted(&kq, true), e));
+            return Some(LinearExpr::var(q));
+        }
+        if let Some(c) = a.as_constant() {
+            let v = if op == "/" {
+                c.checked_div_euclid(k)?
+            } else {
+                c.checked_rem_euclid(k)?
+            };
+            return Some(LinearExpr::constant(v));
+        }
+        let e = linear_expr_to_smt_sexp(&a);
+        let q = self.fresh("div", &format!("{e} {k}"), false);
+        if k > 0 && self.is_nat(&a) {
+            self.nat_fresh.insert(q.clone());
+        }
+        let kq = LinearExpr::var(q.clone()).scale(k)?;
+        let kq_max = kq.add_scaled(&LinearExpr::constant(k.checked_abs()? - 1), 1)?;
+        self.add_side(t::le(linear_expr_to_smt_sexp(&kq), e.clone()));
+        self.add_side(t::le(e, linear_expr_to_smt_sexp(&kq_max)));
+        if op == "/" {
+            Some(LinearExpr::var(q))
+        } else {
+            a.add_scaled(&kq, -1)
+        }
+    }
+
+    fn min_max(&mut self, which: &str, a: LinearExpr, b: LinearExpr) -> Option<LinearExpr> {
+        use smtkit::smt2::t;
+        let real = self.joint_real(&[&a, &b])?;
+        if let (Some(x), Some(y)) = (a.as_constant(), b.as_constant()) {
+            return Some(LinearExpr::constant(if which == "min" {
+                x.min(y)
+            } else {
+                x.max(y)
+            }));
+        }
+        let sa = linear_expr_to_smt_sexp_sorted(&a, real);
+        let sb = linear_expr_to_smt_sexp_sorted(&b, real);
+        let m = self.fresh(which, &format!("{sa} {sb}"), real);
+        if self.is_nat(&a) && self.is_nat(&b) {
+            self.nat_fresh.insert(m.clone());
+        }
+        let sm = t::sym(m.clone());
+        if which == "min" {
+            self.add_side(t::le(sm.clone(), sa.clone()));
+            self.add_side(t::le(sm.clone(), sb.clone()));
+        } else {
+            self.add_side(t::ge(sm.clone(), sa.clone()));
+            self.add_side(t::ge(sm.clone(), sb.clone()));
+        }
+        self.add_side(t::app("or", vec![t::eq(sm.clone(), sa), t::eq(sm, sb)]));
+        Some(LinearExpr::var(m))
+    }
+
+    /// `if c then a else b` (also `if h : c then ...`); extends as far right as possible.
+    fn ite(&mut self) -> Option<LinearExpr> {
+        use smtkit::smt2::t;
+        self.eat_kw("if");
+        if matches!(
+            (self.toks.get(self.pos), self.toks.get(self.pos + 1)),
+            (Some(Tok::Ident(_)), Some(Tok::Sym(":")))
+        ) {
+            self.pos += 2;
+        }
+        let c = self.cond()?;
+        if !self.eat_kw("then") {
+            return None;
+        }
+        let a = self.expr(0)?;
+        if !self.eat_kw("else") {
+            return None;
+        }
+        let b = self.expr(0)?;
+        let real = self.joint_real(&[&a, &b])?;
+        let sa = linear_expr_to_smt_sexp_sorted(&a, real);
+        let sb = linear_expr_to_smt_sexp_sorted(&b, real);
+        let v = self.fresh("ite", &format!("{c} {sa} {sb}"), real);
+        if self.is_nat(&a) && self.is_nat(&b) {
+            self.nat_fresh.insert(v.clone());
+        }
+        let sv = t::sym(v.clone());
+        self.add_side(t::app("ite", vec![c, t::eq(sv.clone(), sa), t::eq(sv, sb)]));
+        Some(LinearExpr::var(v))
+    }
+
+    /// Boolean condition of an `if`: relations under `¬`, `∧`, `∨` and parentheses.
+    fn cond(&mut self) -> Option<smtkit::sexp::Sexp> {
+        let mut xs = vec![self.conj()?];
+        while self.eat_sym("∨") {
+            xs.push(self.conj()?);
+        }
+        Some(if xs.len() == 1 {
+            xs.remove(0)
+        } else {
+            smtkit::smt2::t::app("or", xs)
+        })
+    }
+
+    fn conj(&mut self) -> Option<smtkit::sexp::Sexp> {
+        let mut xs = vec![self.cond_lit()?];
+        while self.eat_sym("∧") {
+            xs.push(self.cond_lit()?);
+        }
+        Some(if xs.len() == 1 {
+            xs.remove(0)
+        } else {
+            smtkit::smt2::t::app("and", xs)
+        })
+    }
+
+    fn cond_lit(&mut self) -> Option<smtkit::sexp::Sexp> {
+        if self.eat_sym("¬") {
+            return Some(smtkit::smt2::t::not(self.cond_lit()?));
+        }
+        // `(a + 1) ≤ b` and `(a ≤ b ∧ c)` both start with `(`: try a relation first.
+        let saved = (
+            self.pos,
+            self.side.len(),
+            self.vars.clone(),
+            self.real_vars.clone(),
+        );
+        if let Some((op, a, b)) = self.rel() {
+            return self.rel_sexp(op, &a, &b);
+        }
+        (self.pos, self.vars, self.real_vars) = (saved.0, saved.2, saved.3);
+        self.side.truncate(saved.1);
+        if !self.eat_sym("(") {
+            return None;
+        }
+        let c = self.cond()?;
+        self.eat_sym(")").then_some(c)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ParsedRelConstraint {
+    rel: ParsedRel,
+    sexp: smtkit::sexp::Sexp,
+    vars: std::collections::BTreeSet<String>,
+    src: String,
+    /// Definitions of the fresh variables and cast side conditions (see `ArithParser`).
+    side: Vec<smtkit::sexp::Sexp>,
+    /// The subset of `vars` with sort `Real`.
+    real_vars: std::collections::BTreeSet<String>,
+    /// Opaque applications, for congruence across the fragment.
+    apps: Vec<UfApp>,
+}
+
+fn select_constraints_by_var_depth(
+    target_vars: &std::collections::BTreeSet<String>,
+    hyps: &[ParsedRelConstraint],
+    depth: usize,
+) -> Vec<ParsedRelConstraint> {
+    // Depth semantics (deterministic, bounded):
+    // - depth == 0: return all hyps (status quo behavior)
+    // - depth == 1: include only hyps mentioning a target var
+    // - depth == 2+: expand by variable connectivity up to `depth` passes
+    //
+    // This is a performance/robustness knob, not a soundness boundary: SMT here is only a heuristic signal.
+    if depth == 0 {
+        return hyps.to_vec();
+    }
+    if target_vars.is_empty() || hyps.is_empty() {
+        return Vec::new();
+    }
+
+    let mut included_vars: std::collections::BTreeSet<String> = target_vars.clone();
+    let mut picked: Vec<bool> = vec![false; hyps.len()];
+
+    // One pass per "hop" from the target vars.
+    for _ in 0..depth {
+        // Snapshot the current frontier so we don't chain within one hop.
+        // (We only expand `included_vars` *after* finishing the pass.)
+        let frontier = included_vars.clone();
+        let mut pending_vars: std::collections::BTreeSet<String> =
+            std::collections::BTreeSet::new();
+        let mut changed = false;
+        for (i, h) in hyps.iter().enumerate() {
+            if picked[i] {
+                continue;
+            }
+            let touches = h.vars.iter().any(|v| frontier.contains(v));
+            if touches {
+                picked[i] = true;
+                pending_vars.extend(h.vars.iter().cloned());
+                changed = true;
+            }
+        }
+        included_vars.extend(pending_vars);
+        if !changed {
+            break;
+        }
+    }
+
+    let mut out: Vec<ParsedRelConstraint> = Vec::new();
+    for (i, h) in hyps.iter().enumerate() {
+        if picked[i] {
+            out.push(h.clone());
+        }
+    }
+    out
+}
+
+fn linear_expr_to_smt_sexp(e: &LinearExpr) -> smtkit::sexp::Sexp {
+    linear_expr_to_smt_sexp_sorted(e, false)
+}
+
+fn real_lit(c: i64) -> smtkit::sexp::Sexp {
+    let abs = smtkit::sexp::Sexp::atom(format!("{}.0", c.unsigned_abs()));
+    if c < 0 {
+        smtkit::smt2::t::app("-", vec![abs])
+    } else {
+        abs
+    }
+}
+
+/// Render with integer literals, or with decimal literals when `real`.
+fn linear_expr_to_smt_sexp_sorted(e: &LinearExpr, real: bool) -> smtkit::sexp::Sexp {
+    use smtkit::smt2::t;
+    let lit = |c: i64| if real { real_lit(c) } else { t::int_lit(c) };
+    let mut terms: Vec<smtkit::sexp::Sexp> = Vec::new();
+    if e.c0 != 0 {
+        terms.push(lit(e.c0));
+    }
+    for (v, c) in e.coeffs.iter() {
+        if *c == 0 {
+            continue;
         }
         let sym = t::sym(v.clone());
         if *c == 1 {
@@ -547,11 +2003,11 @@ fn linear_expr_to_smt_sexp(e: &LinearExpr) -> smtkit::sexp::Sexp {
         } else if *c == -1 {
             terms.push(t::app("-", vec![sym]));
         } else {
-            terms.push(t::app("*", vec![t::int_lit(*c), sym]));
+            terms.push(t::app("*", vec![lit(*c), sym]));
         }
     }
     if terms.is_empty() {
-        t::int_lit(0)
+        lit(0)
     } else if terms.len() == 1 {
         terms[0].clone()
     } else {
@@ -559,70 +2015,538 @@ fn linear_expr_to_smt_sexp(e: &LinearExpr) -> smtkit::sexp::Sexp {
     }
 }
 
-fn normalize_expr_text(s: &str) -> String {
-    // Try to normalize a few common pretty-printed shapes into linear syntax.
-    // This is intentionally small; if it doesn't match, we fall back to "unknown".
-    let mut out = s.to_string();
-    // Nat.succ x  -> x + 1
-    if let Ok(re1) = Regex::new(r"\bNat\.succ\s+\(?([A-Za-z0-9_\.]+)\)?") {
-        out = re1.replace_all(&out, "$1 + 1").to_string();
+fn parse_rel_constraint(
+    s: &str,
+    kinds: &std::collections::BTreeMap<String, VarKind>,
+) -> Option<ParsedRelConstraint> {
+    let s = s.trim();
+    let src = s.to_string();
+    let mut p = ArithParser::new(s, kinds)?;
+    let (op, lhs_e, rhs_e) = p.rel()?;
+    if !p.at_end() || p.bad {
+        return None;
+    }
+    let rel_op = match op {
+        "≤" => RelOp::Le,
+        "≥" => RelOp::Ge,
+        "<" => RelOp::Lt,
+        ">" => RelOp::Gt,
+        "=" => RelOp::Eq,
+        _ => return None,
+    };
+    let sexp = p.rel_sexp(op, &lhs_e, &rhs_e)?;
+    let real_vars = p.real_vars.intersection(&p.vars).cloned().collect();
+    Some(ParsedRelConstraint {
+        rel: ParsedRel {
+            op: rel_op,
+            lhs: lhs_e,
+            rhs: rhs_e,
+        },
+        sexp,
+        vars: p.vars,
+        src,
+        side: p.side,
+        real_vars,
+        apps: p.apps,
+    })
+}
+
+/// A bitvector term, with the literal it is when it is one (before reduction modulo `2^w`).
+type BvTerm = (smtkit::sexp::Sexp, Option<u128>);
+
+/// Parser for relations over `UInt8` … `UInt64` and `BitVec w` (literal `w`), rendered as
+/// `QF_BV` terms.
+///
+/// All terms of a relation have one width, taken from its bitvector variables, a `5#32` literal
+/// or an ascription like `(e : UInt32)`. Operators keep Lean's semantics:
+/// - `+ - *` and unary `-` wrap around (`bvadd`, `bvsub`, `bvmul`, `bvneg`)
+/// - `/` is `bvudiv` except that `x / 0 = 0`; `%` is `bvurem`
+/// - `&&&`, `|||`, `^^^`, `~~~` are `bvand`, `bvor`, `bvxor`, `bvnot`
+/// - `<<<`, `>>>` are `bvshl`, `bvlshr`; `UInt` shift amounts are taken modulo `w`
+/// - `≤ < ≥ >` are unsigned comparisons
+///
+/// Anything else (integer variables, `x.toNat`, applications) makes the parse fail.
+struct BvParser<'a> {
+    toks: Vec<Tok>,
+    pos: usize,
+    kinds: &'a std::collections::BTreeMap<String, VarKind>,
+    /// `UInt(w)` or `BitVec(w)`.
+    kind: VarKind,
+    vars: std::collections::BTreeSet<String>,
+}
+
+impl<'a> BvParser<'a> {
+    fn new(s: &str, kinds: &'a std::collections::BTreeMap<String, VarKind>) -> Option<Self> {
+        let toks = tokenize_arith(s)?;
+        let mut kind = None;
+        for (i, tok) in toks.iter().enumerate() {
+            let k = match (tok, toks.get(i + 1)) {
+                (Tok::Ident(ty), Some(Tok::Num(w))) if ty == "BitVec" => {
+                    Some(VarKind::BitVec(u32::try_from(*w).ok()?))
+                }
+                (Tok::Sym("#"), Some(Tok::Num(w))) => {
+                    Some(VarKind::BitVec(u32::try_from(*w).ok()?))
+                }
+                (Tok::Ident(x), _) => extract_decl_kind(&format!("_ : {x}"))
+                    .map(|(_, k)| k)
+                    .or_else(|| kinds.get(&sanitize_name(x)).copied())
+                    .filter(|k| k.bv_width().is_some()),
+                _ => None,
+            };
+            match (kind, k) {
+                (_, None) => {}
+                (None, k) => kind = k,
+                (Some(a), Some(b)) if a == b => {}
+                _ => return None,
+            }
+        }
+        let kind = kind.filter(|k| (1..=MAX_BV_WIDTH).contains(&k.bv_width().unwrap_or(0)))?;
+        Some(BvParser {
+            toks,
+            pos: 0,
+            kinds,
+            kind,
+            vars: std::collections::BTreeSet::new(),
+        })
+    }
+
+    fn width(&self) -> u32 {
+        self.kind.bv_width().unwrap_or(0)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let t = self.toks.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn eat_sym(&mut self, s: &str) -> bool {
+        if matches!(self.toks.get(self.pos), Some(Tok::Sym(x)) if *x == s) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.toks.len()
+    }
+
+    /// `(_ bvN w)` for `v` modulo `2^w`.
+    fn lit(&self, v: u128) -> smtkit::sexp::Sexp {
+        use smtkit::sexp::Sexp;
+        let w = self.width();
+        let v = if w < 128 { v % (1u128 << w) } else { v };
+        smtkit::smt2::t::app(
+            "_",
+            vec![Sexp::atom(format!("bv{v}")), Sexp::atom(w.to_string())],
+        )
+    }
+
+    /// `lhs op rhs` with `op` one of `≤ ≥ < > = ≠`.
+    fn rel(&mut self) -> Option<smtkit::sexp::Sexp> {
+        use smtkit::smt2::t;
+        let (a, _) = self.expr(0)?;
+        let op = match self.next()? {
+            Tok::Sym(s @ ("≤" | "≥" | "<" | ">" | "=" | "≠")) => s,
+            _ => return None,
+        };
+        let (b, _) = self.expr(0)?;
+        Some(match op {
+            "≤" => t::app("bvule", vec![a, b]),
+            "≥" => t::app("bvuge", vec![a, b]),
+            "<" => t::app("bvult", vec![a, b]),
+            ">" => t::app("bvugt", vec![a, b]),
+            "=" => t::eq(a, b),
+            _ => t::not(t::eq(a, b)),
+        })
+    }
+
+    fn expr(&mut self, min_prec: u8) -> Option<BvTerm> {
+        let mut lhs = self.unary()?;
+        loop {
+            let (op, prec) = match self.toks.get(self.pos) {
+                Some(Tok::Sym(s @ "|||")) => (*s, 55),
+                Some(Tok::Sym(s @ "^^^")) => (*s, 58),
+                Some(Tok::Sym(s @ "&&&")) => (*s, 60),
+                Some(Tok::Sym(s @ ("+" | "-"))) => (*s, 65),
+                Some(Tok::Sym(s @ ("*" | "/" | "%"))) => (*s, 70),
+                Some(Tok::Sym(s @ ("<<<" | ">>>"))) => (*s, 75),
+                _ => break,
+            };
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expr(prec + 1)?;
+            lhs = (self.binary(op, lhs.0, rhs), None);
+        }
+        Some(lhs)
+    }
+
+    fn unary(&mut self) -> Option<BvTerm> {
+        use smtkit::smt2::t;
+        if self.eat_sym("-") {
+            let (e, _) = self.expr(75)?;
+            return Some((t::app("bvneg", vec![e]), None));
+        }
+        if self.eat_sym("~~~") {
+            let (e, _) = self.unary()?;
+            return Some((t::app("bvnot", vec![e]), None));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Option<BvTerm> {
+        match self.next()? {
+            Tok::Num(v) => {
+                let v = u128::try_from(v).ok()?;
+                // `5#32`: the width was taken into account by `new`.
+                if self.eat_sym("#") {
+                    self.next()?;
+                }
+                Some((self.lit(v), Some(v)))
+            }
+            Tok::Ident(x) => {
+                let name = sanitize_name(&x);
+                if self.kinds.get(&name) != Some(&self.kind) {
+                    return None;
+                }
+                self.vars.insert(name.clone());
+                Some((smtkit::smt2::t::sym(name), None))
+            }
+            Tok::Sym("(") => {
+                let e = self.expr(0)?;
+                // Type ascription: `(e : UInt32)`, `(e : BitVec 32)`.
+                if self.eat_sym(":") {
+                    let ty = match (self.next()?, self.toks.get(self.pos)) {
+                        (Tok::Ident(ty), Some(Tok::Num(w))) if ty == "BitVec" => {
+                            let ty = format!("BitVec {w}");
+                            self.pos += 1;
+                            ty
+                        }
+                        (Tok::Ident(ty), _) => ty,
+                        _ => return None,
+                    };
+                    let k = extract_decl_kind(&format!("_ : {ty}")).map(|(_, k)| k);
+                    if k != Some(self.kind) {
+                        return None;
+                    }
+                }
+                self.eat_sym(")").then_some(e)
+            }
+            _ => None,
+        }
+    }
+
+    fn binary(&self, op: &str, a: smtkit::sexp::Sexp, b: BvTerm) -> smtkit::sexp::Sexp {
+        use smtkit::smt2::t;
+        let w = self.width();
+        let (b, b_lit) = b;
+        let f = match op {
+            "+" => "bvadd",
+            "-" => "bvsub",
+            "*" => "bvmul",
+            "%" => "bvurem",
+            "&&&" => "bvand",
+            "|||" => "bvor",
+            "^^^" => "bvxor",
+            "/" => {
+                let zero = self.lit(0);
+                return t::app(
+                    "ite",
+                    vec![
+                        t::eq(b.clone(), zero.clone()),
+                        zero,
+                        t::app("bvudiv", vec![a, b]),
+                    ],
+                );
+            }
+            _ => {
+                let f = if op == "<<<" { "bvshl" } else { "bvlshr" };
+                let uint = matches!(self.kind, VarKind::UInt(_));
+                let amount = match b_lit {
+                    // A literal amount of `w` or more clears a `BitVec`.
+                    Some(k) if uint => self.lit(k % u128::from(w)),
+                    Some(k) => self.lit(k.min(u128::from(w))),
+                    None if uint => t::app("bvurem", vec![b, self.lit(u128::from(w))]),
+                    None => b,
+                };
+                return t::app(f, vec![a, amount]);
+            }
+        };
+        t::app(f, vec![a, b])
+    }
+}
+
+/// A relation over bitvector variables (see `BvParser`); `None` if `s` is not one.
+fn parse_bv_constraint(
+    s: &str,
+    kinds: &std::collections::BTreeMap<String, VarKind>,
+) -> Option<ParsedRelConstraint> {
+    let s = s.trim();
+    let mut p = BvParser::new(s, kinds)?;
+    let sexp = p.rel()?;
+    if !p.at_end() {
+        return None;
+    }
+    Some(ParsedRelConstraint {
+        rel: ParsedRel {
+            op: RelOp::Bv,
+            lhs: LinearExpr::constant(0),
+            rhs: LinearExpr::constant(0),
+        },
+        sexp,
+        vars: p.vars,
+        src: s.to_string(),
+        side: Vec::new(),
+        real_vars: std::collections::BTreeSet::new(),
+        apps: Vec::new(),
+    })
+}
+
+/// Congruence for the opaque applications of a fragment (Ackermann's reduction of EUF): two
+/// applications of the same function with equal arguments are equal. Each axiom goes into the
+/// side constraints of the later relation, which also takes on the earlier application's
+/// variables so they are declared (and selected by depth) wherever the axiom is asserted.
+fn add_congruence(rels: &mut [&mut ParsedRelConstraint]) {
+    use smtkit::smt2::t;
+    let mut seen: Vec<UfApp> = Vec::new();
+    for rel in rels.iter_mut() {
+        for app in rel.apps.clone() {
+            for prev in &seen {
+                let sorts_match = prev.real == app.real
+                    && prev.args.len() == app.args.len()
+                    && prev.args.iter().zip(&app.args).all(|(a, b)| a.1 == b.1);
+                if prev.func != app.func || prev.sym == app.sym || !sorts_match {
+                    continue;
+                }
+                let eqs: Vec<smtkit::sexp::Sexp> = prev
+                    .args
+                    .iter()
+                    .zip(&app.args)
+                    .map(|(a, b)| t::eq(a.0.clone(), b.0.clone()))
+                    .collect();
+                let premise = match eqs.len() {
+                    1 => eqs[0].clone(),
+                    _ => t::app("and", eqs),
+                };
+                let axiom = t::app(
+                    "=>",
+                    vec![
+                        premise,
+                        t::eq(t::sym(prev.sym.clone()), t::sym(app.sym.clone())),
+                    ],
+                );
+                let key = axiom.to_string();
+                if !rel.side.iter().any(|x| x.to_string() == key) {
+                    rel.side.push(axiom);
+                }
+                rel.vars.extend(prev.vars.iter().cloned());
+                rel.real_vars.extend(prev.real_vars.iter().cloned());
+            }
+            if !seen.iter().any(|p| p.sym == app.sym) {
+                seen.push(app);
+            }
+        }
+    }
+}
+
+/// Definitional constraints of a fragment, deduplicated. Always asserted, never negated.
+fn side_constraints<'a>(
+    cs: impl IntoIterator<Item = &'a ParsedRelConstraint>,
+) -> Vec<smtkit::sexp::Sexp> {
+    let mut seen: std::collections::BTreeSet<String> = std::collections::BTreeSet::new();
+    let mut out = Vec::new();
+    for c in cs {
+        for s in &c.side {
+            if seen.insert(s.to_string()) {
+                out.push(s.clone());
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn sanitize_smt_sym(s: &str) -> Option<String> {
+    let raw = s.trim();
+    if raw.is_empty() {
+        return None;
+    }
+    let mut out = String::new();
+    for ch in raw.chars() {
+        if ch.is_ascii_alphanumeric() || ch == '_' {
+            out.push(ch);
+        } else {
+            out.push('_');
+        }
+    }
+    if out.is_empty() {
+        return None;
+    }
+    if out
+        .chars()
+        .next()
+        .map(|c| c.is_ascii_digit())
+        .unwrap_or(false)
+    {
+        out.insert_str(0, "h_");
+    }
+    Some(out)
+}
+
+/// The arithmetic fragment of one `pp_dump` goal.
+struct GoalFragment {
+    target: ParsedRelConstraint,
+    /// Parseable hypotheses with a name hint taken from `h : ...`.
+    hyps: Vec<(ParsedRelConstraint, Option<String>)>,
+    /// Kinds from declarations (`n : ℕ`, `x : ℝ`, ...), plus `Real` for real-sorted variables
+    /// without one. Callers default the remaining variables to `Int`.
+    var_kinds: std::collections::BTreeMap<String, VarKind>,
+}
+
+fn goal_fragment(goal: &Value) -> Option<GoalFragment> {
+    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
+    let target = pretty
+        .lines()
+        .find_map(|ln| {
+            ln.trim_start()
+                .strip_prefix("⊢")
+                .map(|r| r.trim().to_string())
+        })
+        .unwrap_or_default();
+    if target.is_empty() {
+        return None;
     }
-    // Int.succ x -> x + 1
-    if let Ok(re2) = Regex::new(r"\bInt\.succ\s+\(?([A-Za-z0-9_\.]+)\)?") {
-        out = re2.replace_all(&out, "$1 + 1").to_string();
+    let hyp_texts: Vec<&str> = goal
+        .get("hyps")
+        .and_then(|v| v.as_array())
+        .map(|hs| {
+            hs.iter()
+                .filter_map(|h| h.get("text").and_then(|v| v.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut var_kinds: std::collections::BTreeMap<String, VarKind> =
+        std::collections::BTreeMap::new();
+    for txt in &hyp_texts {
+        if let Some((names, kind)) = extract_decl_kind(txt) {
+            for name in names {
+                var_kinds.insert(name, kind);
+            }
+        }
     }
-    // Drop parentheses which often wrap pretty-printed terms.
-    out = out
-        .chars()
-        .map(|c| if c == '(' || c == ')' { ' ' } else { c })
+    // Fixed-width variables without an encoding (`BitVec n`, `USize`) must not pass for
+    // unbounded integers: relations mentioning them are left out.
+    let opaque: std::collections::BTreeSet<String> = hyp_texts
+        .iter()
+        .filter(|txt| extract_decl_kind(txt).is_none())
+        .filter_map(|txt| txt.split_once(':'))
+        .filter(|(_, ty)| {
+            let ty = ty.trim();
+            ty == "USize" || ty.starts_with("BitVec ")
+        })
+        .flat_map(|(names, _)| names.split_whitespace().map(sanitize_name))
         .collect();
-    out
-}
-
-fn parse_rel_constraint_int(s: &str) -> Option<ParsedRelConstraint> {
-    let s = s.trim();
-    let src = s.to_string();
-    let ops = ["<=", "≤", ">=", "≥", "<", ">", "="];
-    let (op, idx) = ops.iter().find_map(|op| s.find(op).map(|i| (*op, i)))?;
-    let (lhs, rhs0) = s.split_at(idx);
-    let rhs = rhs0.get(op.len()..)?;
-    let lhs_n = normalize_expr_text(lhs.trim());
-    let rhs_n = normalize_expr_text(rhs.trim());
-    let lhs_e = parse_linear_expr_int(lhs_n.trim())?;
-    let rhs_e = parse_linear_expr_int(rhs_n.trim())?;
-    let rel_op = match op {
-        "<=" | "≤" => RelOp::Le,
-        ">=" | "≥" => RelOp::Ge,
-        "<" => RelOp::Lt,
-        ">" => RelOp::Gt,
-        "=" => RelOp::Eq,
-        _ => return None,
+    // A bitvector target makes a `QF_BV` fragment of the bitvector hypotheses; otherwise
+    // only integer and real relations are read.
+    let bv_target = parse_bv_constraint(&target, &var_kinds);
+    let bv = bv_target.is_some();
+    let parse = |s: &str| {
+        let c = if bv {
+            parse_bv_constraint(s, &var_kinds)
+        } else {
+            parse_rel_constraint(s, &var_kinds)
+        }?;
+        c.vars.is_disjoint(&opaque).then_some(c)
     };
-    use smtkit::smt2::t;
-    let a = linear_expr_to_smt_sexp(&lhs_e);
-    let b = linear_expr_to_smt_sexp(&rhs_e);
-    let sexp = match rel_op {
-        RelOp::Le => t::le(a, b),
-        RelOp::Ge => t::ge(a, b),
-        RelOp::Lt => t::lt(a, b),
-        RelOp::Gt => t::app(">", vec![a, b]),
-        RelOp::Eq => t::eq(a, b),
+    let target = match bv_target {
+        Some(t) => t,
+        None => parse(&target)?,
     };
-    let mut vars: std::collections::BTreeSet<String> = std::collections::BTreeSet::new();
-    vars.extend(lhs_e.coeffs.keys().cloned());
-    vars.extend(rhs_e.coeffs.keys().cloned());
-    Some(ParsedRelConstraint {
-        rel: ParsedRel {
-            op: rel_op,
-            lhs: lhs_e,
-            rhs: rhs_e,
-        },
-        sexp,
-        vars,
-        src,
+
+    // Each variable gets one sort: the target decides first, then hypotheses in order; a
+    // hypothesis that disagrees (an undeclared name used as both `ℤ` and `ℝ`) is dropped.
+    let mut real = target.real_vars.clone();
+    let mut int: std::collections::BTreeSet<String> =
+        target.vars.difference(&target.real_vars).cloned().collect();
+    let mut hyps: Vec<(ParsedRelConstraint, Option<String>)> = Vec::new();
+    // `i : Fin n` with a symbolic `n` contributes `↑i < n`.
+    let fin_facts: Vec<String> = hyp_texts
+        .iter()
+        .filter_map(|txt| fin_bound_fact(txt))
+        .flatten()
+        .collect();
+    for txt in hyp_texts
+        .iter()
+        .copied()
+        .chain(fin_facts.iter().map(String::as_str))
+    {
+        let (name_hint, rhs) = if let Some((lhs, r)) = txt.split_once(':') {
+            let nm = lhs.split_whitespace().next().and_then(sanitize_smt_sym);
+            (nm, r.trim())
+        } else {
+            (None, txt.trim())
+        };
+        if rhs.is_empty() {
+            continue;
+        }
+        let Some(h) = parse(rhs) else {
+            continue;
+        };
+        let h_int: std::collections::BTreeSet<String> =
+            h.vars.difference(&h.real_vars).cloned().collect();
+        if !h.real_vars.is_disjoint(&int) || !h_int.is_disjoint(&real) {
+            continue;
+        }
+        real.extend(h.real_vars.iter().cloned());
+        int.extend(h_int);
+        hyps.push((h, name_hint));
+    }
+    for v in real {
+        var_kinds.entry(v).or_insert(VarKind::Real);
+    }
+    let mut target = target;
+    let mut rels: Vec<&mut ParsedRelConstraint> = std::iter::once(&mut target)
+        .chain(hyps.iter_mut().map(|(h, _)| h))
+        .collect();
+    add_congruence(&mut rels);
+    Some(GoalFragment {
+        target,
+        hyps,
+        var_kinds,
     })
 }
 
+/// The arithmetic reading of a goal, for `hyp_rank`: the hypotheses (by type text, the part
+/// after `h :`) that parse as relations, and those a difference-logic certificate for the target
+/// uses. `None` when the target is not an encodable relation. No solver is involved.
+pub(crate) fn arith_hyp_srcs(goal: &Value) -> Option<(Vec<String>, Vec<String>)> {
+    let GoalFragment {
+        target,
+        hyps,
+        mut var_kinds,
+    } = goal_fragment(goal)?;
+    let rels: Vec<ParsedRelConstraint> = hyps.into_iter().map(|(h, _)| h).collect();
+    let mut used_vars = target.vars.clone();
+    for h in &rels {
+        used_vars.extend(h.vars.iter().cloned());
+    }
+    for v in used_vars {
+        var_kinds.entry(v).or_insert(VarKind::Int);
+    }
+    let core = idl_certificate(&target, &rels, &var_kinds)
+        .map(|cert| {
+            cert.hyps()
+                .into_iter()
+                .map(|i| rels[i].src.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+    Some((rels.into_iter().map(|h| h.src).collect(), core))
+}
+
 fn entails_by_offset_addition(target: &ParsedRel, hyps: &[ParsedRelConstraint]) -> Option<bool> {
     if target.op != RelOp::Le {
         return None;
@@ -744,11 +2668,12 @@ fn linear_sub(a: &LinearExpr, b: &LinearExpr) -> LinearExpr {
     }
 }
 
-fn idl_edges_from_rel(r: &ParsedRel) -> Option<Vec<IdlEdge>> {
+fn idl_edges_from_rel(r: &ParsedRel, int: bool) -> Option<Vec<IdlEdge>> {
     // Extract difference-logic constraints from a relation when possible.
     //
     // We only use these edges to *prove entailment* (never to prove non-entailment),
-    // so it is safe to ignore constraints we cannot encode.
+    // so it is safe to ignore constraints we cannot encode. Strict relations are only
+    // encodable over the integers (`int`), where `a < b` is `a - b + 1 <= 0`.
     match r.op {
         RelOp::Le => {
             // lhs - rhs <= 0  ==>  sum_i coeff_i*v_i + c0 <= 0
@@ -777,7 +2702,17 @@ fn idl_edges_from_rel(r: &ParsedRel) -> Option<Vec<IdlEdge>> {
             }
             Some(out)
         }
-        RelOp::Lt | RelOp::Gt => None,
+        RelOp::Lt if int => {
+            let mut e = linear_sub(&r.lhs, &r.rhs);
+            e.c0 = e.c0.checked_add(1)?;
+            idl_edges_from_linear_leq0(&e)
+        }
+        RelOp::Gt if int => {
+            let mut e = linear_sub(&r.rhs, &r.lhs);
+            e.c0 = e.c0.checked_add(1)?;
+            idl_edges_from_linear_leq0(&e)
+        }
+        RelOp::Lt | RelOp::Gt | RelOp::Bv => None,
     }
 }
 
@@ -839,6 +2774,75 @@ fn idl_edges_from_linear_leq0(e: &LinearExpr) -> Option<Vec<IdlEdge>> {
     None
 }
 
+/// One edge of an IDL certificate and what justifies it: hypothesis `hyp` (an index into the
+/// hypotheses given to `idl_certificate`), or `to : ℕ` (`0 ≤ to`) when `hyp` is `None`.
+#[derive(Debug, Clone)]
+struct IdlStep {
+    edge: IdlEdge,
+    hyp: Option<usize>,
+}
+
+/// Why the difference-logic check succeeded.
+#[derive(Debug, Clone)]
+enum IdlCertificate {
+    /// The hypotheses (indices) with IDL edges admit a negative cycle, so they are contradictory.
+    Contradiction(Vec<usize>),
+    /// For each target edge, a path `from → … → to` whose total weight is at most the edge's.
+    Paths(Vec<(IdlEdge, Vec<IdlStep>)>),
+}
+
+impl IdlCertificate {
+    /// Indices of the hypotheses the certificate uses, sorted and deduplicated.
+    fn hyps(&self) -> Vec<usize> {
+        let mut out: Vec<usize> = match self {
+            IdlCertificate::Contradiction(xs) => xs.clone(),
+            IdlCertificate::Paths(ps) => ps
+                .iter()
+                .flat_map(|(_, steps)| steps.iter().filter_map(|s| s.hyp))
+                .collect(),
+        };
+        out.sort_unstable();
+        out.dedup();
+        out
+    }
+}
+
+/// Decide `hyps ⊨ target` with the in-process procedure (`lia_solver`), on exactly the
+/// assertions an external solver would get. `None` when it cannot decide within `timeout_ms`.
+fn builtin_entails(
+    target: &ParsedRelConstraint,
+    hyps: &[ParsedRelConstraint],
+    var_kinds: &std::collections::BTreeMap<String, VarKind>,
+    timeout_ms: u64,
+) -> Option<bool> {
+    use smtkit::smt2::t;
+    // The procedure decides linear integer/real arithmetic only.
+    if hyps.iter().chain([target]).any(|c| c.rel.op == RelOp::Bv) {
+        return None;
+    }
+    let mut ints: std::collections::BTreeSet<String> = std::collections::BTreeSet::new();
+    let mut asserts: Vec<smtkit::sexp::Sexp> = Vec::new();
+    for c in hyps.iter().chain([target]) {
+        for v in c.vars.difference(&c.real_vars) {
+            if !var_kinds.get(v).is_some_and(|k| k.is_real())
+                && ints.insert(v.clone())
+                && var_kinds.get(v) == Some(&VarKind::Nat)
+            {
+                asserts.push(t::ge(t::sym(v.clone()), t::int_lit(0)));
+            }
+        }
+    }
+    asserts.extend(hyps.iter().map(|h| h.sexp.clone()));
+    asserts.extend(side_constraints(hyps.iter().chain([target])));
+    asserts.push(t::not(target.sexp.clone()));
+    let deadline = std::time::Instant::now() + std::time::Duration::from_millis(timeout_ms);
+    match crate::lia_solver::check_sat(&asserts, &ints, Some(deadline)) {
+        smtkit::session::Status::Unsat => Some(true),
+        smtkit::session::Status::Sat => Some(false),
+        smtkit::session::Status::Unknown => None,
+    }
+}
+
 fn idl_proves_entails(
     target: &ParsedRelConstraint,
     hyps: &[ParsedRelConstraint],
@@ -846,7 +2850,15 @@ fn idl_proves_entails(
 ) -> Option<bool> {
     // Only ever returns Some(true) (proved) or None (unknown).
     // We do not attempt to prove non-entailment with IDL because the encoding is partial.
-    let target_edges = idl_edges_from_rel(&target.rel)?;
+    idl_certificate(target, hyps, var_kinds).map(|_| true)
+}
+
+fn idl_certificate(
+    target: &ParsedRelConstraint,
+    hyps: &[ParsedRelConstraint],
+    var_kinds: &std::collections::BTreeMap<String, VarKind>,
+) -> Option<IdlCertificate> {
+    let target_edges = idl_edges_from_rel(&target.rel, target.real_vars.is_empty())?;
     if target_edges.is_empty() {
         // target is a pure constant relation; treat it as unknown.
         return None;
@@ -876,22 +2888,34 @@ fn idl_proves_entails(
         return None;
     }
 
-    let mut edges: Vec<(usize, usize, i64)> = Vec::new();
+    let mut edges: Vec<(usize, usize, IdlStep)> = Vec::new();
     // Nat nonneg constraints: x >= 0  -> edge x -> 0 with w=0 in our representation.
     for (name, kind) in var_kinds.iter() {
         if *kind == VarKind::Nat {
             if let (Some(&u), Some(&v0)) = (idx.get(name), idx.get("__ZERO__")) {
-                edges.push((u, v0, 0));
+                let edge = IdlEdge {
+                    from: name.clone(),
+                    to: "__ZERO__".to_string(),
+                    w: 0,
+                };
+                edges.push((u, v0, IdlStep { edge, hyp: None }));
             }
         }
     }
     // Hypothesis edges (best-effort; skip unencodable ones).
-    for h in hyps {
-        if let Some(es) = idl_edges_from_rel(&h.rel) {
+    for (i, h) in hyps.iter().enumerate() {
+        if let Some(es) = idl_edges_from_rel(&h.rel, h.real_vars.is_empty()) {
             for e in es {
                 let from = *idx.get(&e.from)?;
                 let to = *idx.get(&e.to)?;
-                edges.push((from, to, e.w));
+                edges.push((
+                    from,
+                    to,
+                    IdlStep {
+                        edge: e,
+                        hyp: Some(i),
+                    },
+                ));
             }
         }
     }
@@ -901,10 +2925,10 @@ fn idl_proves_entails(
     let mut dist = vec![0i64; n];
     for _ in 0..n {
         let mut changed = false;
-        for (u, v, w) in edges.iter().copied() {
-            let cand = dist[u].saturating_add(w);
-            if cand < dist[v] {
-                dist[v] = cand;
+        for (u, v, s) in edges.iter() {
+            let cand = dist[*u].saturating_add(s.edge.w);
+            if cand < dist[*v] {
+                dist[*v] = cand;
                 changed = true;
             }
         }
@@ -913,29 +2937,34 @@ fn idl_proves_entails(
         }
     }
     // One more pass: if any relaxes, negative cycle exists.
-    for (u, v, w) in edges.iter().copied() {
-        let cand = dist[u].saturating_add(w);
-        if cand < dist[v] {
-            return Some(true);
+    for (u, v, s) in edges.iter() {
+        let cand = dist[*u].saturating_add(s.edge.w);
+        if cand < dist[*v] {
+            let mut used: Vec<usize> = edges.iter().filter_map(|(_, _, s)| s.hyp).collect();
+            used.dedup();
+            return Some(IdlCertificate::Contradiction(used));
         }
     }
 
     // Prove each target edge y->x with bound w by computing shortest paths from y.
+    let mut paths = Vec::new();
     for te in target_edges {
         let src = *idx.get(&te.from)?;
         let dst = *idx.get(&te.to)?;
-        // Bellman-Ford from src.
+        // Bellman-Ford from src, remembering the last edge into each node.
         let mut d = vec![i64::MAX / 4; n];
+        let mut pred: Vec<Option<usize>> = vec![None; n];
         d[src] = 0;
         for _ in 0..n.saturating_sub(1) {
             let mut changed = false;
-            for (u, v, w) in edges.iter().copied() {
-                if d[u] >= i64::MAX / 8 {
+            for (ei, (u, v, s)) in edges.iter().enumerate() {
+                if d[*u] >= i64::MAX / 8 {
                     continue;
                 }
-                let cand = d[u].saturating_add(w);
-                if cand < d[v] {
-                    d[v] = cand;
+                let cand = d[*u].saturating_add(s.edge.w);
+                if cand < d[*v] {
+                    d[*v] = cand;
+                    pred[*v] = Some(ei);
                     changed = true;
                 }
             }
@@ -944,15 +2973,25 @@ fn idl_proves_entails(
             }
         }
         // If we have a shortest-path bound from te.from to te.to that is <= te.w, the target holds.
-        let bound = d[dst];
-        if bound <= te.w {
-            continue;
-        } else {
-            // Cannot prove this target edge from the partial encoding.
+        // Otherwise we cannot prove this target edge from the partial encoding.
+        if d[dst] > te.w {
             return None;
         }
+        // Walk the predecessor edges back to the source (there are no negative cycles here).
+        let mut steps = Vec::new();
+        let mut cur = dst;
+        while cur != src {
+            let (u, _, s) = &edges[pred[cur]?];
+            steps.push(s.clone());
+            cur = *u;
+            if steps.len() > n {
+                return None;
+            }
+        }
+        steps.reverse();
+        paths.push((te, steps));
     }
-    Some(true)
+    Some(IdlCertificate::Paths(paths))
 }
 
 /// Entailment check on a `pp_dump`-shaped JSON payload:
@@ -984,53 +3023,15 @@ pub fn entails_from_pp_dump_with_depth_reuse(
         .and_then(|a| a.first())
         .ok_or_else(|| "pp_dump missing goals[0]".to_string())?;
 
-    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
-    let target = pretty
-        .lines()
-        .find_map(|ln| {
-            ln.trim_start()
-                .strip_prefix("⊢")
-                .map(|r| r.trim().to_string())
-        })
-        .unwrap_or_default();
-    if target.is_empty() {
-        return Ok(None);
-    }
-
-    let mut var_kinds: std::collections::BTreeMap<String, VarKind> =
-        std::collections::BTreeMap::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                if let Some((name, kind)) = extract_decl_kind(txt) {
-                    var_kinds.insert(name, kind);
-                }
-            }
-        }
-    }
-
-    let target_rel = match parse_rel_constraint_int(&target) {
-        Some(r) => r,
+    let GoalFragment {
+        target: target_rel,
+        hyps,
+        mut var_kinds,
+    } = match goal_fragment(goal) {
+        Some(f) => f,
         None => return Ok(None),
     };
-
-    let mut hyp_rels: Vec<ParsedRelConstraint> = Vec::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                let rhs = txt
-                    .split_once(':')
-                    .map(|(_, r)| r.trim())
-                    .unwrap_or(txt.trim());
-                if rhs.is_empty() {
-                    continue;
-                }
-                if let Some(r) = parse_rel_constraint_int(rhs) {
-                    hyp_rels.push(r);
-                }
-            }
-        }
-    }
+    let hyp_rels: Vec<ParsedRelConstraint> = hyps.into_iter().map(|(h, _)| h).collect();
     let hyp_rels = select_constraints_by_var_depth(&target_rel.vars, &hyp_rels, depth);
 
     // Fast proofs before any solver use.
@@ -1057,48 +3058,31 @@ pub fn entails_from_pp_dump_with_depth_reuse(
         var_kinds.entry(m.clone()).or_insert(VarKind::Int);
     }
 
+    // The warm session is `QF_LIA` with `Int` declarations; real and bitvector fragments take
+    // the fallback.
+    if fragment_logic(&used_vars, &var_kinds) != "QF_LIA" {
+        return entails_from_pp_dump_with_depth(pp_dump, timeout_ms, seed, depth);
+    }
+
     // Try the reused session (if available), otherwise initialize it.
     if reuse.is_none() {
         *reuse = ReusableSmtSession::new()?;
     }
     if let Some(sess) = reuse.as_mut() {
         // Best-effort: if reuse fails, disable it (but keep stats) and fall back to per-call spawning.
-        let reused = (|| -> Result<Option<bool>, String> {
-            if sess.disabled {
-                return Ok(None);
-            }
-            sess.ensure_declared(&used_vars, &var_kinds)?;
-            // Solver-specific strategy:
-            // - Z3: push/pop + cached fragment is fast/stable.
-            // - cvc5: prefer `check-sat-assuming` (avoids some stdout oddities), then fall back to push/pop.
-            if sess.solver_used.trim_start().starts_with("cvc5") {
-                if let Some(r) = sess.check_entails_assuming(
-                    timeout_ms,
-                    seed,
-                    &hyp_rels,
-                    &target_rel,
-                    &used_vars,
-                )? {
-                    return Ok(Some(r));
-                }
-                sess.check_entails_pushpop(timeout_ms, seed, &hyp_rels, &target_rel, &used_vars)
-            } else {
-                // Prefer fragment caching + push/pop (smaller per-check traffic).
-                if let Some(r) = sess.check_entails_pushpop(
-                    timeout_ms,
-                    seed,
-                    &hyp_rels,
-                    &target_rel,
-                    &used_vars,
-                )? {
-                    return Ok(Some(r));
-                }
-                // Fallback to assuming-mode if push/pop path yields unknown.
-                sess.check_entails_assuming(timeout_ms, seed, &hyp_rels, &target_rel, &used_vars)
-            }
-        })();
+        let disagreements_before = sess.disagreements_total;
+        let reused = sess.check_entails_portfolio(
+            timeout_ms,
+            seed,
+            &hyp_rels,
+            &target_rel,
+            &used_vars,
+            &var_kinds,
+        );
         match reused {
             Ok(Some(r)) => return Ok(Some(r)),
+            // Solvers contradicted each other: a single fallback solver should not break the tie.
+            Ok(None) if sess.disagreements_total > disagreements_before => return Ok(None),
             Ok(None) => { /* continue to fallback */ }
             Err(e) => sess.disable(e),
         }
@@ -1129,53 +3113,15 @@ pub fn entails_from_pp_dump_with_depth(
         .and_then(|a| a.first())
         .ok_or_else(|| "pp_dump missing goals[0]".to_string())?;
 
-    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
-    let target = pretty
-        .lines()
-        .find_map(|ln| {
-            ln.trim_start()
-                .strip_prefix("⊢")
-                .map(|r| r.trim().to_string())
-        })
-        .unwrap_or_default();
-    if target.is_empty() {
-        return Ok(None);
-    }
-
-    let mut var_kinds: std::collections::BTreeMap<String, VarKind> =
-        std::collections::BTreeMap::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                if let Some((name, kind)) = extract_decl_kind(txt) {
-                    var_kinds.insert(name, kind);
-                }
-            }
-        }
-    }
-
-    let target_rel = match parse_rel_constraint_int(&target) {
-        Some(r) => r,
+    let GoalFragment {
+        target: target_rel,
+        hyps,
+        mut var_kinds,
+    } = match goal_fragment(goal) {
+        Some(f) => f,
         None => return Ok(None),
     };
-
-    let mut hyp_rels: Vec<ParsedRelConstraint> = Vec::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                let rhs = txt
-                    .split_once(':')
-                    .map(|(_, r)| r.trim())
-                    .unwrap_or(txt.trim());
-                if rhs.is_empty() {
-                    continue;
-                }
-                if let Some(r) = parse_rel_constraint_int(rhs) {
-                    hyp_rels.push(r);
-                }
-            }
-        }
-    }
+    let hyp_rels: Vec<ParsedRelConstraint> = hyps.into_iter().map(|(h, _)| h).collect();
 
     // Optionally restrict hyps by variable connectivity. This can materially reduce
     // SMT search time when there are many unrelated arithmetic hypotheses in scope.
@@ -1205,13 +3151,16 @@ pub fn entails_from_pp_dump_with_depth(
     let (mut sess, _used) = match smtkit::session::spawn_auto() {
         Ok(v) => v,
         Err(_) => {
-            // Solver not available: fall back to cheap proofs only (best-effort).
-            return Ok(idl_proves_entails(&target_rel, &hyp_rels, &var_kinds)
-                .or_else(|| entails_by_drop_nonneg_var(&target_rel.rel, &hyp_rels))
-                .or_else(|| entails_by_offset_addition(&target_rel.rel, &hyp_rels)));
+            // Solver not available: decide in-process, then fall back to cheap proofs.
+            return Ok(
+                builtin_entails(&target_rel, &hyp_rels, &var_kinds, timeout_ms)
+                    .or_else(|| entails_by_drop_nonneg_var(&target_rel.rel, &hyp_rels))
+                    .or_else(|| entails_by_offset_addition(&target_rel.rel, &hyp_rels)),
+            );
         }
     };
-    sess.set_logic("QF_LIA").map_err(|e| e.to_string())?;
+    sess.set_logic(fragment_logic(&used_vars, &var_kinds))
+        .map_err(|e| e.to_string())?;
     sess.set_print_success(false).map_err(|e| e.to_string())?;
     sess.set_produce_models(false).map_err(|e| e.to_string())?;
     sess.set_timeout_ms(timeout_ms).map_err(|e| e.to_string())?;
@@ -1221,7 +3170,7 @@ pub fn entails_from_pp_dump_with_depth(
     // problem instance small and avoid failing on unrelated missing decls.
     for name in used_vars.iter() {
         let kind = *var_kinds.get(name).unwrap_or(&VarKind::Int);
-        sess.declare_const(name, &smtkit::smt2::Sort::Int.to_smt2())
+        sess.declare_const(name, &smt_sort(kind).to_smt2())
             .map_err(|e| e.to_string())?;
         if kind == VarKind::Nat {
             sess.assert_sexp(&t::ge(t::sym(name.clone()), t::int_lit(0)))
@@ -1231,6 +3180,9 @@ pub fn entails_from_pp_dump_with_depth(
     for r in &hyp_rels {
         sess.assert_sexp(&r.sexp).map_err(|e| e.to_string())?;
     }
+    for s in side_constraints(hyp_rels.iter().chain([&target_rel])) {
+        sess.assert_sexp(&s).map_err(|e| e.to_string())?;
+    }
     // Prefer `check-sat-assuming` (temporary assertion) when available; it matches the
     // “assumptions / warmed solver” pattern and avoids polluting the assertion stack.
     let st = match sess.check_sat_assuming(&[t::not(target_rel.sexp.clone())]) {
@@ -1246,9 +3198,11 @@ pub fn entails_from_pp_dump_with_depth(
         smtkit::session::Status::Unsat => Ok(Some(true)),
         smtkit::session::Status::Sat => Ok(Some(false)),
         smtkit::session::Status::Unknown => {
-            Ok(idl_proves_entails(&target_rel, &hyp_rels, &var_kinds)
-                .or_else(|| entails_by_drop_nonneg_var(&target_rel.rel, &hyp_rels))
-                .or_else(|| entails_by_offset_addition(&target_rel.rel, &hyp_rels)))
+            Ok(
+                builtin_entails(&target_rel, &hyp_rels, &var_kinds, timeout_ms)
+                    .or_else(|| entails_by_drop_nonneg_var(&target_rel.rel, &hyp_rels))
+                    .or_else(|| entails_by_offset_addition(&target_rel.rel, &hyp_rels)),
+            )
         }
     }
 }
@@ -1265,37 +3219,12 @@ pub fn explain_fragment_from_pp_dump(
         .get("goals")
         .and_then(|v| v.as_array())
         .and_then(|a| a.first())?;
-    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
-    let target = pretty
-        .lines()
-        .find_map(|ln| {
-            ln.trim_start()
-                .strip_prefix("⊢")
-                .map(|r| r.trim().to_string())
-        })
-        .unwrap_or_default();
-    if target.is_empty() {
-        return None;
-    }
-    let target_rel = parse_rel_constraint_int(&target)?;
-
-    let mut hyp_rels: Vec<ParsedRelConstraint> = Vec::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                let rhs = txt
-                    .split_once(':')
-                    .map(|(_, r)| r.trim())
-                    .unwrap_or(txt.trim());
-                if rhs.is_empty() {
-                    continue;
-                }
-                if let Some(r) = parse_rel_constraint_int(rhs) {
-                    hyp_rels.push(r);
-                }
-            }
-        }
-    }
+    let GoalFragment {
+        target: target_rel,
+        hyps,
+        var_kinds,
+    } = goal_fragment(goal)?;
+    let hyp_rels: Vec<ParsedRelConstraint> = hyps.into_iter().map(|(h, _)| h).collect();
     let selected = select_constraints_by_var_depth(&target_rel.vars, &hyp_rels, depth);
 
     let mut used_vars: std::collections::BTreeSet<String> = std::collections::BTreeSet::new();
@@ -1314,6 +3243,7 @@ pub fn explain_fragment_from_pp_dump(
 
     Some(serde_json::json!({
         "depth": depth,
+        "logic": fragment_logic(&used_vars, &var_kinds),
         "target": target_rel.src,
         "selected_hyps_count": selected.len(),
         "selected_hyps_sample": sample,
@@ -1336,33 +3266,6 @@ pub fn unsat_core_from_pp_dump(
 ) -> Result<Option<Value>, String> {
     use smtkit::smt2::t;
 
-    fn sanitize_smt_sym(s: &str) -> Option<String> {
-        let raw = s.trim();
-        if raw.is_empty() {
-            return None;
-        }
-        let mut out = String::new();
-        for ch in raw.chars() {
-            if ch.is_ascii_alphanumeric() || ch == '_' {
-                out.push(ch);
-            } else {
-                out.push('_');
-            }
-        }
-        if out.is_empty() {
-            return None;
-        }
-        if out
-            .chars()
-            .next()
-            .map(|c| c.is_ascii_digit())
-            .unwrap_or(false)
-        {
-            out.insert_str(0, "h_");
-        }
-        Some(out)
-    }
-
     fn select_pairs_by_var_depth(
         seed_vars: &std::collections::BTreeSet<String>,
         hyps: &[(ParsedRelConstraint, Option<String>)],
@@ -1404,47 +3307,15 @@ pub fn unsat_core_from_pp_dump(
         Some(g) => g,
         None => return Ok(None),
     };
-    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
-    let target = pretty
-        .lines()
-        .find_map(|ln| {
-            ln.trim_start()
-                .strip_prefix("⊢")
-                .map(|r| r.trim().to_string())
-        })
-        .unwrap_or_default();
-    if target.is_empty() {
-        return Ok(None);
-    }
-    let target_rel = match parse_rel_constraint_int(&target) {
-        Some(r) => r,
+    let GoalFragment {
+        target: target_rel,
+        hyps,
+        mut var_kinds,
+    } = match goal_fragment(goal) {
+        Some(f) => f,
         None => return Ok(None),
     };
-
-    let mut hyp_pairs: Vec<(ParsedRelConstraint, Option<String>)> = Vec::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                let (name_hint, rhs) = if let Some((lhs, r)) = txt.split_once(':') {
-                    let nm = lhs
-                        .trim()
-                        .split_whitespace()
-                        .next()
-                        .and_then(sanitize_smt_sym);
-                    (nm, r.trim())
-                } else {
-                    (None, txt.trim())
-                };
-                if rhs.is_empty() {
-                    continue;
-                }
-                if let Some(r) = parse_rel_constraint_int(rhs) {
-                    hyp_pairs.push((r, name_hint));
-                }
-            }
-        }
-    }
-    let hyp_pairs = select_pairs_by_var_depth(&target_rel.vars, &hyp_pairs, depth);
+    let hyp_pairs = select_pairs_by_var_depth(&target_rel.vars, &hyps, depth);
 
     // Vars we actually need.
     let mut used_vars: std::collections::BTreeSet<String> = std::collections::BTreeSet::new();
@@ -1456,18 +3327,6 @@ pub fn unsat_core_from_pp_dump(
         return Ok(None);
     }
 
-    // Best-effort kind recovery (see entailment code).
-    let mut var_kinds: std::collections::BTreeMap<String, VarKind> =
-        std::collections::BTreeMap::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                if let Some((name, kind)) = extract_decl_kind(txt) {
-                    var_kinds.insert(name, kind);
-                }
-            }
-        }
-    }
     for m in used_vars.iter() {
         var_kinds.entry(m.clone()).or_insert(VarKind::Int);
     }
@@ -1477,7 +3336,8 @@ pub fn unsat_core_from_pp_dump(
         Ok(v) => v,
         Err(_) => return Ok(None),
     };
-    sess.set_logic("QF_LIA").map_err(|e| e.to_string())?;
+    sess.set_logic(fragment_logic(&used_vars, &var_kinds))
+        .map_err(|e| e.to_string())?;
     sess.set_print_success(false).map_err(|e| e.to_string())?;
     sess.set_produce_models(false).map_err(|e| e.to_string())?;
     sess.set_produce_unsat_cores(true)
@@ -1488,7 +3348,7 @@ pub fn unsat_core_from_pp_dump(
     // Declare vars.
     for name in used_vars.iter() {
         let kind = *var_kinds.get(name).unwrap_or(&VarKind::Int);
-        sess.declare_const(name, &smtkit::smt2::Sort::Int.to_smt2())
+        sess.declare_const(name, &smt_sort(kind).to_smt2())
             .map_err(|e| e.to_string())?;
         if kind == VarKind::Nat {
             sess.assert_sexp(&t::ge(t::sym(name.clone()), t::int_lit(0)))
@@ -1496,6 +3356,11 @@ pub fn unsat_core_from_pp_dump(
         }
     }
 
+    // Definitional side constraints stay unnamed: they are not hypotheses of the goal.
+    for s in side_constraints(hyp_pairs.iter().map(|(h, _)| h).chain([&target_rel])) {
+        sess.assert_sexp(&s).map_err(|e| e.to_string())?;
+    }
+
     // Assert hypotheses with names (SMT-LIB `:named`) so the core reports identifiers.
     //
     // Note: `:named` is widely supported (incl. Z3). If a solver doesn't support cores, it may
@@ -1601,41 +3466,15 @@ pub fn unsat_proof_from_pp_dump(
         Some(g) => g,
         None => return Ok(None),
     };
-    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
-    let target = pretty
-        .lines()
-        .find_map(|ln| {
-            ln.trim_start()
-                .strip_prefix("⊢")
-                .map(|r| r.trim().to_string())
-        })
-        .unwrap_or_default();
-    if target.is_empty() {
-        return Ok(None);
-    }
-    let target_rel = match parse_rel_constraint_int(&target) {
-        Some(r) => r,
+    let GoalFragment {
+        target: target_rel,
+        hyps,
+        mut var_kinds,
+    } = match goal_fragment(goal) {
+        Some(f) => f,
         None => return Ok(None),
     };
-
-    // Collect parseable hypotheses, then (optionally) depth-filter.
-    let mut hyp_rels: Vec<ParsedRelConstraint> = Vec::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                let rhs = txt
-                    .split_once(':')
-                    .map(|(_, r)| r.trim())
-                    .unwrap_or(txt.trim());
-                if rhs.is_empty() {
-                    continue;
-                }
-                if let Some(r) = parse_rel_constraint_int(rhs) {
-                    hyp_rels.push(r);
-                }
-            }
-        }
-    }
+    let hyp_rels: Vec<ParsedRelConstraint> = hyps.into_iter().map(|(h, _)| h).collect();
     let hyp_rels = select_constraints_by_var_depth(&target_rel.vars, &hyp_rels, depth);
 
     // Vars we actually need.
@@ -1648,18 +3487,6 @@ pub fn unsat_proof_from_pp_dump(
         return Ok(None);
     }
 
-    // Best-effort kind recovery.
-    let mut var_kinds: std::collections::BTreeMap<String, VarKind> =
-        std::collections::BTreeMap::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                if let Some((name, kind)) = extract_decl_kind(txt) {
-                    var_kinds.insert(name, kind);
-                }
-            }
-        }
-    }
     for m in used_vars.iter() {
         var_kinds.entry(m.clone()).or_insert(VarKind::Int);
     }
@@ -1669,7 +3496,8 @@ pub fn unsat_proof_from_pp_dump(
         Ok(v) => v,
         Err(_) => return Ok(None),
     };
-    sess.set_logic("QF_LIA").map_err(|e| e.to_string())?;
+    sess.set_logic(fragment_logic(&used_vars, &var_kinds))
+        .map_err(|e| e.to_string())?;
     sess.set_print_success(false).map_err(|e| e.to_string())?;
     sess.set_produce_models(false).map_err(|e| e.to_string())?;
     sess.set_produce_proofs(true).map_err(|e| e.to_string())?;
@@ -1679,7 +3507,7 @@ pub fn unsat_proof_from_pp_dump(
     // Declare vars.
     for name in used_vars.iter() {
         let kind = *var_kinds.get(name).unwrap_or(&VarKind::Int);
-        sess.declare_const(name, &smtkit::smt2::Sort::Int.to_smt2())
+        sess.declare_const(name, &smt_sort(kind).to_smt2())
             .map_err(|e| e.to_string())?;
         if kind == VarKind::Nat {
             sess.assert_sexp(&t::ge(t::sym(name.clone()), t::int_lit(0)))
@@ -1691,6 +3519,9 @@ pub fn unsat_proof_from_pp_dump(
     for h in &hyp_rels {
         sess.assert_sexp(&h.sexp).map_err(|e| e.to_string())?;
     }
+    for s in side_constraints(hyp_rels.iter().chain([&target_rel])) {
+        sess.assert_sexp(&s).map_err(|e| e.to_string())?;
+    }
     sess.assert_sexp(&t::not(target_rel.sexp.clone()))
         .map_err(|e| e.to_string())?;
 
@@ -1723,33 +3554,6 @@ pub fn smt2_script_from_pp_dump(
 ) -> Option<String> {
     use smtkit::smt2::t;
 
-    fn sanitize_smt_sym(s: &str) -> Option<String> {
-        let raw = s.trim();
-        if raw.is_empty() {
-            return None;
-        }
-        let mut out = String::new();
-        for ch in raw.chars() {
-            if ch.is_ascii_alphanumeric() || ch == '_' {
-                out.push(ch);
-            } else {
-                out.push('_');
-            }
-        }
-        if out.is_empty() {
-            return None;
-        }
-        if out
-            .chars()
-            .next()
-            .map(|c| c.is_ascii_digit())
-            .unwrap_or(false)
-        {
-            out.insert_str(0, "h_");
-        }
-        Some(out)
-    }
-
     fn select_pairs_by_var_depth(
         seed_vars: &std::collections::BTreeSet<String>,
         hyps: &[(ParsedRelConstraint, Option<String>)],
@@ -1788,128 +3592,702 @@ pub fn smt2_script_from_pp_dump(
         .and_then(|v| v.as_array())
         .and_then(|a| a.first())?;
 
-    let pretty = goal.get("pretty").and_then(|v| v.as_str()).unwrap_or("");
-    let target = pretty
-        .lines()
-        .find_map(|ln| {
-            ln.trim_start()
-                .strip_prefix("⊢")
-                .map(|r| r.trim().to_string())
-        })
-        .unwrap_or_default();
-    if target.is_empty() {
+    let GoalFragment {
+        target: target_rel,
+        hyps,
+        mut var_kinds,
+    } = goal_fragment(goal)?;
+    let hyp_pairs = select_pairs_by_var_depth(&target_rel.vars, &hyps, depth);
+
+    // Vars we actually need.
+    let mut used_vars: std::collections::BTreeSet<String> = std::collections::BTreeSet::new();
+    used_vars.extend(target_rel.vars.iter().cloned());
+    for (r, _) in &hyp_pairs {
+        used_vars.extend(r.vars.iter().cloned());
+    }
+    if used_vars.is_empty() {
         return None;
     }
-    let target_rel = parse_rel_constraint_int(&target)?;
+    for m in used_vars.iter() {
+        var_kinds.entry(m.clone()).or_insert(VarKind::Int);
+    }
 
-    let mut var_kinds: std::collections::BTreeMap<String, VarKind> =
+    // Delegate SMT-LIB formatting to `smtkit` so `proofpatch` and other clients share a single,
+    // canonical script surface.
+    let mut script = smtkit::smt2::Script::new();
+    let logic = fragment_logic(&used_vars, &var_kinds);
+    script.comment(&format!(" proofpatch SMT fragment dump ({logic})"));
+    script.comment(" Run with: z3 -in -smt2 < this_file.smt2");
+    script.set_option(":print-success", smtkit::sexp::Sexp::atom("false"));
+    script.set_option(":produce-models", smtkit::sexp::Sexp::atom("false"));
+    // For debugging/auditability, prefer a script that can produce cores/proofs if the solver supports them.
+    script.set_option(":produce-unsat-cores", smtkit::sexp::Sexp::atom("true"));
+    script.set_option(":produce-proofs", smtkit::sexp::Sexp::atom("true"));
+    script.set_option(":timeout", smtkit::sexp::Sexp::atom(timeout_ms.to_string()));
+    script.set_option(":random-seed", smtkit::sexp::Sexp::atom(seed.to_string()));
+    script.set_logic(logic);
+
+    for name in used_vars.iter() {
+        let kind = *var_kinds.get(name).unwrap_or(&VarKind::Int);
+        script.declare_const(&smtkit::smt2::Var::new(name.clone(), smt_sort(kind)));
+        if kind == VarKind::Nat {
+            script.assert(t::ge(t::sym(name.clone()), t::int_lit(0)));
+        }
+    }
+
+    for s in side_constraints(hyp_pairs.iter().map(|(h, _)| h).chain([&target_rel])) {
+        script.assert(s);
+    }
+
+    let mut used_names: std::collections::HashSet<String> = std::collections::HashSet::new();
+    for (i, (h, hint)) in hyp_pairs.iter().enumerate() {
+        // Named assertions help when you later ask for a core.
+        let mut nm = hint.clone().unwrap_or_else(|| format!("h{i}"));
+        if used_names.contains(&nm) {
+            nm = format!("{nm}_{i}");
+        }
+        used_names.insert(nm.clone());
+        let named = t::app(
+            "!",
+            vec![
+                h.sexp.clone(),
+                smtkit::sexp::Sexp::atom(":named"),
+                t::sym(nm),
+            ],
+        );
+        script.assert(named);
+    }
+    let nt = t::app(
+        "!",
+        vec![
+            t::not(target_rel.sexp.clone()),
+            smtkit::sexp::Sexp::atom(":named"),
+            t::sym("neg_target".to_string()),
+        ],
+    );
+    script.assert(nt);
+    script.check_sat();
+    script.comment(" If UNSAT, you can fetch artifacts by uncommenting:");
+    script.comment(" (get-unsat-core)");
+    script.comment(" (get-proof)");
+    script.comment(" (exit)");
+    Some(script.to_string())
+}
+
+/// The Lean name of each hypothesis line (`h₁ : a ≤ b`), keyed by its relation text.
+/// Inaccessible names (`h✝`) are skipped: a tactic cannot refer to them.
+fn lean_hyp_names(goal: &Value) -> std::collections::BTreeMap<String, String> {
+    let mut out = std::collections::BTreeMap::new();
+    let texts = goal
+        .get("hyps")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|h| h.get("text").and_then(|v| v.as_str()));
+    for txt in texts {
+        let Some((lhs, rhs)) = txt.split_once(':') else {
+            continue;
+        };
+        let Some(name) = lhs.split_whitespace().next() else {
+            continue;
+        };
+        let ok = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+            && name
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '\'');
+        if ok {
+            out.entry(rhs.trim().to_string())
+                .or_insert_with(|| name.to_string());
+        }
+    }
+    out
+}
+
+/// The node an expression is, when it is a bare variable or `0` (`__ZERO__`).
+fn idl_plain_node(e: &LinearExpr) -> Option<&str> {
+    match (e.coeffs.len(), e.c0) {
+        (0, 0) => Some("__ZERO__"),
+        (1, 0) => e
+            .coeffs
+            .iter()
+            .next()
+            .filter(|(_, c)| **c == 1)
+            .map(|(v, _)| v.as_str()),
+        _ => None,
+    }
+}
+
+/// `(a, b)` for `a ≤ b` / `b ≥ a`.
+fn le_sides(r: &ParsedRel) -> Option<(&LinearExpr, &LinearExpr)> {
+    match r.op {
+        RelOp::Le => Some((&r.lhs, &r.rhs)),
+        RelOp::Ge => Some((&r.rhs, &r.lhs)),
+        _ => None,
+    }
+}
+
+/// `(a, b)` for `a < b` / `b > a`.
+fn lt_sides(r: &ParsedRel) -> Option<(&LinearExpr, &LinearExpr)> {
+    match r.op {
+        RelOp::Lt => Some((&r.lhs, &r.rhs)),
+        RelOp::Gt => Some((&r.rhs, &r.lhs)),
+        _ => None,
+    }
+}
+
+fn lean_type_name(kind: VarKind) -> String {
+    match kind {
+        VarKind::Nat => "ℕ".to_string(),
+        VarKind::Int => "ℤ".to_string(),
+        VarKind::Real => "ℝ".to_string(),
+        VarKind::Rat => "ℚ".to_string(),
+        VarKind::Fin(Some(n)) => format!("Fin {n}"),
+        VarKind::Fin(None) => "Fin _".to_string(),
+        VarKind::UInt(w) => format!("UInt{w}"),
+        VarKind::BitVec(w) => format!("BitVec {w}"),
+    }
+}
+
+/// Explicit proofs along a single IDL path `from → … → to` proving the target:
+/// `Nat.le_of_lt_succ h`, an `exact le_trans …` chain when every step is a plain `a ≤ b`, and a
+/// `calc` chain (with per-step `omega` / `linarith [h]`) otherwise.
+fn idl_path_tactics(
+    target: &ParsedRelConstraint,
+    path: &(IdlEdge, Vec<IdlStep>),
+    hyps: &[(ParsedRelConstraint, Option<String>)],
+    var_kinds: &std::collections::BTreeMap<String, VarKind>,
+    int: bool,
+) -> Vec<String> {
+    let (te, steps) = path;
+    let mut out = Vec::new();
+
+    // `a < b + 1` over `ℕ` proves `a ≤ b`.
+    if let ([step], Some((ta, tb))) = (steps.as_slice(), le_sides(&target.rel)) {
+        if let Some((h, Some(name))) = step.hyp.map(|i| &hyps[i]) {
+            let nat = target
+                .vars
+                .iter()
+                .chain(&h.vars)
+                .all(|v| var_kinds.get(v) == Some(&VarKind::Nat));
+            let succ = tb.add_scaled(&LinearExpr::constant(1), 1);
+            if let Some((ha, hb)) = lt_sides(&h.rel) {
+                if nat && ha == ta && Some(hb) == succ.as_ref() {
+                    out.push(format!("by\n  exact Nat.le_of_lt_succ {name}"));
+                }
+            }
+        }
+    }
+
+    // Zero-weight chains of plain `a ≤ b` facts compose with `le_trans`.
+    let plain_target = le_sides(&target.rel)
+        .is_some_and(|(a, b)| idl_plain_node(a).is_some() && idl_plain_node(b).is_some());
+    if te.w == 0 && plain_target {
+        // Each step proves `to ≤ from`.
+        let step_term = |s: &IdlStep| -> Option<String> {
+            if s.edge.w != 0 {
+                return None;
+            }
+            let Some(i) = s.hyp else {
+                let v = &s.edge.from;
+                return (!v.starts_with('_')).then(|| format!("Nat.zero_le {v}"));
+            };
+            let (h, name) = &hyps[i];
+            let name = name.as_ref()?;
+            idl_plain_node(&h.rel.lhs)?;
+            idl_plain_node(&h.rel.rhs)?;
+            match h.rel.op {
+                RelOp::Le | RelOp::Ge => Some(name.clone()),
+                RelOp::Eq if idl_plain_node(&h.rel.lhs) == Some(s.edge.to.as_str()) => {
+                    Some(format!("{name}.le"))
+                }
+                RelOp::Eq => Some(format!("{name}.ge")),
+                _ => None,
+            }
+        };
+        let terms: Option<Vec<String>> = steps.iter().map(step_term).collect();
+        if let Some(terms) = terms {
+            let paren = |t: String| if t.contains(' ') { format!("({t})") } else { t };
+            let term = terms
+                .into_iter()
+                .reduce(|acc, t| format!("le_trans {} {}", paren(t), paren(acc)))
+                .unwrap_or_else(|| "le_rfl".to_string());
+            out.push(format!("by\n  exact {term}"));
+        }
+    }
+
+    // Otherwise spell the chain out: `x + P ≤ n₁ + c₁ ≤ … ≤ y + c`, shifted by `P` so no
+    // constant is negative (subtraction on `ℕ` truncates).
+    if steps.len() >= 2 {
+        let vars: Vec<&String> = std::iter::once(&te.from)
+            .chain(steps.iter().map(|s| &s.edge.to))
+            .filter(|v| *v != "__ZERO__")
+            .collect();
+        let kind = vars
+            .first()
+            .map(|v| var_kinds.get(*v).copied().unwrap_or(VarKind::Int));
+        // `x + c` wraps around in `Fin n`, so only unbounded sorts are spelled out.
+        let renderable = !matches!(kind, Some(VarKind::Fin(_)))
+            && vars.iter().all(|v| {
+                !v.starts_with('_')
+                    && Some(var_kinds.get(*v).copied().unwrap_or(VarKind::Int)) == kind
+            });
+        if let (true, Some(kind)) = (renderable, kind) {
+            let ty = lean_type_name(kind);
+            let render = |v: &str, c: i64| match (v, c) {
+                ("__ZERO__", _) => format!("({c} : {ty})"),
+                (_, 0) => v.to_string(),
+                _ => format!("{v} + {c}"),
+            };
+            // From `to` back to `from`: after each step, `to ≤ node + total`.
+            let mut totals = Vec::new();
+            let mut total = 0i64;
+            for s in steps.iter().rev() {
+                match total.checked_add(s.edge.w) {
+                    Some(t) => total = t,
+                    None => return out,
+                }
+                totals.push((s, total));
+            }
+            let shift = totals.iter().map(|(_, t)| -*t).max().unwrap_or(0).max(0);
+            let lhs = render(&te.to, shift);
+            let proof = |s: &IdlStep| match (int, s.hyp.and_then(|i| hyps[i].1.as_ref())) {
+                (true, _) => "by omega".to_string(),
+                (false, Some(name)) => format!("by linarith [{name}]"),
+                (false, None) => "by linarith".to_string(),
+            };
+            let mut calc = String::new();
+            let mut rhs = String::new();
+            for (i, (s, t)) in totals.iter().enumerate() {
+                rhs = render(&s.edge.from, t + shift);
+                if i == 0 {
+                    calc.push_str(&format!("    calc {lhs} ≤ {rhs} := {}\n", proof(s)));
+                } else {
+                    calc.push_str(&format!("      _ ≤ {rhs} := {}\n", proof(s)));
+                }
+            }
+            let closer = if int { "omega" } else { "linarith" };
+            out.push(format!(
+                "by\n  have : {lhs} ≤ {rhs} := by\n{calc}  {closer}"
+            ));
+        }
+    }
+    out
+}
+
+/// Turn an UNSAT certificate for the first goal of a `pp_dump` into Lean tactic candidates.
+///
+/// A difference-logic (IDL) proof gives explicit `exact le_trans …` / `Nat.le_of_lt_succ` terms
+/// or a `calc` chain over the hypotheses on its path; otherwise an unsat core (when a solver is
+/// available) is used. Either way the certificate's hypotheses also give `linarith only [h₁, h₂]`,
+/// and `omega` / `linarith` after clearing every other arithmetic hypothesis. A `QF_BV` core
+/// gives `bv_decide` instead.
+///
+/// Candidates are `by …` blocks for Lean to verify; they are only as good as the encoding.
+/// Returns `Ok(None)` when the fragment is not encodable or no certificate is found.
+pub fn tactics_from_pp_dump(
+    pp_dump: &Value,
+    timeout_ms: u64,
+    seed: u64,
+    depth: usize,
+) -> Result<Option<Value>, String> {
+    let Some(goal) = pp_dump
+        .get("goals")
+        .and_then(|v| v.as_array())
+        .and_then(|a| a.first())
+    else {
+        return Ok(None);
+    };
+    let Some(GoalFragment {
+        target,
+        hyps,
+        mut var_kinds,
+    }) = goal_fragment(goal)
+    else {
+        return Ok(None);
+    };
+    let names = lean_hyp_names(goal);
+    let hyp_rels: Vec<ParsedRelConstraint> = hyps.into_iter().map(|(h, _)| h).collect();
+    let selected: Vec<(ParsedRelConstraint, Option<String>)> =
+        select_constraints_by_var_depth(&target.vars, &hyp_rels, depth)
+            .into_iter()
+            .map(|h| {
+                let name = names.get(&h.src).cloned();
+                (h, name)
+            })
+            .collect();
+
+    let mut used_vars: std::collections::BTreeSet<String> = target.vars.clone();
+    for (h, _) in &selected {
+        used_vars.extend(h.vars.iter().cloned());
+    }
+    for v in used_vars.iter() {
+        var_kinds.entry(v.clone()).or_insert(VarKind::Int);
+    }
+    let logic = fragment_logic(&used_vars, &var_kinds);
+    let int = logic == "QF_LIA";
+
+    let rels: Vec<ParsedRelConstraint> = selected.iter().map(|(h, _)| h.clone()).collect();
+    let (source, srcs, mut tactics) =
+        if let Some(cert) = idl_certificate(&target, &rels, &var_kinds) {
+            let tactics = match &cert {
+                IdlCertificate::Paths(ps) if ps.len() == 1 => {
+                    idl_path_tactics(&target, &ps[0], &selected, &var_kinds, int)
+                }
+                _ => Vec::new(),
+            };
+            let srcs: Vec<String> = cert
+                .hyps()
+                .into_iter()
+                .map(|i| selected[i].0.src.clone())
+                .collect();
+            ("idl", srcs, tactics)
+        } else {
+            let Some(core) = unsat_core_from_pp_dump(pp_dump, timeout_ms, seed, depth, usize::MAX)?
+            else {
+                return Ok(None);
+            };
+            let srcs: Vec<String> = core
+                .get("core_items")
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .filter(|it| it.get("name").and_then(|v| v.as_str()) != Some("neg_target"))
+                .filter_map(|it| it.get("src").and_then(|v| v.as_str()).map(String::from))
+                .collect();
+            ("unsat_core", srcs, Vec::new())
+        };
+
+    // The certificate's hypotheses, and the other arithmetic hypotheses it does not need.
+    let core: Vec<Option<String>> = srcs.iter().map(|s| names.get(s).cloned()).collect();
+    let core_names: Vec<String> = core.iter().flatten().cloned().collect();
+    let mut cleared: Vec<String> = Vec::new();
+    for h in &hyp_rels {
+        if let Some(name) = names.get(&h.src) {
+            if !srcs.contains(&h.src) && !core_names.contains(name) && !cleared.contains(name) {
+                cleared.push(name.clone());
+            }
+        }
+    }
+    let bv = logic == "QF_BV";
+    if core.iter().all(|n| n.is_some()) && !bv {
+        tactics.push(format!("by\n  linarith only [{}]", core_names.join(", ")));
+    }
+    let listed = if core_names.is_empty() {
+        String::new()
+    } else {
+        format!(" [{}]", core_names.join(", "))
+    };
+    let clear = if cleared.is_empty() {
+        String::new()
+    } else {
+        format!("\n  clear {}", cleared.join(" "))
+    };
+    if bv {
+        tactics.push(format!("by{clear}\n  bv_decide"));
+    } else {
+        if int {
+            tactics.push(format!("by{clear}\n  omega"));
+        }
+        tactics.push(format!("by{clear}\n  linarith{listed}"));
+    }
+
+    Ok(Some(serde_json::json!({
+        "source": source,
+        "logic": logic,
+        "depth": depth,
+        "hyps": srcs
+            .iter()
+            .zip(&core)
+            .map(|(src, name)| serde_json::json!({ "name": name, "src": src }))
+            .collect::<Vec<_>>(),
+        "cleared": cleared,
+        "tactics": tactics,
+    })))
+}
+
+/// A model value `num / den` (`den > 0`, reduced) from a `get-model` term: an integer or decimal
+/// atom, optionally under `(- …)` and `(/ … …)`, or a bitvector (`#x0a`, `#b1010`, `(_ bv10 8)`).
+fn model_value(v: &smtkit::sexp::Sexp) -> Option<(i64, i64)> {
+    use smtkit::sexp::Sexp;
+    fn gcd(a: i64, b: i64) -> i64 {
+        if b == 0 {
+            a.abs()
+        } else {
+            gcd(b, a % b)
+        }
+    }
+    let (num, den) = match v {
+        Sexp::Atom(a) if a.starts_with("#x") || a.starts_with("#b") => {
+            let radix = if a.starts_with("#x") { 16 } else { 2 };
+            (i64::from_str_radix(&a[2..], radix).ok()?, 1)
+        }
+        Sexp::Atom(a) => match a.split_once('.') {
+            Some((int, frac)) => {
+                let den = 10i64.checked_pow(frac.len() as u32)?;
+                let num = format!("{int}{frac}").parse::<i64>().ok()?;
+                (num, den)
+            }
+            None => (a.parse::<i64>().ok()?, 1),
+        },
+        Sexp::List(xs) => match xs.as_slice() {
+            [Sexp::Atom(us), Sexp::Atom(bv), _] if us == "_" && bv.starts_with("bv") => {
+                (bv[2..].parse::<i64>().ok()?, 1)
+            }
+            [Sexp::Atom(op), x] if op == "-" => {
+                let (n, d) = model_value(x)?;
+                (n.checked_neg()?, d)
+            }
+            [Sexp::Atom(op), a, b] if op == "/" => {
+                let (an, ad) = model_value(a)?;
+                let (bn, bd) = model_value(b)?;
+                if bn == 0 {
+                    return None;
+                }
+                let (n, d) = (an.checked_mul(bd)?, ad.checked_mul(bn)?);
+                if d < 0 {
+                    (n.checked_neg()?, d.checked_neg()?)
+                } else {
+                    (n, d)
+                }
+            }
+            _ => return None,
+        },
+    };
+    let g = gcd(num, den).max(1);
+    Some((num / g, den / g))
+}
+
+/// `define-fun` values of a `get-model` response (with or without the old `(model …)` wrapper).
+fn model_values(model: &smtkit::sexp::Sexp) -> std::collections::BTreeMap<String, (i64, i64)> {
+    use smtkit::sexp::Sexp;
+    let mut out = std::collections::BTreeMap::new();
+    let Sexp::List(items) = model else {
+        return out;
+    };
+    for it in items {
+        match it {
+            Sexp::List(xs) if matches!(xs.first(), Some(Sexp::Atom(a)) if a == "define-fun") => {
+                if let [_, Sexp::Atom(name), Sexp::List(args), _, v] = xs.as_slice() {
+                    if let (true, Some(val)) = (args.is_empty(), model_value(v)) {
+                        out.insert(name.clone(), val);
+                    }
+                }
+            }
+            Sexp::List(_) => out.extend(model_values(it)),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Whether a hypothesis line only declares variables of a type the fragment understands
+/// (`x y : ℝ`), as opposed to a fact or a variable of some other type.
+fn is_plain_decl(hyp_text: &str) -> bool {
+    hyp_text
+        .split_once(':')
+        .is_some_and(|(_, ty)| !ty.contains('→'))
+        && extract_decl_kind(hyp_text).is_some()
+}
+
+/// Replace the variables of a relation's source text by values (`x` and `x.succ` alike).
+fn instantiate_src(src: &str, values: &std::collections::BTreeMap<String, String>) -> String {
+    let mut out = String::new();
+    let mut word = String::new();
+    let flush = |word: &mut String, out: &mut String| {
+        let (head, tail) = match word.split_once('.') {
+            Some((h, t)) if !h.chars().all(|c| c.is_ascii_digit()) => (h, Some(t)),
+            _ => (word.as_str(), None),
+        };
+        match (values.get(head), tail) {
+            (Some(v), None) => out.push_str(v),
+            (Some(v), Some(t)) => out.push_str(&format!("{v}.{t}")),
+            (None, _) => out.push_str(word),
+        }
+        word.clear();
+    };
+    for c in src.chars() {
+        if is_ident_char(c) {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut out);
+            out.push(c);
+        }
+    }
+    flush(&mut word, &mut out);
+    out
+}
+
+/// Render a model of `hyps ∧ ¬target` for the goal: values under the Lean variable names, the
+/// instantiated relations, and an `example` stating that the instantiated goal is false.
+///
+/// `complete` is set when every hypothesis of the goal is a plain declaration or one of `hyps`,
+/// every variable of the model is declared by a hypothesis, and nothing was abstracted, i.e. the
+/// counterexample refutes the goal itself and not just its arithmetic fragment. An undeclared
+/// name is usually a constant (`def N := 3`), which the model is not free to choose.
+fn render_counterexample(
+    goal: &Value,
+    target: &ParsedRelConstraint,
+    hyps: &[ParsedRelConstraint],
+    var_kinds: &std::collections::BTreeMap<String, VarKind>,
+    model: &std::collections::BTreeMap<String, (i64, i64)>,
+) -> Value {
+    let hyp_texts: Vec<&str> = goal
+        .get("hyps")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|h| h.get("text").and_then(|v| v.as_str()))
+        .collect();
+    // SMT names are sanitized Lean names; map them back through the declarations.
+    let mut lean_names: std::collections::BTreeMap<String, String> =
         std::collections::BTreeMap::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                if let Some((name, kind)) = extract_decl_kind(txt) {
-                    var_kinds.insert(name, kind);
-                }
+    for txt in &hyp_texts {
+        if let Some((names, _)) = txt.split_once(':') {
+            for raw in names.split_whitespace() {
+                lean_names.insert(sanitize_name(raw), raw.to_string());
             }
         }
     }
 
-    let mut hyp_pairs: Vec<(ParsedRelConstraint, Option<String>)> = Vec::new();
-    if let Some(hyps) = goal.get("hyps").and_then(|v| v.as_array()) {
-        for h in hyps {
-            if let Some(txt) = h.get("text").and_then(|v| v.as_str()) {
-                let (name_hint, rhs) = if let Some((lhs, r)) = txt.split_once(':') {
-                    let nm = lhs
-                        .trim()
-                        .split_whitespace()
-                        .next()
-                        .and_then(sanitize_smt_sym);
-                    (nm, r.trim())
-                } else {
-                    (None, txt.trim())
-                };
-                if rhs.is_empty() {
-                    continue;
-                }
-                if let Some(r) = parse_rel_constraint_int(rhs) {
-                    hyp_pairs.push((r, name_hint));
-                }
-            }
-        }
+    let mut vars: std::collections::BTreeSet<&String> = target.vars.iter().collect();
+    for h in hyps {
+        vars.extend(h.vars.iter());
+    }
+    let mut assignment = serde_json::Map::new();
+    let mut values: std::collections::BTreeMap<String, String> = std::collections::BTreeMap::new();
+    let mut declared = true;
+    // Fresh variables (`_div_…`) are definitions, not part of the counterexample.
+    for v in vars.into_iter().filter(|v| !v.starts_with('_')) {
+        declared &= lean_names.contains_key(v);
+        let kind = var_kinds.get(v).copied().unwrap_or(VarKind::Int);
+        let (num, den) = model.get(v).copied().unwrap_or((0, 1));
+        let lit = if den == 1 {
+            num.to_string()
+        } else {
+            format!("{num}/{den}")
+        };
+        let name = lean_names.get(v).cloned().unwrap_or_else(|| v.clone());
+        values.insert(name.clone(), format!("({lit} : {})", lean_type_name(kind)));
+        assignment.insert(name, Value::String(lit));
     }
-    let hyp_pairs = select_pairs_by_var_depth(&target_rel.vars, &hyp_pairs, depth);
 
-    // Vars we actually need.
-    let mut used_vars: std::collections::BTreeSet<String> = std::collections::BTreeSet::new();
-    used_vars.extend(target_rel.vars.iter().cloned());
-    for (r, _) in &hyp_pairs {
+    let inst_hyps: Vec<String> = hyps
+        .iter()
+        .map(|h| instantiate_src(&h.src, &values))
+        .collect();
+    let inst_target = instantiate_src(&target.src, &values);
+    let mut stmt = String::new();
+    for h in &inst_hyps {
+        stmt.push_str(&format!("({h}) → "));
+    }
+    stmt.push_str(&format!("({inst_target})"));
+    // Closed bitvector statements evaluate by `decide`.
+    let tactic = if target.rel.op == RelOp::Bv {
+        "decide"
+    } else {
+        "norm_num"
+    };
+    // Opaque applications (`f x`) are not instantiated, so they also leave it incomplete.
+    let complete = declared
+        && hyps.iter().chain([target]).all(|h| h.apps.is_empty())
+        && hyp_texts.iter().all(|txt| {
+            is_plain_decl(txt)
+                || txt
+                    .split_once(':')
+                    .is_some_and(|(_, rhs)| hyps.iter().any(|h| h.src == rhs.trim()))
+        });
+    serde_json::json!({
+        "assignment": assignment,
+        "hyps": inst_hyps,
+        "target": inst_target,
+        "complete": complete,
+        "lean_example": format!("example : ¬({stmt}) := by\n  {tactic}\n"),
+    })
+}
+
+/// Optional: a counterexample when `hyps ∧ ¬target` is SAT for the selected fragment.
+///
+/// The solver's model is reported under the Lean variable names, together with a Lean
+/// `example` that checks the instantiated goal is false (see `render_counterexample`). Like
+/// unsat cores, this needs a solver with model production and spawns a fresh session.
+pub fn counterexample_from_pp_dump(
+    pp_dump: &Value,
+    timeout_ms: u64,
+    seed: u64,
+    depth: usize,
+) -> Result<Option<Value>, String> {
+    use smtkit::smt2::t;
+
+    let Some(goal) = pp_dump
+        .get("goals")
+        .and_then(|v| v.as_array())
+        .and_then(|a| a.first())
+    else {
+        return Ok(None);
+    };
+    let Some(GoalFragment {
+        target,
+        hyps,
+        mut var_kinds,
+    }) = goal_fragment(goal)
+    else {
+        return Ok(None);
+    };
+    let hyp_rels: Vec<ParsedRelConstraint> = hyps.into_iter().map(|(h, _)| h).collect();
+    let hyp_rels = select_constraints_by_var_depth(&target.vars, &hyp_rels, depth);
+
+    let mut used_vars: std::collections::BTreeSet<String> = target.vars.clone();
+    for r in &hyp_rels {
         used_vars.extend(r.vars.iter().cloned());
     }
     if used_vars.is_empty() {
-        return None;
+        return Ok(None);
     }
     for m in used_vars.iter() {
         var_kinds.entry(m.clone()).or_insert(VarKind::Int);
     }
 
-    // Delegate SMT-LIB formatting to `smtkit` so `proofpatch` and other clients share a single,
-    // canonical script surface.
-    let mut script = smtkit::smt2::Script::new();
-    script.comment(" proofpatch SMT fragment dump (LIA)");
-    script.comment(" Run with: z3 -in -smt2 < this_file.smt2");
-    script.set_option(":print-success", smtkit::sexp::Sexp::atom("false"));
-    script.set_option(":produce-models", smtkit::sexp::Sexp::atom("false"));
-    // For debugging/auditability, prefer a script that can produce cores/proofs if the solver supports them.
-    script.set_option(":produce-unsat-cores", smtkit::sexp::Sexp::atom("true"));
-    script.set_option(":produce-proofs", smtkit::sexp::Sexp::atom("true"));
-    script.set_option(":timeout", smtkit::sexp::Sexp::atom(timeout_ms.to_string()));
-    script.set_option(":random-seed", smtkit::sexp::Sexp::atom(seed.to_string()));
-    script.set_logic("QF_LIA");
-
+    let (mut sess, used) = match smtkit::session::spawn_auto() {
+        Ok(v) => v,
+        Err(_) => return Ok(None),
+    };
+    sess.set_logic(fragment_logic(&used_vars, &var_kinds))
+        .map_err(|e| e.to_string())?;
+    sess.set_print_success(false).map_err(|e| e.to_string())?;
+    sess.set_produce_models(true).map_err(|e| e.to_string())?;
+    sess.set_timeout_ms(timeout_ms).map_err(|e| e.to_string())?;
+    sess.set_random_seed(seed).map_err(|e| e.to_string())?;
     for name in used_vars.iter() {
-        script.declare_const(&smtkit::smt2::Var::new(
-            name.clone(),
-            smtkit::smt2::Sort::Int,
-        ));
         let kind = *var_kinds.get(name).unwrap_or(&VarKind::Int);
+        sess.declare_const(name, &smt_sort(kind).to_smt2())
+            .map_err(|e| e.to_string())?;
         if kind == VarKind::Nat {
-            script.assert(t::ge(t::sym(name.clone()), t::int_lit(0)));
+            sess.assert_sexp(&t::ge(t::sym(name.clone()), t::int_lit(0)))
+                .map_err(|e| e.to_string())?;
         }
     }
+    for s in side_constraints(hyp_rels.iter().chain([&target])) {
+        sess.assert_sexp(&s).map_err(|e| e.to_string())?;
+    }
+    for h in &hyp_rels {
+        sess.assert_sexp(&h.sexp).map_err(|e| e.to_string())?;
+    }
+    sess.assert_sexp(&t::not(target.sexp.clone()))
+        .map_err(|e| e.to_string())?;
 
-    let mut used_names: std::collections::HashSet<String> = std::collections::HashSet::new();
-    for (i, (h, hint)) in hyp_pairs.iter().enumerate() {
-        // Named assertions help when you later ask for a core.
-        let mut nm = hint.clone().unwrap_or_else(|| format!("h{i}"));
-        if used_names.contains(&nm) {
-            nm = format!("{nm}_{i}");
-        }
-        used_names.insert(nm.clone());
-        let named = t::app(
-            "!",
-            vec![
-                h.sexp.clone(),
-                smtkit::sexp::Sexp::atom(":named"),
-                t::sym(nm),
-            ],
-        );
-        script.assert(named);
+    if sess.check_sat().map_err(|e| e.to_string())? != smtkit::session::Status::Sat {
+        return Ok(None);
     }
-    let nt = t::app(
-        "!",
-        vec![
-            t::not(target_rel.sexp.clone()),
-            smtkit::sexp::Sexp::atom(":named"),
-            t::sym("neg_target".to_string()),
-        ],
-    );
-    script.assert(nt);
-    script.check_sat();
-    script.comment(" If UNSAT, you can fetch artifacts by uncommenting:");
-    script.comment(" (get-unsat-core)");
-    script.comment(" (get-proof)");
-    script.comment(" (exit)");
-    Some(script.to_string())
+    let model = sess.get_model().map_err(|e| e.to_string())?;
+    let mut cx = render_counterexample(goal, &target, &hyp_rels, &var_kinds, &model_values(&model));
+    if let Some(obj) = cx.as_object_mut() {
+        obj.insert("solver".to_string(), Value::String(used));
+        obj.insert("depth".to_string(), serde_json::json!(depth));
+    }
+    Ok(Some(cx))
 }
 
 #[cfg(test)]
@@ -1990,4 +4368,592 @@ mod tests {
         kinds.insert("c".to_string(), VarKind::Int);
         assert_eq!(idl_proves_entails(&target, &[h1, h2], &kinds), Some(true));
     }
+
+    #[test]
+    fn arith_parser_handles_coefficients_casts_and_precedence() {
+        let mk = |s: &str| parse_rel_constraint_int(s).expect(s);
+        let r = mk("2 * n + 1 ≤ m");
+        assert_eq!(r.rel.op, RelOp::Le);
+        assert_eq!(r.rel.lhs.coeffs.get("n"), Some(&2));
+        assert_eq!(r.rel.lhs.c0, 1);
+        assert!(r.side.is_empty());
+
+        // Parentheses, unary minus, literal powers, `n.succ`.
+        let r = mk("-(3 * (a - 2 * b)) + 2 ^ 3 * c = Nat.succ (a) + a.succ");
+        assert_eq!(r.rel.lhs.coeffs.get("a"), Some(&-3));
+        assert_eq!(r.rel.lhs.coeffs.get("b"), Some(&6));
+        assert_eq!(r.rel.lhs.coeffs.get("c"), Some(&8));
+        assert_eq!((r.rel.rhs.coeffs.get("a"), r.rel.rhs.c0), (Some(&2), 2));
+
+        // Casts carry non-negativity.
+        let r = mk("(↑n : ℤ) - 1 < ↑(m + 1)");
+        assert_eq!(r.rel.op, RelOp::Lt);
+        let side: Vec<String> = r.side.iter().map(|s| s.to_string()).collect();
+        assert_eq!(side.len(), 2, "{side:?}");
+        assert!(side[0].contains("n") && side[0].contains(">="), "{side:?}");
+
+        // `ℕ` subtraction truncates, inside casts and in relations over `ℕ`: `↑(n - 1)` must not
+        // assert `n - 1 ≥ 0`, which would rule out `n = 0`.
+        let kinds: std::collections::BTreeMap<String, VarKind> =
+            [("n".to_string(), VarKind::Nat)].into_iter().collect();
+        let r = parse_rel_constraint("(↑(n - 1) : ℤ) = ↑n - 1", &kinds).unwrap();
+        let side: Vec<String> = r.side.iter().map(|s| s.to_string()).collect();
+        assert!(!side.contains(&"(>= (+ -1 n) 0)".to_string()), "{side:?}");
+        assert!(
+            side.iter()
+                .any(|s| s.starts_with("(ite (>= (+ -1 n) 0) (= _natsub_")),
+            "{side:?}"
+        );
+        assert_eq!(r.rel.rhs.c0, -1);
+        let r = parse_rel_constraint("n - 1 < n", &kinds).unwrap();
+        assert!(r.rel.lhs.coeffs.keys().all(|v| v.starts_with("_natsub_")));
+        // Without `ℕ` operands subtraction stays integer subtraction.
+        assert_eq!(mk("a - 1 < a").rel.lhs.coeffs.get("a"), Some(&1));
+        for target in ["(↑(n - 1) : ℤ) = ↑n - 1", "n - 1 < n"] {
+            assert_ne!(
+                entails_from_pp_dump(&pp_goal(target, &["n : ℕ"]), 1_000, 0).unwrap(),
+                Some(true),
+                "{target} is false for n = 0"
+            );
+        }
+
+        // Outside the fragment.
+        for s in [
+            "n * m ≤ 3",
+            "f n * m ≤ 3",
+            "(x : ℂ) ≤ 1",
+            "n ^ 2 ≤ 4",
+            "a ≠ b",
+            "∑ i, i ≤ n",
+        ] {
+            assert!(parse_rel_constraint_int(s).is_none(), "{s}");
+        }
+    }
+
+    #[test]
+    fn arith_parser_encodes_div_mod_min_max_and_ite_with_fresh_vars() {
+        let mk = |s: &str| parse_rel_constraint_int(s).expect(s);
+        // `/` and `%` by the same literal share one quotient variable.
+        let r = mk("n / 2 + n % 2 ≤ n");
+        let q: Vec<&String> = r
+            .rel
+            .lhs
+            .coeffs
+            .keys()
+            .filter(|v| v.starts_with("_div_"))
+            .collect();
+        assert_eq!(q.len(), 1);
+        assert_eq!(r.rel.lhs.coeffs.get(q[0]), Some(&-1));
+        assert_eq!(r.rel.lhs.coeffs.get("n"), Some(&1));
+        assert_eq!(r.side.len(), 2);
+        assert!(r.vars.contains("n") && r.vars.contains(q[0].as_str()));
+        assert_eq!(mk("7 / 2 + 7 % 2 = x").rel.lhs.c0, 4);
+        assert_eq!(mk("n / 0 + n % 0 = x").rel.lhs.coeffs.get("n"), Some(&1));
+
+        // min/max: bounds plus "equals one of them".
+        let r = mk("min a (b + 1) ≤ max a b");
+        assert_eq!(r.side.len(), 6);
+        assert_eq!(r.vars.len(), 4);
+
+        // if-then-else, including dependent `if` and boolean structure in the condition.
+        let r = mk("(if h : n ≤ 3 ∧ ¬(m = 0) then n + 1 else 0) ≤ 4");
+        assert_eq!(r.side.len(), 1);
+        let ite = r.side[0].to_string();
+        assert!(
+            ite.starts_with("(ite (and (<= n 3) (not (= m 0)))"),
+            "{ite}"
+        );
+
+        // The same subterm in a hypothesis and the target maps to the same variable, so the
+        // solver-free difference-logic check can still use it.
+        let h = mk("n / 2 ≤ m");
+        let target = mk("n / 2 ≤ m + 1");
+        let kinds = std::collections::BTreeMap::new();
+        assert_eq!(
+            idl_proves_entails(&target, std::slice::from_ref(&h), &kinds),
+            Some(true)
+        );
+        let sides = side_constraints([&h, &target]);
+        assert_eq!(sides.len(), 2);
+    }
+
+    #[test]
+    fn real_relations_get_real_sorts_decimals_and_casts() {
+        let kinds: std::collections::BTreeMap<String, VarKind> = [
+            ("x", VarKind::Real),
+            ("q", VarKind::Rat),
+            ("n", VarKind::Nat),
+            ("k", VarKind::Int),
+        ]
+        .into_iter()
+        .map(|(v, k)| (v.to_string(), k))
+        .collect();
+        let mk = |s: &str| parse_rel_constraint(s, &kinds).expect(s);
+
+        let r = mk("x / 2 + 0.5 ≤ ↑q");
+        assert!(r.real_vars.contains("x") && r.real_vars.contains("q"));
+        assert_eq!(r.real_vars, r.vars);
+        let side: Vec<String> = r.side.iter().map(|s| s.to_string()).collect();
+        assert_eq!(side.len(), 2, "{side:?}");
+        assert!(
+            side[0].contains("2.0") && side[0].ends_with(" x)"),
+            "{side:?}"
+        );
+        assert!(
+            side[1].contains("10.0") && side[1].ends_with(" 5.0)"),
+            "{side:?}"
+        );
+        assert!(r.sexp.to_string().starts_with("(<="));
+
+        // `ℕ → ℝ` casts: a real copy of the integer term, non-negative for `ℕ` only.
+        let r = mk("(↑n : ℝ) + 1 < x");
+        assert!(!r.real_vars.contains("n") && r.vars.contains("n"));
+        let side: Vec<String> = r.side.iter().map(|s| s.to_string()).collect();
+        assert_eq!(side.len(), 2, "{side:?}");
+        assert!(side[1].contains("(to_real n)"), "{side:?}");
+        assert_eq!(mk("(↑k : ℝ) ≤ x").side.len(), 1);
+        // Casts between integers in an integer relation keep the old meaning.
+        assert!(mk("↑n ≤ k").real_vars.is_empty());
+
+        for s in ["x + k ≤ 1", "x % 2 = 0", "(x : ℤ) ≤ 1", "x * x ≤ 1"] {
+            assert!(parse_rel_constraint(s, &kinds).is_none(), "{s}");
+        }
+    }
+
+    #[test]
+    fn goal_fragment_mixes_sorts_and_entails_over_the_reals() {
+        let pp = pp_goal(
+            "x ≤ 3",
+            &[
+                "x : ℝ",
+                "n : ℕ",
+                "h : x ≤ ↑n",
+                "hn : n ≤ 3",
+                "y : ℝ",
+                "hy : y ≤ x",
+            ],
+        );
+        let f = goal_fragment(&pp["goals"][0]).unwrap();
+        assert_eq!(f.hyps.len(), 3);
+        let ex = explain_fragment_from_pp_dump(&pp, 0, 8).unwrap();
+        assert_eq!(ex["logic"], "QF_LIRA");
+        let ex =
+            explain_fragment_from_pp_dump(&pp_goal("y ≤ x", &["x : ℝ", "y : ℝ"]), 0, 8).unwrap();
+        assert_eq!(ex["logic"], "QF_LRA");
+
+        // An undeclared name is real in the target, so an integer reading elsewhere is dropped.
+        let f = goal_fragment(&pp_goal("z ≤ 0.5", &["h1 : z ≤ 3", "h2 : z ≤ 0.25"])["goals"][0])
+            .unwrap();
+        assert_eq!(f.hyps.len(), 1);
+        assert_eq!(f.var_kinds.get("z"), Some(&VarKind::Real));
+
+        // Difference constraints over the reals are proved without a solver.
+        let pp = pp_goal("x ≤ z + 2", &["x y z : ℝ", "h1 : x ≤ y + 2", "h2 : y ≤ z"]);
+        assert_eq!(entails_from_pp_dump(&pp, 1_000, 0).unwrap(), Some(true));
+    }
+
+    #[test]
+    fn idl_certificates_become_lean_tactics() {
+        let tactics = |pp: &Value| -> Vec<String> {
+            let v = tactics_from_pp_dump(pp, 1_000, 0, 0).unwrap().unwrap();
+            assert_eq!(v["source"], "idl");
+            serde_json::from_value(v["tactics"].clone()).unwrap()
+        };
+
+        let pp = pp_goal(
+            "a ≤ d",
+            &[
+                "a b c d e : ℕ",
+                "h₁ : a ≤ b",
+                "h₂ : c ≥ b",
+                "h₃ : c = d",
+                "hx : e ≤ 7",
+            ],
+        );
+        let v = tactics_from_pp_dump(&pp, 1_000, 0, 0).unwrap().unwrap();
+        assert_eq!(v["cleared"], serde_json::json!(["hx"]));
+        let ts = tactics(&pp);
+        assert_eq!(ts[0], "by\n  exact le_trans h₁ (le_trans h₂ h₃.le)");
+        assert!(ts.contains(&"by\n  linarith only [h₁, h₂, h₃]".to_string()));
+        assert!(ts.contains(&"by\n  clear hx\n  omega".to_string()));
+
+        // Strict integer steps and offsets need a `calc` chain; `0 ≤ n` comes from `n : ℕ`.
+        let ts = tactics(&pp_goal(
+            "x + 2 ≤ z",
+            &["x y z : ℤ", "h1 : x < y", "h2 : y < z"],
+        ));
+        assert_eq!(
+            ts[0],
+            "by\n  have : x + 2 ≤ z := by\n    calc x + 2 ≤ y + 1 := by omega\n      _ ≤ z := by omega\n  omega"
+        );
+        let ts = tactics(&pp_goal("0 ≤ n", &["n : ℕ"]));
+        assert_eq!(ts[0], "by\n  exact Nat.zero_le n");
+
+        let ts = tactics(&pp_goal("n ≤ m", &["n m : ℕ", "h : n < m + 1"]));
+        assert_eq!(ts[0], "by\n  exact Nat.le_of_lt_succ h");
+
+        // Over the reals the steps cite their hypothesis and the closer is `linarith`.
+        let ts = tactics(&pp_goal(
+            "x ≤ z + 2",
+            &["x y z : ℝ", "h1 : x ≤ y + 2", "h2 : y ≤ z"],
+        ));
+        assert_eq!(
+            ts[0],
+            "by\n  have : x ≤ z + 2 := by\n    calc x ≤ y + 2 := by linarith [h1]\n      _ ≤ z + 2 := by linarith [h2]\n  linarith"
+        );
+        assert!(!ts.iter().any(|t| t.contains("omega")), "{ts:?}");
+    }
+
+    #[test]
+    fn models_render_as_lean_counterexamples() {
+        use smtkit::sexp::Sexp;
+        let define = |name: &str, sort: &str, v: Sexp| {
+            Sexp::list(vec![
+                Sexp::atom("define-fun"),
+                Sexp::atom(name),
+                Sexp::list(vec![]),
+                Sexp::atom(sort),
+                v,
+            ])
+        };
+        let neg = |v: Sexp| Sexp::list(vec![Sexp::atom("-"), v]);
+        let div =
+            |a: &str, b: &str| Sexp::list(vec![Sexp::atom("/"), Sexp::atom(a), Sexp::atom(b)]);
+        let model = Sexp::list(vec![
+            define("n", "Int", Sexp::atom("3")),
+            define("k", "Int", neg(Sexp::atom("2"))),
+            define("x", "Real", neg(div("1.0", "2.0"))),
+            define("y", "Real", Sexp::atom("0.75")),
+        ]);
+        let values = model_values(&model);
+        assert_eq!(values["n"], (3, 1));
+        assert_eq!(values["k"], (-2, 1));
+        assert_eq!(values["x"], (-1, 2));
+        assert_eq!(values["y"], (3, 4));
+        // Older solvers wrap the definitions in `(model …)`.
+        let wrapped = Sexp::list(vec![
+            Sexp::atom("model"),
+            define("n", "Int", Sexp::atom("3")),
+        ]);
+        assert_eq!(model_values(&wrapped)["n"], (3, 1));
+
+        let render = |g: &Value| {
+            let f = goal_fragment(g).unwrap();
+            let hyps: Vec<ParsedRelConstraint> = f.hyps.into_iter().map(|(h, _)| h).collect();
+            render_counterexample(g, &f.target, &hyps, &f.var_kinds, &values)
+        };
+
+        let cx = render(&pp_goal("n.succ ≤ k + 5", &["n : ℕ", "k : ℤ", "h : k ≤ n"])["goals"][0]);
+        assert_eq!(cx["assignment"], serde_json::json!({ "n": "3", "k": "-2" }));
+        assert_eq!(cx["complete"], true);
+        assert_eq!(
+            cx["lean_example"],
+            "example : ¬(((-2 : ℤ) ≤ (3 : ℕ)) → ((3 : ℕ).succ ≤ (-2 : ℤ) + 5)) := by\n  norm_num\n"
+        );
+
+        let cx = render(&pp_goal("y ≤ x", &["x y : ℝ", "hf : f x = 2"])["goals"][0]);
+        assert_eq!(
+            cx["assignment"],
+            serde_json::json!({ "x": "-1/2", "y": "3/4" })
+        );
+        assert_eq!(cx["target"], "(3/4 : ℝ) ≤ (-1/2 : ℝ)");
+        assert_eq!(cx["complete"], false);
+
+        // `k` is not declared, so it may be a constant (`def k := -3`) the model cannot pick.
+        let cx = render(&pp_goal("n ≤ k + 5", &["n : ℕ"])["goals"][0]);
+        assert_eq!(cx["assignment"], serde_json::json!({ "n": "3", "k": "-2" }));
+        assert_eq!(cx["complete"], false);
+    }
+
+    #[test]
+    fn applications_are_shared_opaque_atoms_with_congruence() {
+        let atom = |e: &LinearExpr| -> String {
+            let (v, c) = e.coeffs.iter().next().unwrap();
+            assert_eq!((e.coeffs.len(), *c), (1, 1), "{e:?}");
+            v.clone()
+        };
+        let r = parse_rel_constraint_int("f x + 1 ≤ f x + 2").unwrap();
+        assert!(atom(&r.rel.lhs).starts_with("_app_"));
+        assert_eq!(atom(&r.rel.lhs), atom(&r.rel.rhs));
+        assert!(r.vars.contains("x"));
+        // Arguments are normalized, projections are the function they abbreviate, and `ℕ`-valued
+        // functions are non-negative.
+        let r = parse_rel_constraint_int("f (x + 1) = f (1 + x)").unwrap();
+        assert_eq!(atom(&r.rel.lhs), atom(&r.rel.rhs));
+        let r = parse_rel_constraint_int("l.length = List.length l").unwrap();
+        assert_eq!(atom(&r.rel.lhs), atom(&r.rel.rhs));
+        assert_eq!(r.side.len(), 1);
+        assert!(r.side[0].to_string().contains(">="), "{:?}", r.side);
+
+        // Declarations give function result sorts; other types are not arithmetic.
+        assert_eq!(
+            extract_decl_kind("f g : ℕ → ℤ → ℝ"),
+            Some((vec!["f".to_string(), "g".to_string()], VarKind::Real))
+        );
+        assert_eq!(extract_decl_kind("l : List ℕ"), None);
+        let kinds = [
+            ("f".to_string(), VarKind::Real),
+            ("n".to_string(), VarKind::Nat),
+        ]
+        .into_iter()
+        .collect();
+        let r = parse_rel_constraint("f n ≤ 1", &kinds).unwrap();
+        assert_eq!(r.real_vars.len(), 1);
+        assert!(!r.real_vars.contains("n"));
+
+        let pp = pp_goal(
+            "List.length l ≤ List.length l + k",
+            &["l : List ℕ", "k : ℕ"],
+        );
+        assert_eq!(entails_from_pp_dump(&pp, 1_000, 0).unwrap(), Some(true));
+
+        // Two applications of `f` get an Ackermann axiom in the later relation, which also picks
+        // up the earlier application so it is declared wherever the axiom is asserted.
+        let pp = pp_goal("f b ≤ 3", &["a b : ℤ", "h1 : f a ≤ 3", "h2 : a = b"]);
+        let f = goal_fragment(&pp["goals"][0]).unwrap();
+        let (h1, _) = &f.hyps[0];
+        assert_eq!(h1.side.len(), 1);
+        let axiom = h1.side[0].to_string();
+        assert!(axiom.starts_with("(=> (= b a)"), "{axiom}");
+        assert!(f.target.vars.is_subset(&h1.vars));
+        assert!(f.target.side.is_empty() && f.hyps[1].0.side.is_empty());
+        let script = smt2_script_from_pp_dump(&pp, 1_000, 0, 0);
+        assert!(script.is_some());
+    }
+
+    #[test]
+    fn portfolio_takes_first_definitive_answer_and_flags_disagreements() {
+        let answer = |solver: &str, entails: Result<Option<bool>, String>| PortfolioAnswer {
+            solver: solver.to_string(),
+            entails,
+        };
+        let answers = vec![
+            answer("cvc5", Ok(None)),
+            answer("yices-smt2", Err("broken pipe".to_string())),
+            answer("z3", Ok(Some(true))),
+            answer("cvc5'", Ok(Some(true))),
+        ];
+        assert_eq!(portfolio_verdict(&answers), (Some(2), false));
+
+        let answers = vec![
+            answer("z3", Ok(Some(false))),
+            answer("cvc5", Ok(Some(true))),
+        ];
+        assert_eq!(portfolio_verdict(&answers), (Some(0), true));
+
+        let answers = vec![answer("z3", Ok(None)), answer("cvc5", Ok(None))];
+        assert_eq!(portfolio_verdict(&answers), (None, false));
+    }
+
+    #[test]
+    fn portfolio_returns_the_first_answer_and_checks_late_ones() {
+        let mut lead = ReusableSmtSession::with_session(None, "portfolio".to_string(), false);
+        let (tx, rx) = std::sync::mpsc::channel();
+        lead.portfolio_chan = Some((tx.clone(), rx));
+        for solver in ["z3", "cvc5", BUILTIN_SOLVER] {
+            lead.solver_stats.entry(solver.to_string()).or_default();
+        }
+        lead.portfolio_open.insert(
+            0,
+            OpenPortfolioQuery {
+                target: "x ≤ 1".to_string(),
+                hyps: vec!["h : x ≤ 0".to_string()],
+                answers: Vec::new(),
+                outstanding: 3,
+                flagged: false,
+            },
+        );
+        let send = |solver: &str, entails: Option<bool>| {
+            tx.send(PortfolioReturn {
+                query: 0,
+                slot: None,
+                session: None,
+                answer: PortfolioAnswer {
+                    solver: solver.to_string(),
+                    entails: Ok(entails),
+                },
+            })
+            .unwrap();
+        };
+        send("z3", Some(true));
+
+        // Two solvers are still out; the first answer comes back without waiting for them.
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(60);
+        let t0 = std::time::Instant::now();
+        assert_eq!(lead.await_portfolio_answer(0, deadline), Some(true));
+        assert!(t0.elapsed() < std::time::Duration::from_secs(10));
+        assert_eq!(lead.portfolio_open[&0].outstanding, 2);
+        assert_eq!(lead.solver_stats["z3"].wins, 1);
+
+        send(BUILTIN_SOLVER, None);
+        send("cvc5", Some(false));
+        lead.settle();
+        assert!(lead.portfolio_open.is_empty());
+        assert_eq!(lead.disagreements_total, 1);
+        assert_eq!(
+            lead.disagreements()[0]["answers"].as_array().unwrap().len(),
+            2
+        );
+        assert_eq!(lead.solver_stats["cvc5"].wins, 0);
+        assert_eq!(lead.solver_stats[BUILTIN_SOLVER].timeouts, 1);
+    }
+
+    fn parse_rel_constraint_int(s: &str) -> Option<ParsedRelConstraint> {
+        parse_rel_constraint(s, &std::collections::BTreeMap::new())
+    }
+
+    /// A `pp_dump` result with one goal: `hyps` (one hypothesis per entry) and `⊢ target`.
+    fn pp_goal(target: &str, hyps: &[&str]) -> Value {
+        serde_json::json!({
+            "goals": [{
+                "pretty": format!("{}\n⊢ {target}", hyps.join("\n")),
+                "hyps": hyps.iter().map(|h| serde_json::json!({ "text": h })).collect::<Vec<_>>(),
+            }]
+        })
+    }
+
+    #[test]
+    fn builtin_procedure_decides_beyond_difference_logic() {
+        let hyps = [
+            "a b : ℤ",
+            "h : 2 * a + 3 * b ≤ 7",
+            "ha : 1 ≤ a",
+            "hb : 1 ≤ b",
+        ];
+        let f = goal_fragment(&pp_goal("a ≤ 2", &hyps)["goals"][0]).unwrap();
+        let rels: Vec<ParsedRelConstraint> = f.hyps.into_iter().map(|(h, _)| h).collect();
+        assert_eq!(
+            builtin_entails(&f.target, &rels, &f.var_kinds, 1_000),
+            Some(true)
+        );
+        assert_eq!(
+            entails_from_pp_dump(&pp_goal("a ≤ 2", &hyps), 1_000, 0).unwrap(),
+            Some(true)
+        );
+        // `a = 2, b = 1` is a counterexample.
+        assert_eq!(
+            entails_from_pp_dump(&pp_goal("a ≤ 1", &hyps), 1_000, 0).unwrap(),
+            Some(false)
+        );
+
+        // Needs the `/` side constraints and integrality of the quotient.
+        let pp = pp_goal("n / 2 ≤ n", &["n : ℕ"]);
+        assert_eq!(entails_from_pp_dump(&pp, 1_000, 0).unwrap(), Some(true));
+    }
+
+    #[test]
+    fn fixed_width_types_encode_as_bitvectors_and_bounded_fin() {
+        assert_eq!(
+            extract_decl_kind("x y : UInt32"),
+            Some((vec!["x".to_string(), "y".to_string()], VarKind::UInt(32)))
+        );
+        assert_eq!(
+            extract_decl_kind("b : BitVec 8").map(|d| d.1),
+            Some(VarKind::BitVec(8))
+        );
+        assert_eq!(
+            extract_decl_kind("i : Fin 5").map(|d| d.1),
+            Some(VarKind::Fin(Some(5)))
+        );
+        assert_eq!(
+            extract_decl_kind("i : Fin (n + 1)").map(|d| d.1),
+            Some(VarKind::Fin(None))
+        );
+        assert_eq!(extract_decl_kind("b : BitVec n"), None);
+
+        let kinds: std::collections::BTreeMap<String, VarKind> = [
+            ("x", VarKind::UInt(32)),
+            ("y", VarKind::UInt(32)),
+            ("b", VarKind::BitVec(8)),
+            ("i", VarKind::Fin(Some(5))),
+            ("j", VarKind::Fin(Some(5))),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        let bv = |s: &str| {
+            parse_bv_constraint(s, &kinds)
+                .map(|c| c.sexp.to_string())
+                .unwrap_or_default()
+        };
+        assert_eq!(
+            bv("x &&& 0xff ||| y ≤ 255"),
+            "(bvule (bvor (bvand x (_ bv255 32)) y) (_ bv255 32))"
+        );
+        assert_eq!(bv("x <<< 33 = y"), "(= (bvshl x (_ bv1 32)) y)");
+        assert_eq!(
+            bv("x >>> y ≠ ~~~x"),
+            "(not (= (bvlshr x (bvurem y (_ bv32 32))) (bvnot x)))"
+        );
+        assert_eq!(bv("b >>> 9 = 0#8"), "(= (bvlshr b (_ bv8 8)) (_ bv0 8))");
+        assert_eq!(bv("b - 1 < 300"), "(bvult (bvsub b (_ bv1 8)) (_ bv44 8))");
+        assert_eq!(
+            bv("x / y + 1 > x"),
+            "(bvugt (bvadd (ite (= y (_ bv0 32)) (_ bv0 32) (bvudiv x y)) (_ bv1 32)) x)"
+        );
+        assert_eq!(bv("(5 : UInt32) ≤ x"), "(bvule (_ bv5 32) x)");
+        for s in ["x = b", "x ≤ i", "x.toNat ≤ 5", "(5 : UInt8) ≤ x"] {
+            assert!(parse_bv_constraint(s, &kinds).is_none(), "{s}");
+        }
+        // Integer relations take bitvectors only through `toNat`.
+        assert!(parse_rel_constraint("x ≤ 5", &kinds).is_none());
+        let r = parse_rel_constraint("↑x + b.toNat ≤ 5", &kinds).unwrap();
+        assert!(r.vars.iter().all(|v| v.starts_with("_bvnat_")));
+        assert_eq!(r.apps.len(), 2);
+        assert!(r.side.iter().any(|s| s.to_string().ends_with(" 255)")));
+
+        // `Fin` literals are reduced and arithmetic wraps around.
+        let r = parse_rel_constraint("i = 7", &kinds).unwrap();
+        assert_eq!(r.rel.rhs.as_constant(), Some(2));
+        // `i + 4` and `i - 1` become fresh `Fin 5` variables `i + 4 - 5q`, `i - 1 - 5q'`.
+        let r = parse_rel_constraint("i + 4 = i - 1", &kinds).unwrap();
+        let fresh = |r: &ParsedRelConstraint, kind: &str| {
+            r.vars.iter().filter(|v| v.starts_with(kind)).count()
+        };
+        assert_eq!((fresh(&r, "_fin_"), fresh(&r, "_div_")), (2, 2));
+        assert!(r.side.iter().any(|s| s.to_string() == "(< i 5)"));
+        // A cast reads the value: `↑(i + 1) + 4` wraps once.
+        let r = parse_rel_constraint("↑(i + 1) + 4 ≤ 8", &kinds).unwrap();
+        assert_eq!((fresh(&r, "_fin_"), fresh(&r, "_val_")), (1, 1));
+        let hyps = ["i j : Fin 5"];
+        // The literal in `i < 5` is `(5 : Fin 5) = 0`.
+        for (target, entails) in [
+            ("i ≤ 4", Some(true)),
+            ("i < 5", Some(false)),
+            ("j ≤ i", Some(false)),
+        ] {
+            assert_eq!(
+                entails_from_pp_dump(&pp_goal(target, &hyps), 1_000, 0).unwrap(),
+                entails,
+                "{target}"
+            );
+        }
+        // A symbolic bound becomes a hypothesis; wrap-around needs a literal one.
+        let hyps = ["n : ℕ", "i : Fin n"];
+        assert_eq!(
+            entails_from_pp_dump(&pp_goal("↑i + 1 ≤ n", &hyps), 1_000, 0).unwrap(),
+            Some(true)
+        );
+        assert!(goal_fragment(&pp_goal("i + 1 ≤ n", &hyps)["goals"][0]).is_none());
+
+        // A bitvector target keeps the bitvector hypotheses only and is left to a solver.
+        let hyps = [
+            "x y : UInt32",
+            "n : ℕ",
+            "c : BitVec n",
+            "hx : x < 16",
+            "hn : n ≤ 3",
+            "hc : c = c",
+        ];
+        let pp = pp_goal("x &&& 15 = x", &hyps);
+        let f = goal_fragment(&pp["goals"][0]).unwrap();
+        let srcs: Vec<&str> = f.hyps.iter().map(|(h, _)| h.src.as_str()).collect();
+        assert_eq!(srcs, ["x < 16"]);
+        let explain = explain_fragment_from_pp_dump(&pp, 0, 8).unwrap();
+        assert_eq!(explain["logic"], "QF_BV");
+        let rels: Vec<ParsedRelConstraint> = f.hyps.into_iter().map(|(h, _)| h).collect();
+        assert_eq!(builtin_entails(&f.target, &rels, &f.var_kinds, 1_000), None);
+        // `BitVec n` variables are not read as integers either.
+        assert!(goal_fragment(&pp_goal("c ≤ c", &hyps)["goals"][0]).is_none());
+    }
 }
@@ -0,0 +1,633 @@
+//! Delta-debugging minimizer for SMT-LIB repro scripts (`proofpatch smt-minimize`).
+//!
+//! Greedily drops commands (assertions first, in shrinking chunks), then shrinks the asserted
+//! terms. After every step the script is re-run and the step is kept only if the outcome is
+//! unchanged, so an `unsat`, `unknown`, solver error or crash survives minimization.
+//!
+//! The solver is an external process fed the whole script on stdin, so no session state or
+//! capability probing is involved: what gets minimized is exactly what a bug report would show.
+
+use serde_json::Value;
+use smtkit::sexp::Sexp;
+use std::io::{Read, Write};
+
+/// What running a script produced: the first `check-sat` answer, or how the solver failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Sat,
+    Unsat,
+    /// `unknown`, or killed at the wall-clock timeout.
+    Unknown,
+    /// No answer, only `(error ...)` responses.
+    Error,
+    /// Abnormal exit without an answer (exit code; `None` for a signal).
+    Crash(Option<i32>),
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Sat => "sat",
+            Outcome::Unsat => "unsat",
+            Outcome::Unknown => "unknown",
+            Outcome::Error => "error",
+            Outcome::Crash(_) => "crash",
+        }
+    }
+}
+
+/// The result of `minimize_script`.
+#[derive(Debug, Clone)]
+pub struct Minimized {
+    pub script: String,
+    pub outcome: Outcome,
+    /// Solver runs, including the initial one.
+    pub checks: u64,
+    /// The check budget ran out before a fixpoint (the script is still outcome-preserving).
+    pub exhausted: bool,
+    pub assertions_before: usize,
+    pub assertions_after: usize,
+    /// `:named` labels of the remaining assertions.
+    pub named: Vec<String>,
+    /// Symbols still declared with `declare-const` / `declare-fun`.
+    pub declared: Vec<String>,
+}
+
+/// Parse SMT-LIB text into its top-level commands. Comments are dropped; string literals and
+/// `|quoted|` symbols are kept verbatim as atoms.
+pub fn parse_script(text: &str) -> Result<Vec<Sexp>, String> {
+    let mut stack: Vec<Vec<Sexp>> = vec![Vec::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '(' => stack.push(Vec::new()),
+            ')' => {
+                let list = stack.pop().filter(|_| !stack.is_empty());
+                let (Some(list), Some(parent)) = (list, stack.last_mut()) else {
+                    return Err("unbalanced `)`".to_string());
+                };
+                parent.push(Sexp::List(list));
+            }
+            c if c.is_whitespace() => {}
+            '"' | '|' => {
+                let mut atom = c.to_string();
+                let mut closed = false;
+                while let Some(d) = chars.next() {
+                    atom.push(d);
+                    if d == c {
+                        // `""` is an escaped quote inside a string literal.
+                        if c == '"' && chars.peek() == Some(&'"') {
+                            atom.push(chars.next().unwrap_or('"'));
+                            continue;
+                        }
+                        closed = true;
+                        break;
+                    }
+                }
+                if !closed {
+                    return Err(format!("unterminated {c}"));
+                }
+                stack
+                    .last_mut()
+                    .expect("stack is never empty")
+                    .push(Sexp::Atom(atom));
+            }
+            c => {
+                let mut atom = c.to_string();
+                while let Some(&d) = chars.peek() {
+                    if d.is_whitespace() || matches!(d, '(' | ')' | ';' | '"' | '|') {
+                        break;
+                    }
+                    atom.push(d);
+                    chars.next();
+                }
+                stack
+                    .last_mut()
+                    .expect("stack is never empty")
+                    .push(Sexp::Atom(atom));
+            }
+        }
+    }
+    match stack.pop() {
+        Some(top) if stack.is_empty() => Ok(top),
+        _ => Err("unbalanced `(`".to_string()),
+    }
+}
+
+fn render(cmds: &[Sexp]) -> String {
+    let mut out = String::new();
+    for c in cmds {
+        out.push_str(&c.to_string());
+        out.push('\n');
+    }
+    out
+}
+
+fn head(s: &Sexp) -> Option<&str> {
+    match s {
+        Sexp::List(xs) => match xs.first() {
+            Some(Sexp::Atom(a)) => Some(a.as_str()),
+            _ => None,
+        },
+        Sexp::Atom(_) => None,
+    }
+}
+
+fn size(s: &Sexp) -> usize {
+    match s {
+        Sexp::Atom(_) => 1,
+        Sexp::List(xs) => 1 + xs.iter().map(size).sum::<usize>(),
+    }
+}
+
+fn mentions(s: &Sexp, sym: &str) -> bool {
+    match s {
+        Sexp::Atom(a) => a == sym,
+        Sexp::List(xs) => xs.iter().any(|x| mentions(x, sym)),
+    }
+}
+
+/// The symbol a `declare-const` / `declare-fun` introduces.
+fn declared_symbol(cmd: &Sexp) -> Option<&str> {
+    match (head(cmd), cmd) {
+        (Some("declare-const" | "declare-fun"), Sexp::List(xs)) => match xs.get(1) {
+            Some(Sexp::Atom(a)) => Some(a.as_str()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The `:named` label of an assertion `(assert (! t :named nm))`.
+fn assertion_name(cmd: &Sexp) -> Option<&str> {
+    let Sexp::List(xs) = cmd else {
+        return None;
+    };
+    let Some(Sexp::List(bang)) = xs.get(1) else {
+        return None;
+    };
+    if !matches!(bang.first(), Some(Sexp::Atom(a)) if a == "!") {
+        return None;
+    }
+    bang.windows(2).find_map(|w| match w {
+        [Sexp::Atom(k), Sexp::Atom(v)] if k == ":named" => Some(v.as_str()),
+        _ => None,
+    })
+}
+
+/// Commands that are never removed: without them there is no query left to run.
+fn is_structural(cmd: &Sexp) -> bool {
+    matches!(
+        head(cmd),
+        Some("set-logic" | "check-sat" | "check-sat-assuming" | "exit")
+    ) || declared_symbol(cmd).is_some()
+}
+
+fn is_numeral(a: &str) -> bool {
+    !a.is_empty() && a.chars().all(|c| c.is_ascii_digit() || c == '.')
+}
+
+/// Same-sort, smaller replacements for one term.
+fn shrink_candidates(term: &Sexp) -> Vec<Sexp> {
+    let mut out: Vec<Sexp> = Vec::new();
+    match term {
+        Sexp::Atom(a) => {
+            // Decimals stay decimals so a `Real` position keeps its sort.
+            let small: [&str; 2] = if a.contains('.') {
+                ["0.0", "1.0"]
+            } else {
+                ["0", "1"]
+            };
+            if is_numeral(a) && !small.contains(&a.as_str()) {
+                out.extend(small.map(Sexp::atom));
+            }
+        }
+        Sexp::List(xs) => {
+            let op = head(term).unwrap_or_default();
+            let args = &xs[1..];
+            match op {
+                "and" | "or" | "+" | "*" | "-" | "=>" | "not" => {
+                    out.extend(args.iter().cloned());
+                    if args.len() > 2 && op != "-" {
+                        for i in 0..args.len() {
+                            let mut ys = xs.clone();
+                            ys.remove(i + 1);
+                            out.push(Sexp::List(ys));
+                        }
+                    }
+                }
+                "ite" if args.len() == 3 => out.extend(args[1..].iter().cloned()),
+                _ => {}
+            }
+            if matches!(
+                op,
+                "and" | "or" | "not" | "=>" | "<=" | "<" | ">=" | ">" | "=" | "distinct"
+            ) {
+                out.push(Sexp::atom("true"));
+                out.push(Sexp::atom("false"));
+            }
+        }
+    }
+    out
+}
+
+/// Preorder paths (child indices) of every subterm; `!` annotations are entered, never replaced.
+fn subterm_paths(term: &Sexp, path: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+    if head(term) != Some("!") {
+        out.push(path.clone());
+    }
+    if let Sexp::List(xs) = term {
+        for (i, x) in xs.iter().enumerate().skip(1) {
+            path.push(i);
+            subterm_paths(x, path, out);
+            path.pop();
+        }
+    }
+}
+
+fn subterm<'a>(term: &'a Sexp, path: &[usize]) -> Option<&'a Sexp> {
+    path.iter().try_fold(term, |t, &i| match t {
+        Sexp::List(xs) => xs.get(i),
+        Sexp::Atom(_) => None,
+    })
+}
+
+fn replace_subterm(term: &Sexp, path: &[usize], new: &Sexp) -> Sexp {
+    match (path.split_first(), term) {
+        (None, _) => new.clone(),
+        (Some((&i, rest)), Sexp::List(xs)) => {
+            let mut ys = xs.clone();
+            if let Some(x) = ys.get_mut(i) {
+                *x = replace_subterm(x, rest, new);
+            }
+            Sexp::List(ys)
+        }
+        (Some(_), Sexp::Atom(_)) => term.clone(),
+    }
+}
+
+/// Counts checks against the budget and remembers the outcome to preserve.
+struct Oracle<F> {
+    run: F,
+    want: Outcome,
+    checks: u64,
+    max_checks: u64,
+}
+
+impl<F: FnMut(&str) -> Outcome> Oracle<F> {
+    fn exhausted(&self) -> bool {
+        self.checks >= self.max_checks
+    }
+
+    fn preserves(&mut self, cmds: &[Sexp]) -> bool {
+        if self.exhausted() {
+            return false;
+        }
+        self.checks += 1;
+        (self.run)(&render(cmds)) == self.want
+    }
+}
+
+/// Minimize `script` while `run` keeps returning the original outcome.
+///
+/// `run` is usually `run_solver` with a fixed solver; it is a parameter so the search can be
+/// replayed against a recorded oracle. A script whose outcome is `sat` is rejected: the empty
+/// script is `sat` too, so there is nothing to preserve.
+pub fn minimize_script(
+    script: &str,
+    max_checks: u64,
+    mut run: impl FnMut(&str) -> Outcome,
+) -> Result<Minimized, String> {
+    let mut cmds = parse_script(script)?;
+    let want = run(&render(&cmds));
+    if want == Outcome::Sat {
+        return Err(
+            "script is `sat`; only unsat/unknown/error/crash outcomes are minimized".into(),
+        );
+    }
+    let assertions_before = cmds.iter().filter(|c| head(c) == Some("assert")).count();
+    let mut oracle = Oracle {
+        run,
+        want: want.clone(),
+        checks: 1,
+        max_checks: max_checks.max(1),
+    };
+
+    // 1) Remove commands: assertions in halving chunks (ddmin-style), then the remaining
+    //    options/queries one at a time.
+    let removable = |cmds: &[Sexp], asserts: bool| -> Vec<usize> {
+        (0..cmds.len())
+            .filter(|&i| !is_structural(&cmds[i]) && (head(&cmds[i]) == Some("assert")) == asserts)
+            .collect()
+    };
+    for asserts in [true, false] {
+        let mut chunk = if asserts {
+            removable(&cmds, true).len().div_ceil(2).max(1)
+        } else {
+            1
+        };
+        loop {
+            let mut removed_any = false;
+            let mut start = 0;
+            loop {
+                let idx = removable(&cmds, asserts);
+                if start >= idx.len() {
+                    break;
+                }
+                let drop: Vec<usize> = idx[start..(start + chunk).min(idx.len())].to_vec();
+                let trial: Vec<Sexp> = (0..cmds.len())
+                    .filter(|i| !drop.contains(i))
+                    .map(|i| cmds[i].clone())
+                    .collect();
+                if oracle.preserves(&trial) {
+                    cmds = trial;
+                    removed_any = true;
+                } else {
+                    start += chunk;
+                }
+            }
+            if chunk == 1 && !removed_any || oracle.exhausted() {
+                break;
+            }
+            chunk = (chunk / 2).max(1);
+        }
+    }
+
+    // 2) Shrink asserted terms: replace a subterm by a smaller one of the same sort, restarting
+    //    the assertion after each accepted step. Every step shrinks the term, so this terminates.
+    for i in 0..cmds.len() {
+        if head(&cmds[i]) != Some("assert") {
+            continue;
+        }
+        'restart: loop {
+            let mut paths = Vec::new();
+            subterm_paths(&cmds[i], &mut Vec::new(), &mut paths);
+            for path in paths.iter().filter(|p| !p.is_empty()) {
+                let Some(old) = subterm(&cmds[i], path) else {
+                    continue;
+                };
+                for cand in shrink_candidates(old) {
+                    // Numerals only ever become `0`/`1`, which have no candidates themselves.
+                    if matches!(old, Sexp::List(_)) && size(&cand) >= size(old) {
+                        continue;
+                    }
+                    let mut trial = cmds.clone();
+                    trial[i] = replace_subterm(&cmds[i], path, &cand);
+                    if oracle.preserves(&trial) {
+                        cmds = trial;
+                        continue 'restart;
+                    }
+                    if oracle.exhausted() {
+                        break 'restart;
+                    }
+                }
+            }
+            break;
+        }
+    }
+
+    // 3) Drop declarations nothing mentions any more.
+    let unused: Vec<usize> = (0..cmds.len())
+        .filter(|&i| {
+            declared_symbol(&cmds[i]).is_some_and(|sym| {
+                !cmds
+                    .iter()
+                    .enumerate()
+                    .any(|(j, c)| j != i && mentions(c, sym))
+            })
+        })
+        .collect();
+    if !unused.is_empty() {
+        let trial: Vec<Sexp> = (0..cmds.len())
+            .filter(|i| !unused.contains(i))
+            .map(|i| cmds[i].clone())
+            .collect();
+        if oracle.preserves(&trial) {
+            cmds = trial;
+        }
+    }
+
+    Ok(Minimized {
+        script: render(&cmds),
+        outcome: want,
+        checks: oracle.checks,
+        exhausted: oracle.exhausted(),
+        assertions_before,
+        assertions_after: cmds.iter().filter(|c| head(c) == Some("assert")).count(),
+        named: cmds
+            .iter()
+            .filter_map(assertion_name)
+            .map(str::to_string)
+            .collect(),
+        declared: cmds
+            .iter()
+            .filter_map(declared_symbol)
+            .map(str::to_string)
+            .collect(),
+    })
+}
+
+/// Run `script` through `cmdline` (e.g. `z3 -in -smt2`) on stdin, with a wall-clock timeout.
+///
+/// Errors only when the solver cannot be started; everything it does after that is an `Outcome`.
+pub fn run_solver(cmdline: &str, script: &str, timeout_ms: u64) -> Result<Outcome, String> {
+    let mut parts = cmdline.split_whitespace();
+    let prog = parts
+        .next()
+        .ok_or_else(|| "empty solver command line".to_string())?;
+    let mut child = std::process::Command::new(prog)
+        .args(parts)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .map_err(|e| format!("failed to start `{cmdline}`: {e}"))?;
+
+    // Feed and drain on threads so neither pipe can fill up and wedge the solver.
+    let mut stdin = child.stdin.take();
+    let text = script.to_string();
+    let writer = std::thread::spawn(move || {
+        if let Some(w) = stdin.as_mut() {
+            let _ = w.write_all(text.as_bytes());
+        }
+    });
+    let mut stdout = child.stdout.take();
+    let reader = std::thread::spawn(move || {
+        let mut s = String::new();
+        if let Some(r) = stdout.as_mut() {
+            let _ = r.read_to_string(&mut s);
+        }
+        s
+    });
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_millis(timeout_ms.max(1));
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(st)) => break Some(st),
+            Ok(None) if std::time::Instant::now() < deadline => {
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+        }
+    };
+    let _ = writer.join();
+    let stdout = reader.join().unwrap_or_default();
+    Ok(classify_output(
+        &stdout,
+        status.map(|st| (st.success(), st.code())),
+    ))
+}
+
+/// `status` is `(success, exit code)`, or `None` when the solver was killed at the timeout.
+fn classify_output(stdout: &str, status: Option<(bool, Option<i32>)>) -> Outcome {
+    for line in stdout.lines() {
+        match line.trim() {
+            "sat" => return Outcome::Sat,
+            "unsat" => return Outcome::Unsat,
+            "unknown" => return Outcome::Unknown,
+            _ => {}
+        }
+    }
+    match status {
+        None => Outcome::Unknown,
+        Some((false, code)) => Outcome::Crash(code),
+        Some((true, _)) => Outcome::Error,
+    }
+}
+
+/// The solver `smt-minimize` uses by default: `SMTKIT_SOLVER`, else the first solver on `PATH`.
+pub fn default_solver_cmdline() -> Option<String> {
+    std::env::var("SMTKIT_SOLVER")
+        .ok()
+        .filter(|s| !s.trim().is_empty())
+        .or_else(|| {
+            crate::smt_lia::portfolio_solvers_on_path()
+                .first()
+                .map(|(_, cmdline)| cmdline.to_string())
+        })
+}
+
+/// Map a minimized script back to the Lean goal it was generated from (`smt2_script_from_pp_dump`
+/// names assertions after their hypotheses).
+///
+/// Keeps the hypotheses whose assertion survived and the declarations of variables that are
+/// still declared. `target_needed` is false when the hypotheses alone are contradictory.
+pub fn lean_hyps_for(pp_dump: &Value, min: &Minimized) -> Value {
+    let goal = pp_dump
+        .get("goals")
+        .and_then(|v| v.as_array())
+        .and_then(|a| a.first());
+    let target = goal
+        .and_then(|g| g.get("pretty"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .lines()
+        .find_map(|ln| {
+            ln.trim_start()
+                .strip_prefix("⊢")
+                .map(|r| r.trim().to_string())
+        });
+    let texts: Vec<&str> = goal
+        .and_then(|g| g.get("hyps"))
+        .and_then(|v| v.as_array())
+        .map(|hs| {
+            hs.iter()
+                .filter_map(|h| h.get("text").and_then(|v| v.as_str()))
+                .collect()
+        })
+        .unwrap_or_default();
+    let kept: Vec<&str> = texts
+        .into_iter()
+        .filter(|txt| {
+            if let Some((names, _)) = crate::smt_lia::extract_decl_kind(txt) {
+                return names.iter().any(|n| min.declared.contains(n));
+            }
+            txt.split_once(':')
+                .and_then(|(lhs, _)| lhs.split_whitespace().next())
+                .and_then(crate::smt_lia::sanitize_smt_sym)
+                .is_some_and(|nm| min.named.contains(&nm))
+        })
+        .collect();
+    serde_json::json!({
+        "hyps": kept,
+        "target": target,
+        "target_needed": min.named.iter().any(|n| n == "neg_target"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimizer_keeps_only_what_preserves_the_outcome() {
+        let script = "; header\n(set-option :produce-proofs true)\n(set-logic QF_LIA)\n\
+            (declare-const a Int)\n(declare-const b Int)\n(declare-const c Int)\n\
+            (assert (>= a 0))\n(assert (! (<= (+ a 7) b) :named h1))\n\
+            (assert (! (<= c 12) :named h2))\n(assert (! (not (<= a (* 3 b))) :named neg_target))\n\
+            (check-sat)\n";
+        // A fake solver: `unsat` exactly when `h1` offsets `a` by 7 or 1 and `neg_target`
+        // compares `a` with `3 * b` or `b`.
+        let run = |s: &str| {
+            let h1 = ["7", "1"]
+                .iter()
+                .any(|k| s.contains(&format!("(! (<= (+ a {k}) b) :named h1)")));
+            let neg = ["(* 3 b)", "b"]
+                .iter()
+                .any(|x| s.contains(&format!("(! (not (<= a {x})) :named neg_target)")));
+            if h1 && neg {
+                Outcome::Unsat
+            } else {
+                Outcome::Sat
+            }
+        };
+        let min = minimize_script(script, 500, run).unwrap();
+        assert_eq!(min.outcome, Outcome::Unsat);
+        assert!(!min.exhausted);
+        assert_eq!((min.assertions_before, min.assertions_after), (4, 2));
+        assert_eq!(min.named, vec!["h1", "neg_target"]);
+        assert_eq!(min.declared, vec!["a", "b"]);
+        assert!(min.script.contains("(not (<= a b))"), "{}", min.script);
+        assert!(min.script.contains("(<= (+ a 1) b)"), "{}", min.script);
+        assert!(!min.script.contains("produce-proofs"), "{}", min.script);
+        assert!(min.script.contains("(check-sat)"));
+
+        let pp = serde_json::json!({"goals": [{
+            "pretty": "a b c : ℕ\nh1 : a + 7 ≤ b\nh2 : c ≤ 12\n⊢ a ≤ 3 * b",
+            "hyps": [{"text": "a b c : ℕ"}, {"text": "h1 : a + 7 ≤ b"}, {"text": "h2 : c ≤ 12"}],
+        }]});
+        let lean = lean_hyps_for(&pp, &min);
+        assert_eq!(
+            lean["hyps"],
+            serde_json::json!(["a b c : ℕ", "h1 : a + 7 ≤ b"])
+        );
+        assert_eq!(lean["target_needed"], true);
+
+        assert!(minimize_script(script, 10, |_| Outcome::Sat).is_err());
+        assert_eq!(
+            classify_output("(error \"x\")\n", Some((false, Some(139)))),
+            Outcome::Crash(Some(139))
+        );
+        assert_eq!(classify_output("", None), Outcome::Unknown);
+        assert_eq!(
+            classify_output("(error \"x\")\nunsat\n", Some((true, Some(0)))),
+            Outcome::Unsat
+        );
+        assert_eq!(
+            parse_script("(a \"x)\" |y z|)").unwrap()[0].to_string(),
+            "(a \"x)\" |y z|)"
+        );
+        assert!(parse_script("(a").is_err() && parse_script("a)").is_err());
+    }
+}
@@ -222,10 +222,28 @@ pub fn is_made_no_progress(first_error: Option<&str>) -> bool {
         .contains("made no progress")
 }
 
+/// `heartbeats` / `recursion` when the error is a resource limit rather than a wrong proof.
+pub fn resource_limit_kind(first_error: Option<&str>) -> Option<&'static str> {
+    crate::heartbeats::resource_limit_kind(first_error.unwrap_or(""))
+}
+
 pub fn adapt_candidates_for_error(base: &[String], first_error: Option<&str>) -> Vec<String> {
     let mut out = base.to_vec();
     let err = first_error.unwrap_or("").to_lowercase();
 
+    // Resource limits: the last attempt was too *expensive*, not wrong. Heavy automation is the
+    // usual culprit, so prefer cheap, targeted tactics over widening the search.
+    if resource_limit_kind(first_error).is_some() {
+        const HEAVY: [&str; 4] = ["aesop", "nlinarith", "polyrith", "simp_all"];
+        out.retain(|c| {
+            let t = c.to_lowercase();
+            !t.contains('?') && HEAVY.iter().all(|h| !t.contains(h))
+        });
+        out.push("by\n  (omega; done)".to_string());
+        out.push("by\n  (linarith; done)".to_string());
+        out.push("by\n  (norm_num; done)".to_string());
+    }
+
     // If automation made no progress, widen the surface with suggestion tactics.
     if err.contains("made no progress") {
         // If the failure is specifically `simp`/`simp_all` making no progress, don't respond by
@@ -390,6 +408,24 @@ pub fn extract_initial_goal_block(text: &str) -> Option<String> {
     None
 }
 
+/// Ranking penalty for a candidate that verifies but is slow (`--slow-verify-ms`).
+///
+/// `decl_ms` is the profiled time of the declaration being searched (see
+/// `profile::decl_profile_ms`), so slow declarations elsewhere in the file do not count.
+///
+/// Zero for failing candidates, unknown timings, or anything under `slow_ms`; otherwise `weight`
+/// per whole multiple of `slow_ms`, capped at 9 whatever the weight. That keeps it below one
+/// synthetic-sorry warning (10) in `verify_score_key`: a slow candidate that closes the goal still
+/// ranks ahead of a fast one that leaves a `sorry`.
+pub fn slow_verify_penalty(ok: bool, decl_ms: Option<u64>, slow_ms: u64, weight: i64) -> i64 {
+    match decl_ms {
+        Some(ms) if ok && slow_ms > 0 && ms >= slow_ms => {
+            ((ms / slow_ms) as i64).saturating_mul(weight).clamp(0, 9)
+        }
+        _ => 0,
+    }
+}
+
 pub fn verify_score_key(
     summary: &Value,
     sorries: usize,
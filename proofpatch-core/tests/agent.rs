@@ -0,0 +1,274 @@
+#![cfg(feature = "axi-agent")]
+
+mod common;
+
+use proofpatch_core::agent::{self, AgentConfig};
+use proofpatch_core::llm;
+use proofpatch_core::mock_llm::{
+    MockLlmServer, MockMatch, MockReply, MockRule, MockScript, MockToolCall,
+};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::time::Duration;
+
+fn calls(times: Option<u64>, tool_calls: &[(&str, Value)]) -> MockRule {
+    MockRule {
+        when: MockMatch::default(),
+        times,
+        reply: MockReply {
+            tool_calls: tool_calls
+                .iter()
+                .map(|(name, arguments)| MockToolCall {
+                    name: name.to_string(),
+                    arguments: arguments.clone(),
+                })
+                .collect(),
+            ..MockReply::default()
+        },
+    }
+}
+
+fn lean_repo(dir: &Path) {
+    std::fs::write(dir.join("lean-toolchain"), "leanprover/lean4:v4.20.0\n").unwrap();
+    std::fs::write(dir.join("lakefile.lean"), "import Lake\nopen Lake DSL\n").unwrap();
+    std::fs::write(
+        dir.join("Foo.lean"),
+        "theorem helper_true : True := trivial\n\ntheorem foo : True := by\n  sorry\n",
+    )
+    .unwrap();
+    // `lake env lean <file>` stand-in: rejects `bad`, warns about `sorry`.
+    let lake = dir.join("fake-lake.sh");
+    std::fs::write(
+        &lake,
+        "#!/bin/sh\nf=\"$3\"\nif grep -q bad \"$f\"; then echo \"$f:2:2: error: unknown identifier 'bad'\"; exit 1; fi\nif grep -q sorry \"$f\"; then echo \"$f:2:2: warning: declaration uses 'sorry'\"; fi\nexit 0\n",
+    )
+    .unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&lake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    std::env::set_var("LAKE", &lake);
+    std::env::set_var("PROOFPATCH_VERIFY_BACKEND", "lake");
+    std::env::set_var("PROOFPATCH_AUTO_BUILD", "0");
+    std::env::set_var("PROOFPATCH_DOTENV_SEARCH", "0");
+}
+
+fn config(dir: &Path, max_steps: usize) -> AgentConfig {
+    AgentConfig {
+        repo_root: dir.to_path_buf(),
+        file: "Foo.lean".to_string(),
+        lemma: "foo".to_string(),
+        max_steps,
+        max_turns: 6,
+        llm_timeout: Duration::from_secs(5),
+        tool_timeout: Duration::from_secs(10),
+    }
+}
+
+#[test]
+fn tool_schemas_are_strict() {
+    let specs = agent::tool_specs();
+    let names: Vec<&str> = specs
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["function"]["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "locate_sorries",
+            "context_pack",
+            "goal_dump",
+            "try_tactics",
+            "patch_decl",
+            "verify",
+            "search_decls"
+        ]
+    );
+    let patch = &specs[4]["function"]["parameters"];
+    assert_eq!(patch["additionalProperties"], false);
+    assert_eq!(patch["required"], json!(["replacement"]));
+}
+
+#[tokio::test]
+async fn agent_searches_tries_tactics_and_patches() {
+    let _g = common::LOCK.lock().await;
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path());
+    let server = MockLlmServer::start(MockScript {
+        models: vec![],
+        rules: vec![
+            calls(
+                Some(1),
+                &[
+                    (
+                        "search_decls",
+                        json!({ "query": "helper", "max_results": 5 }),
+                    ),
+                    (
+                        "try_tactics",
+                        json!({ "tactics": ["exact bad", "exact helper_true"], "line": null }),
+                    ),
+                ],
+            ),
+            calls(
+                Some(1),
+                &[(
+                    "patch_decl",
+                    json!({ "replacement": "exact helper_true", "apply": true }),
+                )],
+            ),
+            calls(None, &[("verify", json!({}))]),
+        ],
+    })
+    .await
+    .unwrap();
+    common::mock_registry_file(dir.path(), &server.base_url);
+
+    let run = agent::run_agent(&config(dir.path(), 10)).await.unwrap();
+    assert!(run.solved);
+    assert_eq!(run.stop_reason, "solved");
+    assert_eq!((run.turns, run.steps.len()), (2, 3));
+    assert_eq!(run.applied, vec!["exact helper_true"]);
+    assert!(run.changed && run.text.contains("exact helper_true"));
+    // The file on disk is untouched; writing is the caller's call.
+    let on_disk = std::fs::read_to_string(dir.path().join("Foo.lean")).unwrap();
+    assert!(on_disk.contains("sorry"));
+
+    let search = &run.steps[0].result["matches"];
+    assert_eq!(search[0]["name"], "helper_true");
+    let tried = &run.steps[1].result["results"];
+    assert_eq!(tried[0]["verify"]["ok"], false);
+    assert_eq!(tried[1]["verify"]["ok"], true);
+
+    // Tool results go back to the model as `tool` messages, one per call.
+    let reqs = server.requests();
+    let msgs = reqs[1].body["messages"].as_array().unwrap();
+    let tool_msgs: Vec<&Value> = msgs.iter().filter(|m| m["role"] == "tool").collect();
+    assert_eq!(tool_msgs.len(), 2);
+    assert_eq!(tool_msgs[1]["tool_call_id"], "call_1");
+    assert!(reqs[0].body["tools"].as_array().unwrap().len() == 7);
+    llm::set_provider_registry(None);
+}
+
+#[tokio::test]
+async fn step_budget_stops_the_run_and_answers_every_call() {
+    let _g = common::LOCK.lock().await;
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path());
+    let server = MockLlmServer::start(MockScript {
+        models: vec![],
+        rules: vec![calls(
+            None,
+            &[
+                ("verify", json!({})),
+                (
+                    "locate_sorries",
+                    json!({ "max_sorries": 5, "context_lines": 0 }),
+                ),
+                ("nope", json!({})),
+            ],
+        )],
+    })
+    .await
+    .unwrap();
+    common::mock_registry_file(dir.path(), &server.base_url);
+
+    let run = agent::run_agent(&config(dir.path(), 4)).await.unwrap();
+    assert!(!run.solved);
+    assert_eq!(run.stop_reason, "max_steps");
+    assert_eq!((run.turns, run.steps.len()), (2, 4));
+    assert_eq!(run.steps[0].result["sorry_warnings"], 1);
+    assert_eq!(run.steps[1].result["sorries"][0]["line"], 4);
+    assert!(!run.steps[2].ok);
+    // The sixth call was over budget: answered as skipped, not run.
+    let last = run.messages.last().unwrap();
+    assert_eq!(last["role"], "tool");
+    assert!(last["content"].as_str().unwrap().contains("step budget"));
+    llm::set_provider_registry(None);
+}
+
+#[tokio::test]
+async fn a_plain_reply_ends_the_run() {
+    let _g = common::LOCK.lock().await;
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path());
+    let server = MockLlmServer::start(MockScript {
+        models: vec![],
+        rules: vec![MockRule {
+            when: MockMatch::default(),
+            times: None,
+            reply: MockReply {
+                content: Some("I am stuck on this one.".to_string()),
+                ..MockReply::default()
+            },
+        }],
+    })
+    .await
+    .unwrap();
+    common::mock_registry_file(dir.path(), &server.base_url);
+
+    let run = agent::run_agent(&config(dir.path(), 4)).await.unwrap();
+    assert_eq!(run.stop_reason, "final_answer");
+    assert_eq!(
+        run.final_message.as_deref(),
+        Some("I am stuck on this one.")
+    );
+    assert!(run.steps.is_empty() && !run.changed);
+    llm::set_provider_registry(None);
+}
+
+#[tokio::test]
+async fn tools_stay_on_the_lemma_and_see_the_working_copy() {
+    let _g = common::LOCK.lock().await;
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path());
+    std::fs::write(
+        dir.path().join("Foo.lean"),
+        "theorem other : True := by\n  sorry\n\ntheorem foo : True := by\n  sorry\n",
+    )
+    .unwrap();
+    let server = MockLlmServer::start(MockScript {
+        models: vec![],
+        rules: vec![
+            calls(
+                Some(1),
+                &[
+                    // Nearest hole overall is `other`'s (line 2); the lemma's is on line 5.
+                    ("try_tactics", json!({ "tactics": ["trivial"], "line": 2 })),
+                    (
+                        "patch_decl",
+                        json!({ "replacement": "first | sorry", "apply": true }),
+                    ),
+                    ("context_pack", json!({ "decl": "foo" })),
+                ],
+            ),
+            MockRule {
+                when: MockMatch::default(),
+                times: None,
+                reply: MockReply {
+                    content: Some("done".to_string()),
+                    ..MockReply::default()
+                },
+            },
+        ],
+    })
+    .await
+    .unwrap();
+    common::mock_registry_file(dir.path(), &server.base_url);
+
+    let run = agent::run_agent(&config(dir.path(), 10)).await.unwrap();
+    assert!(!run.solved);
+    assert_eq!(run.steps[0].result["sorry_line"], 5);
+    assert_eq!(run.steps[1].result["kept"], true);
+    // The pack is built from the patched working copy, not the file on disk.
+    let pack = &run.steps[2].result;
+    assert!(pack["focus"]["excerpt"]
+        .as_str()
+        .unwrap()
+        .contains("first | sorry"));
+    assert!(pack["file_abs"].as_str().unwrap().ends_with("Foo.lean"));
+    llm::set_provider_registry(None);
+}
@@ -0,0 +1,148 @@
+use proofpatch_core::autofix;
+use proofpatch_core::config::LintConfig;
+use proofpatch_core::lint;
+
+#[test]
+fn lint_style_output_formats_are_parsed() {
+    let out = "\
+Mathlib/Foo.lean:12: ERR_LIN: Line has more than 100 characters
+::ERR file=Mathlib/Bar.lean,line=1,code=ERR_COP::Mathlib/Bar.lean:1 ERR_COP: Malformed or missing copyright header
+Mathlib/Baz.lean : line 3 : ERR_TWS : Trailing whitespace detected on line
+./Mathlib/Foo.lean:4:2: error: unknown identifier 'x'
+";
+    let fs = lint::parse_lint_style_output(out);
+    assert_eq!(fs.len(), 3);
+    assert_eq!(fs[0].file, "Mathlib/Foo.lean");
+    assert_eq!(fs[0].line, 12);
+    assert_eq!(fs[0].rule, "long_line");
+    assert_eq!(fs[1].file, "Mathlib/Bar.lean");
+    assert_eq!(fs[1].rule, "copyright");
+    assert_eq!(fs[1].code.as_deref(), Some("ERR_COP"));
+    assert_eq!(fs[1].message, "Malformed or missing copyright header");
+    assert_eq!(fs[2].rule, "trailing_whitespace");
+    assert!(fs.iter().all(|f| f.source == "lint-style"));
+}
+
+#[test]
+fn native_rules_skip_comments_and_strings() {
+    let src = "\
+/-
+Copyright (c) 2026 Someone. All rights reserved.
+-/
+import Mathlib
+
+-- fun x => x $ y in a comment
+def s : String := \"fun x => $ x\"
+def f := List.map (fun x => x + 1) $ [1, 2]  \n\
+def g := fun | 0 => 1 | _ => 2
+def h := macro_rules | `($x) => `($x)
+";
+    let all = LintConfig {
+        rules: Some(lint::RULES.iter().map(|r| r.to_string()).collect()),
+        ..LintConfig::default()
+    };
+    let fs = lint::lint_text("F.lean", src, &all);
+    let rules: Vec<(usize, &str)> = fs.iter().map(|f| (f.line, f.rule.as_str())).collect();
+    assert_eq!(
+        rules,
+        vec![(8, "trailing_whitespace"), (8, "fun_arrow"), (8, "dollar")]
+    );
+
+    // By default only the rules `lint-style` itself reports are checked.
+    let fs = lint::lint_text("F.lean", src, &LintConfig::default());
+    assert_eq!(fs.len(), 1);
+    assert_eq!(fs[0].rule, "trailing_whitespace");
+}
+
+#[test]
+fn fixes_rewrite_mechanical_rules() {
+    let src = "import Mathlib\n\ntheorem t : True := by  \n  exact (fun h => h) $ trivial\n";
+    let cfg = LintConfig {
+        rules: Some(lint::RULES.iter().map(|r| r.to_string()).collect()),
+        copyright_header: Some(
+            "/-\nCopyright (c) 2026 Someone. All rights reserved.\n-/".to_string(),
+        ),
+        ..LintConfig::default()
+    };
+    let fs = lint::lint_text("F.lean", src, &cfg);
+    assert!(fs.iter().all(|f| f.fixable));
+    let (out, fixes) = autofix::apply_lint_fixes(src, lint::RULES, &cfg);
+    assert!(out.starts_with("/-\nCopyright"));
+    assert!(out.contains("theorem t : True := by\n"));
+    assert!(out.contains("  exact (fun h ↦ h) <| trivial\n"));
+    assert_eq!(fixes.len(), 4);
+    assert!(lint::lint_text("F.lean", &out, &cfg).is_empty());
+}
+
+#[test]
+fn long_lines_break_after_operators_only() {
+    let cfg = LintConfig {
+        max_line_length: 40,
+        ..LintConfig::default()
+    };
+    let src = "theorem t : a + b = c := by\n  simp only [foo_bar_baz, qux_quux, corge_grault]\n  exact someVeryLongFunctionName argumentNumberOne\n";
+    let fs = lint::lint_text("F.lean", src, &cfg);
+    let long: Vec<(usize, bool)> = fs
+        .iter()
+        .filter(|f| f.rule == "long_line")
+        .map(|f| (f.line, f.fixable))
+        .collect();
+    assert_eq!(long, vec![(2, true), (3, false)]);
+    let (out, _) = autofix::apply_lint_fixes(src, &["long_line"], &cfg);
+    assert!(out.contains("  simp only [foo_bar_baz, qux_quux,\n      corge_grault]\n"));
+}
+
+#[test]
+fn lint_file_dry_run_reports_diff_without_writing() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("F.lean"), "def x := 1 \n").unwrap();
+    let cfg = LintConfig {
+        rules: Some(vec!["trailing_whitespace".to_string()]),
+        ..LintConfig::default()
+    };
+    let r = autofix::lint_file(dir.path(), "F.lean", &cfg, &[], autofix::FixMode::DryRun).unwrap();
+    assert_eq!(r.findings.len(), 1);
+    assert!(r.remaining.is_empty());
+    assert!(!r.written);
+    assert!(r.diff.contains("+def x := 1\n"));
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("F.lean")).unwrap(),
+        "def x := 1 \n"
+    );
+
+    let r = autofix::lint_file(dir.path(), "F.lean", &cfg, &[], autofix::FixMode::Write).unwrap();
+    assert!(r.written);
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("F.lean")).unwrap(),
+        "def x := 1\n"
+    );
+}
+
+#[test]
+fn lint_file_refuses_paths_outside_the_repo() {
+    let dir = tempfile::tempdir().unwrap();
+    let repo = dir.path().join("repo");
+    std::fs::create_dir(&repo).unwrap();
+    std::fs::write(dir.path().join("Outside.lean"), "def x := 1 \n").unwrap();
+    let cfg = LintConfig::default();
+    for rel in [
+        "../Outside.lean",
+        dir.path().join("Outside.lean").to_str().unwrap(),
+    ] {
+        let e = autofix::lint_file(&repo, rel, &cfg, &[], autofix::FixMode::Write).unwrap_err();
+        assert!(e.contains("not under repo root"), "{e}");
+    }
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("Outside.lean")).unwrap(),
+        "def x := 1 \n"
+    );
+}
+
+#[test]
+fn first_error_fix_inserts_classical_before_decide() {
+    let txt = "theorem t : True := by\n  have h : True := by\n    decide\n  exact True.intro\n";
+    let msg = "Foo.lean:3:5: error: failed to synthesize\n  Decidable\n    True\n";
+    let (out, edits) = autofix::apply_mechanical_fixes_for_first_error(txt, Some(3), Some(msg));
+    assert!(out.contains("\n    classical\n    decide\n"));
+    assert_eq!(edits.len(), 1);
+}
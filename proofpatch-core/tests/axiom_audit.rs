@@ -0,0 +1,82 @@
+use proofpatch_core::axiom_audit;
+use proofpatch_core::{config, index_decls_in_text};
+
+#[test]
+fn index_decls_qualifies_names_by_namespace() {
+    let txt = r#"
+namespace Foo
+
+theorem bar : True := trivial
+
+/- theorem commented : True := trivial -/
+
+section
+lemma baz (n : Nat) : n = n := rfl
+end
+
+namespace Inner
+theorem qux : True := trivial
+end Inner
+
+theorem _root_.top : True := trivial
+
+instance : Inhabited Nat := ⟨0⟩
+
+end Foo
+
+theorem outside : True := trivial
+"#;
+    let decls = index_decls_in_text(txt);
+    let names: Vec<&str> = decls.iter().map(|d| d.full_name.as_str()).collect();
+    assert_eq!(
+        names,
+        vec!["Foo.bar", "Foo.baz", "Foo.Inner.qux", "top", "outside"]
+    );
+    let bar = &decls[0];
+    assert_eq!(bar.kind, "theorem");
+    assert_eq!(bar.line, 4);
+    assert_eq!(bar.end_line, 4);
+}
+
+#[test]
+fn parse_print_axioms_handles_wrapped_lists_and_no_axioms() {
+    let out = r#"
+/tmp/x.lean:10:0: info: 'Foo.bar' depends on axioms: [propext,
+ sorryAx,
+ Classical.choice]
+/tmp/x.lean:11:0: info: 'Foo.baz' does not depend on any axioms
+'Foo.«weird name»' depends on axioms: [Quot.sound]
+"#;
+    let m = axiom_audit::parse_print_axioms_output(out);
+    assert_eq!(
+        m.get("Foo.bar").cloned(),
+        Some(vec![
+            "propext".to_string(),
+            "sorryAx".to_string(),
+            "Classical.choice".to_string()
+        ])
+    );
+    assert_eq!(m.get("Foo.baz").cloned(), Some(vec![]));
+    assert_eq!(
+        m.get("Foo.weird name").cloned(),
+        Some(vec!["Quot.sound".to_string()])
+    );
+}
+
+#[test]
+fn axioms_config_defaults_to_standard_trio() {
+    let cfg: config::ProofpatchConfig = toml::from_str("").expect("toml parse");
+    assert_eq!(
+        cfg.axioms.allowed,
+        vec!["propext", "Classical.choice", "Quot.sound"]
+    );
+
+    let txt = r#"
+[axioms]
+allowed = ["propext", "Quot.sound"]
+ignore_decls = ["Foo.uses_choice"]
+"#;
+    let cfg: config::ProofpatchConfig = toml::from_str(txt).expect("toml parse");
+    assert_eq!(cfg.axioms.allowed, vec!["propext", "Quot.sound"]);
+    assert_eq!(cfg.axioms.ignore_decls, vec!["Foo.uses_choice"]);
+}
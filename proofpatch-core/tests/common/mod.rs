@@ -0,0 +1,56 @@
+//! Fixtures shared by the integration tests. Each test binary uses a different subset.
+#![allow(dead_code)]
+
+use proofpatch_core::config::{LlmConfig, LlmProviderConfig, WireFormat};
+use proofpatch_core::llm;
+use std::path::Path;
+
+/// Held by tests that touch process-global state: env vars, the provider registry, the LLM cache,
+/// and usage/budget counters.
+pub static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// A provider serving `mock-model` at `base_url`, with probing off.
+pub fn provider(base_url: &str, wire: WireFormat) -> LlmProviderConfig {
+    LlmProviderConfig {
+        wire_format: Some(wire),
+        base_url: Some(base_url.to_string()),
+        model: Some("mock-model".to_string()),
+        probe: Some(false),
+        ..LlmProviderConfig::default()
+    }
+}
+
+/// Install `entries`, in order, as the provider registry; env overrides of the order and models are
+/// cleared so they cannot leak in from the environment.
+pub fn registry(entries: &[(&str, LlmProviderConfig)]) {
+    std::env::remove_var("PROOFPATCH_PROVIDER_ORDER");
+    let mut cfg = LlmConfig {
+        provider_order: Some(entries.iter().map(|(n, _)| n.to_string()).collect()),
+        ..LlmConfig::default()
+    };
+    for (n, p) in entries {
+        std::env::remove_var(format!("{}_MODEL", n.to_uppercase()));
+        cfg.providers.insert(n.to_string(), p.clone());
+    }
+    llm::set_provider_registry(Some(cfg));
+}
+
+/// A single `mock` provider at `base_url`.
+pub fn mock_registry(base_url: &str, wire: WireFormat) {
+    registry(&[("mock", provider(base_url, wire))]);
+}
+
+/// Like [`mock_registry`], but through `<dir>/proofpatch.toml`, for code paths that reload the
+/// registry from the repo (e.g. verification).
+pub fn mock_registry_file(dir: &Path, base_url: &str) {
+    std::fs::write(
+        dir.join("proofpatch.toml"),
+        format!(
+            "[llm]\nprovider_order = [\"mock\"]\n\n[llm.providers.mock]\nbase_url = \"{base_url}\"\nmodel = \"mock-model\"\nprobe = false\n"
+        ),
+    )
+    .unwrap();
+    std::env::remove_var("PROOFPATCH_PROVIDER_ORDER");
+    std::env::remove_var("MOCK_MODEL");
+    llm::load_provider_registry(dir).unwrap();
+}
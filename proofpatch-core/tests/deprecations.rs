@@ -0,0 +1,53 @@
+use proofpatch_core::deprecations as dep;
+
+#[test]
+fn parses_deprecation_warnings_with_and_without_replacement() {
+    let out = "\
+/tmp/x.lean:12:8: warning: `Nat.pos_of_ne_zero` has been deprecated: use `Nat.pos_iff_ne_zero` instead
+/tmp/x.lean:14:2: warning: `Foo.bar` has been deprecated, use `Foo.baz` instead
+/tmp/x.lean:15:2: warning: `Foo.old` has been deprecated
+/tmp/x.lean:16:2: warning: declaration uses 'sorry'
+";
+    let ws = dep::parse_deprecation_warnings(out, "");
+    assert_eq!(ws.len(), 3);
+    assert_eq!((ws[0].line, ws[0].col), (12, 8));
+    assert_eq!(ws[0].replacement.as_deref(), Some("Nat.pos_iff_ne_zero"));
+    assert_eq!(ws[1].old, "Foo.bar");
+    assert_eq!(ws[2].replacement, None);
+}
+
+#[test]
+fn plan_rename_keeps_relative_spelling() {
+    // Fully qualified.
+    let (b, a) = dep::plan_rename("  exact Foo.bar h", 8, "Foo.bar", "Foo.baz").unwrap();
+    assert_eq!((b.as_str(), a.as_str()), ("Foo.bar", "Foo.baz"));
+    // Opened namespace.
+    let (b, a) = dep::plan_rename("  exact bar h", 8, "Foo.bar", "Foo.baz").unwrap();
+    assert_eq!((b.as_str(), a.as_str()), ("bar", "baz"));
+    // Moved to another namespace: requalify.
+    let (_, a) = dep::plan_rename("  exact bar h", 8, "Foo.bar", "Qux.baz").unwrap();
+    assert_eq!(a, "Qux.baz");
+    // Dot notation into another namespace cannot be rewritten mechanically.
+    assert!(dep::plan_rename("  exact h.bar", 10, "Foo.bar", "Qux.baz").is_err());
+    // Trailing projection is kept.
+    let (_, a) = dep::plan_rename("  exact (Foo.bar.trans h)", 9, "Foo.bar", "Foo.baz").unwrap();
+    assert_eq!(a, "Foo.baz.trans");
+}
+
+#[test]
+fn apply_rename_edits_is_char_based() {
+    let text = "theorem t : α = α := by\n  rw [Foo.bar, Foo.bar]\n";
+    let mk = |col| dep::RenameEdit {
+        line: 2,
+        col,
+        before: "Foo.bar".to_string(),
+        after: "Foo.baz_long".to_string(),
+        old: "Foo.bar".to_string(),
+        replacement: "Foo.baz_long".to_string(),
+    };
+    let out = dep::apply_rename_edits(text, &[mk(6), mk(15)]);
+    assert_eq!(
+        out,
+        "theorem t : α = α := by\n  rw [Foo.baz_long, Foo.baz_long]\n"
+    );
+}
@@ -0,0 +1,83 @@
+use proofpatch_core::heartbeats as hb;
+
+const SRC: &str = r#"import Mathlib
+
+/-- Slow. -/
+theorem slow (n : Nat) : n + 0 = n := by
+  induction n with
+  | zero => rfl
+  | succ k ih => simp
+  nlinarith [sq_nonneg n]
+  aesop
+"#;
+
+#[test]
+fn parses_resource_limit_errors_with_continuation_lines() {
+    let out = "\
+/tmp/x.lean:9:2: error: (deterministic) timeout at `whnf`, maximum number of heartbeats (200000) has been reached
+Use `set_option maxHeartbeats <num>` to set the limit.
+/tmp/x.lean:10:2: error: unsolved goals
+/tmp/x.lean:11:0: error: maximum recursion depth has been reached
+";
+    let errs = hb::parse_resource_limit_errors(out, "");
+    assert_eq!(errs.len(), 2);
+    assert_eq!(errs[0].kind, "heartbeats");
+    assert_eq!((errs[0].line, errs[0].col), (9, 2));
+    assert!(errs[0].message.contains("set_option maxHeartbeats"));
+    assert_eq!(hb::reported_heartbeat_limit(&errs[0].message), Some(200000));
+    assert_eq!(errs[1].kind, "recursion");
+}
+
+#[test]
+fn budget_ladder_doubles_up_to_cap() {
+    assert_eq!(
+        hb::budget_ladder(200_000, 1_000_000),
+        vec![400_000, 800_000, 1_000_000]
+    );
+    assert!(hb::budget_ladder(200_000, 200_000).is_empty());
+    assert_eq!(
+        hb::scoped_set_option("recursion", 1024),
+        "set_option maxRecDepth 1024 in"
+    );
+}
+
+#[test]
+fn tactic_block_truncation_and_scoped_option_keep_lines() {
+    let decls = proofpatch_core::index_decls_in_text(SRC);
+    let d = &decls[0];
+    let tactics = hb::top_level_tactics(SRC, d);
+    let heads: Vec<&str> = tactics
+        .iter()
+        .map(|t| t.text.lines().next().unwrap())
+        .collect();
+    assert_eq!(
+        heads,
+        vec!["induction n with", "nlinarith [sq_nonneg n]", "aesop"]
+    );
+    assert_eq!(tactics[0].end_line, 7);
+
+    let t = hb::truncate_tactic_block(SRC, &tactics, 1);
+    let lines: Vec<&str> = t.lines().collect();
+    assert_eq!(lines.len(), SRC.lines().count());
+    assert_eq!(lines[7], "  sorry");
+    assert_eq!(lines[8], "");
+
+    let t = hb::with_scoped_option(SRC, d, "set_option maxHeartbeats 400000 in");
+    assert_eq!(
+        t.lines().nth(2),
+        Some("set_option maxHeartbeats 400000 in /-- Slow. -/")
+    );
+}
+
+#[test]
+fn cheaper_alternatives_keep_arguments() {
+    assert_eq!(
+        hb::cheaper_tactic_alternatives("simp [foo, bar]"),
+        vec!["simp? [foo, bar]"]
+    );
+    assert!(hb::cheaper_tactic_alternatives("simp only [foo]").is_empty());
+    assert_eq!(
+        hb::cheaper_tactic_alternatives("nlinarith [sq_nonneg n]")[0],
+        "linarith [sq_nonneg n]"
+    );
+}
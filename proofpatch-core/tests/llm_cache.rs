@@ -0,0 +1,103 @@
+mod common;
+
+use proofpatch_core::config::{LlmConfig, LlmProviderConfig};
+use proofpatch_core::llm;
+use proofpatch_core::llm_cache::{self, CachedResponse, LlmCacheMode};
+use proofpatch_core::llm_usage::{self, TokenUsage};
+use serde_json::json;
+use std::time::Duration;
+
+#[test]
+fn cache_key_ignores_key_order_but_not_content() {
+    let a = json!({ "model": "m", "temperature": 0.2, "messages": [{ "role": "user", "content": "hi" }] });
+    let b = json!({ "messages": [{ "content": "hi", "role": "user" }], "temperature": 0.2, "model": "m" });
+    assert_eq!(llm_cache::cache_key("p", &a), llm_cache::cache_key("p", &b));
+    assert_ne!(llm_cache::cache_key("p", &a), llm_cache::cache_key("q", &a));
+    let mut c = a.clone();
+    c["temperature"] = json!(0.7);
+    assert_ne!(llm_cache::cache_key("p", &a), llm_cache::cache_key("p", &c));
+    assert_eq!(
+        LlmCacheMode::parse("read_only").unwrap(),
+        LlmCacheMode::ReadOnly
+    );
+    assert!(LlmCacheMode::parse("sometimes").is_err());
+}
+
+#[tokio::test]
+async fn read_only_replays_without_keys_or_network() {
+    let _g = common::LOCK.lock().await;
+    let dir = tempfile::tempdir().unwrap();
+    llm_cache::set_repo_root(dir.path());
+    std::env::remove_var("PROOFPATCH_LLM_CACHE_DIR");
+    std::env::remove_var("PROOFPATCH_PROVIDER_ORDER");
+    std::env::remove_var("REPLAY_KEY");
+    std::env::remove_var("REPLAY_MODEL");
+
+    // Nothing listens on port 9, and the key env var is unset: a real call would fail.
+    let mut cfg = LlmConfig {
+        provider_order: Some(vec!["replay".to_string()]),
+        ..LlmConfig::default()
+    };
+    cfg.providers.insert(
+        "replay".to_string(),
+        LlmProviderConfig {
+            base_url: Some("http://127.0.0.1:9/v1".to_string()),
+            api_key_env: Some("REPLAY_KEY".to_string()),
+            model: Some("mock-model".to_string()),
+            ..LlmProviderConfig::default()
+        },
+    );
+    llm::set_provider_registry(Some(cfg));
+
+    // The request `chat_completion` builds for this prompt.
+    let payload = json!({
+        "model": "mock-model",
+        "messages": [
+            { "role": "system", "content": "sys" },
+            { "role": "user", "content": "prove it" }
+        ],
+        "temperature": 0.2
+    });
+    let key = llm_cache::cache_key("replay", &payload);
+    llm_cache::store(&CachedResponse {
+        key: key.clone(),
+        provider: "replay".to_string(),
+        model: "mock-model".to_string(),
+        created_unix_ms: llm_cache::now_unix_ms(),
+        usage: TokenUsage {
+            prompt_tokens: 5,
+            completion_tokens: 3,
+            total_tokens: 8,
+            ..TokenUsage::default()
+        },
+        response: json!({ "choices": [{ "message": { "role": "assistant", "content": "by simp" } }] }),
+    })
+    .unwrap();
+    assert!(dir
+        .path()
+        .join(".generated/proofpatch-llm-cache")
+        .join(&key[..2])
+        .join(format!("{key}.json"))
+        .exists());
+
+    llm_cache::set_mode_override(Some(LlmCacheMode::ReadOnly));
+    llm_usage::reset();
+    let hit = llm::chat_completion("sys", "prove it", Duration::from_secs(5))
+        .await
+        .expect("replayed completion");
+    assert_eq!(hit.content, "by simp");
+    assert!(hit.usage.cached);
+    assert_eq!(hit.usage.total_tokens, 8);
+
+    let miss = llm::chat_completion("sys", "something else", Duration::from_secs(5))
+        .await
+        .unwrap_err();
+    assert!(miss.contains("cache miss"), "{miss}");
+
+    let s = llm_usage::summary();
+    assert_eq!(s.cache_hits, 1);
+    assert_eq!(s.calls, 0);
+
+    llm_cache::set_mode_override(None);
+    llm::set_provider_registry(None);
+}
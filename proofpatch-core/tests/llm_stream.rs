@@ -0,0 +1,223 @@
+mod common;
+
+use proofpatch_core::config::WireFormat;
+use proofpatch_core::llm::{self, StreamControl};
+use proofpatch_core::llm_cache::{self, LlmCacheMode};
+use proofpatch_core::llm_wire::{self, SseParser};
+use proofpatch_core::mock_llm::{
+    MockLlmServer, MockMatch, MockReply, MockRule, MockScript, MockUsage,
+};
+use std::time::Duration;
+
+fn serve(reply: MockReply) -> MockScript {
+    MockScript {
+        models: vec![],
+        rules: vec![MockRule {
+            when: MockMatch::default(),
+            times: None,
+            reply,
+        }],
+    }
+}
+
+#[test]
+fn sse_parser_handles_split_frames_and_both_wire_formats() {
+    let mut p = SseParser::default();
+    assert!(p.push(b"data: {\"a\"").is_empty());
+    assert_eq!(
+        p.push(b":1}\r\n\r\nevent: x\ndata: [DONE]\n"),
+        vec!["{\"a\":1}"]
+    );
+    assert_eq!(p.finish(), vec!["[DONE]"]);
+
+    // `ℕ` is three bytes; a chunk boundary inside it must not turn it into U+FFFD.
+    let n = "ℕ".as_bytes();
+    let mut p = SseParser::default();
+    let mut first = b"data: n : ".to_vec();
+    first.extend_from_slice(&n[..1]);
+    assert!(p.push(&first).is_empty());
+    let mut rest = n[1..].to_vec();
+    rest.extend_from_slice(b"\n\n");
+    assert_eq!(p.push(&rest), vec!["n : ℕ"]);
+
+    let d = llm_wire::stream_delta(
+        WireFormat::OpenAi,
+        r#"{"choices":[{"delta":{"content":"by "},"finish_reason":null}]}"#,
+    );
+    assert_eq!(d.text, "by ");
+    assert!(llm_wire::stream_delta(WireFormat::OpenAi, "[DONE]").done);
+
+    let d = llm_wire::stream_delta(
+        WireFormat::Anthropic,
+        r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":4}}"#,
+    );
+    assert_eq!(d.finish_reason.as_deref(), Some("stop"));
+    assert_eq!(d.completion_tokens, Some(4));
+    let d = llm_wire::stream_delta(
+        WireFormat::Anthropic,
+        r#"{"type":"error","error":{"type":"overloaded_error","message":"overloaded"}}"#,
+    );
+    assert_eq!(d.error.as_deref(), Some("overloaded"));
+
+    assert_eq!(llm::complete_lean_code_block("```lean\nsimp\n"), None);
+    assert_eq!(
+        llm::complete_lean_code_block("see:\n```text\nx\n```\n```lean4\nsimp\nring\n```"),
+        Some("simp\nring".to_string())
+    );
+}
+
+#[tokio::test]
+async fn streams_deltas_and_usage_for_both_wire_formats() {
+    let _g = common::LOCK.lock().await;
+    let server = MockLlmServer::start(serve(MockReply {
+        content: Some("intro n; simp [foo]".to_string()),
+        chunk_chars: Some(4),
+        usage: Some(MockUsage {
+            prompt_tokens: 9,
+            completion_tokens: 5,
+        }),
+        ..MockReply::default()
+    }))
+    .await
+    .unwrap();
+
+    for wire in [WireFormat::OpenAi, WireFormat::Anthropic] {
+        common::mock_registry(&server.base_url, wire);
+        let mut deltas = Vec::new();
+        let r = llm::chat_completion_stream("sys", "prove it", Duration::from_secs(5), |d, _| {
+            deltas.push(d.to_string());
+            StreamControl::Continue
+        })
+        .await
+        .unwrap();
+        assert_eq!(r.content, "intro n; simp [foo]", "{wire:?}");
+        assert_eq!(r.finish, "complete");
+        assert!(!r.partial);
+        assert_eq!(r.finish_reason.as_deref(), Some("stop"));
+        assert_eq!(deltas.len(), 5);
+        assert_eq!(r.chunks, 5);
+        assert_eq!(r.usage.total_tokens, 14);
+        assert!(!r.usage.estimated);
+    }
+    let reqs = server.requests();
+    assert!(reqs.iter().all(|r| r.body["stream"] == true));
+    assert!(reqs[1].path.ends_with("/messages"));
+    llm::set_provider_registry(None);
+}
+
+#[tokio::test]
+async fn stops_once_a_lean_block_is_complete() {
+    let _g = common::LOCK.lock().await;
+    let explanation = "This closes the goal because ".repeat(20);
+    let server = MockLlmServer::start(serve(MockReply {
+        content: Some(format!("```lean\nsimp [foo]\n```\n{explanation}")),
+        chunk_chars: Some(6),
+        chunk_delay_ms: Some(5),
+        ..MockReply::default()
+    }))
+    .await
+    .unwrap();
+    common::mock_registry(&server.base_url, WireFormat::OpenAi);
+
+    let r = llm::chat_completion_stream("sys", "prove it", Duration::from_secs(5), |_, text| {
+        if llm::complete_lean_code_block(text).is_some() {
+            StreamControl::Stop
+        } else {
+            StreamControl::Continue
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(r.finish, "cancelled");
+    assert!(r.partial);
+    assert_eq!(r.code_block.as_deref(), Some("simp [foo]"));
+    assert!(r.content.len() < explanation.len(), "{}", r.content);
+    // No usage event arrived before the cancel; counts are estimated but still recorded.
+    assert!(r.usage.estimated);
+    assert!(r.usage.completion_tokens > 0);
+    llm::set_provider_registry(None);
+}
+
+#[tokio::test]
+async fn timeout_salvages_partial_output() {
+    let _g = common::LOCK.lock().await;
+    let server = MockLlmServer::start(MockScript {
+        models: vec![],
+        rules: vec![
+            MockRule {
+                when: MockMatch {
+                    contains: Some("silent".to_string()),
+                    ..MockMatch::default()
+                },
+                times: None,
+                reply: MockReply {
+                    content: Some("never".to_string()),
+                    delay_ms: Some(2_000),
+                    ..MockReply::default()
+                },
+            },
+            MockRule {
+                when: MockMatch::default(),
+                times: None,
+                reply: MockReply {
+                    content: Some("abcdefghij".repeat(10)),
+                    chunk_chars: Some(10),
+                    chunk_delay_ms: Some(100),
+                    ..MockReply::default()
+                },
+            },
+        ],
+    })
+    .await
+    .unwrap();
+    common::mock_registry(&server.base_url, WireFormat::OpenAi);
+
+    let t = Duration::from_millis(450);
+    let r = llm::chat_completion_stream("sys", "slow", t, |_, _| StreamControl::Continue)
+        .await
+        .unwrap();
+    assert_eq!(r.finish, "timeout");
+    assert!(r.partial);
+    assert!(
+        !r.content.is_empty() && r.content.len() < 100,
+        "{}",
+        r.content
+    );
+    assert!("abcdefghij".repeat(10).starts_with(&r.content));
+
+    let e = llm::chat_completion_stream("sys", "silent", t, |_, _| StreamControl::Continue)
+        .await
+        .unwrap_err();
+    assert!(e.contains("timed out"), "{e}");
+    llm::set_provider_registry(None);
+}
+
+#[tokio::test]
+async fn dropped_connection_is_truncated_and_not_cached() {
+    let _g = common::LOCK.lock().await;
+    let server = MockLlmServer::start(serve(MockReply {
+        content: Some("abcdefghij".repeat(3)),
+        chunk_chars: Some(5),
+        // Role event plus two text chunks, then the connection closes.
+        drop_after_events: Some(3),
+        ..MockReply::default()
+    }))
+    .await
+    .unwrap();
+    common::mock_registry(&server.base_url, WireFormat::OpenAi);
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("PROOFPATCH_LLM_CACHE_DIR", dir.path());
+    llm_cache::set_mode_override(Some(LlmCacheMode::ReadWrite));
+
+    let t = Duration::from_secs(5);
+    let r = llm::chat_completion_stream("sys", "prove", t, |_, _| StreamControl::Continue).await;
+    llm_cache::set_mode_override(None);
+    std::env::remove_var("PROOFPATCH_LLM_CACHE_DIR");
+    let r = r.unwrap();
+    assert_eq!(r.finish, "truncated");
+    assert!(r.partial);
+    assert_eq!(r.content, "abcdefghij");
+    assert!(r.error.is_some());
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    llm::set_provider_registry(None);
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use proofpatch_core::config::{LlmBudget, LlmConfig, ModelPrice};
+use proofpatch_core::{llm, llm_usage};
+use serde_json::json;
+
+#[test]
+fn usage_is_priced_and_budget_refuses_once_spent() {
+    let _g = common::LOCK.blocking_lock();
+    let mut cfg = LlmConfig {
+        budget: LlmBudget {
+            max_tokens: None,
+            max_cost_usd: Some(0.001),
+        },
+        ..LlmConfig::default()
+    };
+    cfg.prices.insert(
+        "m".to_string(),
+        ModelPrice {
+            input_per_mtok: 1.0,
+            output_per_mtok: 1.0,
+        },
+    );
+    cfg.prices.insert(
+        "mock/m".to_string(),
+        ModelPrice {
+            input_per_mtok: 2.0,
+            output_per_mtok: 10.0,
+        },
+    );
+    llm::set_provider_registry(Some(cfg));
+    llm_usage::set_budget_override(LlmBudget::default());
+    llm_usage::reset();
+
+    let raw = json!({ "usage": { "prompt_tokens": 100, "completion_tokens": 50 } });
+    let u = llm_usage::usage_from_response(&raw).unwrap();
+    assert_eq!(u.total_tokens, 150);
+    let u = llm_usage::record("mock", "m", u);
+    // The provider-qualified price wins over the bare model entry.
+    assert!((u.cost_usd.unwrap() - 0.0007).abs() < 1e-12);
+    assert!(llm_usage::check_budget("mock", "m").is_ok());
+
+    llm_usage::record("mock", "m", u);
+    let err = llm_usage::check_budget("mock", "m").unwrap_err();
+    assert!(err.starts_with(llm_usage::BUDGET_EXCEEDED));
+    let s = llm_usage::summary();
+    assert_eq!(s.calls, 2);
+    assert_eq!(s.total_tokens, 300);
+    assert_eq!(s.refused_calls, 1);
+    assert!(s.budget_exhausted);
+    assert_eq!(
+        s.budget_source.max_cost_usd,
+        llm_usage::BudgetSource::Config
+    );
+    assert_eq!(s.budget_source.max_tokens, llm_usage::BudgetSource::None);
+
+    // CLI flags override the config budget field by field.
+    llm_usage::set_budget_override(LlmBudget {
+        max_tokens: Some(1_000),
+        max_cost_usd: Some(1.0),
+    });
+    assert!(llm_usage::check_budget("mock", "m").is_ok());
+    assert_eq!(
+        llm_usage::summary().budget_source.max_tokens,
+        llm_usage::BudgetSource::Flag
+    );
+    // A cost limit cannot see calls to a model without a price, so they are refused.
+    let err = llm_usage::check_budget("other", "unpriced").unwrap_err();
+    assert!(err.starts_with(llm_usage::UNPRICED_MODEL), "{err}");
+    let est = llm_usage::estimate_usage(
+        &json!({ "messages": [{ "role": "user", "content": "x".repeat(4_000) }] }),
+        &json!({ "choices": [{ "message": { "content": "y".repeat(40) } }] }),
+    );
+    assert!(est.estimated);
+    assert_eq!(est.completion_tokens, 10);
+    llm_usage::record("other", "unpriced", est);
+    let s = llm_usage::summary();
+    assert_eq!(s.unpriced_calls, 1);
+    assert_eq!(s.estimated_calls, 1);
+    assert_eq!(s.by_model.len(), 2);
+    assert!(s.total_tokens >= 1_000);
+    assert!(llm_usage::check_budget("mock", "m").is_err());
+
+    // Without a cost limit they only count towards `max_tokens`.
+    llm::set_provider_registry(None);
+    llm_usage::set_budget_override(LlmBudget {
+        max_tokens: Some(1_000),
+        max_cost_usd: None,
+    });
+    llm_usage::reset();
+    assert!(llm_usage::check_budget("other", "unpriced").is_ok());
+
+    llm_usage::set_budget_override(LlmBudget::default());
+}
+
+#[test]
+fn anthropic_usage_is_normalized() {
+    let raw = proofpatch_core::llm_wire::anthropic_to_openai_response(&json!({
+        "content": [{ "type": "text", "text": "ok" }],
+        "stop_reason": "end_turn",
+        "usage": { "input_tokens": 11, "output_tokens": 7 },
+    }));
+    let u = llm_usage::usage_from_response(&raw).unwrap();
+    assert_eq!(
+        (u.prompt_tokens, u.completion_tokens, u.total_tokens),
+        (11, 7, 18)
+    );
+    assert!(!u.estimated);
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use proofpatch_core::config::{LlmProviderConfig, WireFormat};
+use proofpatch_core::mock_llm::{
+    MockLlmServer, MockMatch, MockReply, MockRule, MockScript, MockToolCall, MockUsage,
+};
+use proofpatch_core::{llm, llm_wire};
+use serde_json::json;
+use std::time::Duration;
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct Decision {
+    confidence: f64,
+    rationale: String,
+}
+
+fn script() -> MockScript {
+    let emit = MockToolCall {
+        name: "emit".to_string(),
+        arguments: json!({ "confidence": 0.75, "rationale": "split on n" }),
+    };
+    let usage = Some(MockUsage {
+        prompt_tokens: 11,
+        completion_tokens: 7,
+    });
+    let rule = |tool: Option<&str>, path: Option<&str>, reply: MockReply| MockRule {
+        when: MockMatch {
+            tool: tool.map(|s| s.to_string()),
+            path: path.map(|s| s.to_string()),
+            ..MockMatch::default()
+        },
+        times: None,
+        reply,
+    };
+    MockScript {
+        models: vec![],
+        rules: vec![
+            rule(
+                Some("emit"),
+                Some("/messages"),
+                MockReply {
+                    tool_calls: vec![emit.clone()],
+                    usage,
+                    ..MockReply::default()
+                },
+            ),
+            rule(
+                Some("emit"),
+                None,
+                MockReply {
+                    tool_calls: vec![emit],
+                    ..MockReply::default()
+                },
+            ),
+            rule(
+                None,
+                Some("/messages"),
+                MockReply {
+                    content: Some("hello from messages".to_string()),
+                    usage,
+                    ..MockReply::default()
+                },
+            ),
+            rule(
+                None,
+                None,
+                MockReply {
+                    content: Some("hello from chat".to_string()),
+                    ..MockReply::default()
+                },
+            ),
+        ],
+    }
+}
+
+#[test]
+fn anthropic_request_translation_maps_roles_and_tools() {
+    let payload = json!({
+        "model": "m",
+        "temperature": 0.2,
+        "messages": [
+            { "role": "system", "content": "be terse" },
+            { "role": "user", "content": "hi" },
+            { "role": "assistant", "content": null, "tool_calls": [
+                { "id": "c1", "type": "function", "function": { "name": "read", "arguments": "{\"path\":\"A.lean\"}" } },
+                { "id": "c2", "type": "function", "function": { "name": "read", "arguments": "{\"path\":\"B.lean\"}" } }
+            ]},
+            { "role": "tool", "tool_call_id": "c1", "content": "a" },
+            { "role": "tool", "tool_call_id": "c2", "content": "b" }
+        ],
+        "tools": [{ "type": "function", "function": { "name": "read", "description": "d", "parameters": { "type": "object" } } }],
+        "tool_choice": "auto"
+    });
+    let out = llm_wire::openai_to_anthropic_request(&payload);
+    assert_eq!(out["system"], "be terse");
+    assert_eq!(out["max_tokens"], llm_wire::ANTHROPIC_DEFAULT_MAX_TOKENS);
+    let msgs = out["messages"].as_array().unwrap();
+    assert_eq!(msgs.len(), 3);
+    assert_eq!(msgs[1]["content"][1]["type"], "tool_use");
+    assert_eq!(msgs[1]["content"][1]["input"]["path"], "B.lean");
+    // Both tool results share one user turn.
+    assert_eq!(msgs[2]["role"], "user");
+    assert_eq!(msgs[2]["content"][1]["tool_use_id"], "c2");
+    assert_eq!(out["tools"][0]["input_schema"]["type"], "object");
+    assert_eq!(out["tool_choice"]["type"], "auto");
+}
+
+#[tokio::test]
+async fn structured_calls_work_across_wire_formats() {
+    let _g = common::LOCK.lock().await;
+    std::env::set_var("MOCK_ANTHROPIC_KEY", "sk-test");
+    let server = MockLlmServer::start(script()).await.unwrap();
+    let base = server.base_url.clone();
+
+    for (wire, key_env) in [
+        (WireFormat::OpenAi, None),
+        (WireFormat::Anthropic, Some("MOCK_ANTHROPIC_KEY")),
+    ] {
+        common::registry(&[(
+            "mock",
+            LlmProviderConfig {
+                api_key_env: key_env.map(|s| s.to_string()),
+                ..common::provider(&base, wire)
+            },
+        )]);
+        let done = llm::chat_completion_structured::<Decision>(
+            "You are a planner.",
+            "{}",
+            Duration::from_secs(10),
+        )
+        .await
+        .expect("structured completion");
+        assert_eq!(done.provider, "mock");
+        assert_eq!(done.mode, "tool_call");
+        assert_eq!(done.value.confidence, 0.75);
+        assert_eq!(done.value.rationale, "split on n");
+        if wire == WireFormat::Anthropic {
+            assert_eq!(done.usage.prompt_tokens, 11);
+            assert!(!done.usage.estimated);
+        } else {
+            // The mock's chat-completions replies carry no `usage` block.
+            assert!(done.usage.estimated);
+        }
+
+        let plain = llm::chat_completion("sys", "hi", Duration::from_secs(10))
+            .await
+            .expect("chat completion");
+        assert!(plain.content.starts_with("hello from"));
+    }
+    llm::set_provider_registry(None);
+
+    let seen = server.requests();
+    assert_eq!(seen.len(), 4);
+    assert_eq!(seen[0].path, "/v1/chat/completions");
+    assert_eq!(seen[0].body["tool_choice"]["function"]["name"], "emit");
+    assert_eq!(seen[2].path, "/v1/messages");
+    assert_eq!(seen[2].header("x-api-key"), Some("sk-test"));
+    assert_eq!(
+        seen[2].header("anthropic-version"),
+        Some(llm_wire::ANTHROPIC_VERSION)
+    );
+    assert!(seen[2].header("authorization").is_none());
+    assert_eq!(seen[2].body["system"], "You are a planner.");
+    assert_eq!(
+        seen[2].body["tool_choice"],
+        json!({ "type": "tool", "name": "emit" })
+    );
+    assert_eq!(seen[3].body["messages"][0]["content"][0]["text"], "hi");
+}
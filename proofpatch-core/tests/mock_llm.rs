@@ -0,0 +1,204 @@
+mod common;
+
+use proofpatch_core::config::{LlmProviderConfig, WireFormat};
+use proofpatch_core::llm;
+use proofpatch_core::mock_llm::{MockLlmServer, MockMatch, MockReply, MockRule, MockScript};
+use serde_json::json;
+use std::time::Duration;
+
+fn probed(base_url: &str) -> LlmProviderConfig {
+    LlmProviderConfig {
+        probe: Some(true),
+        ..common::provider(base_url, WireFormat::OpenAi)
+    }
+}
+
+fn script(rules: Vec<MockRule>) -> MockScript {
+    MockScript {
+        models: vec![],
+        rules,
+    }
+}
+
+fn reply(r: MockReply) -> MockRule {
+    MockRule {
+        when: MockMatch::default(),
+        times: None,
+        reply: r,
+    }
+}
+
+#[tokio::test]
+async fn fixture_rules_match_in_order_and_run_out() {
+    let _g = common::LOCK.lock().await;
+    let s: MockScript = serde_json::from_value(json!({
+        "rules": [
+            { "when": { "contains": "theorem foo" }, "times": 1, "reply": { "content": "first" } },
+            { "reply": { "content": "rest", "usage": { "prompt_tokens": 3, "completion_tokens": 2 } } }
+        ]
+    }))
+    .unwrap();
+    let server = MockLlmServer::start(s).await.unwrap();
+    common::mock_registry(&server.base_url, WireFormat::OpenAi);
+
+    let t = Duration::from_secs(5);
+    let a = llm::chat_completion("sys", "theorem foo : True", t)
+        .await
+        .unwrap();
+    let b = llm::chat_completion("sys", "theorem foo : True", t)
+        .await
+        .unwrap();
+    assert_eq!(a.content, "first");
+    assert_eq!(b.content, "rest");
+    assert_eq!(b.usage.total_tokens, 5);
+    let reqs = server.requests();
+    assert_eq!(
+        reqs.iter().map(|r| r.rule).collect::<Vec<_>>(),
+        vec![Some(0), Some(1)]
+    );
+    llm::set_provider_registry(None);
+}
+
+#[tokio::test]
+async fn errors_malformed_json_and_latency_surface_as_errors() {
+    let _g = common::LOCK.lock().await;
+    let server = MockLlmServer::start(script(vec![
+        MockRule {
+            when: MockMatch {
+                contains: Some("overloaded".to_string()),
+                ..MockMatch::default()
+            },
+            times: None,
+            reply: MockReply {
+                status: Some(503),
+                error: Some("overloaded".to_string()),
+                ..MockReply::default()
+            },
+        },
+        MockRule {
+            when: MockMatch {
+                contains: Some("garbled".to_string()),
+                ..MockMatch::default()
+            },
+            times: None,
+            reply: MockReply {
+                body: Some("{\"choices\": [".to_string()),
+                ..MockReply::default()
+            },
+        },
+        reply(MockReply {
+            content: Some("late".to_string()),
+            delay_ms: Some(2_000),
+            ..MockReply::default()
+        }),
+    ]))
+    .await
+    .unwrap();
+    common::mock_registry(&server.base_url, WireFormat::OpenAi);
+
+    let t = Duration::from_millis(300);
+    let e = llm::chat_completion("sys", "overloaded", t)
+        .await
+        .unwrap_err();
+    assert!(e.contains("returned 503"), "{e}");
+    let e = llm::chat_completion("sys", "garbled", t).await.unwrap_err();
+    assert!(e.contains("json decode"), "{e}");
+    let e = llm::chat_completion("sys", "slow", t).await.unwrap_err();
+    assert!(e.contains("http"), "{e}");
+    llm::set_provider_registry(None);
+}
+
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct Pick {
+    index: u32,
+}
+
+#[tokio::test]
+async fn structured_output_falls_back_to_content_when_tools_are_ignored() {
+    let _g = common::LOCK.lock().await;
+    let server = MockLlmServer::start(script(vec![reply(MockReply {
+        content: Some("Sure: {\"index\": 2}".to_string()),
+        ..MockReply::default()
+    })]))
+    .await
+    .unwrap();
+    common::mock_registry(&server.base_url, WireFormat::OpenAi);
+
+    let done = llm::chat_completion_structured::<Pick>("sys", "{}", Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(done.value.index, 2);
+    assert_eq!(done.mode, "content_extract");
+    // The request still offered the `emit` tool.
+    assert_eq!(
+        server.requests()[0].body["tools"][0]["function"]["name"],
+        "emit"
+    );
+    llm::set_provider_registry(None);
+}
+
+#[tokio::test]
+async fn unreachable_providers_are_skipped_in_favor_of_a_live_one() {
+    let _g = common::LOCK.lock().await;
+    let server = MockLlmServer::start(script(vec![reply(MockReply {
+        content: Some("ok".to_string()),
+        ..MockReply::default()
+    })]))
+    .await
+    .unwrap();
+    common::registry(&[
+        ("down", probed("http://127.0.0.1:9/v1")),
+        ("mock", probed(&server.base_url)),
+    ]);
+
+    let info = llm::select_provider_info(Duration::from_secs(2))
+        .await
+        .unwrap();
+    assert_eq!(info.provider, "mock");
+    assert_eq!(info.skipped.len(), 1);
+    assert_eq!(info.skipped[0].provider, "down");
+    let done = llm::chat_completion("sys", "hi", Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(done.content, "ok");
+    // The probe hit `/models` before the completion.
+    let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+    assert!(paths.first().is_some_and(|p| p.ends_with("/models")));
+    assert!(paths
+        .last()
+        .is_some_and(|p| p.ends_with("/chat/completions")));
+    llm::set_provider_registry(None);
+}
+
+#[cfg(feature = "planner")]
+#[tokio::test]
+async fn planner_decision_comes_back_through_the_emit_tool() {
+    use proofpatch_core::mock_llm::MockToolCall;
+    use proofpatch_core::planner;
+
+    let _g = common::LOCK.lock().await;
+    let server = MockLlmServer::start(script(vec![reply(MockReply {
+        tool_calls: vec![MockToolCall {
+            name: "emit".to_string(),
+            arguments: json!({
+                "confidence": 0.8,
+                "focus_line_1": 12,
+                "oracle_passes": null,
+                "oracle_tactics": ["simp?"],
+                "ban_oracle_tactics": [],
+                "rationale": "mock"
+            }),
+        }],
+        ..MockReply::default()
+    })]))
+    .await
+    .unwrap();
+    common::mock_registry(&server.base_url, WireFormat::OpenAi);
+
+    let (d, _json) = planner::plan("plan", "{}", Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(d.focus_line_1, Some(12));
+    assert_eq!(d.oracle_tactics, vec!["simp?".to_string()]);
+    llm::set_provider_registry(None);
+}
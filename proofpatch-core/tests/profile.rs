@@ -0,0 +1,122 @@
+use proofpatch_core::profile;
+
+const SRC: &str = r#"import Mathlib
+
+namespace Foo
+
+/-- A doc comment. -/
+@[simp]
+theorem slow : 1 + 1 = 2 := by
+  simp
+
+lemma fast : True := trivial
+
+end Foo
+"#;
+
+#[test]
+fn profiled_source_keeps_decl_lines_aligned() {
+    let decls = proofpatch_core::index_decls_in_text(SRC);
+    let src = profile::profiled_source(SRC, &decls, 25, true);
+    let lines: Vec<&str> = src.text.lines().collect();
+    assert_eq!(src.inserted_at, 1);
+    assert_eq!(lines[1], "set_option profiler true");
+    assert_eq!(lines[2], "set_option profiler.threshold 25");
+    // `count_heartbeats in` goes in front of the docstring, not between it and the decl.
+    assert_eq!(lines[4 + 2], "count_heartbeats in /-- A doc comment. -/");
+    assert_eq!(
+        lines[9 + 2],
+        "count_heartbeats in lemma fast : True := trivial"
+    );
+    assert_eq!(src.original_line(7 + 2), Some(7));
+    assert_eq!(src.original_line(2), None);
+}
+
+#[test]
+fn profiler_output_is_attributed_to_decls_and_tactics() {
+    let out = "\
+/tmp/x.lean:6:0: info: tactic execution of Lean.Parser.Tactic.simp took 1.5s
+simp took 700ms
+/tmp/x.lean:6:0: info: Used 12345 heartbeats, which is less than the current maximum of 200000.
+/tmp/x.lean:10:0: info: elaboration took 20ms
+
+import took 300ms
+cumulative profiling times:
+\telaboration 1.52s
+\tsimp 700ms
+";
+    let (items, cumulative) = profile::parse_profiler_output(out);
+    assert_eq!(items.len(), 4);
+    assert_eq!(items[0].category, "tactic execution");
+    assert_eq!(items[0].ms, 1500.0);
+    assert_eq!(items[1].line, Some(6));
+    assert_eq!(items[3].line, None);
+    assert_eq!(cumulative.get("simp"), Some(&700.0));
+
+    let hb = profile::parse_heartbeats_output(out);
+    assert_eq!(hb, vec![(6, 12345)]);
+
+    let decls = proofpatch_core::index_decls_in_text(SRC);
+    let (ranked, unattributed) = profile::attribute_profile(SRC, &decls, &items, &hb);
+    assert_eq!(ranked[0].name, "Foo.slow");
+    assert_eq!(ranked[0].total_ms, 2200.0);
+    assert_eq!(ranked[0].heartbeats, Some(12345));
+    assert_eq!(ranked[0].tactics[0].name, "simp");
+    assert_eq!(ranked[1].name, "Foo.fast");
+    assert_eq!(ranked[1].total_ms, 20.0);
+    assert_eq!(unattributed.get("import"), Some(&300.0));
+}
+
+#[test]
+fn plain_block_comments_do_not_join_the_decl_below() {
+    let src = r#"import Mathlib
+
+/-- First. -/
+theorem a : True := trivial
+
+/- A section note,
+   not a docstring. -/
+theorem b : True := trivial
+
+/-- Third. -/
+theorem c : True := trivial
+"#;
+    let decls = proofpatch_core::index_decls_in_text(src);
+    let p = profile::profiled_source(src, &decls, 0, true);
+    let lines: Vec<&str> = p.text.lines().collect();
+    // Shifted by the two `set_option` lines.
+    assert_eq!(lines[2 + 2], "count_heartbeats in /-- First. -/");
+    assert_eq!(lines[3 + 2], "theorem a : True := trivial");
+    assert_eq!(lines[5 + 2], "/- A section note,");
+    assert_eq!(
+        lines[7 + 2],
+        "count_heartbeats in theorem b : True := trivial"
+    );
+    assert_eq!(lines[9 + 2], "count_heartbeats in /-- Third. -/");
+
+    // Time reported on `b` belongs to `b`, not to `a` above the comment.
+    let out = "/tmp/x.lean:8:0: info: elaboration took 40ms\n";
+    let (items, _) = profile::parse_profiler_output(out);
+    let (ranked, _) = profile::attribute_profile(src, &decls, &items, &[]);
+    assert_eq!(ranked[0].name, "b");
+    assert_eq!(ranked[0].total_ms, 40.0);
+}
+
+#[test]
+fn decl_profile_covers_only_the_target_decl() {
+    let p = profile::profiled_decl_source(SRC, 7, 50).unwrap();
+    let lines: Vec<&str> = p.lines().collect();
+    assert_eq!(lines.len(), SRC.lines().count());
+    assert_eq!(
+        lines[4],
+        "set_option profiler true in set_option profiler.threshold 50 in /-- A doc comment. -/"
+    );
+    assert_eq!(lines[9], "lemma fast : True := trivial");
+
+    let out = "\
+/tmp/x.lean:7:0: info: simp took 900ms
+/tmp/x.lean:10:0: info: elaboration took 5s
+";
+    assert_eq!(profile::decl_profile_ms(SRC, 8, out), Some(900.0));
+    assert_eq!(profile::decl_profile_ms(SRC, 1, out), None);
+}
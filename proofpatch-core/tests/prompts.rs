@@ -0,0 +1,185 @@
+use proofpatch_core::prompts::{self, PromptTemplates};
+use std::path::Path;
+
+// Tests here share the installed templates.
+static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+fn lean_repo(dir: &Path) {
+    std::fs::write(dir.join("lean-toolchain"), "leanprover/lean4:v4.20.0\n").unwrap();
+    std::fs::write(dir.join("lakefile.lean"), "import Lake\nopen Lake DSL\n").unwrap();
+    std::fs::write(dir.join("Foo.lean"), "theorem foo : True := by\n  sorry\n").unwrap();
+    std::env::set_var("PROOFPATCH_DOTENV_SEARCH", "0");
+}
+
+#[test]
+fn builtin_defaults_match_the_historical_prompts() {
+    let _g = LOCK.lock().unwrap();
+    prompts::set_templates(None);
+    assert_eq!(
+        proofpatch_core::proof_user_prompt("theorem foo : True := by\n  sorry"),
+        "We are working in a Lean 4 + Mathlib project.\nHere is the declaration context (excerpt):\n\ntheorem foo : True := by\n  sorry\n\nTask: provide the Lean proof code that replaces the `sorry`/`admit` (the proof term only)."
+    );
+    assert!(proofpatch_core::proof_system_prompt()
+        .starts_with("You are a Lean 4 proof assistant.\nReturn ONLY Lean code"));
+
+    let t = PromptTemplates::default();
+    let region = |diag: &str| {
+        t.render(
+            "region_patch_user",
+            &prompts::vars([
+                ("excerpt", "  sorry"),
+                ("diagnostics", diag),
+                ("start_line", "3"),
+                ("end_line", "4"),
+            ]),
+        )
+        .unwrap()
+    };
+    assert_eq!(
+        region(""),
+        "We are working in a Lean 4 + Mathlib project.\n\nHere is a focused region (lines 3..=4):\n\n  sorry\n\nTask: provide the Lean proof code that replaces the `sorry` (the proof term only)."
+    );
+    assert_eq!(
+        region("F.lean:3:2: error: boom"),
+        "We are working in a Lean 4 + Mathlib project.\n\nRecent Lean diagnostics (raw):\nF.lean:3:2: error: boom\n\nHere is a focused region (lines 3..=4):\n\n  sorry\n\nTask: provide the Lean proof code that replaces the `sorry` (the proof term only)."
+    );
+
+    // Every default passes its own validation.
+    for spec in prompts::TEMPLATES {
+        prompts::validate(spec.name, spec.default).unwrap();
+    }
+}
+
+#[test]
+fn sections_render_only_for_set_variables() {
+    let src = "A{{#goal}}\ngoal: {{ goal }}{{#hints}} ({{hints}}){{/hints}}{{/goal}}.";
+    let r = |pairs: &[(&str, &str)]| {
+        prompts::render_source(src, &prompts::vars(pairs.iter().copied())).unwrap()
+    };
+    assert_eq!(r(&[]), "A.");
+    assert_eq!(r(&[("goal", "  ")]), "A.");
+    assert_eq!(r(&[("goal", "⊢ True")]), "A\ngoal: ⊢ True.");
+    assert_eq!(
+        r(&[("goal", "⊢ True"), ("hints", "use trivial")]),
+        "A\ngoal: ⊢ True (use trivial)."
+    );
+    assert_eq!(prompts::template_vars(src).unwrap(), vec!["goal", "hints"]);
+
+    let err = |src: &str| prompts::render_source(src, &prompts::PromptVars::new()).unwrap_err();
+    assert!(err("{{#goal}}x").contains("never closed"));
+    assert!(err("x{{/goal}}").contains("does not close"));
+    assert!(err("{{goal").contains("unclosed"));
+    assert!(err("{{Goal}}").contains("lower_snake_case"));
+
+    let e = prompts::validate("proof_user", "{{excerpt}} {{diagnostics}}").unwrap_err();
+    assert!(e.contains("unknown variable `diagnostics`"), "{e}");
+    assert!(prompts::validate("proof_usr", "x")
+        .unwrap_err()
+        .contains("unknown prompt template"));
+}
+
+#[test]
+fn repo_templates_override_builtins_and_reach_the_builders() {
+    let _g = LOCK.lock().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path());
+    let tdir = dir.path().join(".proofpatch").join("prompts");
+    std::fs::create_dir_all(&tdir).unwrap();
+    std::fs::write(tdir.join("proof_system.txt"), "FILE SYSTEM").unwrap();
+    std::fs::write(tdir.join("proof_user.txt"), "FILE USER {{excerpt}}").unwrap();
+    // Not a template name: skipped, not an error.
+    std::fs::write(tdir.join("README.txt"), "Notes on our prompts.").unwrap();
+    std::fs::write(
+        dir.path().join("proofpatch.toml"),
+        "[prompts.templates]\nproof_user = \"Prove it.\\n{{excerpt}}{{#hints}}\\nHints: {{hints}}{{/hints}}\"\n",
+    )
+    .unwrap();
+
+    let t = PromptTemplates::load(dir.path()).unwrap();
+    assert_eq!(
+        t.get("proof_system").unwrap(),
+        (
+            "FILE SYSTEM",
+            tdir.join("proof_system.txt").display().to_string().as_str()
+        )
+    );
+    assert_eq!(t.get("proof_user").unwrap().1, "proofpatch.toml");
+    assert_eq!(t.get("region_patch_user").unwrap().1, "builtin");
+    assert!(t.get("README").is_none());
+    assert_eq!(
+        t.render(
+            "proof_user",
+            &prompts::vars([("excerpt", "theorem foo"), ("hints", "omega")])
+        )
+        .unwrap(),
+        "Prove it.\ntheorem foo\nHints: omega"
+    );
+
+    // `load_dotenv_smart` (run by the builders) installs them.
+    let payload = proofpatch_core::build_proof_prompt(dir.path(), "Foo.lean", "foo").unwrap();
+    assert_eq!(payload.system, "FILE SYSTEM");
+    assert!(payload
+        .user
+        .starts_with("Prove it.\ntheorem foo : True := by"));
+
+    // A bad override is reported by `load` and ignored by the builders.
+    std::fs::write(tdir.join("proof_system.txt"), "{{nope}}").unwrap();
+    let e = PromptTemplates::load(dir.path()).unwrap_err();
+    assert!(
+        e.contains("proof_system.txt") && e.contains("unknown variable `nope`"),
+        "{e}"
+    );
+    let payload = proofpatch_core::build_proof_prompt(dir.path(), "Foo.lean", "foo").unwrap();
+    assert!(payload
+        .system
+        .starts_with("You are a Lean 4 proof assistant."));
+    prompts::set_templates(None);
+}
+
+#[test]
+fn builders_fill_the_extras_they_are_given() {
+    let _g = LOCK.lock().unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path());
+    std::fs::write(
+        dir.path().join("Foo.lean"),
+        "import Mathlib.Tactic\n\ntheorem bar : True := trivial\n\ntheorem foo : True := by\n  sorry\n",
+    )
+    .unwrap();
+    let txt = std::fs::read_to_string(dir.path().join("Foo.lean")).unwrap();
+    let pack =
+        proofpatch_core::build_context_pack_in_text(&txt, None, Some(6), 0, 80, 12, 30).unwrap();
+    let extras = prompts::PromptExtras {
+        goal: Some("⊢ True".to_string()),
+        context_pack: Some(prompts::context_pack_text(&pack)),
+        research_notes: Some("  see notes.md  ".to_string()),
+        hints: None,
+    };
+    let payload =
+        proofpatch_core::build_region_patch_prompt(dir.path(), "Foo.lean", 5, 6, None, &extras)
+            .unwrap();
+    assert!(
+        payload.user.contains(
+            "  sorry\n\nGoal state:\n⊢ True\n\nContext pack:\nImports:\n- import Mathlib.Tactic\n\
+             Nearby declarations:\n- line 3: theorem bar : True := trivial\n\
+             - line 5: theorem foo : True := by\n\nResearch notes:\nsee notes.md\n\nTask:"
+        ),
+        "{}",
+        payload.user
+    );
+    assert!(!payload.user.contains("Hints:"));
+
+    let payload = proofpatch_core::build_rubberduck_prompt(
+        dir.path(),
+        "Foo.lean",
+        "foo",
+        None,
+        &prompts::PromptExtras {
+            hints: Some("try trivial".to_string()),
+            ..prompts::PromptExtras::default()
+        },
+    )
+    .unwrap();
+    assert!(payload.user.contains("\n\nHints:\ntry trivial\n\n"));
+    assert!(!payload.user.contains("Goal state:"));
+}
@@ -0,0 +1,118 @@
+use proofpatch_core::repair::{self, RepairConversation};
+use proofpatch_core::VerifyResult;
+
+const BEFORE: &str = "theorem foo (n : Nat) : n + 0 = n := by\n  sorry\n";
+
+fn verify(ok: bool, stdout: &str) -> VerifyResult {
+    VerifyResult {
+        ok,
+        timeout: false,
+        returncode: Some(if ok { 0 } else { 1 }),
+        stdout: stdout.to_string(),
+        stderr: String::new(),
+        cmd: vec![],
+        cwd: String::new(),
+        tmp_file: None,
+    }
+}
+
+#[test]
+fn feedback_carries_error_goal_and_diff() {
+    let out = "/tmp/pp_1.lean:2:2: error: unsolved goals\nn : Nat\n⊢ n + 0 =\n    n\n/tmp/pp_1.lean:5:0: warning: unused variable";
+    let after = BEFORE.replace("sorry", "intro");
+    let f = repair::attempt_feedback(1, "intro", BEFORE, &after, "foo", &verify(false, out));
+
+    assert_eq!(
+        f.first_error_loc.as_ref().map(|l| (l.line, l.col)),
+        Some((2, 2))
+    );
+    assert_eq!(
+        f.first_error.as_deref(),
+        Some("/tmp/pp_1.lean:2:2: error: unsolved goals\nn : Nat\n⊢ n + 0 =\n    n")
+    );
+    assert_eq!(f.goal.as_deref(), Some("n : Nat\n⊢ n + 0 =\n    n"));
+    assert!(f.diff.contains("-  sorry\n+  intro\n"), "{}", f.diff);
+
+    let turn = f.render();
+    assert!(turn.contains("line 2, column 2"));
+    assert!(turn.contains("error: unsolved goals"));
+    assert!(!turn.contains("/tmp/pp_1.lean"));
+    assert!(turn.contains("The patch was discarded"));
+    assert_eq!(
+        f.summary_line(),
+        "- attempt 1: `intro` -> error at line 2: unsolved goals"
+    );
+
+    // Errors without a goal state (and no diagnostics at all).
+    let f = repair::attempt_feedback(
+        2,
+        "exact bad",
+        BEFORE,
+        &BEFORE.replace("sorry", "exact bad"),
+        "foo",
+        &verify(false, "F.lean:2:8: error: unknown identifier 'bad'"),
+    );
+    assert_eq!(f.goal, None);
+    // Those get their goal from a dump at the error line instead.
+    assert_eq!(
+        repair::goal_probe_text(&BEFORE.replace("sorry", "exact bad"), 2).as_deref(),
+        Some("theorem foo (n : Nat) : n + 0 = n := by\n  sorry\n  exact bad\n")
+    );
+    assert_eq!(repair::goal_probe_text(BEFORE, 1), None);
+    let dump = serde_json::json!({ "goals": [{ "pretty": "n : Nat\n⊢ n + 0 = n" }] });
+    assert_eq!(
+        repair::goal_from_pp_dump(&dump).as_deref(),
+        Some("n : Nat\n⊢ n + 0 = n")
+    );
+    let f = repair::attempt_feedback(3, "simp", BEFORE, BEFORE, "foo", &verify(false, ""));
+    assert!(f.first_error.is_none() && f.diff.is_empty());
+    assert!(f.render().contains("possibly a timeout"));
+}
+
+#[test]
+fn old_turns_are_summarized_past_the_byte_budget() {
+    let mut c = RepairConversation::new("sys".into(), "task".into(), 100_000);
+    for i in 1..=4 {
+        let proof = format!("simp [lemma_{i}]");
+        let after = BEFORE.replace("sorry", &proof);
+        let out = format!(
+            "F.lean:2:2: error: simp made no progress {}",
+            "x".repeat(600)
+        );
+        c.push(repair::attempt_feedback(
+            i,
+            &proof,
+            BEFORE,
+            &after,
+            "foo",
+            &verify(false, &out),
+        ));
+    }
+    // Under budget: system, task, and an assistant/user pair per attempt.
+    let m = c.messages();
+    assert_eq!(m.len(), 2 + 2 * 4);
+    assert_eq!(m[2]["role"], "assistant");
+    assert_eq!(m[2]["content"], "simp [lemma_1]");
+    assert_eq!(c.stats().summarized, 0);
+
+    // Tight budget: all but the latest attempt collapse into one summary turn.
+    c.max_bytes = 1;
+    let m = c.messages();
+    assert_eq!(m.len(), 2 + 1 + 2);
+    let summary = m[2]["content"].as_str().unwrap();
+    assert!(summary.starts_with("Earlier attempts (summarized):"));
+    assert_eq!(summary.lines().count(), 4);
+    assert!(
+        summary.contains("- attempt 3: `simp [lemma_3]` -> error at line 2: simp made no progress")
+    );
+    assert_eq!(m[3]["content"], "simp [lemma_4]");
+    let stats = c.stats();
+    assert_eq!((stats.attempts, stats.summarized), (4, 3));
+
+    // A budget in between keeps the newest turns in full.
+    let full = c.attempts[3].render().len() + c.attempts[3].replacement.len();
+    c.max_bytes = 2 * full + 500;
+    let stats = c.stats();
+    assert_eq!(stats.summarized, 2);
+    assert!(stats.history_bytes <= c.max_bytes);
+}
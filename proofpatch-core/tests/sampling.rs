@@ -0,0 +1,215 @@
+mod common;
+
+use proofpatch_core::config::{LlmBudget, WireFormat};
+use proofpatch_core::mock_llm::{
+    MockLlmServer, MockMatch, MockReply, MockRule, MockScript, MockUsage,
+};
+use proofpatch_core::{llm, llm_usage, sampling};
+use std::path::Path;
+use std::time::Duration;
+
+fn rule(times: Option<u64>, reply: MockReply) -> MockRule {
+    MockRule {
+        when: MockMatch::default(),
+        times,
+        reply,
+    }
+}
+
+fn text(s: &str) -> MockReply {
+    MockReply {
+        content: Some(s.to_string()),
+        ..MockReply::default()
+    }
+}
+
+#[test]
+fn proposals_dedupe_across_fences_by_and_whitespace() {
+    let samples: Vec<String> = [
+        "omega",
+        "by simp",
+        "```lean\nsimp\n```",
+        "  simp  ",
+        ":= by\n  omega",
+        "",
+        "ring",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect();
+    assert_eq!(
+        sampling::normalize_proof("by\n  intro n\n  simp"),
+        "intro n simp"
+    );
+    assert_eq!(sampling::normalize_proof("byContra h"), "byContra h");
+
+    let ranked = sampling::rank_candidates(&samples);
+    let got: Vec<(&str, usize)> = ranked
+        .iter()
+        .map(|c| (c.normalized.as_str(), c.votes))
+        .collect();
+    assert_eq!(got, vec![("simp", 3), ("omega", 2), ("ring", 1)]);
+    assert_eq!(ranked[0].proof, "by simp");
+    assert_eq!(ranked[0].samples, vec![1, 2, 3]);
+}
+
+#[tokio::test]
+async fn samples_use_native_n_and_top_up_in_parallel() {
+    let _g = common::LOCK.lock().await;
+    let native = MockLlmServer::start(MockScript {
+        models: vec![],
+        rules: vec![rule(
+            None,
+            MockReply {
+                n_choices: Some(8),
+                ..text("simp")
+            },
+        )],
+    })
+    .await
+    .unwrap();
+    common::mock_registry(&native.base_url, WireFormat::OpenAi);
+    let t = Duration::from_secs(5);
+    let s = llm::chat_completion_samples("sys", "prove", 3, Some(0.8), t)
+        .await
+        .unwrap();
+    assert_eq!(s.mode, "native");
+    assert_eq!((s.samples.len(), s.requests), (3, 1));
+    let reqs = native.requests();
+    assert_eq!(reqs[0].body["n"], 3);
+    assert_eq!(reqs[0].body["temperature"], 0.8);
+
+    // Ignores `n`: one choice comes back, the other two are requested separately.
+    let ignoring = MockLlmServer::start(MockScript {
+        models: vec![],
+        rules: vec![rule(Some(1), text("omega")), rule(None, text("simp"))],
+    })
+    .await
+    .unwrap();
+    common::mock_registry(&ignoring.base_url, WireFormat::OpenAi);
+    let s = llm::chat_completion_samples("sys", "prove", 3, None, t)
+        .await
+        .unwrap();
+    assert_eq!(s.mode, "native+parallel");
+    assert_eq!(s.samples, vec!["omega", "simp", "simp"]);
+    let mut seeds: Vec<u64> = ignoring.requests()[1..]
+        .iter()
+        .map(|r| r.body["seed"].as_u64().unwrap())
+        .collect();
+    seeds.sort();
+    assert_eq!(seeds, vec![1, 2]);
+
+    // Anthropic has no `n`: all parallel.
+    common::mock_registry(&ignoring.base_url, WireFormat::Anthropic);
+    let s = llm::chat_completion_samples("sys", "prove", 2, None, t)
+        .await
+        .unwrap();
+    assert_eq!((s.mode.as_str(), s.requests), ("parallel", 2));
+    llm::set_provider_registry(None);
+}
+
+#[tokio::test]
+async fn parallel_samples_respect_the_budget() {
+    let _g = common::LOCK.lock().await;
+    let server = MockLlmServer::start(MockScript {
+        models: vec![],
+        rules: vec![rule(
+            None,
+            MockReply {
+                usage: Some(MockUsage {
+                    prompt_tokens: 40,
+                    completion_tokens: 2,
+                }),
+                ..text("simp")
+            },
+        )],
+    })
+    .await
+    .unwrap();
+    common::mock_registry(&server.base_url, WireFormat::Anthropic);
+    llm_usage::reset();
+    llm_usage::set_budget_override(LlmBudget {
+        max_tokens: Some(10),
+        max_cost_usd: None,
+    });
+    let s = llm::chat_completion_samples("sys", "prove", 4, None, Duration::from_secs(5))
+        .await
+        .unwrap();
+    // The first response spends the budget; the other three are refused before being sent.
+    assert_eq!(s.samples, vec!["simp"]);
+    assert_eq!(server.requests().len(), 1);
+    assert_eq!(llm_usage::summary().refused_calls, 3);
+    llm_usage::set_budget_override(LlmBudget::default());
+    llm_usage::reset();
+    llm::set_provider_registry(None);
+}
+
+fn lean_repo(dir: &Path) {
+    std::fs::write(dir.join("lean-toolchain"), "leanprover/lean4:v4.20.0\n").unwrap();
+    std::fs::write(dir.join("lakefile.lean"), "import Lake\nopen Lake DSL\n").unwrap();
+    // `lake env lean <file>` stand-in: rejects `bad`, warns about `sorry`.
+    let lake = dir.join("fake-lake.sh");
+    std::fs::write(
+        &lake,
+        "#!/bin/sh\nf=\"$3\"\nif grep -q bad \"$f\"; then echo \"$f:2:2: error: unknown identifier 'bad'\"; exit 1; fi\nif grep -q sorry \"$f\"; then echo \"$f:2:2: warning: declaration uses 'sorry'\"; fi\nexit 0\n",
+    )
+    .unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&lake, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    std::env::set_var("LAKE", &lake);
+    std::env::set_var("PROOFPATCH_VERIFY_BACKEND", "lake");
+    std::env::set_var("PROOFPATCH_AUTO_BUILD", "0");
+    std::env::set_var("PROOFPATCH_DOTENV_SEARCH", "0");
+}
+
+#[tokio::test]
+async fn survivors_are_verified_and_the_best_is_picked() {
+    let _g = common::LOCK.lock().await;
+    let dir = tempfile::tempdir().unwrap();
+    lean_repo(dir.path());
+    let text = "theorem foo : True := by\n  sorry\n";
+    let samples: Vec<String> = ["exact bad", "exact bad", "trivial", "by\n  trivial", "bad2"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    let candidates = sampling::rank_candidates(&samples);
+    let checks = sampling::verify_candidates(
+        dir.path(),
+        text,
+        "foo",
+        &candidates,
+        8,
+        2,
+        Duration::from_secs(10),
+    )
+    .await;
+
+    assert_eq!(
+        checks.iter().map(|c| c.ok).collect::<Vec<_>>(),
+        vec![false, true, false]
+    );
+    let best = sampling::best_check(&checks).unwrap();
+    assert_eq!((best.rank, best.proof.as_str()), (1, "trivial"));
+    assert!(best.solved);
+    let stats = sampling::sample_stats(samples.len(), &candidates, &checks);
+    assert_eq!((stats.distinct, stats.compiled, stats.solved), (3, 1, 1));
+    assert_eq!(stats.compiled_samples, 2);
+    assert!((stats.compiled_rate - 0.4).abs() < 1e-9);
+
+    // Only the top candidate when `max_verify` is 1.
+    let checks = sampling::verify_candidates(
+        dir.path(),
+        text,
+        "foo",
+        &candidates,
+        1,
+        2,
+        Duration::from_secs(10),
+    )
+    .await;
+    assert_eq!(checks.len(), 1);
+    assert!(sampling::best_check(&checks).is_none());
+}
@@ -36,6 +36,17 @@ fn made_no_progress_detection() {
     assert!(!ts::is_made_no_progress(Some("unknown constant")));
 }
 
+#[test]
+fn resource_limit_errors_drop_heavy_automation() {
+    let err =
+        "(deterministic) timeout at `whnf`, maximum number of heartbeats (200000) has been reached";
+    assert_eq!(ts::resource_limit_kind(Some(err)), Some("heartbeats"));
+    let base = vec!["by\n  aesop".to_string(), "by\n  simp".to_string()];
+    let out = ts::adapt_candidates_for_error(&base, Some(err));
+    assert!(out.iter().all(|c| !c.contains("aesop")));
+    assert!(out.contains(&"by\n  simp".to_string()));
+}
+
 #[test]
 fn adapt_candidates_for_sorry_line_strips_by_prefix() {
     let base = vec!["by\n  simp".to_string(), "by aesop".to_string()];
@@ -123,3 +134,12 @@ fn hint_rules_can_rewrite_mod8_binder_name() {
     // Ensure we didn't keep the brittle binder.
     assert!(!out.iter().any(|c| c.contains("have h2 : n % 2 = 1")));
 }
+
+#[test]
+fn slow_verify_penalty_stays_below_one_sorry() {
+    assert_eq!(ts::slow_verify_penalty(true, Some(4_999), 5_000, 1), 0);
+    assert_eq!(ts::slow_verify_penalty(true, Some(10_000), 5_000, 1), 2);
+    assert_eq!(ts::slow_verify_penalty(false, Some(60_000), 5_000, 1), 0);
+    // A slow proof still ranks ahead of a fast candidate with one `sorry` (10 per warning).
+    assert_eq!(ts::slow_verify_penalty(true, Some(600_000), 5_000, 7), 9);
+}